@@ -0,0 +1,1075 @@
+use anyhow::anyhow;
+use bip39::Mnemonic;
+use bitcoin::hashes::hex::FromHex;
+use bitcoin::hashes::sha256;
+use bitcoin::psbt::PartiallySignedTransaction;
+use bitcoin::secp256k1::PublicKey;
+use bitcoin::util::bip32::{ExtendedPrivKey, ExtendedPubKey, Fingerprint};
+use bitcoin::{Address, Network, OutPoint};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use fedimint_core::{api::InviteCode, config::FederationId};
+use lightning::routing::gossip::NodeId;
+use lightning_invoice::Bolt11Invoice;
+use mutiny_core::chainsource::ChainSourceConfig;
+use mutiny_core::encrypt::encryption_key_from_pass;
+use mutiny_core::event::CustomTlv;
+use mutiny_core::labels::LabelStorage;
+use mutiny_core::logging::{LogFilter, LogLevel, LogRetention, MutinyLogger};
+use mutiny_core::nodemanager::NodeManager;
+use mutiny_core::nostr::nwc::{
+    BudgetPeriod, BudgetedSpendingConditions, NwcNotificationType, NwcProfileTag,
+    SpendingConditions,
+};
+use mutiny_core::nostr::ProfileType;
+use mutiny_core::routing::{PaymentSource, RoutingPolicy};
+use mutiny_core::scb::StaticChannelBackup;
+use mutiny_core::sqlite::SqliteStorage;
+use mutiny_core::storage::MutinyStorage;
+use mutiny_core::vss::MutinyVssClient;
+use mutiny_core::{
+    generate_seed, InvoiceHandler, LspFeeLimits, LspSelection, MutinyWalletBuilder,
+    MutinyWalletConfigBuilder,
+};
+use serde_json::{json, Value};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+type MutinyWallet = mutiny_core::MutinyWallet<SqliteStorage>;
+
+/// A command line wallet for mutiny-core.
+///
+/// All output is printed as JSON so it can be used in scripts.
+///
+/// Passwords are only read from the `MUTINY_PASSWORD` and `MUTINY_BITCOIND_PASSWORD`
+/// environment variables so they don't end up in the process list or shell history.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct Cli {
+    /// Directory the wallet database is stored in
+    #[arg(long, env = "MUTINY_DATA_DIR", default_value = ".mutiny")]
+    data_dir: PathBuf,
+    /// Password used to encrypt the wallet
+    #[arg(skip = std::env::var("MUTINY_PASSWORD").ok())]
+    password: Option<String>,
+    #[arg(long, env = "MUTINY_NETWORK", default_value = "bitcoin")]
+    network: Network,
+    #[arg(long, env = "MUTINY_WEBSOCKET_PROXY")]
+    websocket_proxy_addr: Option<String>,
+    /// Esplora servers to use, comma separated, later ones are used when earlier ones fail
+    #[arg(long, env = "MUTINY_ESPLORA_URL", value_delimiter = ',')]
+    esplora_url: Vec<String>,
+    /// Use an electrum server instead of esplora
+    #[arg(long, env = "MUTINY_ELECTRUM_URL", conflicts_with = "bitcoind_url")]
+    electrum_url: Option<String>,
+    /// Use a bitcoind node's RPC instead of esplora
+    #[arg(long, env = "MUTINY_BITCOIND_URL")]
+    bitcoind_url: Option<String>,
+    #[arg(long, env = "MUTINY_BITCOIND_USER")]
+    bitcoind_user: Option<String>,
+    #[arg(skip = std::env::var("MUTINY_BITCOIND_PASSWORD").ok())]
+    bitcoind_password: Option<String>,
+    /// Watch-only wallet on the bitcoind node used to track the wallet's transactions
+    #[arg(long, env = "MUTINY_BITCOIND_WALLET", default_value = "mutiny")]
+    bitcoind_wallet: String,
+    #[arg(long, env = "MUTINY_RGS_URL")]
+    rgs_url: Option<String>,
+    #[arg(long, env = "MUTINY_LSP_URL")]
+    lsp_url: Option<String>,
+    #[arg(long, env = "MUTINY_LSP_CONNECTION_STRING")]
+    lsp_connection_string: Option<String>,
+    #[arg(long, env = "MUTINY_LSP_TOKEN")]
+    lsp_token: Option<String>,
+    /// Voltage flow LSPs used alongside the primary LSP, comma separated
+    #[arg(long, env = "MUTINY_ADDITIONAL_LSP_URL", value_delimiter = ',')]
+    additional_lsp_url: Vec<String>,
+    /// LSPS LSPs used alongside the primary LSP, comma separated
+    #[arg(
+        long,
+        env = "MUTINY_ADDITIONAL_LSP_CONNECTION_STRING",
+        value_delimiter = ','
+    )]
+    additional_lsp_connection_string: Vec<String>,
+    /// How an LSP is picked for an invoice: cheapest or most_reliable
+    #[arg(long, env = "MUTINY_LSP_SELECTION", default_value = "cheapest")]
+    lsp_selection: LspSelection,
+    /// Most to pay an LSP to open a channel for an invoice, in satoshis
+    #[arg(long, env = "MUTINY_MAX_LSP_FEE_SAT")]
+    max_lsp_fee_sat: Option<u64>,
+    /// Most to pay an LSP to open a channel for an invoice, in parts per million of the amount
+    #[arg(long, env = "MUTINY_MAX_LSP_FEE_PPM")]
+    max_lsp_fee_ppm: Option<u32>,
+    /// VSS server to mirror the wallet state to
+    #[arg(long, env = "MUTINY_STORAGE_URL")]
+    storage_url: Option<String>,
+    #[arg(long, env = "MUTINY_SCORER_URL")]
+    scorer_url: Option<String>,
+    /// Use a watch-only on-chain wallet for this taproot account xpub,
+    /// its transactions have to be signed externally
+    #[arg(
+        long,
+        env = "MUTINY_WATCH_ONLY_XPUB",
+        requires = "watch_only_fingerprint"
+    )]
+    watch_only_xpub: Option<ExtendedPubKey>,
+    /// Fingerprint of the master key the watch-only xpub was derived from
+    #[arg(
+        long,
+        env = "MUTINY_WATCH_ONLY_FINGERPRINT",
+        requires = "watch_only_xpub"
+    )]
+    watch_only_fingerprint: Option<Fingerprint>,
+    /// Do not connect to peers on startup
+    #[arg(long)]
+    do_not_connect_peers: bool,
+    #[arg(long)]
+    skip_device_lock: bool,
+    /// Start the wallet without lightning nodes or remote services
+    #[arg(long)]
+    safe_mode: bool,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Create or restore the wallet's mnemonic
+    #[command(subcommand)]
+    Mnemonic(MnemonicCommand),
+    /// Get the balances of the wallet
+    Balance,
+    /// List the activity of the wallet
+    Activity,
+    /// Get an address and invoice to receive to
+    Receive {
+        /// Amount in satoshis
+        #[arg(long)]
+        amount: Option<u64>,
+        #[arg(long = "label")]
+        labels: Vec<String>,
+    },
+    #[command(subcommand)]
+    Onchain(OnchainCommand),
+    #[command(subcommand)]
+    Lightning(LightningCommand),
+    #[command(subcommand)]
+    Channel(ChannelCommand),
+    #[command(subcommand)]
+    Peer(PeerCommand),
+    #[command(subcommand)]
+    Federation(FederationCommand),
+    /// Manage nostr wallet connect profiles
+    #[command(subcommand)]
+    Nwc(NwcCommand),
+    #[command(subcommand)]
+    Label(LabelCommand),
+    /// Export the wallet state as JSON
+    Export {
+        /// File to write to, prints to stdout if not given
+        path: Option<PathBuf>,
+    },
+    /// Replace the wallet state with a JSON export
+    Import { path: PathBuf },
+    /// Show the wallet's logs, secrets are redacted
+    Logs {
+        /// Only logs at this level or more severe
+        #[arg(long)]
+        level: Option<LogLevel>,
+        /// Only logs after this time, in seconds since the epoch
+        #[arg(long)]
+        since: Option<u64>,
+        /// Only logs before this time, in seconds since the epoch
+        #[arg(long)]
+        until: Option<u64>,
+        /// Only logs from modules starting with this path
+        #[arg(long)]
+        module: Option<String>,
+        /// Write the logs as text to a file, for sharing with support
+        #[arg(long)]
+        export: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum MnemonicCommand {
+    /// Create a new wallet with a freshly generated mnemonic
+    Create,
+    /// Restore a wallet from a mnemonic, this deletes the existing wallet state
+    Restore { words: String },
+    /// Print the wallet's mnemonic
+    Show,
+}
+
+#[derive(Subcommand, Debug)]
+enum OnchainCommand {
+    /// Get a new on-chain address
+    NewAddress {
+        #[arg(long = "label")]
+        labels: Vec<String>,
+    },
+    /// Send to an on-chain address
+    Send {
+        address: Address,
+        /// Amount in satoshis
+        amount: u64,
+        /// Fee rate in sat/vbyte
+        #[arg(long)]
+        fee_rate: Option<f32>,
+        #[arg(long = "label")]
+        labels: Vec<String>,
+    },
+    /// Send the entire on-chain balance to an address
+    Sweep {
+        address: Address,
+        /// Fee rate in sat/vbyte
+        #[arg(long)]
+        fee_rate: Option<f32>,
+        #[arg(long = "label")]
+        labels: Vec<String>,
+    },
+    /// Create an unsigned PSBT sending to an address, to be signed externally
+    UnsignedPsbt {
+        address: Address,
+        /// Amount in satoshis
+        amount: u64,
+        /// Fee rate in sat/vbyte
+        #[arg(long)]
+        fee_rate: Option<f32>,
+    },
+    /// Broadcast a PSBT created by this wallet and signed externally
+    BroadcastPsbt {
+        /// The signed PSBT as base64
+        psbt: PartiallySignedTransaction,
+        #[arg(long = "label")]
+        labels: Vec<String>,
+    },
+    /// List the on-chain transactions
+    Transactions,
+    /// List the unspent outputs
+    Utxos,
+    /// Show the health of the esplora servers
+    Endpoints,
+}
+
+#[derive(Subcommand, Debug)]
+enum LightningCommand {
+    /// Create a lightning invoice
+    Invoice {
+        /// Amount in satoshis
+        amount: Option<u64>,
+        #[arg(long = "label")]
+        labels: Vec<String>,
+    },
+    /// Pay a lightning invoice
+    Pay {
+        invoice: Bolt11Invoice,
+        /// Amount in satoshis, only for invoices without an amount
+        #[arg(long)]
+        amount: Option<u64>,
+        #[arg(long = "label")]
+        labels: Vec<String>,
+        /// Only pay from this federation
+        #[arg(long, conflicts_with = "node")]
+        federation: Option<String>,
+        /// Only pay from our lightning node
+        #[arg(long)]
+        node: bool,
+    },
+    /// Send a spontaneous payment to a node
+    Keysend {
+        to_node: PublicKey,
+        /// Amount in satoshis
+        amount: u64,
+        #[arg(long)]
+        message: Option<String>,
+        /// Custom TLV record as type=hex, can be repeated
+        #[arg(long = "tlv", value_parser = parse_custom_tlv)]
+        custom_tlvs: Vec<CustomTlv>,
+        #[arg(long = "label")]
+        labels: Vec<String>,
+    },
+    /// Show the expected fee of paying an invoice from each source
+    Quote {
+        invoice: Bolt11Invoice,
+        /// Amount in satoshis, only for invoices without an amount
+        #[arg(long)]
+        amount: Option<u64>,
+    },
+    /// Show the routing policy, or replace it with the given json
+    RoutingPolicy {
+        #[arg(long)]
+        set: Option<String>,
+    },
+    /// List the lightning invoices
+    Invoices,
+    /// Create a hold invoice, payments are held until settled or cancelled
+    HoldInvoice {
+        payment_hash: sha256::Hash,
+        /// Amount in satoshis
+        amount: Option<u64>,
+    },
+    /// Claim the payment held for a hold invoice
+    Settle {
+        /// Hex encoded preimage of the hold invoice's payment hash
+        preimage: String,
+    },
+    /// Cancel a hold invoice, failing back any held payment
+    Cancel { payment_hash: sha256::Hash },
+    /// List the hold invoices
+    HoldInvoices,
+    /// List the lightning nodes
+    Nodes,
+    /// Create a new lightning node
+    NewNode,
+}
+
+#[derive(Subcommand, Debug)]
+enum ChannelCommand {
+    List,
+    /// Open a channel, to the LSP if no pubkey is given
+    Open {
+        /// Amount in satoshis
+        amount: u64,
+        #[arg(long)]
+        pubkey: Option<PublicKey>,
+        /// Fee rate in sat/vbyte
+        #[arg(long)]
+        fee_rate: Option<f32>,
+    },
+    /// Start opening a channel funded by a watch-only wallet, prints the funding PSBT to sign
+    OpenUnsigned {
+        /// Amount in satoshis
+        amount: u64,
+        #[arg(long)]
+        pubkey: Option<PublicKey>,
+        /// Fee rate in sat/vbyte
+        #[arg(long)]
+        fee_rate: Option<f32>,
+    },
+    /// Fund a channel started with open-unsigned with its signed funding PSBT
+    Fund {
+        user_channel_id: u128,
+        /// The signed PSBT as base64
+        psbt: PartiallySignedTransaction,
+    },
+    Close {
+        outpoint: OutPoint,
+        /// Address to send the funds to, uses the wallet if not given
+        #[arg(long)]
+        address: Option<Address>,
+        #[arg(long)]
+        force: bool,
+        /// Force close without broadcasting, only for channels that will never open
+        #[arg(long)]
+        abandon: bool,
+    },
+    /// List the channels that have been closed
+    Closures,
+    /// Create a static channel backup
+    Backup,
+    /// Recover channel funds from a static channel backup
+    Recover {
+        backup: String,
+    },
+    /// Show the channels an LSPS1 LSP sells
+    OrderInfo {
+        lsp_url: String,
+    },
+    /// Buy a channel from an LSPS1 LSP for inbound liquidity
+    Order {
+        lsp_url: String,
+        /// Inbound capacity in satoshis
+        lsp_balance: u64,
+        /// Outbound capacity in satoshis, paid for with the order
+        #[arg(long, default_value_t = 0)]
+        client_balance: u64,
+        /// How long the LSP keeps the channel open, in blocks
+        #[arg(long, default_value_t = 13_140)]
+        expiry_blocks: u32,
+        #[arg(long)]
+        token: Option<String>,
+    },
+    /// Show a channel order, or list them all if no id is given
+    Orders {
+        order_id: Option<String>,
+    },
+    /// Pay for a channel order
+    PayOrder {
+        order_id: String,
+        /// Pay on-chain instead of with lightning
+        #[arg(long)]
+        onchain: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum PeerCommand {
+    List,
+    Connect {
+        /// Connection string in the form pubkey@host:port
+        connection_string: String,
+        #[arg(long)]
+        label: Option<String>,
+    },
+    Disconnect {
+        pubkey: PublicKey,
+    },
+    /// Disconnect and forget a peer
+    Delete {
+        pubkey: PublicKey,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum FederationCommand {
+    List,
+    /// Join a federation with its invite code
+    Join {
+        invite_code: String,
+    },
+    Remove {
+        federation_id: String,
+    },
+    Balances,
+    /// Get an address to deposit bitcoin into a federation
+    Deposit {
+        federation_id: String,
+        #[arg(long = "label")]
+        labels: Vec<String>,
+    },
+    /// Withdraw from a federation to an on-chain address
+    Withdraw {
+        federation_id: String,
+        address: Address,
+        /// Amount in satoshis
+        amount: u64,
+        /// Only show the federation's fee, without withdrawing
+        #[arg(long)]
+        estimate: bool,
+        #[arg(long = "label")]
+        labels: Vec<String>,
+    },
+    /// List deposits and withdrawals
+    Transactions,
+}
+
+#[derive(Subcommand, Debug)]
+enum NwcCommand {
+    List,
+    Create(CreateNwcArgs),
+    Delete {
+        index: u32,
+    },
+    /// Set which payment notifications the profile's client receives, none if not given
+    Notifications {
+        index: u32,
+        #[arg(long = "notification", value_enum)]
+        notifications: Vec<Notification>,
+    },
+}
+
+#[derive(Args, Debug)]
+struct CreateNwcArgs {
+    name: String,
+    /// Budget in satoshis, payments require approval if not given
+    #[arg(long)]
+    budget: Option<u64>,
+    #[arg(long, value_enum, default_value_t = Period::Day, requires = "budget")]
+    period: Period,
+    /// Max amount in satoshis for a single payment
+    #[arg(long, requires = "budget")]
+    single_max: Option<u64>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Period {
+    Day,
+    Week,
+    Month,
+    Year,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Notification {
+    PaymentReceived,
+    PaymentSent,
+}
+
+impl From<Notification> for NwcNotificationType {
+    fn from(value: Notification) -> Self {
+        match value {
+            Notification::PaymentReceived => NwcNotificationType::PaymentReceived,
+            Notification::PaymentSent => NwcNotificationType::PaymentSent,
+        }
+    }
+}
+
+impl From<Period> for BudgetPeriod {
+    fn from(value: Period) -> Self {
+        match value {
+            Period::Day => BudgetPeriod::Day,
+            Period::Week => BudgetPeriod::Week,
+            Period::Month => BudgetPeriod::Month,
+            Period::Year => BudgetPeriod::Year,
+        }
+    }
+}
+
+fn parse_custom_tlv(s: &str) -> Result<CustomTlv, String> {
+    let (tlv_type, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("invalid TLV record, expected type=hex: {s}"))?;
+    Ok(CustomTlv {
+        tlv_type: tlv_type
+            .parse()
+            .map_err(|e| format!("invalid TLV type: {e}"))?,
+        value: FromHex::from_hex(value).map_err(|e| format!("invalid TLV value: {e}"))?,
+    })
+}
+
+#[derive(Subcommand, Debug)]
+enum LabelCommand {
+    List,
+    /// Replace the labels of an address
+    SetAddress {
+        address: Address,
+        labels: Vec<String>,
+    },
+    /// Replace the labels of an invoice
+    SetInvoice {
+        invoice: Bolt11Invoice,
+        labels: Vec<String>,
+    },
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+
+    let output = run(cli).await?;
+    println!("{}", serde_json::to_string_pretty(&output)?);
+
+    Ok(())
+}
+
+async fn run(cli: Cli) -> anyhow::Result<Value> {
+    let logger = Arc::new(MutinyLogger::default());
+    let password = cli.password.clone().filter(|p| !p.is_empty());
+    let cipher = password
+        .as_deref()
+        .map(encryption_key_from_pass)
+        .transpose()?;
+
+    // these commands only need storage, not a running wallet
+    match &cli.command {
+        Command::Mnemonic(cmd) => {
+            let storage = SqliteStorage::new(&cli.data_dir, password, cipher, None, logger).await?;
+            return mnemonic_command(storage, cmd).await;
+        }
+        Command::Export { path } => {
+            let storage = SqliteStorage::new(&cli.data_dir, password, cipher, None, logger).await?;
+            if storage.get_mnemonic().is_err() {
+                // if we get an error, then we have the wrong password
+                return Err(anyhow!("Incorrect password"));
+            }
+            let json = NodeManager::export_json(storage).await?;
+            return match path {
+                Some(path) => {
+                    std::fs::write(path, serde_json::to_string(&json)?)?;
+                    Ok(json!({ "path": path }))
+                }
+                None => Ok(json),
+            };
+        }
+        Command::Logs {
+            level,
+            since,
+            until,
+            module,
+            export,
+        } => {
+            let storage = SqliteStorage::new(&cli.data_dir, password, cipher, None, logger).await?;
+            let stop = Arc::new(AtomicBool::new(false));
+            let logger = Arc::new(MutinyLogger::with_writer(
+                stop.clone(),
+                storage.clone(),
+                None,
+                LogRetention::default(),
+            ));
+            let filter = LogFilter {
+                min_level: *level,
+                start: since.map(|s| s * 1_000),
+                end: until.map(|s| s * 1_000),
+                module: module.clone(),
+            };
+            let res = match export {
+                Some(path) => {
+                    let logs = NodeManager::export_logs(storage, logger, &filter)?;
+                    std::fs::write(path, logs.unwrap_or_default())?;
+                    json!({ "path": path })
+                }
+                None => serde_json::to_value(NodeManager::query_logs(storage, logger, &filter)?)?,
+            };
+            stop.store(true, Ordering::Relaxed);
+            return Ok(res);
+        }
+        Command::Import { path } => {
+            let json: Value = serde_json::from_slice(&std::fs::read(path)?)?;
+            // opening the storage selects the database to import into
+            let storage = SqliteStorage::new(&cli.data_dir, password, cipher, None, logger).await?;
+            SqliteStorage::import(json).await?;
+            storage.stop();
+            return Ok(json!({ "imported": true }));
+        }
+        _ => {}
+    }
+
+    let mut wallet = start_wallet(&cli, password, cipher, logger).await?;
+    let result = wallet_command(&mut wallet, cli.command).await;
+    wallet.stop().await?;
+
+    result
+}
+
+async fn mnemonic_command(storage: SqliteStorage, cmd: &MnemonicCommand) -> anyhow::Result<Value> {
+    match cmd {
+        MnemonicCommand::Create => {
+            if storage.get_mnemonic()?.is_some() {
+                return Err(anyhow!("A wallet already exists in this data directory"));
+            }
+            let mnemonic = storage.insert_mnemonic(generate_seed(12)?)?;
+            Ok(json!({ "mnemonic": mnemonic.to_string() }))
+        }
+        MnemonicCommand::Restore { words } => {
+            let mnemonic = Mnemonic::from_str(words)?;
+            MutinyWallet::restore_mnemonic(storage, mnemonic).await?;
+            Ok(json!({ "restored": true }))
+        }
+        MnemonicCommand::Show => {
+            let mnemonic = storage
+                .get_mnemonic()?
+                .ok_or(anyhow!("No wallet in this data directory"))?;
+            Ok(json!({ "mnemonic": mnemonic.to_string() }))
+        }
+    }
+}
+
+async fn start_wallet(
+    cli: &Cli,
+    password: Option<String>,
+    cipher: Option<mutiny_core::encrypt::Cipher>,
+    logger: Arc<MutinyLogger>,
+) -> anyhow::Result<MutinyWallet> {
+    // read the mnemonic without vss, we need it to authenticate with vss
+    let storage = SqliteStorage::new(
+        &cli.data_dir,
+        password.clone(),
+        cipher.clone(),
+        None,
+        logger.clone(),
+    )
+    .await?;
+    let mnemonic = storage.get_mnemonic()?.ok_or(anyhow!(
+        "No wallet in this data directory, create one with `mnemonic create`"
+    ))?;
+    storage.stop();
+
+    let seed = mnemonic.to_seed("");
+    let xprivkey = ExtendedPrivKey::new_master(cli.network, &seed)?;
+
+    let vss_client = match cli.storage_url.clone() {
+        Some(url) if !cli.safe_mode => Some(Arc::new(MutinyVssClient::new_unauthenticated(
+            url,
+            xprivkey.private_key,
+            logger.clone(),
+        ))),
+        _ => None,
+    };
+    let storage =
+        SqliteStorage::new(&cli.data_dir, password, cipher, vss_client, logger.clone()).await?;
+
+    let mut config_builder = MutinyWalletConfigBuilder::new(xprivkey).with_network(cli.network);
+    if let Some(w) = cli.websocket_proxy_addr.clone() {
+        config_builder.with_websocket_proxy_addr(w);
+    }
+    if !cli.esplora_url.is_empty() {
+        config_builder.with_user_esplora_urls(cli.esplora_url.clone());
+    }
+    if let Some(url) = cli.electrum_url.clone() {
+        config_builder.with_chain_source(ChainSourceConfig::Electrum { url });
+    } else if let Some(url) = cli.bitcoind_url.clone() {
+        config_builder.with_chain_source(ChainSourceConfig::BitcoindRpc {
+            url,
+            user: cli.bitcoind_user.clone(),
+            password: cli.bitcoind_password.clone(),
+            wallet: cli.bitcoind_wallet.clone(),
+        });
+    }
+    if let Some(url) = cli.rgs_url.clone() {
+        config_builder.with_user_rgs_url(url);
+    }
+    if let Some(url) = cli.lsp_url.clone() {
+        config_builder.with_lsp_url(url);
+    }
+    if let Some(url) = cli.lsp_connection_string.clone() {
+        config_builder.with_lsp_connection_string(url);
+    }
+    if let Some(token) = cli.lsp_token.clone() {
+        config_builder.with_lsp_token(token);
+    }
+    for url in cli.additional_lsp_url.iter() {
+        config_builder.with_additional_lsp_url(url.clone());
+    }
+    for connection_string in cli.additional_lsp_connection_string.iter() {
+        config_builder.with_additional_lsp_connection_string(connection_string.clone(), None);
+    }
+    config_builder.with_lsp_selection(cli.lsp_selection);
+    config_builder.with_lsp_fee_limits(LspFeeLimits {
+        max_fee_msat: cli.max_lsp_fee_sat.map(|sat| sat * 1_000),
+        max_fee_ppm: cli.max_lsp_fee_ppm,
+    });
+    if let Some(url) = cli.scorer_url.clone() {
+        config_builder.with_scorer_url(url);
+    }
+    if let (Some(xpub), Some(fingerprint)) = (cli.watch_only_xpub, cli.watch_only_fingerprint) {
+        config_builder.with_watch_only_xpub(xpub, fingerprint);
+    }
+    if cli.skip_device_lock {
+        config_builder.with_skip_device_lock();
+    }
+    if cli.do_not_connect_peers {
+        config_builder.do_not_connect_peers();
+    }
+    if cli.safe_mode {
+        config_builder.with_safe_mode();
+    }
+    let config = config_builder.build();
+
+    let mut mw_builder = MutinyWalletBuilder::new(xprivkey, storage).with_config(config);
+    mw_builder.with_session_id(logger.session_id.clone());
+
+    Ok(mw_builder.build().await?)
+}
+
+async fn wallet_command(wallet: &mut MutinyWallet, command: Command) -> anyhow::Result<Value> {
+    let nm = wallet.node_manager.clone();
+    let value = match command {
+        Command::Mnemonic(_)
+        | Command::Export { .. }
+        | Command::Import { .. }
+        | Command::Logs { .. } => {
+            unreachable!("handled before starting the wallet")
+        }
+        Command::Balance => {
+            let balance = wallet.get_balance().await?;
+            json!({
+                "confirmed": balance.confirmed,
+                "unconfirmed": balance.unconfirmed,
+                "lightning": balance.lightning,
+                "federation": balance.federation,
+                "force_close": balance.force_close,
+                "watch_only": wallet.is_watch_only(),
+            })
+        }
+        Command::Activity => serde_json::to_value(wallet.get_activity().await?)?,
+        Command::Receive { amount, labels } => {
+            serde_json::to_value(wallet.create_bip21(amount, labels).await?)?
+        }
+        Command::Onchain(cmd) => match cmd {
+            OnchainCommand::NewAddress { labels } => {
+                json!({ "address": nm.get_new_address(labels)? })
+            }
+            OnchainCommand::Send {
+                address,
+                amount,
+                fee_rate,
+                labels,
+            } => {
+                let txid = nm
+                    .send_to_address(address, amount, labels, fee_rate)
+                    .await?;
+                json!({ "txid": txid })
+            }
+            OnchainCommand::Sweep {
+                address,
+                fee_rate,
+                labels,
+            } => {
+                let txid = nm.sweep_wallet(address, labels, fee_rate).await?;
+                json!({ "txid": txid })
+            }
+            OnchainCommand::UnsignedPsbt {
+                address,
+                amount,
+                fee_rate,
+            } => {
+                let psbt = nm.create_unsigned_psbt(address, amount, None, fee_rate)?;
+                json!({ "psbt": psbt.to_string() })
+            }
+            OnchainCommand::BroadcastPsbt { psbt, labels } => {
+                let txid = nm.broadcast_signed_psbt(psbt, labels).await?;
+                json!({ "txid": txid })
+            }
+            OnchainCommand::Transactions => serde_json::to_value(nm.list_onchain()?)?,
+            OnchainCommand::Utxos => serde_json::to_value(nm.list_utxos()?)?,
+            OnchainCommand::Endpoints => serde_json::to_value(nm.get_chain_endpoint_stats())?,
+        },
+        Command::Lightning(cmd) => match cmd {
+            LightningCommand::Invoice { amount, labels } => {
+                serde_json::to_value(wallet.create_invoice(amount, labels).await?)?
+            }
+            LightningCommand::Pay {
+                invoice,
+                amount,
+                labels,
+                federation,
+                node,
+            } => {
+                let source = match federation {
+                    Some(id) => Some(PaymentSource::Federation(
+                        FederationId::from_str(&id)
+                            .map_err(|e| anyhow!("Invalid federation id: {e}"))?,
+                    )),
+                    None if node => Some(PaymentSource::Node),
+                    None => None,
+                };
+                let invoice = match source {
+                    Some(source) => {
+                        wallet
+                            .pay_invoice_from_source(&invoice, amount, labels, source)
+                            .await?
+                    }
+                    None => wallet.pay_invoice(&invoice, amount, labels).await?,
+                };
+                serde_json::to_value(invoice)?
+            }
+            LightningCommand::Keysend {
+                to_node,
+                amount,
+                message,
+                custom_tlvs,
+                labels,
+            } => serde_json::to_value(
+                wallet
+                    .keysend(to_node, amount, message, custom_tlvs, labels)
+                    .await?,
+            )?,
+            LightningCommand::Quote { invoice, amount } => {
+                serde_json::to_value(wallet.quote_payment(&invoice, amount).await?)?
+            }
+            LightningCommand::RoutingPolicy { set } => {
+                if let Some(json) = set {
+                    let policy: RoutingPolicy = serde_json::from_str(&json)?;
+                    wallet.set_routing_policy(policy)?;
+                }
+                serde_json::to_value(wallet.get_routing_policy()?)?
+            }
+            LightningCommand::Invoices => serde_json::to_value(wallet.list_invoices()?)?,
+            LightningCommand::HoldInvoice {
+                payment_hash,
+                amount,
+            } => serde_json::to_value(nm.create_hold_invoice(payment_hash, amount).await?)?,
+            LightningCommand::Settle { preimage } => {
+                let preimage: [u8; 32] = FromHex::from_hex(&preimage)?;
+                nm.settle_hold_invoice(preimage).await?;
+                json!({ "settled": true })
+            }
+            LightningCommand::Cancel { payment_hash } => {
+                nm.cancel_hold_invoice(payment_hash).await?;
+                json!({ "cancelled": true })
+            }
+            LightningCommand::HoldInvoices => serde_json::to_value(nm.list_hold_invoices()?)?,
+            LightningCommand::Nodes => serde_json::to_value(nm.list_nodes().await?)?,
+            LightningCommand::NewNode => {
+                let node = nm.new_node().await?;
+                json!({ "uuid": node.uuid, "pubkey": node.pubkey })
+            }
+        },
+        Command::Channel(cmd) => match cmd {
+            ChannelCommand::List => serde_json::to_value(nm.list_channels().await?)?,
+            ChannelCommand::Open {
+                amount,
+                pubkey,
+                fee_rate,
+            } => serde_json::to_value(
+                nm.open_channel(None, pubkey, amount, fee_rate, None)
+                    .await?,
+            )?,
+            ChannelCommand::OpenUnsigned {
+                amount,
+                pubkey,
+                fee_rate,
+            } => {
+                let pending = nm
+                    .open_channel_unsigned(None, pubkey, amount, fee_rate, None)
+                    .await?;
+                json!({
+                    "user_channel_id": pending.user_channel_id.to_string(),
+                    "psbt": pending.psbt.to_string(),
+                })
+            }
+            ChannelCommand::Fund {
+                user_channel_id,
+                psbt,
+            } => serde_json::to_value(nm.fund_channel(None, user_channel_id, psbt).await?)?,
+            ChannelCommand::Close {
+                outpoint,
+                address,
+                force,
+                abandon,
+            } => {
+                nm.close_channel(&outpoint, address, force, abandon).await?;
+                json!({ "closed": outpoint })
+            }
+            ChannelCommand::Closures => serde_json::to_value(nm.list_channel_closures().await?)?,
+            ChannelCommand::Backup => {
+                let backup = nm.create_static_channel_backup().await?;
+                json!({ "backup": backup.to_string() })
+            }
+            ChannelCommand::Recover { backup } => {
+                let backup = StaticChannelBackup::from_str(&backup)?;
+                let count = nm.recover_from_static_channel_backup(backup).await?;
+                json!({ "recovering": count })
+            }
+            ChannelCommand::OrderInfo { lsp_url } => {
+                serde_json::to_value(nm.get_channel_order_info(&lsp_url).await?)?
+            }
+            ChannelCommand::Order {
+                lsp_url,
+                lsp_balance,
+                client_balance,
+                expiry_blocks,
+                token,
+            } => serde_json::to_value(
+                nm.create_channel_order(lsp_url, lsp_balance, client_balance, expiry_blocks, token)
+                    .await?,
+            )?,
+            ChannelCommand::Orders { order_id } => match order_id {
+                Some(order_id) => serde_json::to_value(nm.get_channel_order(&order_id).await?)?,
+                None => serde_json::to_value(nm.list_channel_orders()?)?,
+            },
+            ChannelCommand::PayOrder { order_id, onchain } => {
+                serde_json::to_value(nm.pay_channel_order(&order_id, onchain).await?)?
+            }
+        },
+        Command::Peer(cmd) => match cmd {
+            PeerCommand::List => serde_json::to_value(nm.list_peers().await?)?,
+            PeerCommand::Connect {
+                connection_string,
+                label,
+            } => {
+                nm.connect_to_peer(None, &connection_string, label).await?;
+                json!({ "connected": connection_string })
+            }
+            PeerCommand::Disconnect { pubkey } => {
+                nm.disconnect_peer(None, pubkey).await?;
+                json!({ "disconnected": pubkey })
+            }
+            PeerCommand::Delete { pubkey } => {
+                nm.delete_peer(None, &NodeId::from_pubkey(&pubkey)).await?;
+                json!({ "deleted": pubkey })
+            }
+        },
+        Command::Federation(cmd) => match cmd {
+            FederationCommand::List => serde_json::to_value(wallet.list_federations().await?)?,
+            FederationCommand::Join { invite_code } => {
+                let invite_code = InviteCode::from_str(&invite_code)
+                    .map_err(|e| anyhow!("Invalid invite code: {e}"))?;
+                serde_json::to_value(wallet.new_federation(invite_code).await?)?
+            }
+            FederationCommand::Remove { federation_id } => {
+                let id = FederationId::from_str(&federation_id)
+                    .map_err(|e| anyhow!("Invalid federation id: {e}"))?;
+                wallet.remove_federation(id).await?;
+                json!({ "removed": federation_id })
+            }
+            FederationCommand::Balances => {
+                serde_json::to_value(wallet.get_federation_balances().await?)?
+            }
+            FederationCommand::Deposit {
+                federation_id,
+                labels,
+            } => {
+                let id = FederationId::from_str(&federation_id)
+                    .map_err(|e| anyhow!("Invalid federation id: {e}"))?;
+                serde_json::to_value(wallet.get_federation_deposit_address(id, labels).await?)?
+            }
+            FederationCommand::Withdraw {
+                federation_id,
+                address,
+                amount,
+                estimate,
+                labels,
+            } => {
+                let id = FederationId::from_str(&federation_id)
+                    .map_err(|e| anyhow!("Invalid federation id: {e}"))?;
+                if estimate {
+                    let fee = wallet
+                        .estimate_federation_withdrawal_fee(id, address, amount)
+                        .await?;
+                    json!({ "fee_sats": fee })
+                } else {
+                    serde_json::to_value(
+                        wallet
+                            .withdraw_from_federation(id, address, amount, labels)
+                            .await?,
+                    )?
+                }
+            }
+            FederationCommand::Transactions => {
+                serde_json::to_value(wallet.list_federation_onchain_txs()?)?
+            }
+        },
+        Command::Nwc(cmd) => match cmd {
+            NwcCommand::List => serde_json::to_value(wallet.nostr.profiles())?,
+            NwcCommand::Create(args) => {
+                let spending_conditions = match args.budget {
+                    Some(budget) => SpendingConditions::Budget(BudgetedSpendingConditions {
+                        budget,
+                        single_max: args.single_max,
+                        payments: vec![],
+                        period: args.period.into(),
+                    }),
+                    None => SpendingConditions::RequireApproval,
+                };
+                let profile = wallet
+                    .nostr
+                    .create_new_nwc_profile(
+                        ProfileType::Normal { name: args.name },
+                        spending_conditions,
+                        NwcProfileTag::General,
+                    )
+                    .await?;
+                serde_json::to_value(profile)?
+            }
+            NwcCommand::Delete { index } => {
+                wallet.nostr.delete_nwc_profile(index)?;
+                json!({ "deleted": index })
+            }
+            NwcCommand::Notifications {
+                index,
+                notifications,
+            } => {
+                let notifications = notifications.into_iter().map(|n| n.into()).collect();
+                let profile = wallet
+                    .nostr
+                    .set_nwc_profile_notifications(index, notifications)?;
+                serde_json::to_value(profile)?
+            }
+        },
+        Command::Label(cmd) => match cmd {
+            LabelCommand::List => serde_json::to_value(nm.get_labels()?)?,
+            LabelCommand::SetAddress { address, labels } => {
+                nm.set_address_labels(address, labels)?;
+                json!({ "updated": true })
+            }
+            LabelCommand::SetInvoice { invoice, labels } => {
+                nm.set_invoice_labels(invoice, labels)?;
+                json!({ "updated": true })
+            }
+        },
+    };
+
+    Ok(value)
+}
@@ -5,27 +5,27 @@ use lightning::chain::chaininterface::BroadcasterInterface;
 use lightning::chain::{Filter, WatchedOutput};
 use lightning::log_warn;
 use lightning::util::logger::Logger;
-use lightning_transaction_sync::EsploraSyncClient;
 
+use crate::chainsource::ChainSource;
 use crate::logging::MutinyLogger;
 use crate::onchain::OnChainWallet;
 use crate::storage::MutinyStorage;
 use crate::utils;
 
 pub struct MutinyChain<S: MutinyStorage> {
-    pub tx_sync: Arc<EsploraSyncClient<Arc<MutinyLogger>>>,
+    pub chain_source: Arc<dyn ChainSource>,
     pub wallet: Arc<OnChainWallet<S>>,
     logger: Arc<MutinyLogger>,
 }
 
 impl<S: MutinyStorage> MutinyChain<S> {
     pub(crate) fn new(
-        tx_sync: Arc<EsploraSyncClient<Arc<MutinyLogger>>>,
+        chain_source: Arc<dyn ChainSource>,
         wallet: Arc<OnChainWallet<S>>,
         logger: Arc<MutinyLogger>,
     ) -> Self {
         Self {
-            tx_sync,
+            chain_source,
             wallet,
             logger,
         }
@@ -34,11 +34,11 @@ impl<S: MutinyStorage> MutinyChain<S> {
 
 impl<S: MutinyStorage> Filter for MutinyChain<S> {
     fn register_tx(&self, txid: &Txid, script_pubkey: &Script) {
-        self.tx_sync.register_tx(txid, script_pubkey);
+        self.chain_source.register_tx(txid, script_pubkey);
     }
 
     fn register_output(&self, output: WatchedOutput) {
-        self.tx_sync.register_output(output);
+        self.chain_source.register_output(output);
     }
 }
 
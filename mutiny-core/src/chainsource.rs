@@ -0,0 +1,1914 @@
+use crate::error::{MutinyError, MutinyStorageError};
+use crate::esplorapool::{EndpointStats, EsploraPool};
+use crate::logging::MutinyLogger;
+use crate::onchain::get_esplora_url;
+use crate::storage::MutinyStorage;
+use crate::utils;
+use async_trait::async_trait;
+use bdk::wallet::Update;
+use bdk::KeychainKind;
+use bdk_chain::local_chain::LocalChain;
+use bdk_chain::tx_graph::TxGraph;
+use bdk_chain::{BlockId, ConfirmationTimeAnchor};
+use bdk_esplora::EsploraAsyncExt;
+use bitcoin::consensus::encode::Decodable;
+use bitcoin::consensus::{deserialize, serialize};
+use bitcoin::hashes::hex::{FromHex, ToHex};
+use bitcoin::{
+    Address, Block, BlockHash, BlockHeader, Network, OutPoint, Script, Transaction, Txid,
+};
+use esplora_client::{AsyncClient, Builder};
+use futures::try_join;
+use lightning::chain::{Confirm, Filter, WatchedOutput};
+use lightning::util::logger::Logger;
+use lightning::{log_error, log_trace};
+use lightning_transaction_sync::EsploraSyncClient;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+#[cfg(not(target_arch = "wasm32"))]
+use bdk_electrum::electrum_client::{self, Client as ElectrumClient, ElectrumApi};
+#[cfg(not(target_arch = "wasm32"))]
+use bdk_electrum::ElectrumExt;
+
+/// Confirmation targets, in blocks, that fee estimates are fetched for.
+const FEE_TARGETS: [usize; 5] = [1, 3, 6, 12, 1008];
+
+/// Scripts with their derivation index, in derivation order.
+pub type SpkIter = Box<dyn SpkIterator>;
+
+/// An iterator of scripts that can be cloned behind a [`SpkIter`],
+/// so a scan can be retried with another backend.
+pub trait SpkIterator: Iterator<Item = (u32, Script)> + Send {
+    fn clone_box(&self) -> SpkIter;
+}
+
+impl<T> SpkIterator for T
+where
+    T: Iterator<Item = (u32, Script)> + Clone + Send + 'static,
+{
+    fn clone_box(&self) -> SpkIter {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for SpkIter {
+    fn clone(&self) -> Self {
+        (**self).clone_box()
+    }
+}
+
+/// The scripts of each keychain to scan for.
+/// The iterators can be unbounded, scanning stops after `stop_gap` unused scripts.
+pub type KeychainSpks = BTreeMap<KeychainKind, SpkIter>;
+
+/// Which backend is used to get chain data and broadcast transactions.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChainSourceConfig {
+    /// The user's esplora servers if any are set, otherwise Mutiny's default for the network.
+    #[default]
+    Esplora,
+    /// An electrum server, such as `ssl://electrum.blockstream.info:60002`.
+    /// Not available in the browser.
+    Electrum { url: String },
+    /// A bitcoind node's JSON-RPC interface.
+    ///
+    /// Wallet history is tracked with a watch-only descriptor wallet on the node, it is created
+    /// if it doesn't exist yet. Lightning channels need `txindex=1` to be able to find
+    /// transactions that aren't in that wallet.
+    BitcoindRpc {
+        url: String,
+        user: Option<String>,
+        password: Option<String>,
+        wallet: String,
+    },
+}
+
+/// Where a transaction was confirmed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TxConfirmation {
+    pub block_hash: BlockHash,
+    pub height: u32,
+    pub time: u64,
+}
+
+/// The transaction that spent an output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputSpend {
+    pub txid: Txid,
+    /// None if the spending transaction is unconfirmed
+    pub confirmation: Option<TxConfirmation>,
+}
+
+/// A backend we get chain data from and broadcast transactions through.
+///
+/// As a [`Filter`] it is told which transactions and outputs lightning needs to watch,
+/// those are given to the channel manager and chain monitor in [`ChainSource::sync_lightning`].
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+pub trait ChainSource: Filter + Send + Sync {
+    /// Returns the height and header of the best block.
+    async fn get_tip(&self) -> Result<(u32, BlockHeader), MutinyError>;
+
+    async fn get_header(&self, height: u32) -> Result<BlockHeader, MutinyError>;
+
+    async fn get_tx(&self, txid: &Txid) -> Result<Option<Transaction>, MutinyError>;
+
+    /// Returns where the transaction was confirmed,
+    /// None if it is unconfirmed or unknown.
+    async fn get_tx_status(&self, txid: &Txid) -> Result<Option<TxConfirmation>, MutinyError>;
+
+    /// Returns a confirmed transaction and its position in the block.
+    async fn get_block_tx(
+        &self,
+        txid: &Txid,
+        confirmation: &TxConfirmation,
+    ) -> Result<Option<(usize, Transaction)>, MutinyError>;
+
+    /// Returns the transaction that spent the output, if any.
+    async fn get_output_spend(
+        &self,
+        outpoint: &OutPoint,
+    ) -> Result<Option<OutputSpend>, MutinyError>;
+
+    /// Returns the transactions involving the script, newest first.
+    async fn get_script_txs(
+        &self,
+        script: &Script,
+    ) -> Result<Vec<(Transaction, Option<TxConfirmation>)>, MutinyError>;
+
+    async fn broadcast(&self, tx: &Transaction) -> Result<(), MutinyError>;
+
+    /// Returns a map of confirmation target in blocks to fee rate in sats per vbyte.
+    async fn get_fee_estimates(&self) -> Result<HashMap<String, f64>, MutinyError>;
+
+    /// Scans for the on-chain wallet's transactions.
+    async fn scan_wallet(
+        &self,
+        checkpoints: &BTreeMap<u32, BlockHash>,
+        spks: KeychainSpks,
+        txids: Vec<Txid>,
+        stop_gap: usize,
+    ) -> Result<Update, MutinyError>;
+
+    /// Syncs lightning's view of the chain.
+    async fn sync_lightning(
+        &self,
+        confirmables: Vec<&(dyn Confirm + Send + Sync)>,
+    ) -> Result<(), MutinyError>;
+
+    /// Health of the servers behind this source, if it uses several.
+    fn endpoint_stats(&self) -> Vec<EndpointStats> {
+        vec![]
+    }
+}
+
+pub(crate) fn build_chain_source<S: MutinyStorage>(
+    config: &ChainSourceConfig,
+    network: Network,
+    user_esplora_urls: Vec<String>,
+    storage: S,
+    logger: Arc<MutinyLogger>,
+) -> Result<Arc<dyn ChainSource>, MutinyError> {
+    let chain_source: Arc<dyn ChainSource> = match config {
+        ChainSourceConfig::Esplora => {
+            let urls = if user_esplora_urls.is_empty() {
+                vec![get_esplora_url(network, None)]
+            } else {
+                user_esplora_urls
+            };
+            Arc::new(EsploraPool::new(urls, logger)?)
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        ChainSourceConfig::Electrum { url } => Arc::new(ElectrumChainSource::new(url, logger)?),
+        #[cfg(target_arch = "wasm32")]
+        ChainSourceConfig::Electrum { .. } => {
+            log_error!(logger, "Electrum is not supported in the browser");
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+        ChainSourceConfig::BitcoindRpc {
+            url,
+            user,
+            password,
+            wallet,
+        } => Arc::new(BitcoindRpcChainSource::new(
+            url.clone(),
+            user.clone(),
+            password.clone(),
+            wallet.clone(),
+            network,
+            storage,
+            logger,
+        )),
+    };
+
+    Ok(chain_source)
+}
+
+/// Syncs lightning using only the [`ChainSource`] primitives,
+/// for backends without a dedicated LDK sync client.
+pub(crate) struct ConfirmSync {
+    state: Mutex<ConfirmSyncState>,
+    logger: Arc<MutinyLogger>,
+}
+
+#[derive(Default)]
+struct ConfirmSyncState {
+    watched_transactions: HashSet<Txid>,
+    watched_outputs: HashMap<OutPoint, WatchedOutput>,
+    last_sync_hash: Option<BlockHash>,
+}
+
+impl ConfirmSync {
+    pub(crate) fn new(logger: Arc<MutinyLogger>) -> Self {
+        Self {
+            state: Mutex::new(ConfirmSyncState::default()),
+            logger,
+        }
+    }
+
+    fn register_tx(&self, txid: &Txid) {
+        match self.state.lock() {
+            Ok(mut state) => {
+                state.watched_transactions.insert(*txid);
+            }
+            Err(_) => log_error!(self.logger, "Could not get sync lock to watch tx {txid}"),
+        }
+    }
+
+    fn register_output(&self, output: WatchedOutput) {
+        let outpoint = output.outpoint.into_bitcoin_outpoint();
+        match self.state.lock() {
+            Ok(mut state) => {
+                state.watched_outputs.insert(outpoint, output);
+            }
+            Err(_) => log_error!(self.logger, "Could not get sync lock to watch {outpoint}"),
+        }
+    }
+
+    async fn sync(
+        &self,
+        source: &dyn ChainSource,
+        confirmables: Vec<&(dyn Confirm + Send + Sync)>,
+    ) -> Result<(), MutinyError> {
+        let (tip_height, tip_header) = source.get_tip().await?;
+        let tip_hash = tip_header.block_hash();
+
+        // unconfirm anything that was reorged out
+        for confirmable in confirmables.iter() {
+            for (txid, _, block_hash) in confirmable.get_relevant_txids() {
+                let Some(block_hash) = block_hash else {
+                    continue;
+                };
+                let confirmed = source
+                    .get_tx_status(&txid)
+                    .await?
+                    .is_some_and(|c| c.block_hash == block_hash);
+                if !confirmed {
+                    confirmable.transaction_unconfirmed(&txid);
+                    self.state.lock()?.watched_transactions.insert(txid);
+                }
+            }
+        }
+
+        let last_sync_hash = self.state.lock()?.last_sync_hash;
+        if last_sync_hash != Some(tip_hash) {
+            for confirmable in confirmables.iter() {
+                confirmable.best_block_updated(&tip_header, tip_height);
+            }
+        }
+
+        let (txids, outpoints) = {
+            let state = self.state.lock()?;
+            let txids: Vec<Txid> = state.watched_transactions.iter().copied().collect();
+            let outpoints: Vec<OutPoint> = state.watched_outputs.keys().copied().collect();
+            (txids, outpoints)
+        };
+
+        let mut confirmed: Vec<(TxConfirmation, usize, Transaction)> = vec![];
+        for txid in txids {
+            if let Some(conf) = source.get_tx_status(&txid).await? {
+                if let Some((pos, tx)) = source.get_block_tx(&txid, &conf).await? {
+                    confirmed.push((conf, pos, tx));
+                }
+            }
+        }
+        for outpoint in outpoints {
+            let Some(OutputSpend {
+                txid,
+                confirmation: Some(conf),
+            }) = source.get_output_spend(&outpoint).await?
+            else {
+                continue;
+            };
+            if confirmed.iter().any(|(_, _, tx)| tx.txid() == txid) {
+                continue;
+            }
+            if let Some((pos, tx)) = source.get_block_tx(&txid, &conf).await? {
+                confirmed.push((conf, pos, tx));
+            }
+        }
+
+        confirmed.sort_by_key(|(conf, pos, _)| (conf.height, *pos));
+        let mut headers: HashMap<u32, BlockHeader> = HashMap::new();
+        let mut synced: Vec<&Transaction> = vec![];
+        for (conf, pos, tx) in confirmed.iter() {
+            let header = match headers.get(&conf.height) {
+                Some(header) => *header,
+                None => {
+                    let header = source.get_header(conf.height).await?;
+                    headers.insert(conf.height, header);
+                    header
+                }
+            };
+            // the block was reorged out while we were syncing, we'll get it next time
+            if header.block_hash() != conf.block_hash {
+                continue;
+            }
+
+            for confirmable in confirmables.iter() {
+                confirmable.transactions_confirmed(&header, &[(*pos, tx)], conf.height);
+            }
+            synced.push(tx);
+        }
+
+        let mut state = self.state.lock()?;
+        for tx in synced {
+            state.watched_transactions.remove(&tx.txid());
+            for input in tx.input.iter() {
+                state.watched_outputs.remove(&input.previous_output);
+            }
+        }
+        state.last_sync_hash = Some(tip_hash);
+
+        Ok(())
+    }
+}
+
+pub struct EsploraChainSource {
+    client: AsyncClient,
+    tx_sync: EsploraSyncClient<Arc<MutinyLogger>>,
+    logger: Arc<MutinyLogger>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct MempoolFees {
+    fastest_fee: f64,
+    half_hour_fee: f64,
+    hour_fee: f64,
+    economy_fee: f64,
+    minimum_fee: f64,
+}
+
+impl EsploraChainSource {
+    pub fn new(url: &str, logger: Arc<MutinyLogger>) -> Result<Self, MutinyError> {
+        let client = Builder::new(url).build_async()?;
+        let tx_sync = EsploraSyncClient::from_client(client.clone(), logger.clone());
+
+        Ok(Self {
+            client,
+            tx_sync,
+            logger,
+        })
+    }
+
+    async fn get_mempool_recommended_fees(&self) -> anyhow::Result<HashMap<String, f64>> {
+        let client = self.client.client();
+        let request = client
+            .get(format!("{}/v1/fees/recommended", self.client.url()))
+            .build()?;
+
+        let fees_response = utils::fetch_with_timeout(client, request)
+            .await?
+            .error_for_status()?;
+        let fees = fees_response.json::<MempoolFees>().await?;
+
+        // convert to hashmap of num blocks -> fee rate
+        let mut fee_estimates = HashMap::new();
+        fee_estimates.insert("1".to_string(), fees.fastest_fee);
+        fee_estimates.insert("3".to_string(), fees.half_hour_fee);
+        fee_estimates.insert("6".to_string(), fees.hour_fee);
+        fee_estimates.insert("12".to_string(), fees.economy_fee);
+        fee_estimates.insert("1008".to_string(), fees.minimum_fee);
+
+        Ok(fee_estimates)
+    }
+}
+
+impl Filter for EsploraChainSource {
+    fn register_tx(&self, txid: &Txid, script_pubkey: &Script) {
+        self.tx_sync.register_tx(txid, script_pubkey);
+    }
+
+    fn register_output(&self, output: WatchedOutput) {
+        self.tx_sync.register_output(output);
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl ChainSource for EsploraChainSource {
+    async fn get_tip(&self) -> Result<(u32, BlockHeader), MutinyError> {
+        let (height, hash) = try_join!(self.client.get_height(), self.client.get_tip_hash())?;
+        let header = self.client.get_header_by_hash(&hash).await?;
+        Ok((height, header))
+    }
+
+    async fn get_header(&self, height: u32) -> Result<BlockHeader, MutinyError> {
+        let hash = self.client.get_block_hash(height).await?;
+        Ok(self.client.get_header_by_hash(&hash).await?)
+    }
+
+    async fn get_tx(&self, txid: &Txid) -> Result<Option<Transaction>, MutinyError> {
+        Ok(self.client.get_tx(txid).await?)
+    }
+
+    async fn get_tx_status(&self, txid: &Txid) -> Result<Option<TxConfirmation>, MutinyError> {
+        let status = self.client.get_tx_status(txid).await?;
+        Ok(status.and_then(|s| esplora_confirmation(&s)))
+    }
+
+    async fn get_block_tx(
+        &self,
+        txid: &Txid,
+        confirmation: &TxConfirmation,
+    ) -> Result<Option<(usize, Transaction)>, MutinyError> {
+        let Some(proof) = self.client.get_merkle_proof(txid).await? else {
+            return Ok(None);
+        };
+        if proof.block_height != confirmation.height {
+            return Ok(None);
+        }
+
+        let tx = self.client.get_tx(txid).await?;
+        Ok(tx.map(|tx| (proof.pos, tx)))
+    }
+
+    async fn get_output_spend(
+        &self,
+        outpoint: &OutPoint,
+    ) -> Result<Option<OutputSpend>, MutinyError> {
+        let status = self
+            .client
+            .get_output_status(&outpoint.txid, outpoint.vout as u64)
+            .await?;
+
+        Ok(status.and_then(|s| {
+            if !s.spent {
+                return None;
+            }
+            let txid = s.txid?;
+            let confirmation = s.status.as_ref().and_then(esplora_confirmation);
+            Some(OutputSpend { txid, confirmation })
+        }))
+    }
+
+    async fn get_script_txs(
+        &self,
+        script: &Script,
+    ) -> Result<Vec<(Transaction, Option<TxConfirmation>)>, MutinyError> {
+        let txs = self.client.scripthash_txs(script, None).await?;
+        Ok(txs
+            .into_iter()
+            .map(|tx| {
+                let confirmation = esplora_confirmation(&tx.status);
+                (tx.to_tx(), confirmation)
+            })
+            .collect())
+    }
+
+    async fn broadcast(&self, tx: &Transaction) -> Result<(), MutinyError> {
+        Ok(self.client.broadcast(tx).await?)
+    }
+
+    async fn get_fee_estimates(&self) -> Result<HashMap<String, f64>, MutinyError> {
+        // first try mempool.space's API
+        match self.get_mempool_recommended_fees().await {
+            Ok(fees) => {
+                log_trace!(self.logger, "Retrieved fees from mempool");
+                Ok(fees)
+            }
+            Err(e) => {
+                // if that fails, fall back to esplora's API
+                log_trace!(
+                    self.logger,
+                    "Failed to retrieve fees from mempool, falling back to esplora: {e}"
+                );
+                self.client.get_fee_estimates().await.map_err(|e| {
+                    log_trace!(self.logger, "Failed to get esplora fee: {e}");
+                    e.into()
+                })
+            }
+        }
+    }
+
+    async fn scan_wallet(
+        &self,
+        checkpoints: &BTreeMap<u32, BlockHash>,
+        spks: KeychainSpks,
+        txids: Vec<Txid>,
+        stop_gap: usize,
+    ) -> Result<Update, MutinyError> {
+        Ok(self
+            .client
+            .scan(checkpoints, spks, txids, core::iter::empty(), stop_gap, 5)
+            .await?)
+    }
+
+    async fn sync_lightning(
+        &self,
+        confirmables: Vec<&(dyn Confirm + Send + Sync)>,
+    ) -> Result<(), MutinyError> {
+        Ok(self.tx_sync.sync(confirmables).await?)
+    }
+}
+
+fn esplora_confirmation(status: &esplora_client::TxStatus) -> Option<TxConfirmation> {
+    if !status.confirmed {
+        return None;
+    }
+
+    Some(TxConfirmation {
+        block_hash: status.block_hash?,
+        height: status.block_height?,
+        time: status.block_time?,
+    })
+}
+
+/// An electrum server, the client is blocking so calls are run on tokio's blocking threads.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct ElectrumChainSource {
+    client: Arc<ElectrumClient>,
+    tx_sync: ConfirmSync,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl ElectrumChainSource {
+    pub fn new(url: &str, logger: Arc<MutinyLogger>) -> Result<Self, MutinyError> {
+        let client = ElectrumClient::new(url).map_err(|_| MutinyError::ConnectionFailed)?;
+
+        Ok(Self {
+            client: Arc::new(client),
+            tx_sync: ConfirmSync::new(logger),
+        })
+    }
+
+    async fn call<T, F>(&self, f: F) -> Result<T, MutinyError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&ElectrumClient) -> Result<T, electrum_client::Error> + Send + 'static,
+    {
+        let client = self.client.clone();
+        tokio::task::spawn_blocking(move || f(&client))
+            .await
+            .map_err(|_| MutinyError::ChainAccessFailed)?
+            .map_err(|_| MutinyError::ChainAccessFailed)
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn electrum_get_tx(
+    client: &ElectrumClient,
+    txid: &Txid,
+) -> Result<Option<Transaction>, electrum_client::Error> {
+    match client.transaction_get(txid) {
+        Ok(tx) => Ok(Some(tx)),
+        // the server doesn't know about the transaction
+        Err(electrum_client::Error::Protocol(_)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Electrum reports heights of 0 or less for unconfirmed transactions.
+#[cfg(not(target_arch = "wasm32"))]
+fn electrum_confirmation(
+    client: &ElectrumClient,
+    height: i32,
+) -> Result<Option<TxConfirmation>, electrum_client::Error> {
+    if height <= 0 {
+        return Ok(None);
+    }
+
+    let header = client.block_header(height as usize)?;
+    Ok(Some(TxConfirmation {
+        block_hash: header.block_hash(),
+        height: height as u32,
+        time: header.time as u64,
+    }))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Filter for ElectrumChainSource {
+    fn register_tx(&self, txid: &Txid, _script_pubkey: &Script) {
+        self.tx_sync.register_tx(txid);
+    }
+
+    fn register_output(&self, output: WatchedOutput) {
+        self.tx_sync.register_output(output);
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[async_trait]
+impl ChainSource for ElectrumChainSource {
+    async fn get_tip(&self) -> Result<(u32, BlockHeader), MutinyError> {
+        let notification = self.call(|c| c.block_headers_subscribe()).await?;
+        Ok((notification.height as u32, notification.header))
+    }
+
+    async fn get_header(&self, height: u32) -> Result<BlockHeader, MutinyError> {
+        self.call(move |c| c.block_header(height as usize)).await
+    }
+
+    async fn get_tx(&self, txid: &Txid) -> Result<Option<Transaction>, MutinyError> {
+        let txid = *txid;
+        self.call(move |c| electrum_get_tx(c, &txid)).await
+    }
+
+    async fn get_tx_status(&self, txid: &Txid) -> Result<Option<TxConfirmation>, MutinyError> {
+        let txid = *txid;
+        self.call(move |c| {
+            let Some(tx) = electrum_get_tx(c, &txid)? else {
+                return Ok(None);
+            };
+
+            // electrum indexes by script, find the tx in the history of one of its outputs
+            for output in tx.output.iter().filter(|o| !o.script_pubkey.is_op_return()) {
+                let history = c.script_get_history(&output.script_pubkey)?;
+                if let Some(item) = history.iter().find(|h| h.tx_hash == txid) {
+                    return electrum_confirmation(c, item.height);
+                }
+            }
+
+            Ok(None)
+        })
+        .await
+    }
+
+    async fn get_block_tx(
+        &self,
+        txid: &Txid,
+        confirmation: &TxConfirmation,
+    ) -> Result<Option<(usize, Transaction)>, MutinyError> {
+        let txid = *txid;
+        let height = confirmation.height as usize;
+        self.call(move |c| {
+            let merkle = c.transaction_get_merkle(&txid, height)?;
+            let tx = electrum_get_tx(c, &txid)?;
+            Ok(tx.map(|tx| (merkle.pos, tx)))
+        })
+        .await
+    }
+
+    async fn get_output_spend(
+        &self,
+        outpoint: &OutPoint,
+    ) -> Result<Option<OutputSpend>, MutinyError> {
+        let outpoint = *outpoint;
+        self.call(move |c| {
+            let Some(funding_tx) = electrum_get_tx(c, &outpoint.txid)? else {
+                return Ok(None);
+            };
+            let Some(output) = funding_tx.output.get(outpoint.vout as usize) else {
+                return Ok(None);
+            };
+
+            let history = c.script_get_history(&output.script_pubkey)?;
+            for item in history.iter().filter(|h| h.tx_hash != outpoint.txid) {
+                let Some(tx) = electrum_get_tx(c, &item.tx_hash)? else {
+                    continue;
+                };
+                if tx.input.iter().any(|i| i.previous_output == outpoint) {
+                    let confirmation = electrum_confirmation(c, item.height)?;
+                    return Ok(Some(OutputSpend {
+                        txid: item.tx_hash,
+                        confirmation,
+                    }));
+                }
+            }
+
+            Ok(None)
+        })
+        .await
+    }
+
+    async fn get_script_txs(
+        &self,
+        script: &Script,
+    ) -> Result<Vec<(Transaction, Option<TxConfirmation>)>, MutinyError> {
+        let script = script.clone();
+        self.call(move |c| {
+            let history = c.script_get_history(&script)?;
+            let mut txs = Vec::with_capacity(history.len());
+            // electrum returns the oldest first
+            for item in history.iter().rev() {
+                if let Some(tx) = electrum_get_tx(c, &item.tx_hash)? {
+                    txs.push((tx, electrum_confirmation(c, item.height)?));
+                }
+            }
+
+            Ok(txs)
+        })
+        .await
+    }
+
+    async fn broadcast(&self, tx: &Transaction) -> Result<(), MutinyError> {
+        let tx = tx.clone();
+        self.call(move |c| c.transaction_broadcast(&tx)).await?;
+        Ok(())
+    }
+
+    async fn get_fee_estimates(&self) -> Result<HashMap<String, f64>, MutinyError> {
+        self.call(|c| {
+            let mut fee_estimates = HashMap::new();
+            for target in FEE_TARGETS {
+                // in BTC/kvB, negative if the server doesn't have an estimate
+                let fee_rate = c.estimate_fee(target)?;
+                if fee_rate > 0.0 {
+                    fee_estimates.insert(target.to_string(), fee_rate * 100_000.0);
+                }
+            }
+
+            Ok(fee_estimates)
+        })
+        .await
+    }
+
+    async fn scan_wallet(
+        &self,
+        checkpoints: &BTreeMap<u32, BlockHash>,
+        spks: KeychainSpks,
+        txids: Vec<Txid>,
+        stop_gap: usize,
+    ) -> Result<Update, MutinyError> {
+        let checkpoints = checkpoints.clone();
+        self.call(move |c| {
+            let update = c.scan(&checkpoints, spks, txids, core::iter::empty(), stop_gap, 5)?;
+            // we don't have the wallet's tx graph here, so get all the transactions
+            let txids = update.graph_update.keys().copied().collect();
+            update.finalize_as_confirmation_time(c, None, txids)
+        })
+        .await
+    }
+
+    async fn sync_lightning(
+        &self,
+        confirmables: Vec<&(dyn Confirm + Send + Sync)>,
+    ) -> Result<(), MutinyError> {
+        self.tx_sync.sync(self, confirmables).await
+    }
+}
+
+const RPC_INVALID_ADDRESS_OR_KEY: i64 = -5;
+const RPC_WALLET_NOT_FOUND: i64 = -18;
+
+#[derive(Debug)]
+enum RpcError {
+    Rpc { code: i64, message: String },
+    Connection,
+    Parse,
+    Lock,
+}
+
+impl From<RpcError> for MutinyError {
+    fn from(e: RpcError) -> Self {
+        match e {
+            RpcError::Connection => MutinyError::ConnectionFailed,
+            RpcError::Rpc { .. } | RpcError::Parse => MutinyError::ChainAccessFailed,
+            RpcError::Lock => MutinyStorageError::LockError.into(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct RpcResponse {
+    result: Option<Value>,
+    error: Option<RpcErrorObject>,
+}
+
+#[derive(Deserialize)]
+struct RpcErrorObject {
+    code: i64,
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct BlockchainInfo {
+    blocks: u32,
+    bestblockhash: BlockHash,
+}
+
+#[derive(Deserialize)]
+struct HeaderInfo {
+    height: u32,
+    confirmations: i64,
+    time: u64,
+}
+
+#[derive(Deserialize)]
+struct RawTxInfo {
+    blockhash: Option<BlockHash>,
+}
+
+#[derive(Deserialize)]
+struct WalletTx {
+    hex: String,
+    confirmations: i64,
+    blockhash: Option<BlockHash>,
+    blockheight: Option<u32>,
+    blocktime: Option<u64>,
+    time: u64,
+}
+
+impl WalletTx {
+    fn confirmation(&self) -> Option<TxConfirmation> {
+        if self.confirmations <= 0 {
+            return None;
+        }
+
+        Some(TxConfirmation {
+            block_hash: self.blockhash?,
+            height: self.blockheight?,
+            time: self.blocktime?,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct ReceivedByAddress {
+    address: String,
+    txids: Vec<Txid>,
+}
+
+#[derive(Deserialize)]
+struct ListTransactionsItem {
+    txid: Txid,
+}
+
+#[derive(Deserialize)]
+struct ListDescriptors {
+    descriptors: Vec<DescriptorItem>,
+}
+
+#[derive(Deserialize)]
+struct DescriptorItem {
+    desc: String,
+}
+
+#[derive(Deserialize)]
+struct ImportResult {
+    success: bool,
+    error: Option<RpcErrorObject>,
+}
+
+#[derive(Deserialize)]
+struct SmartFee {
+    feerate: Option<f64>,
+}
+
+const SPEND_SCAN_HEIGHTS_KEY: &str = "bitcoind_spend_scan_heights";
+/// How far the node's wallet has been scanned for our scripts, followed by the wallet name.
+const RESCANNED_HEIGHT_PREFIX_KEY: &str = "bitcoind_rescanned_height/";
+
+/// A bitcoind node, talked to over JSON-RPC.
+pub struct BitcoindRpcChainSource<S: MutinyStorage> {
+    client: reqwest::Client,
+    url: String,
+    user: Option<String>,
+    password: Option<String>,
+    wallet: String,
+    network: Network,
+    /// If the watch-only wallet is loaded and `imported` is filled
+    wallet_ready: AtomicBool,
+    imported: Mutex<HashSet<Script>>,
+    /// The next height to look for an output's spend at,
+    /// persisted so we don't scan the same blocks again after a restart
+    spend_scan_heights: Mutex<HashMap<OutPoint, u32>>,
+    tx_sync: ConfirmSync,
+    storage: S,
+    logger: Arc<MutinyLogger>,
+}
+
+impl<S: MutinyStorage> BitcoindRpcChainSource<S> {
+    pub fn new(
+        url: String,
+        user: Option<String>,
+        password: Option<String>,
+        wallet: String,
+        network: Network,
+        storage: S,
+        logger: Arc<MutinyLogger>,
+    ) -> Self {
+        let spend_scan_heights = match storage.get_data(SPEND_SCAN_HEIGHTS_KEY) {
+            Ok(heights) => heights.unwrap_or_default(),
+            Err(e) => {
+                log_error!(logger, "Could not read spend scan heights: {e}");
+                HashMap::new()
+            }
+        };
+
+        Self {
+            client: reqwest::Client::new(),
+            url: url.trim_end_matches('/').to_string(),
+            user,
+            password,
+            wallet,
+            network,
+            wallet_ready: AtomicBool::new(false),
+            imported: Mutex::new(HashSet::new()),
+            spend_scan_heights: Mutex::new(spend_scan_heights),
+            tx_sync: ConfirmSync::new(logger.clone()),
+            storage,
+            logger,
+        }
+    }
+
+    async fn send<T: DeserializeOwned>(
+        &self,
+        wallet: bool,
+        method: &str,
+        params: Value,
+        timeout: bool,
+    ) -> Result<T, RpcError> {
+        let url = if wallet {
+            format!("{}/wallet/{}", self.url, self.wallet)
+        } else {
+            self.url.clone()
+        };
+        let body = json!({
+            "jsonrpc": "1.0",
+            "id": "mutiny",
+            "method": method,
+            "params": params,
+        });
+
+        let mut builder = self.client.post(url).json(&body);
+        if let Some(user) = self.user.as_ref() {
+            builder = builder.basic_auth(user, self.password.as_ref());
+        }
+        let request = builder.build().map_err(|_| RpcError::Connection)?;
+
+        let response = if timeout {
+            utils::fetch_with_timeout(&self.client, request)
+                .await
+                .map_err(|_| RpcError::Connection)?
+        } else {
+            self.client
+                .execute(request)
+                .await
+                .map_err(|_| RpcError::Connection)?
+        };
+
+        // bitcoind uses error status codes for rpc errors, so parse the body either way
+        let response: RpcResponse = response.json().await.map_err(|_| RpcError::Parse)?;
+        if let Some(error) = response.error {
+            return Err(RpcError::Rpc {
+                code: error.code,
+                message: error.message,
+            });
+        }
+
+        serde_json::from_value(response.result.unwrap_or(Value::Null)).map_err(|_| RpcError::Parse)
+    }
+
+    async fn call<T: DeserializeOwned>(&self, method: &str, params: Value) -> Result<T, RpcError> {
+        self.send(false, method, params, true).await
+    }
+
+    async fn call_wallet<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: Value,
+    ) -> Result<T, RpcError> {
+        self.ensure_wallet().await?;
+        self.send(true, method, params, true).await
+    }
+
+    /// Loads our watch-only wallet, creating it if needed.
+    async fn ensure_wallet(&self) -> Result<(), RpcError> {
+        if self.wallet_ready.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        let wallets: Vec<String> = self.call("listwallets", json!([])).await?;
+        if !wallets.contains(&self.wallet) {
+            match self.call::<Value>("loadwallet", json!([self.wallet])).await {
+                Ok(_) => {}
+                Err(RpcError::Rpc {
+                    code: RPC_WALLET_NOT_FOUND,
+                    ..
+                }) => {
+                    // name, disable_private_keys, blank, passphrase, avoid_reuse, descriptors, load_on_startup
+                    self.call::<Value>(
+                        "createwallet",
+                        json!([self.wallet, true, true, "", false, true, true]),
+                    )
+                    .await?;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        let descriptors: ListDescriptors =
+            self.send(true, "listdescriptors", json!([]), true).await?;
+        let scripts = descriptors
+            .descriptors
+            .iter()
+            .filter_map(|d| script_from_raw_descriptor(&d.desc));
+        self.imported
+            .lock()
+            .map_err(|_| RpcError::Lock)?
+            .extend(scripts);
+        self.wallet_ready.store(true, Ordering::Relaxed);
+
+        Ok(())
+    }
+
+    /// Imports the scripts into our wallet. They pick up transactions from the block at
+    /// `rescan_from` on, which bitcoind rescans for them, or only from now on if it is None.
+    async fn import_scripts(
+        &self,
+        scripts: Vec<Script>,
+        rescan_from: Option<u32>,
+    ) -> Result<(), MutinyError> {
+        self.ensure_wallet().await?;
+        let scripts: Vec<Script> = {
+            let imported = self.imported.lock()?;
+            scripts
+                .into_iter()
+                .filter(|s| !imported.contains(s))
+                .collect()
+        };
+        if scripts.is_empty() {
+            return Ok(());
+        }
+
+        let timestamp = match rescan_from {
+            Some(height) => json!(self.get_header(height).await?.time),
+            None => json!("now"),
+        };
+        let requests = scripts
+            .iter()
+            .map(|s| Ok(json!({ "desc": raw_descriptor(s)?, "timestamp": timestamp })))
+            .collect::<Result<Vec<Value>, MutinyError>>()?;
+
+        // rescans can take a long time, so don't time out
+        let results: Vec<ImportResult> = self
+            .send(
+                true,
+                "importdescriptors",
+                json!([requests]),
+                rescan_from.is_none(),
+            )
+            .await?;
+        if let Some(e) = results.iter().find_map(|r| r.error.as_ref()) {
+            log_error!(self.logger, "Could not import scripts: {}", e.message);
+            return Err(MutinyError::ChainAccessFailed);
+        }
+
+        let mut imported = self.imported.lock()?;
+        for (script, result) in scripts.into_iter().zip(results) {
+            if result.success {
+                imported.insert(script);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn rescanned_height_key(&self) -> String {
+        format!("{RESCANNED_HEIGHT_PREFIX_KEY}{}", self.wallet)
+    }
+
+    /// Records the next height to look for the output's spend at.
+    fn set_spend_scan_height(&self, outpoint: &OutPoint, height: u32) -> Result<(), MutinyError> {
+        let mut heights = self.spend_scan_heights.lock()?;
+        if heights.insert(*outpoint, height) != Some(height) {
+            self.save_spend_scan_heights(&heights);
+        }
+        Ok(())
+    }
+
+    fn save_spend_scan_heights(&self, heights: &HashMap<OutPoint, u32>) {
+        if let Err(e) = self
+            .storage
+            .set_data(SPEND_SCAN_HEIGHTS_KEY.to_string(), heights, None)
+        {
+            log_error!(self.logger, "Could not save spend scan heights: {e}");
+        }
+    }
+
+    /// Returns our wallet's scripts that have received funds, with the transactions.
+    async fn received_by_scripts(&self) -> Result<HashMap<Script, Vec<Txid>>, MutinyError> {
+        // minconf, include_empty, include_watchonly
+        let received: Vec<ReceivedByAddress> = self
+            .call_wallet("listreceivedbyaddress", json!([0, false, true]))
+            .await?;
+
+        Ok(received
+            .into_iter()
+            .filter_map(|r| {
+                let address = Address::from_str(&r.address).ok()?;
+                Some((address.script_pubkey(), r.txids))
+            })
+            .collect())
+    }
+
+    async fn list_wallet_txids(&self) -> Result<HashSet<Txid>, MutinyError> {
+        const PAGE_SIZE: usize = 1_000;
+        let mut txids = HashSet::new();
+        let mut skip = 0;
+        loop {
+            // label, count, skip, include_watchonly
+            let page: Vec<ListTransactionsItem> = self
+                .call_wallet("listtransactions", json!(["*", PAGE_SIZE, skip, true]))
+                .await?;
+            let len = page.len();
+            txids.extend(page.into_iter().map(|t| t.txid));
+
+            if len < PAGE_SIZE {
+                break;
+            }
+            skip += PAGE_SIZE;
+        }
+
+        Ok(txids)
+    }
+
+    async fn get_wallet_tx(&self, txid: &Txid) -> Result<Option<WalletTx>, MutinyError> {
+        match self
+            .call_wallet::<WalletTx>("gettransaction", json!([txid, true]))
+            .await
+        {
+            Ok(tx) => Ok(Some(tx)),
+            Err(RpcError::Rpc {
+                code: RPC_INVALID_ADDRESS_OR_KEY,
+                ..
+            }) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn get_block_hash(&self, height: u32) -> Result<BlockHash, MutinyError> {
+        Ok(self.call("getblockhash", json!([height])).await?)
+    }
+
+    async fn get_header_by_hash(&self, hash: &BlockHash) -> Result<BlockHeader, MutinyError> {
+        let hex: String = self.call("getblockheader", json!([hash, false])).await?;
+        deserialize_hex(&hex)
+    }
+
+    async fn get_block(&self, hash: &BlockHash) -> Result<Block, MutinyError> {
+        let hex: String = self.call("getblock", json!([hash, 0])).await?;
+        deserialize_hex(&hex)
+    }
+}
+
+fn deserialize_hex<T: Decodable>(hex: &str) -> Result<T, MutinyError> {
+    let bytes = Vec::<u8>::from_hex(hex).map_err(|_| MutinyError::ChainAccessFailed)?;
+    deserialize(&bytes).map_err(|_| MutinyError::ChainAccessFailed)
+}
+
+/// A descriptor for a single script, with its checksum.
+fn raw_descriptor(script: &Script) -> Result<String, MutinyError> {
+    let desc = format!("raw({})", script.to_hex());
+    let checksum =
+        bdk::descriptor::calc_checksum(&desc).map_err(|_| MutinyError::InvalidArgumentsError)?;
+    Ok(format!("{desc}#{checksum}"))
+}
+
+fn script_from_raw_descriptor(desc: &str) -> Option<Script> {
+    let hex = desc.strip_prefix("raw(")?.split(')').next()?;
+    Script::from_hex(hex).ok()
+}
+
+impl<S: MutinyStorage> Filter for BitcoindRpcChainSource<S> {
+    fn register_tx(&self, txid: &Txid, _script_pubkey: &Script) {
+        self.tx_sync.register_tx(txid);
+    }
+
+    fn register_output(&self, output: WatchedOutput) {
+        self.tx_sync.register_output(output);
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl<S: MutinyStorage> ChainSource for BitcoindRpcChainSource<S> {
+    async fn get_tip(&self) -> Result<(u32, BlockHeader), MutinyError> {
+        let info: BlockchainInfo = self.call("getblockchaininfo", json!([])).await?;
+        let header = self.get_header_by_hash(&info.bestblockhash).await?;
+        Ok((info.blocks, header))
+    }
+
+    async fn get_header(&self, height: u32) -> Result<BlockHeader, MutinyError> {
+        let hash = self.get_block_hash(height).await?;
+        self.get_header_by_hash(&hash).await
+    }
+
+    async fn get_tx(&self, txid: &Txid) -> Result<Option<Transaction>, MutinyError> {
+        if let Some(tx) = self.get_wallet_tx(txid).await? {
+            return deserialize_hex(&tx.hex).map(Some);
+        }
+
+        // needs txindex for transactions outside of our wallet
+        match self
+            .call::<String>("getrawtransaction", json!([txid, false]))
+            .await
+        {
+            Ok(hex) => deserialize_hex(&hex).map(Some),
+            Err(RpcError::Rpc {
+                code: RPC_INVALID_ADDRESS_OR_KEY,
+                ..
+            }) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn get_tx_status(&self, txid: &Txid) -> Result<Option<TxConfirmation>, MutinyError> {
+        if let Some(tx) = self.get_wallet_tx(txid).await? {
+            return Ok(tx.confirmation());
+        }
+
+        let info = match self
+            .call::<RawTxInfo>("getrawtransaction", json!([txid, true]))
+            .await
+        {
+            Ok(info) => info,
+            Err(RpcError::Rpc {
+                code: RPC_INVALID_ADDRESS_OR_KEY,
+                ..
+            }) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let Some(block_hash) = info.blockhash else {
+            return Ok(None);
+        };
+
+        let header: HeaderInfo = self
+            .call("getblockheader", json!([block_hash, true]))
+            .await?;
+        // negative confirmations mean the block is no longer in the best chain
+        if header.confirmations <= 0 {
+            return Ok(None);
+        }
+
+        Ok(Some(TxConfirmation {
+            block_hash,
+            height: header.height,
+            time: header.time,
+        }))
+    }
+
+    async fn get_block_tx(
+        &self,
+        txid: &Txid,
+        confirmation: &TxConfirmation,
+    ) -> Result<Option<(usize, Transaction)>, MutinyError> {
+        let block = self.get_block(&confirmation.block_hash).await?;
+        Ok(block
+            .txdata
+            .into_iter()
+            .enumerate()
+            .find(|(_, tx)| &tx.txid() == txid))
+    }
+
+    async fn get_output_spend(
+        &self,
+        outpoint: &OutPoint,
+    ) -> Result<Option<OutputSpend>, MutinyError> {
+        // txid, n, include_mempool
+        let utxo: Option<Value> = self
+            .call("gettxout", json!([outpoint.txid, outpoint.vout, false]))
+            .await?;
+        if utxo.is_some() {
+            return Ok(None);
+        }
+
+        // bitcoind has no index of spends, look through the blocks since the output confirmed
+        let Some(funding) = self.get_tx_status(&outpoint.txid).await? else {
+            return Ok(None);
+        };
+        // the spend can't be before the output, even if it was reorged into a later block
+        let start = self
+            .spend_scan_heights
+            .lock()?
+            .get(outpoint)
+            .copied()
+            .unwrap_or(funding.height)
+            .max(funding.height);
+        let tip: u32 = self.call("getblockcount", json!([])).await?;
+
+        let mut next = start;
+        let mut spend = None;
+        while next <= tip {
+            let block = match self.get_block_hash(next).await {
+                Ok(hash) => self.get_block(&hash).await.map(|b| (hash, b)),
+                Err(e) => Err(e),
+            };
+            let (hash, block) = match block {
+                Ok(block) => block,
+                Err(e) => {
+                    // keep what we've scanned so far for the next try
+                    self.set_spend_scan_height(outpoint, next)?;
+                    return Err(e);
+                }
+            };
+
+            let tx = block
+                .txdata
+                .iter()
+                .find(|tx| tx.input.iter().any(|i| &i.previous_output == outpoint));
+            if let Some(tx) = tx {
+                spend = Some(OutputSpend {
+                    txid: tx.txid(),
+                    confirmation: Some(TxConfirmation {
+                        block_hash: hash,
+                        height: next,
+                        time: block.header.time as u64,
+                    }),
+                });
+                break;
+            }
+            next += 1;
+        }
+
+        // start from the spend's block next time so it is found again if asked
+        self.set_spend_scan_height(outpoint, next)?;
+
+        Ok(spend)
+    }
+
+    /// Only finds transactions that happened after the script was imported
+    /// into our wallet, which the wallet's own addresses are when synced.
+    async fn get_script_txs(
+        &self,
+        script: &Script,
+    ) -> Result<Vec<(Transaction, Option<TxConfirmation>)>, MutinyError> {
+        self.import_scripts(vec![script.clone()], None).await?;
+        let address =
+            Address::from_script(script, self.network).ok_or(MutinyError::InvalidArgumentsError)?;
+
+        // minconf, include_empty, include_watchonly, address_filter
+        let received: Vec<ReceivedByAddress> = self
+            .call_wallet(
+                "listreceivedbyaddress",
+                json!([0, false, true, address.to_string()]),
+            )
+            .await?;
+
+        let mut txs = vec![];
+        for txid in received.into_iter().flat_map(|r| r.txids) {
+            if let Some(wallet_tx) = self.get_wallet_tx(&txid).await? {
+                let tx: Transaction = deserialize_hex(&wallet_tx.hex)?;
+                txs.push((tx, wallet_tx.confirmation()));
+            }
+        }
+        // unconfirmed first, then newest first
+        txs.sort_by_key(|(_, c)| std::cmp::Reverse(c.map_or(u32::MAX, |c| c.height)));
+
+        Ok(txs)
+    }
+
+    async fn broadcast(&self, tx: &Transaction) -> Result<(), MutinyError> {
+        let hex = serialize(tx).to_hex();
+        match self.call::<Txid>("sendrawtransaction", json!([hex])).await {
+            Ok(_) => Ok(()),
+            Err(RpcError::Rpc { message, .. }) => {
+                log_error!(self.logger, "bitcoind rejected transaction: {message}");
+                Err(MutinyError::ChainAccessFailed)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn get_fee_estimates(&self) -> Result<HashMap<String, f64>, MutinyError> {
+        let mut fee_estimates = HashMap::new();
+        for target in FEE_TARGETS {
+            let fee: SmartFee = self.call("estimatesmartfee", json!([target])).await?;
+            // in BTC/kvB
+            if let Some(fee_rate) = fee.feerate {
+                fee_estimates.insert(target.to_string(), fee_rate * 100_000.0);
+            }
+        }
+
+        Ok(fee_estimates)
+    }
+
+    async fn scan_wallet(
+        &self,
+        checkpoints: &BTreeMap<u32, BlockHash>,
+        spks: KeychainSpks,
+        txids: Vec<Txid>,
+        stop_gap: usize,
+    ) -> Result<Update, MutinyError> {
+        // new scripts only need the history since the wallet last synced, a wallet that
+        // has never synced continues from where a previous scan got to, if any
+        let rescan_from = match checkpoints.keys().next_back() {
+            Some(height) => *height,
+            None => self
+                .storage
+                .get_data::<u32>(self.rescanned_height_key())?
+                .unwrap_or(0),
+        };
+
+        // import the next chunk of every keychain together so each round needs one rescan,
+        // a keychain is done once a chunk of stop_gap scripts has no history
+        let mut last_active_indices = BTreeMap::new();
+        let mut scanning: Vec<(KeychainKind, SpkIter)> = spks.into_iter().collect();
+        while !scanning.is_empty() {
+            let mut chunks = vec![];
+            for (keychain, mut spks) in scanning {
+                let chunk: Vec<(u32, Script)> = spks.by_ref().take(stop_gap.max(1)).collect();
+                if !chunk.is_empty() {
+                    chunks.push((keychain, spks, chunk));
+                }
+            }
+            if chunks.is_empty() {
+                break;
+            }
+
+            let scripts = chunks
+                .iter()
+                .flat_map(|(_, _, chunk)| chunk.iter().map(|(_, s)| s.clone()))
+                .collect();
+            self.import_scripts(scripts, Some(rescan_from)).await?;
+            let received = self.received_by_scripts().await?;
+
+            scanning = vec![];
+            for (keychain, spks, chunk) in chunks {
+                let last_active = chunk
+                    .iter()
+                    .filter(|(_, s)| received.contains_key(s))
+                    .map(|(i, _)| *i)
+                    .max();
+                if let Some(index) = last_active {
+                    last_active_indices.insert(keychain, index);
+                    scanning.push((keychain, spks));
+                }
+            }
+        }
+
+        let mut wallet_txids = self.list_wallet_txids().await?;
+        wallet_txids.extend(txids);
+
+        let mut graph = TxGraph::<ConfirmationTimeAnchor>::default();
+        let mut blocks = BTreeMap::new();
+        for txid in wallet_txids {
+            let Some(wallet_tx) = self.get_wallet_tx(&txid).await? else {
+                continue;
+            };
+            // conflicted with a confirmed transaction
+            if wallet_tx.confirmations < 0 {
+                continue;
+            }
+
+            let tx: Transaction = deserialize_hex(&wallet_tx.hex)?;
+            let _ = graph.insert_tx(tx);
+            match wallet_tx.confirmation() {
+                Some(c) => {
+                    let anchor_block = BlockId {
+                        height: c.height,
+                        hash: c.block_hash,
+                    };
+                    let anchor = ConfirmationTimeAnchor {
+                        anchor_block,
+                        confirmation_height: c.height,
+                        confirmation_time: c.time,
+                    };
+                    let _ = graph.insert_anchor(txid, anchor);
+                    blocks.insert(c.height, c.block_hash);
+                }
+                None => {
+                    let _ = graph.insert_seen_at(txid, wallet_tx.time);
+                }
+            }
+        }
+
+        // include the tip and enough of our checkpoints for the update to connect
+        let info: BlockchainInfo = self.call("getblockchaininfo", json!([])).await?;
+        // the imported scripts have been scanned up to here, the loaded wallet follows new blocks
+        self.storage
+            .set_data(self.rescanned_height_key(), info.blocks, None)?;
+        blocks.insert(info.blocks, info.bestblockhash);
+        for (height, hash) in checkpoints.iter().rev() {
+            let current = self.get_block_hash(*height).await?;
+            blocks.insert(*height, current);
+            if current == *hash {
+                break;
+            }
+        }
+
+        Ok(Update {
+            keychain: last_active_indices,
+            graph,
+            chain: LocalChain::from(blocks),
+        })
+    }
+
+    async fn sync_lightning(
+        &self,
+        confirmables: Vec<&(dyn Confirm + Send + Sync)>,
+    ) -> Result<(), MutinyError> {
+        self.tx_sync.sync(self, confirmables).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use crate::test_utils::*;
+    use bitcoin::hashes::Hash;
+    use bitcoin::{PackedLockTime, TxIn, TxMerkleNode, TxOut};
+    use lightning::chain::transaction::{OutPoint as LdkOutPoint, TransactionData};
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[test]
+    async fn test_raw_descriptor() {
+        let test_name = "test_raw_descriptor";
+        log!("{}", test_name);
+
+        let script = Script::from_hex("0014751e76e8199196d454941c45d1b3a323f1433bd6").unwrap();
+        let desc = raw_descriptor(&script).unwrap();
+        assert!(desc.starts_with("raw(0014751e76e8199196d454941c45d1b3a323f1433bd6)#"));
+        assert_eq!(script_from_raw_descriptor(&desc), Some(script));
+
+        let other = "tr([73c5da0a/86'/0'/0']xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ/0/*)#d5ss2zlx";
+        assert_eq!(script_from_raw_descriptor(other), None);
+    }
+
+    #[test]
+    async fn test_confirm_sync_watches() {
+        let test_name = "test_confirm_sync_watches";
+        log!("{}", test_name);
+
+        let sync = ConfirmSync::new(Arc::new(MutinyLogger::default()));
+        let txid = Txid::all_zeros();
+        sync.register_tx(&txid);
+        sync.register_output(WatchedOutput {
+            block_hash: None,
+            outpoint: LdkOutPoint { txid, index: 1 },
+            script_pubkey: Script::new(),
+        });
+
+        let state = sync.state.lock().unwrap();
+        assert!(state.watched_transactions.contains(&txid));
+        assert!(state
+            .watched_outputs
+            .contains_key(&OutPoint { txid, vout: 1 }));
+        assert!(state.last_sync_hash.is_none());
+    }
+
+    /// A chain kept in memory, blocks at a height can be replaced to reorg.
+    #[derive(Default)]
+    struct MockChain {
+        headers: Mutex<BTreeMap<u32, BlockHeader>>,
+        blocks: Mutex<HashMap<BlockHash, Vec<Transaction>>>,
+    }
+
+    impl MockChain {
+        /// Sets the block at the height, dropping any blocks above it.
+        fn mine(&self, height: u32, nonce: u32, txs: Vec<Transaction>) -> BlockHash {
+            let mut headers = self.headers.lock().unwrap();
+            headers.retain(|h, _| *h < height);
+            let prev_blockhash = headers
+                .values()
+                .next_back()
+                .map(|h| h.block_hash())
+                .unwrap_or(BlockHash::all_zeros());
+            let header = BlockHeader {
+                version: 1,
+                prev_blockhash,
+                merkle_root: TxMerkleNode::all_zeros(),
+                time: height,
+                bits: 0,
+                nonce,
+            };
+            headers.insert(height, header);
+            self.blocks.lock().unwrap().insert(header.block_hash(), txs);
+            header.block_hash()
+        }
+
+        /// Transactions in the best chain, with where they confirmed.
+        fn best_chain_txs(&self) -> Vec<(TxConfirmation, usize, Transaction)> {
+            let headers = self.headers.lock().unwrap();
+            let blocks = self.blocks.lock().unwrap();
+            headers
+                .iter()
+                .flat_map(|(height, header)| {
+                    let conf = TxConfirmation {
+                        block_hash: header.block_hash(),
+                        height: *height,
+                        time: header.time as u64,
+                    };
+                    blocks[&conf.block_hash]
+                        .iter()
+                        .cloned()
+                        .enumerate()
+                        .map(move |(pos, tx)| (conf, pos, tx))
+                })
+                .collect()
+        }
+    }
+
+    impl Filter for MockChain {
+        fn register_tx(&self, _txid: &Txid, _script_pubkey: &Script) {}
+
+        fn register_output(&self, _output: WatchedOutput) {}
+    }
+
+    #[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+    #[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+    impl ChainSource for MockChain {
+        async fn get_tip(&self) -> Result<(u32, BlockHeader), MutinyError> {
+            let headers = self.headers.lock().unwrap();
+            let (height, header) = headers.iter().next_back().unwrap();
+            Ok((*height, *header))
+        }
+
+        async fn get_header(&self, height: u32) -> Result<BlockHeader, MutinyError> {
+            let headers = self.headers.lock().unwrap();
+            headers
+                .get(&height)
+                .copied()
+                .ok_or(MutinyError::ChainAccessFailed)
+        }
+
+        async fn get_tx(&self, _txid: &Txid) -> Result<Option<Transaction>, MutinyError> {
+            unimplemented!()
+        }
+
+        async fn get_tx_status(&self, txid: &Txid) -> Result<Option<TxConfirmation>, MutinyError> {
+            Ok(self
+                .best_chain_txs()
+                .into_iter()
+                .find(|(_, _, tx)| &tx.txid() == txid)
+                .map(|(conf, _, _)| conf))
+        }
+
+        async fn get_block_tx(
+            &self,
+            txid: &Txid,
+            confirmation: &TxConfirmation,
+        ) -> Result<Option<(usize, Transaction)>, MutinyError> {
+            let blocks = self.blocks.lock().unwrap();
+            Ok(blocks.get(&confirmation.block_hash).and_then(|txs| {
+                txs.iter()
+                    .cloned()
+                    .enumerate()
+                    .find(|(_, tx)| &tx.txid() == txid)
+            }))
+        }
+
+        async fn get_output_spend(
+            &self,
+            outpoint: &OutPoint,
+        ) -> Result<Option<OutputSpend>, MutinyError> {
+            Ok(self
+                .best_chain_txs()
+                .into_iter()
+                .find(|(_, _, tx)| tx.input.iter().any(|i| &i.previous_output == outpoint))
+                .map(|(conf, _, tx)| OutputSpend {
+                    txid: tx.txid(),
+                    confirmation: Some(conf),
+                }))
+        }
+
+        async fn get_script_txs(
+            &self,
+            _script: &Script,
+        ) -> Result<Vec<(Transaction, Option<TxConfirmation>)>, MutinyError> {
+            unimplemented!()
+        }
+
+        async fn broadcast(&self, _tx: &Transaction) -> Result<(), MutinyError> {
+            unimplemented!()
+        }
+
+        async fn get_fee_estimates(&self) -> Result<HashMap<String, f64>, MutinyError> {
+            unimplemented!()
+        }
+
+        async fn scan_wallet(
+            &self,
+            _checkpoints: &BTreeMap<u32, BlockHash>,
+            _spks: KeychainSpks,
+            _txids: Vec<Txid>,
+            _stop_gap: usize,
+        ) -> Result<Update, MutinyError> {
+            unimplemented!()
+        }
+
+        async fn sync_lightning(
+            &self,
+            _confirmables: Vec<&(dyn Confirm + Send + Sync)>,
+        ) -> Result<(), MutinyError> {
+            unimplemented!()
+        }
+    }
+
+    /// Keeps what it was told, like a channel manager would.
+    #[derive(Default)]
+    struct MockConfirm {
+        confirmed: Mutex<HashMap<Txid, (u32, BlockHash)>>,
+        unconfirmed: Mutex<Vec<Txid>>,
+        best_height: Mutex<u32>,
+    }
+
+    impl Confirm for MockConfirm {
+        fn transactions_confirmed(
+            &self,
+            header: &BlockHeader,
+            txdata: &TransactionData,
+            height: u32,
+        ) {
+            let mut confirmed = self.confirmed.lock().unwrap();
+            for (_, tx) in txdata.iter() {
+                confirmed.insert(tx.txid(), (height, header.block_hash()));
+            }
+        }
+
+        fn transaction_unconfirmed(&self, txid: &Txid) {
+            self.confirmed.lock().unwrap().remove(txid);
+            self.unconfirmed.lock().unwrap().push(*txid);
+        }
+
+        fn best_block_updated(&self, _header: &BlockHeader, height: u32) {
+            *self.best_height.lock().unwrap() = height;
+        }
+
+        fn get_relevant_txids(&self) -> Vec<(Txid, u32, Option<BlockHash>)> {
+            let confirmed = self.confirmed.lock().unwrap();
+            confirmed
+                .iter()
+                .map(|(txid, (height, hash))| (*txid, *height, Some(*hash)))
+                .collect()
+        }
+    }
+
+    fn test_tx(previous_output: OutPoint, value: u64) -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time: PackedLockTime::ZERO,
+            input: vec![TxIn {
+                previous_output,
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value,
+                script_pubkey: Script::new(),
+            }],
+        }
+    }
+
+    #[test]
+    async fn test_confirm_sync_reorg() {
+        let test_name = "test_confirm_sync_reorg";
+        log!("{}", test_name);
+
+        let chain = MockChain::default();
+        let tx = test_tx(OutPoint::null(), 1_000);
+        let txid = tx.txid();
+        chain.mine(100, 0, vec![]);
+        let first_block = chain.mine(101, 0, vec![tx.clone()]);
+        chain.mine(102, 0, vec![]);
+
+        let sync = ConfirmSync::new(Arc::new(MutinyLogger::default()));
+        let confirm = MockConfirm::default();
+        sync.register_tx(&txid);
+        sync.sync(&chain, vec![&confirm]).await.unwrap();
+
+        assert_eq!(*confirm.best_height.lock().unwrap(), 102);
+        assert_eq!(
+            confirm.confirmed.lock().unwrap().get(&txid),
+            Some(&(101, first_block))
+        );
+        assert!(sync.state.lock().unwrap().watched_transactions.is_empty());
+
+        // the block is reorged out and the transaction confirms a block later
+        chain.mine(101, 1, vec![]);
+        let second_block = chain.mine(102, 1, vec![tx]);
+        chain.mine(103, 1, vec![]);
+        sync.sync(&chain, vec![&confirm]).await.unwrap();
+
+        assert_eq!(*confirm.unconfirmed.lock().unwrap(), vec![txid]);
+        assert_eq!(*confirm.best_height.lock().unwrap(), 103);
+        assert_eq!(
+            confirm.confirmed.lock().unwrap().get(&txid),
+            Some(&(102, second_block))
+        );
+        assert!(sync.state.lock().unwrap().watched_transactions.is_empty());
+
+        // nothing changes without a reorg
+        sync.sync(&chain, vec![&confirm]).await.unwrap();
+        assert_eq!(confirm.unconfirmed.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    async fn test_confirm_sync_spent_output() {
+        let test_name = "test_confirm_sync_spent_output";
+        log!("{}", test_name);
+
+        let chain = MockChain::default();
+        let funding = test_tx(OutPoint::null(), 10_000);
+        let outpoint = OutPoint::new(funding.txid(), 0);
+        chain.mine(100, 0, vec![funding]);
+        chain.mine(101, 0, vec![]);
+
+        let sync = ConfirmSync::new(Arc::new(MutinyLogger::default()));
+        let confirm = MockConfirm::default();
+        sync.register_output(WatchedOutput {
+            block_hash: None,
+            outpoint: LdkOutPoint {
+                txid: outpoint.txid,
+                index: outpoint.vout as u16,
+            },
+            script_pubkey: Script::new(),
+        });
+
+        // still unspent, keep watching
+        sync.sync(&chain, vec![&confirm]).await.unwrap();
+        assert!(confirm.confirmed.lock().unwrap().is_empty());
+        assert!(sync
+            .state
+            .lock()
+            .unwrap()
+            .watched_outputs
+            .contains_key(&outpoint));
+
+        let spend = test_tx(outpoint, 9_000);
+        let spend_block = chain.mine(102, 0, vec![spend.clone()]);
+        // also watched as a transaction, it is only confirmed once
+        sync.register_tx(&spend.txid());
+        sync.sync(&chain, vec![&confirm]).await.unwrap();
+
+        let confirmed = confirm.confirmed.lock().unwrap();
+        assert_eq!(confirmed.len(), 1);
+        assert_eq!(confirmed.get(&spend.txid()), Some(&(102, spend_block)));
+        let state = sync.state.lock().unwrap();
+        assert!(state.watched_outputs.is_empty());
+        assert!(state.watched_transactions.is_empty());
+    }
+
+    #[test]
+    async fn test_chain_source_config_default() {
+        let test_name = "test_chain_source_config_default";
+        log!("{}", test_name);
+
+        assert_eq!(ChainSourceConfig::default(), ChainSourceConfig::Esplora);
+
+        let config = ChainSourceConfig::BitcoindRpc {
+            url: "http://127.0.0.1:18443".to_string(),
+            user: Some("user".to_string()),
+            password: Some("pass".to_string()),
+            wallet: "mutiny".to_string(),
+        };
+        let json = serde_json::to_string(&config).unwrap();
+        assert_eq!(
+            serde_json::from_str::<ChainSourceConfig>(&json).unwrap(),
+            config
+        );
+    }
+
+    #[test]
+    async fn test_spend_scan_heights_persisted() {
+        let test_name = "test_spend_scan_heights_persisted";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::default();
+        let logger = Arc::new(MutinyLogger::default());
+        let new_source = || {
+            BitcoindRpcChainSource::new(
+                "http://127.0.0.1:18443".to_string(),
+                None,
+                None,
+                "mutiny".to_string(),
+                Network::Regtest,
+                storage.clone(),
+                logger.clone(),
+            )
+        };
+
+        let outpoint = OutPoint::new(Txid::all_zeros(), 1);
+        let source = new_source();
+        {
+            let mut heights = source.spend_scan_heights.lock().unwrap();
+            heights.insert(outpoint, 100);
+            source.save_spend_scan_heights(&heights);
+        }
+
+        let source = new_source();
+        let heights = source.spend_scan_heights.lock().unwrap();
+        assert_eq!(heights.get(&outpoint), Some(&100));
+    }
+}
@@ -124,12 +124,26 @@ pub fn decrypt_with_key(
     Ok(decrypted)
 }
 
+/// Decrypts AES-256-CBC with an iv given separately, as used by LNURL-pay AES success actions
+pub fn decrypt_with_iv(key: &[u8; 32], iv: &[u8], bytes: &[u8]) -> Result<Vec<u8>, MutinyError> {
+    if iv.len() != 16 {
+        return Err(MutinyError::InvalidArgumentsError);
+    }
+
+    let cipher = Aes256CbcDec::new(&(*key).into(), iv.into());
+    let decrypted: Vec<u8> = cipher.decrypt_padded_vec_mut::<Pkcs7>(bytes)?;
+
+    Ok(decrypted)
+}
+
 #[cfg(test)]
 mod tests {
     use crate::encrypt::{
-        decrypt_with_key, decrypt_with_password, encrypt, encrypt_with_key,
-        encryption_key_from_pass,
+        decrypt_with_iv, decrypt_with_key, decrypt_with_password, encrypt, encrypt_with_key,
+        encryption_key_from_pass, Aes256CbcEnc,
     };
+    use aes::cipher::block_padding::Pkcs7;
+    use aes::cipher::{BlockEncryptMut, KeyIvInit};
     use bitcoin::secp256k1::SecretKey;
 
     #[test]
@@ -156,4 +170,20 @@ mod tests {
         let decrypted = decrypt_with_key(&key, encrypted).unwrap();
         assert_eq!(content, decrypted);
     }
+
+    #[test]
+    fn test_decryption_with_iv() {
+        let key = [1u8; 32];
+        let iv = [2u8; 16];
+        let content = b"voucher code 1234";
+
+        let cipher = Aes256CbcEnc::new(&key.into(), &iv.into());
+        let encrypted = cipher.encrypt_padded_vec_mut::<Pkcs7>(content);
+
+        let decrypted = decrypt_with_iv(&key, &iv, &encrypted).unwrap();
+        assert_eq!(content.to_vec(), decrypted);
+
+        // wrong iv length
+        assert!(decrypt_with_iv(&key, &iv[..8], &encrypted).is_err());
+    }
 }
@@ -2,6 +2,7 @@ use aes::cipher::block_padding::UnpadError;
 use bitcoin::Network;
 use lightning::ln::channelmanager::RetryableSendFailure;
 use lightning::ln::peer_handler::PeerHandleError;
+use lightning::offers::parse::{Bolt12ParseError, Bolt12SemanticError};
 use lightning_invoice::payment::PaymentError;
 use lightning_invoice::ParseOrSemanticError;
 use lightning_rapid_gossip_sync::GraphSyncError;
@@ -59,6 +60,21 @@ pub enum MutinyError {
     /// We do not have enough balance to pay the given amount.
     #[error("We do not have enough balance to pay the given amount.")]
     InsufficientBalance,
+    /// None of our nodes or federations can pay on its own, only their combined
+    /// balance is enough, and a payment can't be split between them.
+    #[error(
+        "Not enough balance in a single node or federation, payments can't be split between them."
+    )]
+    SplitPaymentUnsupported,
+    /// A UTXO that was frozen was selected to be spent.
+    #[error("Cannot spend a frozen UTXO.")]
+    FrozenUtxo,
+    /// The fee for a payment is above the limit set in the routing policy.
+    #[error("The payment fee is above the configured limit.")]
+    FeeLimitExceeded,
+    /// The hold invoice has not received a payment that can be settled.
+    #[error("The hold invoice has not been paid yet.")]
+    HoldInvoiceNotAccepted,
     /// Failed to call on the given LNURL
     #[error("Failed to call on the given LNURL.")]
     LnUrlFailure,
@@ -77,6 +93,15 @@ pub enum MutinyError {
     /// LSP required an invoice and none was provided.
     #[error("Failed to provide an invoice to the LSP.")]
     LspInvoiceRequired,
+    /// The channel order was already paid for, or can no longer be paid.
+    #[error("The channel order cannot be paid.")]
+    ChannelOrderNotPayable,
+    /// The LSP fee is above the configured maximum.
+    #[error("The LSP fee is above the configured maximum.")]
+    LspFeeTooHigh,
+    /// The LSP fee quote does not exist or has expired.
+    #[error("The LSP fee quote was not found or has expired.")]
+    LspFeeQuoteNotFound,
     /// Subscription Client Not Configured
     #[error("Subscription Client Not Configured")]
     SubscriptionClientNotConfigured,
@@ -208,11 +233,18 @@ impl PartialEq for MutinyError {
             (Self::InvoiceCreationFailed, Self::InvoiceCreationFailed) => true,
             (Self::ReserveAmountError, Self::ReserveAmountError) => true,
             (Self::InsufficientBalance, Self::InsufficientBalance) => true,
+            (Self::SplitPaymentUnsupported, Self::SplitPaymentUnsupported) => true,
+            (Self::FrozenUtxo, Self::FrozenUtxo) => true,
+            (Self::FeeLimitExceeded, Self::FeeLimitExceeded) => true,
+            (Self::HoldInvoiceNotAccepted, Self::HoldInvoiceNotAccepted) => true,
             (Self::LnUrlFailure, Self::LnUrlFailure) => true,
             (Self::LspGenericError, Self::LspGenericError) => true,
             (Self::LspFundingError, Self::LspFundingError) => true,
             (Self::LspAmountTooHighError, Self::LspAmountTooHighError) => true,
             (Self::LspConnectionError, Self::LspConnectionError) => true,
+            (Self::ChannelOrderNotPayable, Self::ChannelOrderNotPayable) => true,
+            (Self::LspFeeTooHigh, Self::LspFeeTooHigh) => true,
+            (Self::LspFeeQuoteNotFound, Self::LspFeeQuoteNotFound) => true,
             (Self::SubscriptionClientNotConfigured, Self::SubscriptionClientNotConfigured) => true,
             (Self::InvalidArgumentsError, Self::InvalidArgumentsError) => true,
             (Self::RoutingFailed, Self::RoutingFailed) => true,
@@ -359,6 +391,26 @@ impl From<ParseOrSemanticError> for MutinyError {
     }
 }
 
+impl From<Bolt12ParseError> for MutinyError {
+    fn from(_e: Bolt12ParseError) -> Self {
+        Self::InvoiceInvalid
+    }
+}
+
+impl From<Bolt12SemanticError> for MutinyError {
+    fn from(e: Bolt12SemanticError) -> Self {
+        match e {
+            Bolt12SemanticError::AlreadyExpired => Self::InvoiceExpired,
+            Bolt12SemanticError::DuplicatePaymentId => Self::NonUniquePaymentHash,
+            Bolt12SemanticError::MissingAmount
+            | Bolt12SemanticError::InvalidAmount
+            | Bolt12SemanticError::InsufficientAmount
+            | Bolt12SemanticError::UnexpectedAmount => Self::BadAmountError,
+            _ => Self::InvoiceInvalid,
+        }
+    }
+}
+
 impl From<PeerHandleError> for MutinyError {
     fn from(_e: PeerHandleError) -> Self {
         // TODO handle the case where `no_connection_possible`
@@ -407,6 +459,12 @@ impl<G> From<std::sync::PoisonError<G>> for MutinyStorageError {
     }
 }
 
+impl<G> From<std::sync::PoisonError<G>> for MutinyError {
+    fn from(_e: std::sync::PoisonError<G>) -> Self {
+        MutinyStorageError::LockError.into()
+    }
+}
+
 impl<G> From<std::sync::TryLockError<G>> for MutinyError {
     fn from(_e: std::sync::TryLockError<G>) -> Self {
         MutinyStorageError::LockError.into()
@@ -479,6 +537,12 @@ impl From<nostr::nips::nip57::Error> for MutinyError {
     }
 }
 
+impl From<nostr::nips::nip44::Error> for MutinyError {
+    fn from(_e: nostr::nips::nip44::Error) -> Self {
+        Self::NostrError
+    }
+}
+
 impl From<nip05::Error> for MutinyError {
     fn from(e: nip05::Error) -> Self {
         match e {
@@ -0,0 +1,441 @@
+use crate::chainsource::{
+    ChainSource, EsploraChainSource, KeychainSpks, OutputSpend, TxConfirmation,
+};
+use crate::error::MutinyError;
+use crate::logging::MutinyLogger;
+use crate::utils;
+use async_trait::async_trait;
+use bdk::wallet::Update;
+use bitcoin::{BlockHash, BlockHeader, OutPoint, Script, Transaction, Txid};
+use futures::future::{self, join_all, Either};
+use futures::{pin_mut, Future};
+use lightning::chain::{Confirm, Filter, WatchedOutput};
+use lightning::log_warn;
+use lightning::util::logger::Logger;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+/// How long a single request can take before we try the next server
+const REQUEST_TIMEOUT_MS: i32 = 15_000;
+/// Wallet scans and lightning syncs make many requests so they get longer
+const SYNC_TIMEOUT_MS: i32 = 5 * 60_000;
+/// How many servers a transaction is broadcast to at once
+const BROADCAST_ENDPOINTS: usize = 3;
+const BASE_BACKOFF_SECS: u64 = 5;
+const MAX_BACKOFF_SECS: u64 = 5 * 60;
+
+/// Request stats for an esplora server.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct EndpointStats {
+    pub url: String,
+    pub requests: u64,
+    pub errors: u64,
+    /// Errors since the last successful request
+    pub consecutive_errors: u32,
+    /// Moving average of the latency of successful requests
+    pub avg_latency_ms: Option<u64>,
+    /// When the last error happened, in seconds since the epoch
+    pub last_error: Option<u64>,
+    /// False while the server is skipped because of recent errors
+    pub healthy: bool,
+}
+
+#[derive(Default)]
+struct Health {
+    requests: u64,
+    errors: u64,
+    consecutive_errors: u32,
+    avg_latency_ms: Option<f64>,
+    last_error: Option<u64>,
+}
+
+impl Health {
+    fn record_success(&mut self, latency_ms: u64) {
+        self.requests += 1;
+        self.consecutive_errors = 0;
+        // weight recent requests more so we notice a server slowing down
+        let latency = latency_ms as f64;
+        self.avg_latency_ms = Some(match self.avg_latency_ms {
+            Some(avg) => avg * 0.8 + latency * 0.2,
+            None => latency,
+        });
+    }
+
+    fn record_error(&mut self, now_secs: u64) {
+        self.requests += 1;
+        self.errors += 1;
+        self.consecutive_errors += 1;
+        self.last_error = Some(now_secs);
+    }
+
+    /// A server that errored is skipped for a while, backing off exponentially.
+    fn is_healthy(&self, now_secs: u64) -> bool {
+        if self.consecutive_errors == 0 {
+            return true;
+        }
+
+        let exp = (self.consecutive_errors - 1).min(10);
+        let backoff = (BASE_BACKOFF_SECS << exp).min(MAX_BACKOFF_SECS);
+        self.last_error
+            .map_or(true, |time| now_secs >= time + backoff)
+    }
+}
+
+struct Endpoint {
+    url: String,
+    source: Arc<EsploraChainSource>,
+    health: Mutex<Health>,
+}
+
+impl Endpoint {
+    async fn call<T>(
+        &self,
+        timeout_ms: i32,
+        fut: impl Future<Output = Result<T, MutinyError>>,
+    ) -> Result<T, MutinyError> {
+        let start = utils::now();
+        let res = with_timeout(timeout_ms, fut)
+            .await
+            .unwrap_or(Err(MutinyError::ConnectionFailed));
+
+        let mut health = self.health.lock().unwrap();
+        match res {
+            Ok(_) => {
+                let latency = utils::now().saturating_sub(start);
+                health.record_success(latency.as_millis() as u64);
+            }
+            Err(_) => health.record_error(utils::now().as_secs()),
+        }
+
+        res
+    }
+
+    fn stats(&self, now_secs: u64) -> EndpointStats {
+        let health = self.health.lock().unwrap();
+        EndpointStats {
+            url: self.url.clone(),
+            requests: health.requests,
+            errors: health.errors,
+            consecutive_errors: health.consecutive_errors,
+            avg_latency_ms: health.avg_latency_ms.map(|l| l as u64),
+            last_error: health.last_error,
+            healthy: health.is_healthy(now_secs),
+        }
+    }
+}
+
+async fn with_timeout<F: Future>(millis: i32, fut: F) -> Option<F::Output> {
+    let timeout = utils::sleep(millis);
+    pin_mut!(fut);
+    pin_mut!(timeout);
+
+    match future::select(fut, timeout).await {
+        Either::Left((res, _)) => Some(res),
+        Either::Right(_) => None,
+    }
+}
+
+/// Several esplora servers, requests go to the healthiest one and move
+/// on to the next one on errors and timeouts.
+pub struct EsploraPool {
+    endpoints: Vec<Endpoint>,
+    logger: Arc<MutinyLogger>,
+}
+
+impl EsploraPool {
+    pub fn new(urls: Vec<String>, logger: Arc<MutinyLogger>) -> Result<Self, MutinyError> {
+        if urls.is_empty() {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+
+        let endpoints = urls
+            .into_iter()
+            .map(|url| {
+                let source = EsploraChainSource::new(&url, logger.clone())?;
+                Ok(Endpoint {
+                    url,
+                    source: Arc::new(source),
+                    health: Mutex::new(Health::default()),
+                })
+            })
+            .collect::<Result<Vec<_>, MutinyError>>()?;
+
+        Ok(Self { endpoints, logger })
+    }
+
+    /// The order to try the servers in. Healthy ones come first, then the ones
+    /// with fewer recent errors and lower latency, otherwise the configured order.
+    fn ranked(&self) -> Vec<usize> {
+        let now = utils::now().as_secs();
+        let mut ranked: Vec<(usize, bool, u32, u64)> = self
+            .endpoints
+            .iter()
+            .enumerate()
+            .map(|(i, e)| {
+                let health = e.health.lock().unwrap();
+                let latency = health.avg_latency_ms.map_or(0, |l| l as u64);
+                (
+                    i,
+                    health.is_healthy(now),
+                    health.consecutive_errors,
+                    latency,
+                )
+            })
+            .collect();
+        ranked.sort_by_key(|(i, healthy, errors, latency)| (!healthy, *errors, *latency, *i));
+
+        ranked.into_iter().map(|(i, ..)| i).collect()
+    }
+
+    async fn request<T, F, Fut>(&self, timeout_ms: i32, f: F) -> Result<T, MutinyError>
+    where
+        F: Fn(Arc<EsploraChainSource>) -> Fut,
+        Fut: Future<Output = Result<T, MutinyError>>,
+    {
+        let mut last_error = MutinyError::ChainAccessFailed;
+        for i in self.ranked() {
+            let endpoint = &self.endpoints[i];
+            match endpoint.call(timeout_ms, f(endpoint.source.clone())).await {
+                Ok(res) => return Ok(res),
+                Err(e) => {
+                    log_warn!(
+                        self.logger,
+                        "Request to esplora server {} failed: {e}",
+                        endpoint.url
+                    );
+                    last_error = e;
+                }
+            }
+        }
+
+        Err(last_error)
+    }
+}
+
+impl Filter for EsploraPool {
+    // every server keeps track of what to watch, so we can sync with any of them
+    fn register_tx(&self, txid: &Txid, script_pubkey: &Script) {
+        for endpoint in self.endpoints.iter() {
+            endpoint.source.register_tx(txid, script_pubkey);
+        }
+    }
+
+    fn register_output(&self, output: WatchedOutput) {
+        for endpoint in self.endpoints.iter() {
+            endpoint.source.register_output(output.clone());
+        }
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl ChainSource for EsploraPool {
+    async fn get_tip(&self) -> Result<(u32, BlockHeader), MutinyError> {
+        self.request(REQUEST_TIMEOUT_MS, |s| async move { s.get_tip().await })
+            .await
+    }
+
+    async fn get_header(&self, height: u32) -> Result<BlockHeader, MutinyError> {
+        self.request(
+            REQUEST_TIMEOUT_MS,
+            |s| async move { s.get_header(height).await },
+        )
+        .await
+    }
+
+    async fn get_tx(&self, txid: &Txid) -> Result<Option<Transaction>, MutinyError> {
+        let txid = *txid;
+        self.request(REQUEST_TIMEOUT_MS, |s| async move { s.get_tx(&txid).await })
+            .await
+    }
+
+    async fn get_tx_status(&self, txid: &Txid) -> Result<Option<TxConfirmation>, MutinyError> {
+        let txid = *txid;
+        self.request(REQUEST_TIMEOUT_MS, |s| async move {
+            s.get_tx_status(&txid).await
+        })
+        .await
+    }
+
+    async fn get_block_tx(
+        &self,
+        txid: &Txid,
+        confirmation: &TxConfirmation,
+    ) -> Result<Option<(usize, Transaction)>, MutinyError> {
+        let txid = *txid;
+        let confirmation = *confirmation;
+        self.request(REQUEST_TIMEOUT_MS, |s| async move {
+            s.get_block_tx(&txid, &confirmation).await
+        })
+        .await
+    }
+
+    async fn get_output_spend(
+        &self,
+        outpoint: &OutPoint,
+    ) -> Result<Option<OutputSpend>, MutinyError> {
+        let outpoint = *outpoint;
+        self.request(REQUEST_TIMEOUT_MS, |s| async move {
+            s.get_output_spend(&outpoint).await
+        })
+        .await
+    }
+
+    async fn get_script_txs(
+        &self,
+        script: &Script,
+    ) -> Result<Vec<(Transaction, Option<TxConfirmation>)>, MutinyError> {
+        self.request(REQUEST_TIMEOUT_MS, |s| {
+            let script = script.clone();
+            async move { s.get_script_txs(&script).await }
+        })
+        .await
+    }
+
+    /// Broadcasts to several servers at once so one server dropping
+    /// the transaction doesn't keep it from getting to miners.
+    async fn broadcast(&self, tx: &Transaction) -> Result<(), MutinyError> {
+        let ranked = self.ranked();
+        let futs = ranked.iter().take(BROADCAST_ENDPOINTS).map(|i| {
+            let endpoint = &self.endpoints[*i];
+            endpoint.call(REQUEST_TIMEOUT_MS, endpoint.source.broadcast(tx))
+        });
+        let results = join_all(futs).await;
+        if results.iter().any(|r| r.is_ok()) {
+            return Ok(());
+        }
+
+        // none of them accepted it, try the rest one at a time
+        for i in ranked.iter().skip(BROADCAST_ENDPOINTS) {
+            let endpoint = &self.endpoints[*i];
+            if endpoint
+                .call(REQUEST_TIMEOUT_MS, endpoint.source.broadcast(tx))
+                .await
+                .is_ok()
+            {
+                return Ok(());
+            }
+        }
+
+        Err(results
+            .into_iter()
+            .find_map(|r| r.err())
+            .unwrap_or(MutinyError::ChainAccessFailed))
+    }
+
+    async fn get_fee_estimates(&self) -> Result<HashMap<String, f64>, MutinyError> {
+        self.request(REQUEST_TIMEOUT_MS, |s| async move {
+            s.get_fee_estimates().await
+        })
+        .await
+    }
+
+    async fn scan_wallet(
+        &self,
+        checkpoints: &BTreeMap<u32, BlockHash>,
+        spks: KeychainSpks,
+        txids: Vec<Txid>,
+        stop_gap: usize,
+    ) -> Result<Update, MutinyError> {
+        self.request(SYNC_TIMEOUT_MS, move |s| {
+            let spks = spks.clone();
+            let txids = txids.clone();
+            async move { s.scan_wallet(checkpoints, spks, txids, stop_gap).await }
+        })
+        .await
+    }
+
+    async fn sync_lightning(
+        &self,
+        confirmables: Vec<&(dyn Confirm + Send + Sync)>,
+    ) -> Result<(), MutinyError> {
+        self.request(SYNC_TIMEOUT_MS, move |s| {
+            let confirmables = confirmables.clone();
+            async move { s.sync_lightning(confirmables).await }
+        })
+        .await
+    }
+
+    fn endpoint_stats(&self) -> Vec<EndpointStats> {
+        let now = utils::now().as_secs();
+        self.endpoints.iter().map(|e| e.stats(now)).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::*;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    fn create_pool() -> EsploraPool {
+        let urls = vec![
+            "https://mutinynet.com/api".to_string(),
+            "https://mempool.space/signet/api".to_string(),
+            "http://localhost:3003".to_string(),
+        ];
+        EsploraPool::new(urls, Arc::new(MutinyLogger::default())).unwrap()
+    }
+
+    #[test]
+    async fn test_health_backoff() {
+        let test_name = "test_health_backoff";
+        log!("{}", test_name);
+
+        let mut health = Health::default();
+        assert!(health.is_healthy(0));
+
+        health.record_error(1_000);
+        assert!(!health.is_healthy(1_000));
+        assert!(health.is_healthy(1_000 + BASE_BACKOFF_SECS));
+
+        // backs off longer after more errors, up to the max
+        health.record_error(1_000);
+        assert!(!health.is_healthy(1_000 + BASE_BACKOFF_SECS));
+        assert!(health.is_healthy(1_000 + BASE_BACKOFF_SECS * 2));
+        for _ in 0..20 {
+            health.record_error(1_000);
+        }
+        assert!(!health.is_healthy(1_000 + MAX_BACKOFF_SECS - 1));
+        assert!(health.is_healthy(1_000 + MAX_BACKOFF_SECS));
+
+        health.record_success(100);
+        assert!(health.is_healthy(1_000));
+        assert_eq!(health.consecutive_errors, 0);
+        assert_eq!(health.errors, 22);
+        assert_eq!(health.requests, 23);
+    }
+
+    #[test]
+    async fn test_pool_ranking() {
+        let test_name = "test_pool_ranking";
+        log!("{}", test_name);
+
+        assert!(EsploraPool::new(vec![], Arc::new(MutinyLogger::default())).is_err());
+
+        let pool = create_pool();
+        // configured order until we know more
+        assert_eq!(pool.ranked(), vec![0, 1, 2]);
+
+        pool.endpoints[0]
+            .health
+            .lock()
+            .unwrap()
+            .record_error(utils::now().as_secs());
+        pool.endpoints[1].health.lock().unwrap().record_success(500);
+        pool.endpoints[2].health.lock().unwrap().record_success(100);
+        assert_eq!(pool.ranked(), vec![2, 1, 0]);
+
+        let stats = pool.endpoint_stats();
+        assert_eq!(stats.len(), 3);
+        assert_eq!(stats[0].url, "https://mutinynet.com/api");
+        assert_eq!(stats[0].errors, 1);
+        assert!(!stats[0].healthy);
+        assert_eq!(stats[2].avg_latency_ms, Some(100));
+        assert!(stats[2].healthy);
+    }
+}
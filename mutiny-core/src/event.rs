@@ -1,20 +1,33 @@
-use crate::ldkstorage::{MutinyNodePersister, PhantomChannelManager};
+use crate::error::MutinyError;
+use crate::federation::FederationOnchainTx;
+use crate::holdinvoice::{persist_hold_invoice, read_hold_invoice, HoldInvoiceStatus};
+use crate::ldkstorage::{
+    ChannelOpenParams, MutinyNodePersister, PhantomChannelManager, UnsignedChannelFunding,
+};
 use crate::logging::MutinyLogger;
 use crate::lsp::{AnyLsp, Lsp};
 use crate::node::BumpTxEventHandler;
 use crate::nodemanager::ChannelClosure;
 use crate::onchain::OnChainWallet;
-use crate::storage::MutinyStorage;
+use crate::storage::{
+    delete_payment_info, persist_offer_payment_hash, read_bolt12_invoice_receive, MutinyStorage,
+};
 use crate::utils::sleep;
 use crate::{fees::MutinyFeeEstimator, storage::read_payment_info};
 use crate::{keymanager::PhantomKeysManager, storage::persist_payment_info};
 use anyhow::anyhow;
+use bdk::FeeRate;
 use bitcoin::hashes::hex::ToHex;
+use bitcoin::hashes::{sha256, Hash};
 use bitcoin::secp256k1::PublicKey;
 use bitcoin::secp256k1::Secp256k1;
 use bitcoin::{LockTime, PackedLockTime};
 use core::fmt;
+use fedimint_core::config::FederationId;
+use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
 use lightning::events::{Event, PaymentPurpose};
+use lightning::ln::channelmanager::PaymentId;
+use lightning::ln::PaymentHash;
 use lightning::sign::SpendableOutputDescriptor;
 use lightning::{
     log_debug, log_error, log_info, log_warn, util::errors::APIError, util::logger::Logger,
@@ -22,7 +35,7 @@ use lightning::{
 use lightning_invoice::Bolt11Invoice;
 use serde::{Deserialize, Serialize};
 use std::str::FromStr;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
 pub(crate) struct PaymentInfo {
@@ -37,11 +50,24 @@ pub(crate) struct PaymentInfo {
     pub fee_paid_msat: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub bolt11: Option<Bolt11Invoice>,
+    /// The BOLT12 offer or refund this payment was made for, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bolt12: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub payee_pubkey: Option<PublicKey>,
+    /// Custom TLV records sent with or received in a keysend payment
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub custom_tlvs: Vec<CustomTlv>,
     pub last_update: u64,
 }
 
+/// A custom TLV record in the onion of a payment, such as a podcasting 2.0 boostagram.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CustomTlv {
+    pub tlv_type: u64,
+    pub value: Vec<u8>,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub(crate) struct MillisatAmount(pub Option<u64>);
 
@@ -88,6 +114,76 @@ impl FromStr for HTLCStatus {
     }
 }
 
+/// An update about the state of the wallet that consumers can subscribe to
+/// instead of polling for changes.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "type")]
+pub enum MutinyEvent {
+    /// A lightning payment to one of our nodes was claimed
+    PaymentReceived {
+        payment_hash: sha256::Hash,
+        amount_sats: u64,
+    },
+    /// A lightning payment from one of our nodes succeeded
+    PaymentSent {
+        payment_hash: sha256::Hash,
+        fee_paid_sats: Option<u64>,
+    },
+    /// A lightning payment from one of our nodes failed
+    PaymentFailed { payment_hash: sha256::Hash },
+    /// A channel is ready to be used
+    ChannelReady {
+        channel_id: String,
+        user_channel_id: String,
+        counterparty_node_id: PublicKey,
+    },
+    /// A channel was closed
+    ChannelClosed { closure: ChannelClosure },
+    /// A payment through a federation succeeded or failed
+    FederationPayment {
+        federation_id: FederationId,
+        payment_hash: sha256::Hash,
+        inbound: bool,
+        status: HTLCStatus,
+        amount_sats: Option<u64>,
+    },
+    /// A deposit into or withdrawal from a federation completed or failed
+    FederationOnchain { transaction: FederationOnchainTx },
+    /// The on-chain wallet finished syncing with the blockchain
+    OnchainSyncCompleted,
+    /// A payment to a hold invoice arrived and is waiting to be settled or cancelled
+    HoldInvoiceAccepted {
+        payment_hash: sha256::Hash,
+        amount_sats: u64,
+    },
+    /// A hold invoice was cancelled, any held payment was failed back
+    HoldInvoiceCancelled { payment_hash: sha256::Hash },
+}
+
+/// Sends [`MutinyEvent`]s to everyone that subscribed to them.
+///
+/// Subscribers that have dropped their receiver are removed the next time an event is sent.
+#[derive(Clone, Default)]
+pub struct EventNotifier {
+    subscribers: Arc<Mutex<Vec<UnboundedSender<MutinyEvent>>>>,
+}
+
+impl EventNotifier {
+    pub fn subscribe(&self) -> UnboundedReceiver<MutinyEvent> {
+        let (sender, receiver) = unbounded();
+        if let Ok(mut subscribers) = self.subscribers.lock() {
+            subscribers.push(sender);
+        }
+        receiver
+    }
+
+    pub(crate) fn notify(&self, event: MutinyEvent) {
+        if let Ok(mut subscribers) = self.subscribers.lock() {
+            subscribers.retain(|s| s.unbounded_send(event.clone()).is_ok());
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct EventHandler<S: MutinyStorage> {
     channel_manager: Arc<PhantomChannelManager<S>>,
@@ -96,7 +192,8 @@ pub struct EventHandler<S: MutinyStorage> {
     keys_manager: Arc<PhantomKeysManager<S>>,
     persister: Arc<MutinyNodePersister<S>>,
     bump_tx_event_handler: Arc<BumpTxEventHandler<S>>,
-    lsp_client: Option<AnyLsp<S>>,
+    lsp_clients: Vec<AnyLsp<S>>,
+    event_notifier: EventNotifier,
     logger: Arc<MutinyLogger>,
 }
 
@@ -109,7 +206,8 @@ impl<S: MutinyStorage> EventHandler<S> {
         keys_manager: Arc<PhantomKeysManager<S>>,
         persister: Arc<MutinyNodePersister<S>>,
         bump_tx_event_handler: Arc<BumpTxEventHandler<S>>,
-        lsp_client: Option<AnyLsp<S>>,
+        lsp_clients: Vec<AnyLsp<S>>,
+        event_notifier: EventNotifier,
         logger: Arc<MutinyLogger>,
     ) -> Self {
         Self {
@@ -117,9 +215,10 @@ impl<S: MutinyStorage> EventHandler<S> {
             fee_estimator,
             wallet,
             keys_manager,
-            lsp_client,
+            lsp_clients,
             persister,
             bump_tx_event_handler,
+            event_notifier,
             logger,
         }
     }
@@ -144,33 +243,60 @@ impl<S: MutinyStorage> EventHandler<S> {
                     }
                 };
 
+                // a watch-only wallet can only create the funding PSBT, it is
+                // given to the channel manager once it has been signed externally
+                let watch_only = self.wallet.is_watch_only();
                 let psbt_result = match &params_opt {
                     None => {
                         log_warn!(
                             self.logger,
                             "WARNING: Could not find channel open params for channel {user_channel_id}"
                         );
-                        self.wallet.create_signed_psbt_to_spk(
-                            output_script,
-                            channel_value_satoshis,
-                            None,
-                        )
+                        if watch_only {
+                            self.wallet.create_unsigned_psbt_to_spk(
+                                output_script,
+                                channel_value_satoshis,
+                                None,
+                                None,
+                            )
+                        } else {
+                            self.wallet.create_signed_psbt_to_spk(
+                                output_script,
+                                channel_value_satoshis,
+                                None,
+                            )
+                        }
                     }
                     Some(params) => {
                         log_debug!(self.logger, "Opening channel with params: {params:?}");
-                        if let Some(utxos) = &params.utxos {
-                            self.wallet.create_sweep_psbt_to_output(
+                        let absolute_fee =
+                            || params.absolute_fee.expect("Absolute fee should be set");
+                        match &params.utxos {
+                            Some(utxos) if watch_only => {
+                                self.wallet.create_unsigned_sweep_psbt_to_output(
+                                    utxos,
+                                    output_script,
+                                    channel_value_satoshis,
+                                    absolute_fee(),
+                                )
+                            }
+                            Some(utxos) => self.wallet.create_sweep_psbt_to_output(
                                 utxos,
                                 output_script,
                                 channel_value_satoshis,
-                                params.absolute_fee.expect("Absolute fee should be set"),
-                            )
-                        } else {
-                            self.wallet.create_signed_psbt_to_spk(
+                                absolute_fee(),
+                            ),
+                            None if watch_only => self.wallet.create_unsigned_psbt_to_spk(
                                 output_script,
                                 channel_value_satoshis,
+                                None,
                                 Some(params.sats_per_vbyte),
-                            )
+                            ),
+                            None => self.wallet.create_signed_psbt_to_spk(
+                                output_script,
+                                channel_value_satoshis,
+                                Some(params.sats_per_vbyte),
+                            ),
                         }
                     }
                 };
@@ -206,6 +332,46 @@ impl<S: MutinyStorage> EventHandler<S> {
                     }
                 };
 
+                if watch_only {
+                    let mut params = params_opt.unwrap_or_else(|| {
+                        let sats_per_kw = self.wallet.fees.get_normal_fee_rate();
+                        ChannelOpenParams::new(
+                            FeeRate::from_sat_per_kwu(sats_per_kw as f32).as_sat_per_vb(),
+                        )
+                    });
+                    params.unsigned_funding = Some(UnsignedChannelFunding {
+                        temporary_channel_id: temporary_channel_id.0,
+                        counterparty_node_id,
+                        psbt,
+                    });
+
+                    if let Err(e) = self
+                        .persister
+                        .persist_channel_open_params(user_channel_id, params)
+                    {
+                        log_error!(
+                            self.logger,
+                            "ERROR: Could not save unsigned funding transaction: {e}"
+                        );
+                        if let Err(e) = self.channel_manager.force_close_without_broadcasting_txn(
+                            &temporary_channel_id,
+                            &counterparty_node_id,
+                        ) {
+                            log_error!(
+                                self.logger,
+                                "ERROR: Could not force close failed channel: {e:?}"
+                            );
+                        }
+                        return;
+                    }
+
+                    log_info!(
+                        self.logger,
+                        "EVENT: FundingGenerationReady waiting for funding PSBT to be signed"
+                    );
+                    return;
+                }
+
                 let tx = psbt.extract_tx();
 
                 if let Err(e) = self.channel_manager.funding_transaction_generated(
@@ -236,16 +402,20 @@ impl<S: MutinyStorage> EventHandler<S> {
                 purpose,
                 amount_msat,
                 counterparty_skimmed_fee_msat,
+                claim_deadline,
+                onion_fields,
                 ..
             } => {
                 log_debug!(self.logger, "EVENT: PaymentReceived received payment from payment hash {} of {amount_msat} millisatoshis to {receiver_node_id:?}", payment_hash.0.to_hex());
 
+                // only the LSP that made the invoice expects a fee for it
                 let expected_skimmed_fee_msat = self
-                    .lsp_client
-                    .as_ref()
+                    .lsp_clients
+                    .iter()
                     .map(|lsp_client| {
                         lsp_client.get_expected_skimmed_fee_msat(payment_hash, amount_msat)
                     })
+                    .max()
                     .unwrap_or(0);
 
                 if counterparty_skimmed_fee_msat > expected_skimmed_fee_msat {
@@ -253,6 +423,22 @@ impl<S: MutinyStorage> EventHandler<S> {
                     return;
                 }
 
+                let custom_tlvs: Vec<CustomTlv> = onion_fields
+                    .map(|fields| {
+                        fields
+                            .custom_tlvs()
+                            .iter()
+                            .map(|(tlv_type, value)| CustomTlv {
+                                tlv_type: *tlv_type,
+                                value: value.clone(),
+                            })
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                if !custom_tlvs.is_empty() {
+                    self.save_custom_tlvs(payment_hash, receiver_node_id, amount_msat, custom_tlvs);
+                }
+
                 if let Some(payment_preimage) = match purpose {
                     PaymentPurpose::InvoicePayment {
                         payment_preimage, ..
@@ -261,7 +447,7 @@ impl<S: MutinyStorage> EventHandler<S> {
                 } {
                     self.channel_manager.claim_funds(payment_preimage);
                 } else {
-                    log_error!(self.logger, "ERROR: No payment preimage found");
+                    self.hold_payment(payment_hash, amount_msat, claim_deadline);
                 };
             }
             Event::PaymentClaimed {
@@ -282,7 +468,7 @@ impl<S: MutinyStorage> EventHandler<S> {
                     } => (payment_preimage, Some(payment_secret)),
                     PaymentPurpose::SpontaneousPayment(preimage) => (Some(preimage), None),
                 };
-                match read_payment_info(
+                let payment_info = match read_payment_info(
                     &self.persister.storage,
                     &payment_hash.0,
                     true,
@@ -292,29 +478,24 @@ impl<S: MutinyStorage> EventHandler<S> {
                         let payment_preimage = payment_preimage.map(|p| p.0);
                         let payment_secret = payment_secret.map(|p| p.0);
                         saved_payment_info.status = HTLCStatus::Succeeded;
-                        saved_payment_info.preimage = payment_preimage;
+                        // hold invoices get their preimage saved when they are settled
+                        saved_payment_info.preimage =
+                            payment_preimage.or(saved_payment_info.preimage);
                         saved_payment_info.secret = payment_secret;
                         saved_payment_info.amt_msat = MillisatAmount(Some(amount_msat));
                         saved_payment_info.last_update = crate::utils::now().as_secs();
-                        match persist_payment_info(
-                            &self.persister.storage,
-                            &payment_hash.0,
-                            &saved_payment_info,
-                            true,
-                        ) {
-                            Ok(_) => (),
-                            Err(e) => log_error!(
-                                self.logger,
-                                "ERROR: could not persist payment info: {e}"
-                            ),
-                        }
+                        saved_payment_info
                     }
                     None => {
                         let payment_preimage = payment_preimage.map(|p| p.0);
                         let payment_secret = payment_secret.map(|p| p.0);
                         let last_update = crate::utils::now().as_secs();
+                        // we save every bolt11 invoice we create, so an invoice
+                        // payment we don't know about may be for a BOLT12 invoice
+                        let bolt12 =
+                            read_bolt12_invoice_receive(&self.persister.storage, &payment_hash.0);
 
-                        let payment_info = PaymentInfo {
+                        PaymentInfo {
                             preimage: payment_preimage,
                             secret: payment_secret,
                             status: HTLCStatus::Succeeded,
@@ -322,24 +503,29 @@ impl<S: MutinyStorage> EventHandler<S> {
                             fee_paid_msat: None,
                             payee_pubkey: receiver_node_id,
                             bolt11: None,
+                            bolt12,
+                            custom_tlvs: vec![],
                             last_update,
-                        };
-                        match persist_payment_info(
-                            &self.persister.storage,
-                            &payment_hash.0,
-                            &payment_info,
-                            true,
-                        ) {
-                            Ok(_) => (),
-                            Err(e) => log_error!(
-                                self.logger,
-                                "ERROR: could not persist payment info: {e}"
-                            ),
                         }
                     }
+                };
+
+                match persist_payment_info(
+                    &self.persister.storage,
+                    &payment_hash.0,
+                    &payment_info,
+                    true,
+                ) {
+                    // notify after persisting so subscribers can look up the payment
+                    Ok(_) => self.event_notifier.notify(MutinyEvent::PaymentReceived {
+                        payment_hash: sha256::Hash::from_inner(payment_hash.0),
+                        amount_sats: amount_msat / 1_000,
+                    }),
+                    Err(e) => log_error!(self.logger, "ERROR: could not persist payment info: {e}"),
                 }
             }
             Event::PaymentSent {
+                payment_id,
                 payment_preimage,
                 payment_hash,
                 fee_paid_msat,
@@ -351,24 +537,22 @@ impl<S: MutinyStorage> EventHandler<S> {
                     payment_hash.0.to_hex()
                 );
 
-                match read_payment_info(
-                    &self.persister.storage,
-                    &payment_hash.0,
-                    false,
-                    &self.logger,
-                ) {
-                    Some(mut saved_payment_info) => {
+                match self.read_outbound_payment_info(&payment_hash, payment_id) {
+                    Some((key, mut saved_payment_info)) => {
                         saved_payment_info.status = HTLCStatus::Succeeded;
                         saved_payment_info.preimage = Some(payment_preimage.0);
                         saved_payment_info.fee_paid_msat = fee_paid_msat;
                         saved_payment_info.last_update = crate::utils::now().as_secs();
-                        match persist_payment_info(
-                            &self.persister.storage,
-                            &payment_hash.0,
+                        match self.persist_outbound_payment_info(
+                            key,
+                            &payment_hash,
                             &saved_payment_info,
-                            false,
                         ) {
-                            Ok(_) => (),
+                            // notify after persisting so subscribers can look up the payment
+                            Ok(_) => self.event_notifier.notify(MutinyEvent::PaymentSent {
+                                payment_hash: sha256::Hash::from_inner(payment_hash.0),
+                                fee_paid_sats: fee_paid_msat.map(|f| f / 1_000),
+                            }),
                             Err(e) => log_error!(
                                 self.logger,
                                 "ERROR: could not persist payment info: {e}"
@@ -408,12 +592,12 @@ impl<S: MutinyStorage> EventHandler<S> {
                     Err(e) => log_debug!(self.logger, "EVENT: OpenChannelRequest error: {e:?}"),
                 };
 
-                let lsp_pubkey = self
-                    .lsp_client
-                    .as_ref()
-                    .map(|client| client.get_lsp_pubkey());
+                let is_lsp = self
+                    .lsp_clients
+                    .iter()
+                    .any(|client| client.get_lsp_pubkey() == counterparty_node_id);
 
-                if lsp_pubkey.as_ref() != Some(&counterparty_node_id) {
+                if !is_lsp {
                     // did not match the lsp pubkey, normal open
                     let result = self.channel_manager.accept_inbound_channel(
                         &temporary_channel_id,
@@ -445,42 +629,21 @@ impl<S: MutinyStorage> EventHandler<S> {
             Event::ProbeFailed { .. } => {
                 log_debug!(self.logger, "EVENT: ProbeFailed, ignored");
             }
-            Event::PaymentFailed { payment_hash, .. } => {
+            Event::PaymentFailed {
+                payment_id,
+                payment_hash,
+                ..
+            } => {
                 log_error!(
                     self.logger,
                     "EVENT: PaymentFailed: {}",
                     payment_hash.0.to_hex()
                 );
 
-                match read_payment_info(
-                    &self.persister.storage,
-                    &payment_hash.0,
-                    false,
-                    &self.logger,
-                ) {
-                    Some(mut saved_payment_info) => {
-                        saved_payment_info.status = HTLCStatus::Failed;
-                        saved_payment_info.last_update = crate::utils::now().as_secs();
-                        match persist_payment_info(
-                            &self.persister.storage,
-                            &payment_hash.0,
-                            &saved_payment_info,
-                            false,
-                        ) {
-                            Ok(_) => (),
-                            Err(e) => log_error!(
-                                self.logger,
-                                "ERROR: could not persist payment info: {e}"
-                            ),
-                        }
-                    }
-                    None => {
-                        // we failed in a payment that we didn't have saved? ...
-                        log_warn!(
-                            self.logger,
-                            "WARN: payment failed but we did not have it stored"
-                        );
-                    }
+                if self.fail_outbound_payment(&payment_hash, Some(payment_id)) {
+                    self.event_notifier.notify(MutinyEvent::PaymentFailed {
+                        payment_hash: sha256::Hash::from_inner(payment_hash.0),
+                    });
                 }
             }
             Event::PaymentForwarded { .. } => {
@@ -539,10 +702,13 @@ impl<S: MutinyStorage> EventHandler<S> {
                 let closure = ChannelClosure::new(user_channel_id, channel_id, node_id, reason);
                 if let Err(e) = self
                     .persister
-                    .persist_channel_closure(user_channel_id, closure)
+                    .persist_channel_closure(user_channel_id, closure.clone())
                 {
                     log_error!(self.logger, "Failed to persist channel closure: {e}");
                 }
+
+                self.event_notifier
+                    .notify(MutinyEvent::ChannelClosed { closure });
             }
             Event::DiscardFunding { .. } => {
                 // A "real" node should probably "lock" the UTXOs spent in funding transactions until
@@ -562,6 +728,12 @@ impl<S: MutinyStorage> EventHandler<S> {
                     user_channel_id,
                     counterparty_node_id.to_hex(),
                     channel_type);
+
+                self.event_notifier.notify(MutinyEvent::ChannelReady {
+                    channel_id: channel_id.0.to_hex(),
+                    user_channel_id: user_channel_id.to_be_bytes().to_hex(),
+                    counterparty_node_id,
+                });
             }
             Event::ChannelPending {
                 channel_id,
@@ -589,12 +761,182 @@ impl<S: MutinyStorage> EventHandler<S> {
                 self.bump_tx_event_handler.handle_event(&event);
             }
             Event::InvoiceRequestFailed { payment_id } => {
-                // we don't support bolt 12 yet
                 log_warn!(self.logger, "EVENT: InvoiceRequestFailed: {payment_id}");
+
+                // we never received an invoice for the offer, so there is no payment hash
+                self.fail_outbound_payment(&PaymentHash(payment_id.0), None);
             }
         }
     }
 
+    /// Reads the outbound payment info for a payment, returning the key it is stored under.
+    ///
+    /// Offer payments are stored under their payment id because we do not
+    /// know the payment hash until we receive the invoice, so if there is no
+    /// payment info for the hash we fall back to the payment id.
+    fn read_outbound_payment_info(
+        &self,
+        payment_hash: &PaymentHash,
+        payment_id: Option<PaymentId>,
+    ) -> Option<([u8; 32], PaymentInfo)> {
+        if let Some(info) = read_payment_info(
+            &self.persister.storage,
+            &payment_hash.0,
+            false,
+            &self.logger,
+        ) {
+            return Some((payment_hash.0, info));
+        }
+
+        let payment_id = payment_id?;
+        read_payment_info(&self.persister.storage, &payment_id.0, false, &self.logger)
+            .filter(|info| info.bolt12.is_some())
+            .map(|info| (payment_id.0, info))
+    }
+
+    /// Persists an outbound payment under its payment hash. Offer payments that
+    /// were stored under their payment id are moved to it, so they can be
+    /// looked up by payment hash like every other payment.
+    fn persist_outbound_payment_info(
+        &self,
+        key: [u8; 32],
+        payment_hash: &PaymentHash,
+        payment_info: &PaymentInfo,
+    ) -> Result<(), MutinyError> {
+        let storage = &self.persister.storage;
+        persist_payment_info(storage, &payment_hash.0, payment_info, false)?;
+        if key != payment_hash.0 {
+            persist_offer_payment_hash(storage, &key, &payment_hash.0)?;
+            delete_payment_info(storage, &key, false)?;
+        }
+
+        Ok(())
+    }
+
+    /// Marks an outbound payment as failed, returning whether it was persisted.
+    fn fail_outbound_payment(
+        &self,
+        payment_hash: &PaymentHash,
+        payment_id: Option<PaymentId>,
+    ) -> bool {
+        match self.read_outbound_payment_info(payment_hash, payment_id) {
+            Some((key, mut saved_payment_info)) => {
+                saved_payment_info.status = HTLCStatus::Failed;
+                saved_payment_info.last_update = crate::utils::now().as_secs();
+                match self.persist_outbound_payment_info(key, payment_hash, &saved_payment_info) {
+                    Ok(_) => true,
+                    Err(e) => {
+                        log_error!(self.logger, "ERROR: could not persist payment info: {e}");
+                        false
+                    }
+                }
+            }
+            None => {
+                // we failed in a payment that we didn't have saved? ...
+                log_warn!(
+                    self.logger,
+                    "WARN: payment failed but we did not have it stored"
+                );
+                false
+            }
+        }
+    }
+
+    /// Saves the custom TLV records of an inbound payment so they are
+    /// kept once the payment is claimed.
+    fn save_custom_tlvs(
+        &self,
+        payment_hash: PaymentHash,
+        receiver_node_id: Option<PublicKey>,
+        amount_msat: u64,
+        custom_tlvs: Vec<CustomTlv>,
+    ) {
+        log_debug!(
+            self.logger,
+            "EVENT: payment {} has {} custom TLV records",
+            payment_hash.0.to_hex(),
+            custom_tlvs.len()
+        );
+
+        let payment_info =
+            match read_payment_info(&self.persister.storage, &payment_hash.0, true, &self.logger) {
+                Some(mut saved_payment_info) => {
+                    saved_payment_info.custom_tlvs = custom_tlvs;
+                    saved_payment_info.last_update = crate::utils::now().as_secs();
+                    saved_payment_info
+                }
+                None => PaymentInfo {
+                    preimage: None,
+                    secret: None,
+                    status: HTLCStatus::Pending,
+                    amt_msat: MillisatAmount(Some(amount_msat)),
+                    fee_paid_msat: None,
+                    payee_pubkey: receiver_node_id,
+                    bolt11: None,
+                    bolt12: None,
+                    custom_tlvs,
+                    last_update: crate::utils::now().as_secs(),
+                },
+            };
+
+        if let Err(e) = persist_payment_info(
+            &self.persister.storage,
+            &payment_hash.0,
+            &payment_info,
+            true,
+        ) {
+            log_error!(self.logger, "ERROR: could not persist payment info: {e}");
+        }
+    }
+
+    /// Holds a payment to one of our hold invoices until it is settled or cancelled.
+    fn hold_payment(
+        &self,
+        payment_hash: PaymentHash,
+        amount_msat: u64,
+        claim_deadline: Option<u32>,
+    ) {
+        let hash = sha256::Hash::from_inner(payment_hash.0);
+        let mut hold_invoice = match read_hold_invoice(&self.persister.storage, &hash) {
+            Ok(Some(hold_invoice)) => hold_invoice,
+            _ => {
+                log_error!(self.logger, "ERROR: No payment preimage found");
+                return;
+            }
+        };
+
+        match hold_invoice.status {
+            HoldInvoiceStatus::Open | HoldInvoiceStatus::Accepted => {}
+            HoldInvoiceStatus::Settled | HoldInvoiceStatus::Cancelled => {
+                log_warn!(
+                    self.logger,
+                    "WARN: failing back payment to closed hold invoice {hash}"
+                );
+                self.channel_manager.fail_htlc_backwards(&payment_hash);
+                return;
+            }
+        }
+
+        log_info!(
+            self.logger,
+            "EVENT: holding payment of {amount_msat} millisatoshis to hold invoice {hash}"
+        );
+        hold_invoice.status = HoldInvoiceStatus::Accepted;
+        hold_invoice.amount_msat = Some(amount_msat);
+        hold_invoice.node_id = Some(self.channel_manager.get_our_node_id());
+        hold_invoice.claim_deadline = claim_deadline;
+        hold_invoice.last_update = crate::utils::now().as_secs();
+        if let Err(e) = persist_hold_invoice(&self.persister.storage, &hold_invoice) {
+            log_error!(self.logger, "ERROR: could not persist hold invoice: {e}");
+        }
+
+        self.event_notifier
+            .notify(MutinyEvent::HoldInvoiceAccepted {
+                payment_hash: hash,
+                amount_sats: amount_msat / 1_000,
+            });
+    }
+
     // Separate function to handle spendable outputs
     // This is so we can return a result and handle errors
     // without having to use a lot of nested if statements
@@ -665,8 +1007,9 @@ impl<S: MutinyStorage> EventHandler<S> {
 
 #[cfg(test)]
 mod test {
-    use crate::event::{HTLCStatus, MillisatAmount, PaymentInfo};
+    use crate::event::{EventNotifier, HTLCStatus, MillisatAmount, MutinyEvent, PaymentInfo};
     use crate::utils;
+    use bitcoin::hashes::{sha256, Hash};
     use bitcoin::secp256k1::PublicKey;
     use std::str::FromStr;
 
@@ -687,6 +1030,8 @@ mod test {
             amt_msat: MillisatAmount(Some(420)),
             fee_paid_msat: None,
             bolt11: None,
+            bolt12: None,
+            custom_tlvs: vec![],
             payee_pubkey: Some(pubkey),
             secret: None,
             last_update: utils::now().as_secs(),
@@ -700,4 +1045,24 @@ mod test {
         let deserialized: PaymentInfo = serde_json::from_value(serialized).unwrap();
         assert_eq!(payment_info, deserialized);
     }
+
+    #[test]
+    fn test_event_notifier() {
+        let notifier = EventNotifier::default();
+        let mut receiver = notifier.subscribe();
+        let dropped = notifier.subscribe();
+        drop(dropped);
+
+        let event = MutinyEvent::PaymentFailed {
+            payment_hash: sha256::Hash::hash(&[1; 32]),
+        };
+        notifier.notify(event.clone());
+
+        assert_eq!(receiver.try_next().unwrap(), Some(event));
+        // the dropped subscriber is removed
+        assert_eq!(notifier.subscribers.lock().unwrap().len(), 1);
+
+        let json = serde_json::to_value(MutinyEvent::OnchainSyncCompleted).unwrap();
+        assert_eq!(json["type"], "OnchainSyncCompleted");
+    }
 }
@@ -1,13 +1,14 @@
 use crate::{
     error::{MutinyError, MutinyStorageError},
-    event::PaymentInfo,
+    event::{EventNotifier, MutinyEvent, PaymentInfo},
     key::{create_root_child_key, ChildKey},
     logging::MutinyLogger,
+    node::InvoiceOptions,
     onchain::coin_type_from_network,
     storage::{
         get_payment_info, list_payment_info, persist_payment_info, MutinyStorage, VersionedValue,
     },
-    utils::sleep,
+    utils::{self, sleep},
     HTLCStatus, MutinyInvoice, DEFAULT_PAYMENT_TIMEOUT,
 };
 use async_trait::async_trait;
@@ -17,7 +18,7 @@ use bitcoin::{
     secp256k1::Secp256k1,
     util::bip32::ExtendedPrivKey,
     util::bip32::{ChildNumber, DerivationPath},
-    Network,
+    Address, Network, Txid,
 };
 use core::fmt;
 use fedimint_bip39::Bip39RootSecretStrategy;
@@ -51,7 +52,7 @@ use fedimint_ln_client::{
 };
 use fedimint_ln_common::LightningCommonInit;
 use fedimint_mint_client::MintClientInit;
-use fedimint_wallet_client::{WalletClientInit, WalletClientModule};
+use fedimint_wallet_client::{DepositState, WalletClientInit, WalletClientModule, WithdrawState};
 use futures::future::{self};
 use futures_util::{pin_mut, StreamExt};
 use hex::FromHex;
@@ -61,7 +62,7 @@ use lightning::{
 use lightning_invoice::Bolt11Invoice;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::sync::atomic::{AtomicU32, Ordering};
-use std::{collections::HashMap, fmt::Debug, sync::Arc};
+use std::{collections::HashMap, fmt::Debug, sync::Arc, time::Duration};
 
 // The amount of time in milliseconds to wait for
 // checking the status of a fedimint payment. This
@@ -76,6 +77,11 @@ const FEDIMINT_OPERATIONS_LIST_MAX: usize = 100;
 
 pub const FEDIMINTS_PREFIX_KEY: &str = "fedimints/";
 
+pub(crate) const FEDIMINT_ONCHAIN_PREFIX_KEY: &str = "fedimint_onchain/";
+
+// How long the federation watches a deposit address for a transaction.
+const DEPOSIT_ADDRESS_EXPIRY_SECS: u64 = 60 * 60 * 24 * 7;
+
 impl From<LnReceiveState> for HTLCStatus {
     fn from(state: LnReceiveState) -> Self {
         match state {
@@ -117,6 +123,28 @@ impl From<LnPayState> for HTLCStatus {
     }
 }
 
+impl From<DepositState> for HTLCStatus {
+    fn from(state: DepositState) -> Self {
+        match state {
+            DepositState::WaitingForTransaction => HTLCStatus::Pending,
+            DepositState::WaitingForConfirmation(_) => HTLCStatus::InFlight,
+            DepositState::Confirmed(_) => HTLCStatus::InFlight,
+            DepositState::Claimed(_) => HTLCStatus::Succeeded,
+            DepositState::Failed(_) => HTLCStatus::Failed,
+        }
+    }
+}
+
+impl From<WithdrawState> for HTLCStatus {
+    fn from(state: WithdrawState) -> Self {
+        match state {
+            WithdrawState::Created => HTLCStatus::InFlight,
+            WithdrawState::Succeeded(_) => HTLCStatus::Succeeded,
+            WithdrawState::Failed(_) => HTLCStatus::Failed,
+        }
+    }
+}
+
 // This is the FederationStorage object saved to the DB
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct FederationStorage {
@@ -142,6 +170,60 @@ pub struct FederationIndex {
     pub federation_code: InviteCode,
 }
 
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FederationOnchainKind {
+    /// Bitcoin sent on-chain to the federation in exchange for ecash (peg-in)
+    Deposit,
+    /// Ecash redeemed for bitcoin sent to an on-chain address (peg-out)
+    Withdrawal,
+}
+
+/// An on-chain deposit into or withdrawal from a federation.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct FederationOnchainTx {
+    /// The fedimint operation id, hex encoded
+    pub operation_id: String,
+    pub federation_id: FederationId,
+    pub kind: FederationOnchainKind,
+    /// The deposit address, or the address we are withdrawing to
+    pub address: Address,
+    pub amount_sats: Option<u64>,
+    pub fee_sats: Option<u64>,
+    /// The on-chain transaction, once known
+    pub txid: Option<Txid>,
+    pub status: HTLCStatus,
+    pub labels: Vec<String>,
+    pub last_updated: u64,
+}
+
+impl FederationOnchainTx {
+    fn key(&self) -> String {
+        format!("{FEDIMINT_ONCHAIN_PREFIX_KEY}{}", self.operation_id)
+    }
+
+    fn operation_id(&self) -> Result<OperationId, MutinyError> {
+        let bytes = <[u8; 32]>::from_hex(&self.operation_id)
+            .map_err(|_| MutinyError::InvalidArgumentsError)?;
+        Ok(OperationId(bytes))
+    }
+}
+
+pub(crate) fn persist_federation_onchain_tx<S: MutinyStorage>(
+    storage: &S,
+    tx: &FederationOnchainTx,
+) -> Result<(), MutinyError> {
+    storage.set_data(tx.key(), tx, None)
+}
+
+pub(crate) fn list_federation_onchain_txs<S: MutinyStorage>(
+    storage: &S,
+) -> Result<Vec<FederationOnchainTx>, MutinyError> {
+    Ok(storage
+        .scan::<FederationOnchainTx>(FEDIMINT_ONCHAIN_PREFIX_KEY, None)?
+        .into_values()
+        .collect())
+}
+
 pub struct FedimintBalance {
     pub amount: u64,
 }
@@ -150,6 +232,7 @@ pub(crate) struct FederationClient<S: MutinyStorage> {
     pub(crate) uuid: String,
     pub(crate) fedimint_client: ClientArc,
     storage: S,
+    event_notifier: EventNotifier,
     pub(crate) logger: Arc<MutinyLogger>,
 }
 
@@ -161,6 +244,7 @@ impl<S: MutinyStorage> FederationClient<S> {
         xprivkey: ExtendedPrivKey,
         storage: &S,
         network: Network,
+        event_notifier: EventNotifier,
         logger: Arc<MutinyLogger>,
     ) -> Result<Self, MutinyError> {
         log_info!(logger, "initializing a new federation client: {uuid}");
@@ -227,14 +311,18 @@ impl<S: MutinyStorage> FederationClient<S> {
             uuid,
             fedimint_client,
             storage: storage.clone(),
+            event_notifier,
             logger,
         })
     }
 
+    /// Creates an invoice paying into this federation. Federation invoices can't
+    /// commit to a description hash, only the description and expiry are used.
     pub(crate) async fn get_invoice(
         &self,
         amount: u64,
         labels: Vec<String>,
+        options: InvoiceOptions,
     ) -> Result<MutinyInvoice, MutinyError> {
         let inbound = true;
 
@@ -242,7 +330,12 @@ impl<S: MutinyStorage> FederationClient<S> {
             .fedimint_client
             .get_first_module::<LightningClientModule>();
         let (_id, invoice) = lightning_module
-            .create_bolt11_invoice(Amount::from_sats(amount), String::new(), None, ())
+            .create_bolt11_invoice(
+                Amount::from_sats(amount),
+                options.description.unwrap_or_default(),
+                Some(options.expiry_secs().into()),
+                (),
+            )
             .await?;
 
         // persist the invoice
@@ -259,6 +352,168 @@ impl<S: MutinyStorage> FederationClient<S> {
         Ok(invoice.into())
     }
 
+    /// Creates an address that bitcoin can be sent to, once confirmed it is
+    /// claimed as ecash in this federation.
+    pub(crate) async fn get_deposit_address(
+        &self,
+        labels: Vec<String>,
+    ) -> Result<FederationOnchainTx, MutinyError> {
+        let wallet_module = self
+            .fedimint_client
+            .get_first_module::<WalletClientModule>();
+        let valid_until =
+            fedimint_core::time::now() + Duration::from_secs(DEPOSIT_ADDRESS_EXPIRY_SECS);
+        let (operation_id, address) = wallet_module.get_deposit_address(valid_until, ()).await?;
+
+        let tx = FederationOnchainTx {
+            operation_id: operation_id.0.to_hex(),
+            federation_id: self.fedimint_client.federation_id(),
+            kind: FederationOnchainKind::Deposit,
+            address,
+            amount_sats: None,
+            fee_sats: None,
+            txid: None,
+            status: HTLCStatus::Pending,
+            labels,
+            last_updated: utils::now().as_secs(),
+        };
+        persist_federation_onchain_tx(&self.storage, &tx)?;
+
+        Ok(tx)
+    }
+
+    /// Gets the fee the federation will charge to withdraw the amount to the given address.
+    pub(crate) async fn estimate_withdrawal_fee(
+        &self,
+        address: Address,
+        amount: u64,
+    ) -> Result<u64, MutinyError> {
+        let wallet_module = self
+            .fedimint_client
+            .get_first_module::<WalletClientModule>();
+        let fees = wallet_module
+            .get_withdraw_fees(address, bitcoin::Amount::from_sat(amount))
+            .await?;
+
+        Ok(fees.amount().to_sat())
+    }
+
+    /// Redeems ecash from this federation for an on-chain payment to the given address.
+    pub(crate) async fn withdraw(
+        &self,
+        address: Address,
+        amount: u64,
+        labels: Vec<String>,
+    ) -> Result<FederationOnchainTx, MutinyError> {
+        let wallet_module = self
+            .fedimint_client
+            .get_first_module::<WalletClientModule>();
+        let amount = bitcoin::Amount::from_sat(amount);
+        let fees = wallet_module
+            .get_withdraw_fees(address.clone(), amount)
+            .await?;
+        let fee_sats = fees.amount().to_sat();
+
+        let balance = self.get_balance().await?;
+        if balance < amount.to_sat() + fee_sats {
+            return Err(MutinyError::InsufficientBalance);
+        }
+
+        let operation_id = wallet_module
+            .withdraw(address.clone(), amount, fees, ())
+            .await?;
+
+        let tx = FederationOnchainTx {
+            operation_id: operation_id.0.to_hex(),
+            federation_id: self.fedimint_client.federation_id(),
+            kind: FederationOnchainKind::Withdrawal,
+            address,
+            amount_sats: Some(amount.to_sat()),
+            fee_sats: Some(fee_sats),
+            txid: None,
+            status: HTLCStatus::InFlight,
+            labels,
+            last_updated: utils::now().as_secs(),
+        };
+        persist_federation_onchain_tx(&self.storage, &tx)?;
+
+        Ok(tx)
+    }
+
+    /// Checks for updates on this federation's pending deposits and withdrawals.
+    async fn check_onchain_activity(&self) -> Result<(), MutinyError> {
+        let federation_id = self.fedimint_client.federation_id();
+        let pending = list_federation_onchain_txs(&self.storage)?
+            .into_iter()
+            .filter(|tx| {
+                tx.federation_id == federation_id
+                    && matches!(tx.status, HTLCStatus::Pending | HTLCStatus::InFlight)
+            });
+
+        let wallet_module = self
+            .fedimint_client
+            .get_first_module::<WalletClientModule>();
+        for tx in pending {
+            let operation_id = tx.operation_id()?;
+            let mut updated = tx.clone();
+            match tx.kind {
+                FederationOnchainKind::Deposit => {
+                    let updates = wallet_module
+                        .subscribe_deposit_updates(operation_id)
+                        .await?;
+                    let Some(state) =
+                        latest_update(updates, FEDIMINT_STATUS_TIMEOUT_CHECK_MS).await
+                    else {
+                        continue;
+                    };
+                    match &state {
+                        DepositState::WaitingForConfirmation(data)
+                        | DepositState::Confirmed(data)
+                        | DepositState::Claimed(data) => {
+                            updated.amount_sats = Some(data.btc_deposited.to_sat());
+                            updated.txid = Some(data.btc_out_point.txid);
+                        }
+                        DepositState::Failed(e) => {
+                            log_warn!(self.logger, "Federation deposit failed: {e}");
+                        }
+                        DepositState::WaitingForTransaction => {}
+                    }
+                    updated.status = state.into();
+                }
+                FederationOnchainKind::Withdrawal => {
+                    let updates = wallet_module
+                        .subscribe_withdraw_updates(operation_id)
+                        .await?;
+                    let Some(state) =
+                        latest_update(updates, FEDIMINT_STATUS_TIMEOUT_CHECK_MS).await
+                    else {
+                        continue;
+                    };
+                    match &state {
+                        WithdrawState::Succeeded(txid) => updated.txid = Some(*txid),
+                        WithdrawState::Failed(e) => {
+                            log_warn!(self.logger, "Federation withdrawal failed: {e}");
+                        }
+                        WithdrawState::Created => {}
+                    }
+                    updated.status = state.into();
+                }
+            }
+
+            if updated != tx {
+                updated.last_updated = utils::now().as_secs();
+                persist_federation_onchain_tx(&self.storage, &updated)?;
+                if matches!(updated.status, HTLCStatus::Succeeded | HTLCStatus::Failed) {
+                    self.event_notifier.notify(MutinyEvent::FederationOnchain {
+                        transaction: updated,
+                    });
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     /// Get the balance of this federation client in sats
     pub(crate) async fn get_balance(&self) -> Result<u64, MutinyError> {
         Ok(self.fedimint_client.get_balance().await.msats / 1_000)
@@ -267,6 +522,10 @@ impl<S: MutinyStorage> FederationClient<S> {
     pub async fn check_activity(&self) -> Result<(), MutinyError> {
         log_trace!(self.logger, "Getting activity");
 
+        if let Err(e) = self.check_onchain_activity().await {
+            log_warn!(self.logger, "Failed to check on-chain activity: {e}");
+        }
+
         let mut pending_invoices = Vec::new();
         // inbound
         pending_invoices.extend(
@@ -370,8 +629,16 @@ impl<S: MutinyStorage> FederationClient<S> {
             log_debug!(self.logger, "Saving updated payment");
             let hash = *updated_invoice.payment_hash.as_inner();
             let inbound = updated_invoice.inbound;
+            let event = MutinyEvent::FederationPayment {
+                federation_id: self.fedimint_client.federation_id(),
+                payment_hash: updated_invoice.payment_hash,
+                inbound,
+                status: updated_invoice.status.clone(),
+                amount_sats: updated_invoice.amount_sats,
+            };
             let payment_info = PaymentInfo::from(updated_invoice);
             persist_payment_info(&self.storage, &hash, &payment_info, inbound)?;
+            self.event_notifier.notify(event);
         }
         Ok(())
     }
@@ -516,6 +783,28 @@ impl<S: MutinyStorage> FederationClient<S> {
         }
     }
 
+    /// Estimates the fee in sats the federation's gateway will charge to pay the invoice.
+    /// Payments to other users of the federation don't go through a gateway so may be free.
+    pub(crate) async fn estimate_invoice_fee(
+        &self,
+        invoice: &Bolt11Invoice,
+    ) -> Result<u64, MutinyError> {
+        let amount_msat = invoice
+            .amount_milli_satoshis()
+            .ok_or(MutinyError::InvoiceInvalid)?;
+
+        let lightning_module = self
+            .fedimint_client
+            .get_first_module::<LightningClientModule>();
+        let gateway = lightning_module.select_active_gateway().await?;
+
+        let fee_msat = gateway.fees.base_msat as u64
+            + (amount_msat * gateway.fees.proportional_millionths as u64) / 1_000_000;
+
+        // round up so we never under estimate
+        Ok((fee_msat + 999) / 1_000)
+    }
+
     pub fn get_mutiny_federation_identity(&self) -> FederationIdentity {
         FederationIdentity {
             uuid: self.uuid.clone(),
@@ -636,6 +925,29 @@ fn process_receive_state(receive_state: LnReceiveState, invoice: &mut MutinyInvo
     invoice.status = receive_state.into();
 }
 
+// Gets the final state of an operation, or the latest update
+// we get before the timeout.
+async fn latest_update<U>(stream_or_outcome: UpdateStreamOrOutcome<U>, timeout: u64) -> Option<U>
+where
+    U: Clone + Serialize + DeserializeOwned + Debug + MaybeSend + MaybeSync + 'static,
+{
+    match stream_or_outcome {
+        UpdateStreamOrOutcome::Outcome(outcome) => Some(outcome),
+        UpdateStreamOrOutcome::UpdateStream(mut s) => {
+            let timeout_future = sleep(timeout as i32);
+            pin_mut!(timeout_future);
+
+            let mut latest = None;
+            while let future::Either::Left((Some(update), _)) =
+                future::select(s.next(), &mut timeout_future).await
+            {
+                latest = Some(update);
+            }
+            latest
+        }
+    }
+}
+
 async fn process_outcome<U, F>(
     stream_or_outcome: UpdateStreamOrOutcome<U>,
     process_fn: F,
@@ -897,6 +1209,41 @@ fn fedimint_seed_generation() {
     );
 }
 
+#[cfg(test)]
+fn federation_onchain_tx_storage() {
+    use crate::storage::MemoryStorage;
+    use std::str::FromStr;
+
+    let storage = MemoryStorage::default();
+    assert!(list_federation_onchain_txs(&storage).unwrap().is_empty());
+
+    let federation_id =
+        FederationId::from_str("c8d423964c7ad944d30f57359b6e5b260e211dcfdb945140e28d4df51fd572d2")
+            .unwrap();
+    let mut tx = FederationOnchainTx {
+        operation_id: [1; 32].to_hex(),
+        federation_id,
+        kind: FederationOnchainKind::Deposit,
+        address: Address::from_str("bcrt1qzvh9u7c43wz0wd0ym3a3xmn3dgqtgxhqwzmt0v").unwrap(),
+        amount_sats: None,
+        fee_sats: None,
+        txid: None,
+        status: HTLCStatus::Pending,
+        labels: vec!["test".to_string()],
+        last_updated: 1,
+    };
+    persist_federation_onchain_tx(&storage, &tx).unwrap();
+    assert_eq!(tx.operation_id().unwrap(), OperationId([1; 32]));
+
+    // updating the same operation should replace it
+    tx.status = HTLCStatus::Succeeded;
+    tx.amount_sats = Some(10_000);
+    persist_federation_onchain_tx(&storage, &tx).unwrap();
+
+    let txs = list_federation_onchain_txs(&storage).unwrap();
+    assert_eq!(txs, vec![tx]);
+}
+
 #[cfg(test)]
 fn fedimint_mnemonic_generation() {
     use super::*;
@@ -944,6 +1291,11 @@ mod tests {
     fn test_fedimint_mnemonic_generation() {
         fedimint_mnemonic_generation();
     }
+
+    #[test]
+    fn test_federation_onchain_tx_storage() {
+        federation_onchain_tx_storage();
+    }
 }
 
 #[cfg(test)]
@@ -964,4 +1316,9 @@ mod wasm_tests {
     fn test_fedimint_mnemonic_generation() {
         fedimint_mnemonic_generation();
     }
+
+    #[test]
+    fn test_federation_onchain_tx_storage() {
+        federation_onchain_tx_storage();
+    }
 }
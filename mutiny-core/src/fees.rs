@@ -1,17 +1,15 @@
+use crate::chainsource::ChainSource;
 use crate::logging::MutinyLogger;
 use crate::storage::MutinyStorage;
 use crate::{error::MutinyError, utils};
 use bdk::FeeRate;
 use bitcoin::Network;
-use esplora_client::AsyncClient;
 use futures::lock::Mutex;
 use lightning::chain::chaininterface::{
     ConfirmationTarget, FeeEstimator, FEERATE_FLOOR_SATS_PER_KW,
 };
 use lightning::log_trace;
 use lightning::util::logger::Logger;
-use serde::Deserialize;
-use std::collections::HashMap;
 use std::sync::Arc;
 
 // Constants for overhead, input, and output sizes
@@ -26,7 +24,7 @@ pub(crate) const TAPROOT_OUTPUT_SIZE: usize = 43;
 pub struct MutinyFeeEstimator<S: MutinyStorage> {
     storage: S,
     network: Network,
-    esplora: Arc<AsyncClient>,
+    chain_source: Arc<dyn ChainSource>,
     logger: Arc<MutinyLogger>,
     last_fee_update_time_secs: Arc<Mutex<Option<u64>>>,
 }
@@ -35,13 +33,13 @@ impl<S: MutinyStorage> MutinyFeeEstimator<S> {
     pub fn new(
         storage: S,
         network: Network,
-        esplora: Arc<AsyncClient>,
+        chain_source: Arc<dyn ChainSource>,
         logger: Arc<MutinyLogger>,
     ) -> MutinyFeeEstimator<S> {
         MutinyFeeEstimator {
             storage,
             network,
-            esplora,
+            chain_source,
             logger,
             last_fee_update_time_secs: Arc::new(Mutex::new(None)),
         }
@@ -76,40 +74,6 @@ impl<S: MutinyStorage> MutinyFeeEstimator<S> {
         let lock = self.last_fee_update_time_secs.lock().await;
         *lock
     }
-}
-
-#[derive(Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-struct MempoolFees {
-    fastest_fee: f64,
-    half_hour_fee: f64,
-    hour_fee: f64,
-    economy_fee: f64,
-    minimum_fee: f64,
-}
-
-impl<S: MutinyStorage> MutinyFeeEstimator<S> {
-    async fn get_mempool_recommended_fees(&self) -> anyhow::Result<HashMap<String, f64>> {
-        let client = self.esplora.client();
-        let request = client
-            .get(format!("{}/v1/fees/recommended", self.esplora.url()))
-            .build()?;
-
-        let fees_response = utils::fetch_with_timeout(client, request)
-            .await?
-            .error_for_status()?;
-        let fees = fees_response.json::<MempoolFees>().await?;
-
-        // convert to hashmap of num blocks -> fee rate
-        let mut fee_estimates = HashMap::new();
-        fee_estimates.insert("1".to_string(), fees.fastest_fee);
-        fee_estimates.insert("3".to_string(), fees.half_hour_fee);
-        fee_estimates.insert("6".to_string(), fees.hour_fee);
-        fee_estimates.insert("12".to_string(), fees.economy_fee);
-        fee_estimates.insert("1008".to_string(), fees.minimum_fee);
-
-        Ok(fee_estimates)
-    }
 
     pub async fn update_fee_estimates_if_necessary(&self) -> Result<(), MutinyError> {
         let last_sync = self.get_last_sync_time().await;
@@ -120,26 +84,10 @@ impl<S: MutinyStorage> MutinyFeeEstimator<S> {
     }
 
     async fn update_fee_estimates(&self) -> Result<(), MutinyError> {
-        // first try mempool.space's API
-        let mempool_fees = self.get_mempool_recommended_fees().await;
-
-        // if that fails, fall back to esplora's API
-        let fee_estimates = match mempool_fees {
-            Ok(mempool_fees) => {
-                log_trace!(self.logger, "Retrieved fees from mempool");
-                mempool_fees
-            }
-            Err(e) => {
-                log_trace!(
-                    self.logger,
-                    "Failed to retrieve fees from mempool, falling back to esplora: {e}"
-                );
-                self.esplora.get_fee_estimates().await.map_err(|e| {
-                    log_trace!(self.logger, "Failed to get esplora fee: {e}");
-                    e
-                })?
-            }
-        };
+        let fee_estimates = self.chain_source.get_fee_estimates().await.map_err(|e| {
+            log_trace!(self.logger, "Failed to get fee estimates: {e}");
+            e
+        })?;
 
         self.storage.insert_fee_estimates(fee_estimates)?;
         let mut update_time_lock = self.last_fee_update_time_secs.lock().await;
@@ -232,25 +180,22 @@ fn fallback_fee_from_conf_target(confirmation_target: ConfirmationTarget) -> u32
 mod test {
     use super::*;
     #[cfg(not(target_arch = "wasm32"))]
+    use crate::chainsource::EsploraChainSource;
+    #[cfg(not(target_arch = "wasm32"))]
     use crate::storage::{MemoryStorage, MutinyStorage};
     #[cfg(not(target_arch = "wasm32"))]
     use crate::test_utils::*;
     #[cfg(not(target_arch = "wasm32"))]
-    use esplora_client::Builder;
-    #[cfg(not(target_arch = "wasm32"))]
     use std::collections::HashMap;
 
     #[cfg(not(target_arch = "wasm32"))]
     async fn create_fee_estimator() -> MutinyFeeEstimator<MemoryStorage> {
         let storage = MemoryStorage::default();
-        let esplora = Arc::new(
-            Builder::new("https://mutinynet.com/api")
-                .build_async()
-                .unwrap(),
-        );
         let logger = Arc::new(MutinyLogger::default());
+        let chain_source =
+            Arc::new(EsploraChainSource::new("https://mutinynet.com/api", logger.clone()).unwrap());
 
-        MutinyFeeEstimator::new(storage, Network::Bitcoin, esplora, logger)
+        MutinyFeeEstimator::new(storage, Network::Bitcoin, chain_source, logger)
     }
 
     #[test]
@@ -0,0 +1,149 @@
+use crate::error::MutinyError;
+use crate::storage::MutinyStorage;
+use bitcoin::hashes::hex::ToHex;
+use bitcoin::hashes::sha256;
+use bitcoin::secp256k1::PublicKey;
+use lightning_invoice::Bolt11Invoice;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+const HOLD_INVOICE_PREFIX_KEY: &str = "hold_invoice/";
+
+/// Hold invoices get a longer final CLTV delta than normal invoices
+/// so the payment can be held for about a day.
+pub(crate) const HOLD_INVOICE_MIN_FINAL_CLTV_EXPIRY_DELTA: u16 = 144;
+
+/// How many blocks before the HTLCs' claim deadline we cancel a held payment
+/// so it is failed back before our channel counterparty has to force close.
+pub(crate) const HOLD_INVOICE_CANCEL_BUFFER_BLOCKS: u32 = 6;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HoldInvoiceStatus {
+    /// Waiting for a payment
+    Open,
+    /// A payment is being held until the invoice is settled or cancelled
+    Accepted,
+    /// The payment was claimed with the preimage
+    Settled,
+    /// The payment was failed back, or will be if one arrives
+    Cancelled,
+}
+
+/// An invoice created from a payment hash given by the caller.
+/// Payments to it are held until they are settled with the preimage or cancelled.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct HoldInvoice {
+    pub payment_hash: sha256::Hash,
+    pub bolt11: Bolt11Invoice,
+    pub status: HoldInvoiceStatus,
+    /// The amount being held, once a payment arrives
+    pub amount_msat: Option<u64>,
+    /// Our node that is holding the payment
+    pub node_id: Option<PublicKey>,
+    /// Block height the payment has to be settled by
+    pub claim_deadline: Option<u32>,
+    pub last_update: u64,
+}
+
+impl HoldInvoice {
+    pub(crate) fn new(bolt11: Bolt11Invoice, last_update: u64) -> Self {
+        Self {
+            payment_hash: *bolt11.payment_hash(),
+            bolt11,
+            status: HoldInvoiceStatus::Open,
+            amount_msat: None,
+            node_id: None,
+            claim_deadline: None,
+            last_update,
+        }
+    }
+
+    /// If a held payment needs to be cancelled at this block height to not
+    /// risk a force close.
+    pub(crate) fn should_cancel(&self, height: u32) -> bool {
+        self.status == HoldInvoiceStatus::Accepted
+            && self
+                .claim_deadline
+                .is_some_and(|deadline| height + HOLD_INVOICE_CANCEL_BUFFER_BLOCKS >= deadline)
+    }
+}
+
+fn hold_invoice_key(payment_hash: &sha256::Hash) -> String {
+    format!("{HOLD_INVOICE_PREFIX_KEY}{}", payment_hash.to_hex())
+}
+
+pub(crate) fn persist_hold_invoice<S: MutinyStorage>(
+    storage: &S,
+    hold_invoice: &HoldInvoice,
+) -> Result<(), MutinyError> {
+    storage.set_data(
+        hold_invoice_key(&hold_invoice.payment_hash),
+        hold_invoice,
+        None,
+    )
+}
+
+pub(crate) fn read_hold_invoice<S: MutinyStorage>(
+    storage: &S,
+    payment_hash: &sha256::Hash,
+) -> Result<Option<HoldInvoice>, MutinyError> {
+    storage.get_data(hold_invoice_key(payment_hash))
+}
+
+pub(crate) fn list_hold_invoices<S: MutinyStorage>(
+    storage: &S,
+) -> Result<Vec<HoldInvoice>, MutinyError> {
+    let map: HashMap<String, HoldInvoice> = storage.scan(HOLD_INVOICE_PREFIX_KEY, None)?;
+    Ok(map.into_values().collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use crate::test_utils::*;
+    use std::str::FromStr;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    const INVOICE: &str = "lnbc923720n1pj9nrefpp5pczykgk37af5388n8dzynljpkzs7sje4melqgazlwv9y3apay8jqhp5rd8saxz3juve3eejq7z5fjttxmpaq88d7l92xv34n4h3mq6kwq2qcqzzsxqzfvsp5z0jwpehkuz9f2kv96h62p8x30nku76aj8yddpcust7g8ad0tr52q9qyyssqfy622q25helv8cj8hyxqltws4rdwz0xx2hw0uh575mn7a76cp3q4jcptmtjkjs4a34dqqxn8uy70d0qlxqleezv4zp84uk30pp5q3nqq4c9gkz";
+
+    #[test]
+    fn test_hold_invoice_should_cancel() {
+        let test_name = "test_hold_invoice_should_cancel";
+        log!("{}", test_name);
+
+        let mut hold_invoice = HoldInvoice::new(Bolt11Invoice::from_str(INVOICE).unwrap(), 0);
+        assert!(!hold_invoice.should_cancel(u32::MAX / 2));
+
+        hold_invoice.status = HoldInvoiceStatus::Accepted;
+        hold_invoice.claim_deadline = Some(1_000);
+        assert!(!hold_invoice.should_cancel(1_000 - HOLD_INVOICE_CANCEL_BUFFER_BLOCKS - 1));
+        assert!(hold_invoice.should_cancel(1_000 - HOLD_INVOICE_CANCEL_BUFFER_BLOCKS));
+
+        hold_invoice.status = HoldInvoiceStatus::Settled;
+        assert!(!hold_invoice.should_cancel(1_000));
+    }
+
+    #[test]
+    fn test_hold_invoice_storage() {
+        let test_name = "test_hold_invoice_storage";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::default();
+        let hold_invoice = HoldInvoice::new(Bolt11Invoice::from_str(INVOICE).unwrap(), 0);
+        assert_eq!(
+            read_hold_invoice(&storage, &hold_invoice.payment_hash).unwrap(),
+            None
+        );
+
+        persist_hold_invoice(&storage, &hold_invoice).unwrap();
+        assert_eq!(
+            read_hold_invoice(&storage, &hold_invoice.payment_hash).unwrap(),
+            Some(hold_invoice.clone())
+        );
+        assert_eq!(list_hold_invoices(&storage).unwrap(), vec![hold_invoice]);
+    }
+}
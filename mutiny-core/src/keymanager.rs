@@ -265,6 +265,7 @@ mod tests {
     };
 
     use super::create_keys_manager;
+    use crate::chainsource::{ChainSource, EsploraChainSource};
     use crate::fees::MutinyFeeEstimator;
     use crate::logging::MutinyLogger;
     use crate::onchain::OnChainWallet;
@@ -272,7 +273,6 @@ mod tests {
     use bip39::Mnemonic;
     use bitcoin::util::bip32::ExtendedPrivKey;
     use bitcoin::Network;
-    use esplora_client::Builder;
     use std::str::FromStr;
     use std::sync::atomic::AtomicBool;
     use std::sync::Arc;
@@ -283,27 +283,27 @@ mod tests {
         log!("{}", test_name);
 
         let mnemonic = Mnemonic::from_str("abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about").expect("could not generate");
-        let esplora = Arc::new(
-            Builder::new("https://blockstream.info/testnet/api/")
-                .build_async()
-                .unwrap(),
-        );
         let network = Network::Testnet;
         let pass = uuid::Uuid::new_v4().to_string();
         let cipher = encryption_key_from_pass(&pass).unwrap();
         let db = MemoryStorage::new(Some(pass), Some(cipher), None);
         let logger = Arc::new(MutinyLogger::default());
+        let chain_source: Arc<dyn ChainSource> = Arc::new(
+            EsploraChainSource::new("https://blockstream.info/testnet/api/", logger.clone())
+                .unwrap(),
+        );
         let fees = Arc::new(MutinyFeeEstimator::new(
             db.clone(),
             network,
-            esplora.clone(),
+            chain_source.clone(),
             logger.clone(),
         ));
         let stop = Arc::new(AtomicBool::new(false));
         let xpriv = ExtendedPrivKey::new_master(network, &mnemonic.to_seed("")).unwrap();
 
         let wallet = Arc::new(
-            OnChainWallet::new(xpriv, db, network, esplora, fees, stop, logger.clone()).unwrap(),
+            OnChainWallet::new(xpriv, db, network, chain_source, fees, stop, logger.clone())
+                .unwrap(),
         );
 
         let km = create_keys_manager(wallet.clone(), xpriv, 1, logger.clone()).unwrap();
@@ -1,3 +1,4 @@
+use crate::chainsource::ChainSource;
 use crate::error::{MutinyError, MutinyStorageError};
 use crate::fees::MutinyFeeEstimator;
 use crate::gossip::PROB_SCORER_KEY;
@@ -6,16 +7,17 @@ use crate::logging::MutinyLogger;
 use crate::node::{default_user_config, ChainMonitor};
 use crate::node::{NetworkGraph, Router};
 use crate::nodemanager::ChannelClosure;
+use crate::scb::ChannelBackup;
 use crate::storage::{MutinyStorage, VersionedValue};
 use crate::utils;
 use crate::utils::{sleep, spawn};
 use crate::{chain::MutinyChain, scorer::HubPreferentialScorer};
 use anyhow::anyhow;
 use bitcoin::hashes::hex::{FromHex, ToHex};
+use bitcoin::psbt::PartiallySignedTransaction;
+use bitcoin::secp256k1::PublicKey;
 use bitcoin::Network;
 use bitcoin::{BlockHash, Transaction};
-use esplora_client::AsyncClient;
-use futures::{try_join, TryFutureExt};
 use futures_util::lock::Mutex;
 use lightning::chain::chainmonitor::{MonitorUpdateId, Persist};
 use lightning::chain::channelmonitor::{ChannelMonitor, ChannelMonitorUpdate};
@@ -41,6 +43,7 @@ pub const MONITORS_PREFIX_KEY: &str = "monitors/";
 const CHANNEL_OPENING_PARAMS_PREFIX: &str = "chan_open_params/";
 const CHANNEL_CLOSURE_PREFIX: &str = "channel_closure/";
 const FAILED_SPENDABLE_OUTPUT_DESCRIPTOR_KEY: &str = "failed_spendable_outputs";
+const CHANNEL_RECOVERY_KEY: &str = "channel_recovery";
 
 pub(crate) type PhantomChannelManager<S: MutinyStorage> = LdkChannelManager<
     Arc<ChainMonitor<S>>,
@@ -201,7 +204,7 @@ impl<S: MutinyStorage> MutinyNodePersister<S> {
         keys_manager: Arc<PhantomKeysManager<S>>,
         router: Arc<Router>,
         channel_monitors: Vec<(BlockHash, ChannelMonitor<InMemorySigner>)>,
-        esplora: &AsyncClient,
+        chain_source: &dyn ChainSource,
     ) -> Result<ReadChannelManager<S>, MutinyError> {
         log_debug!(mutiny_logger, "Reading channel manager from storage");
         let key = self.get_key(CHANNEL_MANAGER_KEY);
@@ -240,7 +243,7 @@ impl<S: MutinyStorage> MutinyNodePersister<S> {
                     keys_manager,
                     router,
                     channel_monitors,
-                    esplora,
+                    chain_source,
                 )
                 .await
             }
@@ -316,21 +319,18 @@ impl<S: MutinyStorage> MutinyNodePersister<S> {
         keys_manager: Arc<PhantomKeysManager<S>>,
         router: Arc<Router>,
         channel_monitors: Vec<(BlockHash, ChannelMonitor<InMemorySigner>)>,
-        esplora: &AsyncClient,
+        chain_source: &dyn ChainSource,
     ) -> Result<ReadChannelManager<S>, MutinyError> {
         // if regtest, we don't need to get the tip hash and can
         // just use genesis, this also lets us use regtest in tests
         let best_block = if network == Network::Regtest {
             BestBlock::from_network(network)
         } else {
-            let height_future = esplora
-                .get_height()
-                .map_err(|_| MutinyError::ChainAccessFailed);
-            let hash_future = esplora
-                .get_tip_hash()
-                .map_err(|_| MutinyError::ChainAccessFailed);
-            let (height, hash) = try_join!(height_future, hash_future)?;
-            BestBlock::new(hash, height)
+            let (height, header) = chain_source
+                .get_tip()
+                .await
+                .map_err(|_| MutinyError::ChainAccessFailed)?;
+            BestBlock::new(header.block_hash(), height)
         };
         let chain_params = ChainParameters {
             network,
@@ -482,6 +482,35 @@ impl<S: MutinyStorage> MutinyNodePersister<S> {
         Ok(())
     }
 
+    /// Sets the channels that are being recovered from a static channel backup.
+    /// Channels are removed once their funds have been swept.
+    pub(crate) fn set_channel_recovery(
+        &self,
+        channels: Vec<ChannelBackup>,
+    ) -> Result<(), MutinyError> {
+        let key = self.get_key(CHANNEL_RECOVERY_KEY);
+        if channels.is_empty() {
+            return self.storage.delete(&[key]);
+        }
+
+        let channels_hex: Vec<String> = channels.iter().map(|c| c.encode().to_hex()).collect();
+        self.storage.set_data(key, channels_hex, None)
+    }
+
+    /// Retrieves the channels that are being recovered from a static channel backup
+    pub(crate) fn get_channel_recovery(&self) -> Result<Vec<ChannelBackup>, MutinyError> {
+        let key = self.get_key(CHANNEL_RECOVERY_KEY);
+        let strings: Vec<String> = self.storage.get_data(key)?.unwrap_or_default();
+
+        strings
+            .into_iter()
+            .map(|hex| {
+                let bytes = Vec::from_hex(&hex)?;
+                Ok(ChannelBackup::read(&mut Cursor::new(bytes))?)
+            })
+            .collect()
+    }
+
     pub(crate) fn persist_channel_open_params(
         &self,
         id: u128,
@@ -520,6 +549,17 @@ pub(crate) struct ChannelOpenParams {
     pub(crate) labels: Option<Vec<String>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) opening_tx: Option<Transaction>,
+    /// Set when a watch-only wallet is waiting for the funding PSBT to be signed externally
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) unsigned_funding: Option<UnsignedChannelFunding>,
+}
+
+/// The funding transaction of a channel that still needs to be signed by an external signer
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct UnsignedChannelFunding {
+    pub(crate) temporary_channel_id: [u8; 32],
+    pub(crate) counterparty_node_id: PublicKey,
+    pub(crate) psbt: PartiallySignedTransaction,
 }
 
 impl ChannelOpenParams {
@@ -530,6 +570,7 @@ impl ChannelOpenParams {
             utxos: None,
             labels: None,
             opening_tx: None,
+            unsigned_funding: None,
         }
     }
 
@@ -544,6 +585,7 @@ impl ChannelOpenParams {
             utxos: Some(utxos),
             labels: None,
             opening_tx: None,
+            unsigned_funding: None,
         }
     }
 }
@@ -697,11 +739,10 @@ mod test {
     use bitcoin::secp256k1::PublicKey;
     use bitcoin::util::bip32::ExtendedPrivKey;
     use bitcoin::Txid;
-    use esplora_client::Builder;
+    use lightning::ln::ChannelId;
     use lightning::routing::scoring::ProbabilisticScoringDecayParameters;
     use lightning::sign::EntropySource;
     use lightning::{ln::PaymentHash, routing::router::DefaultRouter};
-    use lightning_transaction_sync::EsploraSyncClient;
     use std::str::FromStr;
     use std::sync::atomic::AtomicBool;
     use uuid::Uuid;
@@ -709,6 +750,7 @@ mod test {
 
     use super::*;
 
+    use crate::chainsource::EsploraChainSource;
     use crate::test_utils::*;
 
     wasm_bindgen_test_configure!(run_in_browser);
@@ -738,6 +780,8 @@ mod test {
             amt_msat: MillisatAmount(Some(420)),
             fee_paid_msat: None,
             bolt11: None,
+            bolt12: None,
+            custom_tlvs: vec![],
             payee_pubkey: Some(pubkey),
             secret: None,
             last_update: utils::now().as_secs(),
@@ -803,6 +847,38 @@ mod test {
         assert_eq!(result, Some(closure));
     }
 
+    #[test]
+    fn test_persist_channel_recovery() {
+        let test_name = "test_persist_channel_recovery";
+        log!("{}", test_name);
+
+        let persister = get_test_persister();
+        assert!(persister.get_channel_recovery().unwrap().is_empty());
+
+        let pubkey = PublicKey::from_str(
+            "02465ed5be53d04fde66c9418ff14a5f2267723810176c9212b722e542dc1afb1b",
+        )
+        .unwrap();
+        let channel = ChannelBackup {
+            channel_id: ChannelId([1; 32]),
+            funding_txo: OutPoint {
+                txid: Txid::all_zeros(),
+                index: 0,
+            },
+            counterparty: pubkey,
+            peer_connection_string: None,
+            channel_keys_id: [2; 32],
+            closed: false,
+        };
+        persister
+            .set_channel_recovery(vec![channel.clone()])
+            .unwrap();
+        assert_eq!(persister.get_channel_recovery().unwrap(), vec![channel]);
+
+        persister.set_channel_recovery(vec![]).unwrap();
+        assert!(persister.get_channel_recovery().unwrap().is_empty());
+    }
+
     #[test]
     fn test_persist_spendable_output_descriptor() {
         let test_name = "test_persist_spendable_output_descriptor";
@@ -873,22 +949,22 @@ mod test {
         let stop = Arc::new(AtomicBool::new(false));
         let xpriv = ExtendedPrivKey::new_master(network, &mnemonic.to_seed("")).unwrap();
 
-        let esplora_server_url = "https://mutinynet.com/api/".to_string();
-        let esplora = Arc::new(Builder::new(&esplora_server_url).build_async().unwrap());
+        let chain_source: Arc<dyn ChainSource> = Arc::new(
+            EsploraChainSource::new("https://mutinynet.com/api/", logger.clone()).unwrap(),
+        );
         let fees = Arc::new(MutinyFeeEstimator::new(
             persister.storage.clone(),
             network,
-            esplora.clone(),
+            chain_source.clone(),
             logger.clone(),
         ));
-        let tx_sync = Arc::new(EsploraSyncClient::new(esplora_server_url, logger.clone()));
 
         let wallet = Arc::new(
             OnChainWallet::new(
                 xpriv,
                 persister.storage.clone(),
                 network,
-                esplora.clone(),
+                chain_source.clone(),
                 fees.clone(),
                 stop,
                 logger.clone(),
@@ -898,7 +974,11 @@ mod test {
 
         let km = Arc::new(create_keys_manager(wallet.clone(), xpriv, 0, logger.clone()).unwrap());
 
-        let chain = Arc::new(MutinyChain::new(tx_sync, wallet, logger.clone()));
+        let chain = Arc::new(MutinyChain::new(
+            chain_source.clone(),
+            wallet,
+            logger.clone(),
+        ));
 
         let network_graph = Arc::new(NetworkGraph::new(network, logger.clone()));
         let scorer = ProbScorer::new(
@@ -910,7 +990,7 @@ mod test {
 
         // init chain monitor
         let chain_monitor: Arc<ChainMonitor<MemoryStorage>> = Arc::new(ChainMonitor::new(
-            Some(chain.tx_sync.clone()),
+            Some(chain.clone()),
             chain.clone(),
             logger.clone(),
             fees.clone(),
@@ -937,7 +1017,7 @@ mod test {
                 km.clone(),
                 router.clone(),
                 vec![],
-                &esplora,
+                chain_source.as_ref(),
             )
             .await
             .unwrap();
@@ -961,7 +1041,7 @@ mod test {
                 km,
                 router,
                 vec![],
-                &esplora,
+                chain_source.as_ref(),
             )
             .await
             .unwrap();
@@ -11,17 +11,21 @@ extern crate core;
 
 pub mod auth;
 mod chain;
+pub mod chainsource;
 pub mod encrypt;
 pub mod error;
+pub mod esplorapool;
 pub mod event;
 pub mod federation;
 mod fees;
 mod gossip;
+pub mod holdinvoice;
 mod key;
 mod keymanager;
 pub mod labels;
 mod ldkstorage;
 pub mod lnurlauth;
+pub mod lnurlpay;
 pub mod logging;
 mod lsp;
 mod messagehandler;
@@ -30,8 +34,13 @@ mod node;
 pub mod nodemanager;
 pub mod nostr;
 mod onchain;
+pub mod payjoin;
 mod peermanager;
+pub mod routing;
+pub mod scb;
 pub mod scorer;
+#[cfg(all(feature = "sqlite", not(target_arch = "wasm32")))]
+pub mod sqlite;
 pub mod storage;
 mod subscription;
 pub mod utils;
@@ -40,17 +49,37 @@ pub mod vss;
 #[cfg(test)]
 mod test_utils;
 
-use crate::event::{HTLCStatus, MillisatAmount, PaymentInfo};
+use crate::chainsource::ChainSourceConfig;
+use crate::event::{
+    CustomTlv, EventNotifier, HTLCStatus, MillisatAmount, MutinyEvent, PaymentInfo,
+};
 pub use crate::gossip::{GOSSIP_SYNC_TIME_KEY, NETWORK_GRAPH_KEY, PROB_SCORER_KEY};
 pub use crate::keymanager::generate_seed;
 pub use crate::ldkstorage::{CHANNEL_MANAGER_KEY, MONITORS_PREFIX_KEY};
+use crate::lnurlpay::{
+    LnUrlPayConfig, LnUrlPayReceived, LnUrlPayServer, LnUrlPayTransport, PayerData, SuccessAction,
+};
+use crate::lsp::LspConfig;
+pub use crate::lsp::{LspFeeLimits, LspFeeQuote, LspSelection};
+pub use crate::node::InvoiceOptions;
+use crate::nostr::zap::{invoice_commits_to, SentZap, ZapPrivacy};
+use crate::payjoin::{PayjoinReceiver, PayjoinTransport};
+use crate::routing::{
+    get_routing_policy, set_routing_policy, PaymentQuote, PaymentSource, QuoteError, RoutingPolicy,
+};
 use crate::storage::{
     list_payment_info, MutinyStorage, DEVICE_ID_KEY, EXPECTED_NETWORK_KEY, NEED_FULL_SYNC_KEY,
 };
-use crate::{auth::MutinyAuthClient, logging::MutinyLogger};
+use crate::{
+    auth::MutinyAuthClient,
+    logging::{LogRetention, MutinyLogger},
+};
 use crate::{error::MutinyError, nostr::ReservedProfile};
 use crate::{
-    federation::{FederationClient, FederationIdentity, FederationIndex, FederationStorage},
+    federation::{
+        list_federation_onchain_txs, FederationClient, FederationIdentity, FederationIndex,
+        FederationOnchainTx, FederationStorage,
+    },
     labels::{get_contact_key, Contact, LabelStorage},
     nodemanager::NodeBalance,
 };
@@ -67,20 +96,21 @@ use crate::{
 };
 use crate::{nostr::NostrManager, utils::sleep};
 use ::nostr::key::XOnlyPublicKey;
-use ::nostr::nips::nip57;
-use ::nostr::prelude::ZapRequestData;
 use ::nostr::{Event, EventId, JsonUtil, Kind};
 use async_lock::RwLock;
 use bdk_chain::ConfirmationTime;
 use bip39::Mnemonic;
-use bitcoin::hashes::hex::ToHex;
+use bitcoin::hashes::hex::{FromHex, ToHex};
 use bitcoin::hashes::{sha256, Hash};
 use bitcoin::secp256k1::PublicKey;
-use bitcoin::util::bip32::ExtendedPrivKey;
-use bitcoin::Network;
+use bitcoin::util::bip32::{ExtendedPrivKey, ExtendedPubKey, Fingerprint};
+use bitcoin::{Address, Network};
 use fedimint_core::{api::InviteCode, config::FederationId};
-use futures::{pin_mut, select, FutureExt};
+use futures::channel::mpsc::UnboundedReceiver;
+use futures::{pin_mut, select, FutureExt, StreamExt};
 use lightning::ln::PaymentHash;
+use lightning::offers::offer::Offer;
+use lightning::offers::refund::Refund;
 use lightning::{log_debug, util::logger::Logger};
 use lightning::{log_error, log_info, log_warn};
 use lightning_invoice::{Bolt11Invoice, Bolt11InvoiceDescription};
@@ -117,6 +147,28 @@ pub trait InvoiceHandler {
         amount: Option<u64>,
         labels: Vec<String>,
     ) -> Result<MutinyInvoice, MutinyError>;
+    async fn create_invoice_with_options(
+        &self,
+        amount: Option<u64>,
+        labels: Vec<String>,
+        options: InvoiceOptions,
+    ) -> Result<MutinyInvoice, MutinyError>;
+    async fn keysend(
+        &self,
+        to_node: PublicKey,
+        amt_sats: u64,
+        message: Option<String>,
+        custom_tlvs: Vec<CustomTlv>,
+        labels: Vec<String>,
+    ) -> Result<MutinyInvoice, MutinyError>;
+    async fn create_bip21(
+        &self,
+        amount: Option<u64>,
+        labels: Vec<String>,
+    ) -> Result<MutinyBip21RawMaterials, MutinyError>;
+    async fn get_invoice_by_hash(&self, hash: &sha256::Hash) -> Result<MutinyInvoice, MutinyError>;
+    async fn get_balance(&self) -> Result<MutinyBalance, MutinyError>;
+    async fn get_activity(&self) -> Result<Vec<ActivityItem>, MutinyError>;
 }
 
 pub struct LnUrlParams {
@@ -173,6 +225,7 @@ pub enum ActivityItem {
     OnChain(TransactionDetails),
     Lightning(Box<MutinyInvoice>),
     ChannelClosed(ChannelClosure),
+    FederationOnchain(FederationOnchainTx),
 }
 
 impl ActivityItem {
@@ -188,6 +241,11 @@ impl ActivityItem {
                 HTLCStatus::Pending | HTLCStatus::InFlight => None,
             },
             ActivityItem::ChannelClosed(c) => Some(c.timestamp),
+            ActivityItem::FederationOnchain(t) => match t.status {
+                HTLCStatus::Succeeded => Some(t.last_updated),
+                HTLCStatus::Failed => Some(t.last_updated),
+                HTLCStatus::Pending | HTLCStatus::InFlight => None,
+            },
         }
     }
 
@@ -196,6 +254,7 @@ impl ActivityItem {
             ActivityItem::OnChain(t) => t.labels.clone(),
             ActivityItem::Lightning(i) => i.labels.clone(),
             ActivityItem::ChannelClosed(_) => vec![],
+            ActivityItem::FederationOnchain(t) => t.labels.clone(),
         }
     }
 
@@ -206,6 +265,7 @@ impl ActivityItem {
             }
             ActivityItem::Lightning(_) => false,
             ActivityItem::ChannelClosed(_) => false,
+            ActivityItem::FederationOnchain(_) => false,
         }
     }
 }
@@ -258,6 +318,8 @@ impl Ord for ActivityItem {
 #[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
 pub struct MutinyInvoice {
     pub bolt11: Option<Bolt11Invoice>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bolt12: Option<String>,
     pub description: Option<String>,
     pub payment_hash: sha256::Hash,
     pub preimage: Option<String>,
@@ -269,6 +331,12 @@ pub struct MutinyInvoice {
     pub inbound: bool,
     pub labels: Vec<String>,
     pub last_updated: u64,
+    /// Custom TLV records sent with or received in a keysend payment
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub custom_tlvs: Vec<CustomTlv>,
+    /// What the LNURL-pay service gave us to show after paying it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub success_action: Option<SuccessAction>,
 }
 
 impl MutinyInvoice {
@@ -299,6 +367,7 @@ impl From<Bolt11Invoice> for MutinyInvoice {
 
         MutinyInvoice {
             bolt11: Some(value),
+            bolt12: None,
             description,
             payment_hash,
             preimage: None,
@@ -310,6 +379,8 @@ impl From<Bolt11Invoice> for MutinyInvoice {
             inbound: true,
             labels: vec![],
             last_updated: timestamp,
+            custom_tlvs: vec![],
+            success_action: None,
         }
     }
 }
@@ -332,6 +403,8 @@ impl From<MutinyInvoice> for PaymentInfo {
             .unwrap_or(MillisatAmount(None));
         let fee_paid_msat = invoice.fees_paid.map(|f| f * 1_000);
         let bolt11 = invoice.bolt11;
+        let bolt12 = invoice.bolt12;
+        let custom_tlvs = invoice.custom_tlvs;
         let payee_pubkey = invoice.payee_pubkey;
         let last_update = invoice.last_updated;
 
@@ -342,6 +415,8 @@ impl From<MutinyInvoice> for PaymentInfo {
             amt_msat,
             fee_paid_msat,
             bolt11,
+            bolt12,
+            custom_tlvs,
             payee_pubkey,
             last_update,
         }
@@ -376,16 +451,25 @@ impl MutinyInvoice {
                     payee_pubkey: i.payee_pubkey,
                     preimage: i.preimage.map(|p| p.to_hex()),
                     fees_paid: i.fee_paid_msat.map(|f| f / 1_000),
+                    custom_tlvs: i.custom_tlvs,
                     ..invoice.into()
                 })
             }
             None => {
                 let amount_sats: Option<u64> = i.amt_msat.0.map(|s| s / 1_000);
                 let fees_paid = i.fee_paid_msat.map(|f| f / 1_000);
+                // Outbound offer payments are stored by their payment id until we get
+                // the invoice, once they succeed we can derive the payment hash from the preimage
+                let payment_hash = match i.preimage {
+                    Some(preimage) if i.bolt12.is_some() && !inbound => {
+                        sha256::Hash::hash(&preimage)
+                    }
+                    _ => sha256::Hash::from_inner(payment_hash.0),
+                };
                 let preimage = i.preimage.map(|p| p.to_hex());
-                let payment_hash = sha256::Hash::from_inner(payment_hash.0);
                 let invoice = MutinyInvoice {
                     bolt11: None,
+                    bolt12: i.bolt12,
                     description: None,
                     payment_hash,
                     preimage,
@@ -397,6 +481,8 @@ impl MutinyInvoice {
                     inbound,
                     labels,
                     last_updated: i.last_update,
+                    custom_tlvs: i.custom_tlvs,
+                    success_action: None,
                 };
                 Ok(invoice)
             }
@@ -409,15 +495,21 @@ pub struct MutinyWalletConfigBuilder {
     #[cfg(target_arch = "wasm32")]
     websocket_proxy_addr: Option<String>,
     network: Option<Network>,
-    user_esplora_url: Option<String>,
+    user_esplora_urls: Vec<String>,
+    chain_source: ChainSourceConfig,
     user_rgs_url: Option<String>,
     lsp_url: Option<String>,
     lsp_connection_string: Option<String>,
     lsp_token: Option<String>,
+    additional_lsps: Vec<LspConfig>,
+    lsp_selection: LspSelection,
+    lsp_fee_limits: LspFeeLimits,
     auth_client: Option<Arc<MutinyAuthClient>>,
     subscription_url: Option<String>,
     scorer_url: Option<String>,
     primal_url: Option<String>,
+    log_retention: LogRetention,
+    watch_only_xpub: Option<(ExtendedPubKey, Fingerprint)>,
     do_not_connect_peers: bool,
     skip_device_lock: bool,
     pub safe_mode: bool,
@@ -457,15 +549,21 @@ impl MutinyWalletConfigBuilder {
             #[cfg(target_arch = "wasm32")]
             websocket_proxy_addr: None,
             network: None,
-            user_esplora_url: None,
+            user_esplora_urls: vec![],
+            chain_source: ChainSourceConfig::default(),
             user_rgs_url: None,
             lsp_url: None,
             lsp_connection_string: None,
             lsp_token: None,
+            additional_lsps: vec![],
+            lsp_selection: LspSelection::default(),
+            lsp_fee_limits: LspFeeLimits::default(),
             auth_client: None,
             subscription_url: None,
             scorer_url: None,
             primal_url: None,
+            log_retention: LogRetention::default(),
+            watch_only_xpub: None,
             do_not_connect_peers: false,
             skip_device_lock: false,
             safe_mode: false,
@@ -485,7 +583,18 @@ impl MutinyWalletConfigBuilder {
     }
 
     pub fn with_user_esplora_url(&mut self, user_esplora_url: String) {
-        self.user_esplora_url = Some(user_esplora_url);
+        self.user_esplora_urls = vec![user_esplora_url];
+    }
+
+    /// Sets several esplora servers, they are tried in order and
+    /// unhealthy ones are skipped until they recover.
+    pub fn with_user_esplora_urls(&mut self, user_esplora_urls: Vec<String>) {
+        self.user_esplora_urls = user_esplora_urls;
+    }
+
+    /// Sets the backend used for chain data, defaults to esplora.
+    pub fn with_chain_source(&mut self, chain_source: ChainSourceConfig) {
+        self.chain_source = chain_source;
     }
 
     pub fn with_user_rgs_url(&mut self, user_rgs_url: String) {
@@ -504,6 +613,33 @@ impl MutinyWalletConfigBuilder {
         self.lsp_token = Some(lsp_token);
     }
 
+    /// Adds a Voltage flow LSP used alongside the primary LSP.
+    pub fn with_additional_lsp_url(&mut self, lsp_url: String) {
+        self.additional_lsps
+            .push(LspConfig::new_voltage_flow(lsp_url));
+    }
+
+    /// Adds an LSPS LSP used alongside the primary LSP.
+    pub fn with_additional_lsp_connection_string(
+        &mut self,
+        lsp_connection_string: String,
+        lsp_token: Option<String>,
+    ) {
+        self.additional_lsps
+            .push(LspConfig::new_lsps(lsp_connection_string, lsp_token));
+    }
+
+    /// Sets how an LSP is picked when several can open a channel for an invoice.
+    pub fn with_lsp_selection(&mut self, lsp_selection: LspSelection) {
+        self.lsp_selection = lsp_selection;
+    }
+
+    /// Sets the most we pay an LSP to open a channel for an invoice,
+    /// LSPs that charge more are not used.
+    pub fn with_lsp_fee_limits(&mut self, lsp_fee_limits: LspFeeLimits) {
+        self.lsp_fee_limits = lsp_fee_limits;
+    }
+
     pub fn with_auth_client(&mut self, auth_client: Arc<MutinyAuthClient>) {
         self.auth_client = Some(auth_client);
     }
@@ -520,6 +656,24 @@ impl MutinyWalletConfigBuilder {
         self.primal_url = Some(primal_url);
     }
 
+    /// Sets how many logs are kept and which ones are saved.
+    pub fn with_log_retention(&mut self, log_retention: LogRetention) {
+        self.log_retention = log_retention;
+    }
+
+    /// Uses a watch-only on-chain wallet for the given taproot account xpub, its
+    /// transactions have to be signed externally. The fingerprint is of the master key
+    /// the xpub was derived from.
+    ///
+    /// The storage should not have been used by a wallet with keys before.
+    pub fn with_watch_only_xpub(
+        &mut self,
+        account_xpub: ExtendedPubKey,
+        master_fingerprint: Fingerprint,
+    ) {
+        self.watch_only_xpub = Some((account_xpub, master_fingerprint));
+    }
+
     pub fn do_not_connect_peers(&mut self) {
         self.do_not_connect_peers = true;
     }
@@ -545,15 +699,21 @@ impl MutinyWalletConfigBuilder {
             #[cfg(target_arch = "wasm32")]
             websocket_proxy_addr: self.websocket_proxy_addr,
             network,
-            user_esplora_url: self.user_esplora_url,
+            user_esplora_urls: self.user_esplora_urls,
+            chain_source: self.chain_source,
             user_rgs_url: self.user_rgs_url,
             lsp_url: self.lsp_url,
             lsp_connection_string: self.lsp_connection_string,
             lsp_token: self.lsp_token,
+            additional_lsps: self.additional_lsps,
+            lsp_selection: self.lsp_selection,
+            lsp_fee_limits: self.lsp_fee_limits,
             auth_client: self.auth_client,
             subscription_url: self.subscription_url,
             scorer_url: self.scorer_url,
             primal_url: self.primal_url,
+            log_retention: self.log_retention,
+            watch_only_xpub: self.watch_only_xpub,
             do_not_connect_peers: self.do_not_connect_peers,
             skip_device_lock: self.skip_device_lock,
             safe_mode: self.safe_mode,
@@ -568,15 +728,21 @@ pub struct MutinyWalletConfig {
     #[cfg(target_arch = "wasm32")]
     websocket_proxy_addr: Option<String>,
     network: Network,
-    user_esplora_url: Option<String>,
+    user_esplora_urls: Vec<String>,
+    chain_source: ChainSourceConfig,
     user_rgs_url: Option<String>,
     lsp_url: Option<String>,
     lsp_connection_string: Option<String>,
     lsp_token: Option<String>,
+    additional_lsps: Vec<LspConfig>,
+    lsp_selection: LspSelection,
+    lsp_fee_limits: LspFeeLimits,
     auth_client: Option<Arc<MutinyAuthClient>>,
     subscription_url: Option<String>,
     scorer_url: Option<String>,
     primal_url: Option<String>,
+    log_retention: LogRetention,
+    watch_only_xpub: Option<(ExtendedPubKey, Fingerprint)>,
     do_not_connect_peers: bool,
     skip_device_lock: bool,
     pub safe_mode: bool,
@@ -596,6 +762,7 @@ pub struct MutinyWalletBuilder<S: MutinyStorage> {
     skip_hodl_invoices: bool,
     skip_device_lock: bool,
     safe_mode: bool,
+    payjoin_transport: Option<Arc<dyn PayjoinTransport>>,
 }
 
 impl<S: MutinyStorage> MutinyWalletBuilder<S> {
@@ -613,6 +780,7 @@ impl<S: MutinyStorage> MutinyWalletBuilder<S> {
             skip_device_lock: false,
             safe_mode: false,
             skip_hodl_invoices: true,
+            payjoin_transport: None,
         }
     }
 
@@ -665,6 +833,12 @@ impl<S: MutinyStorage> MutinyWalletBuilder<S> {
         self.skip_device_lock = true;
     }
 
+    /// Enables receiving payjoins, requests from senders will come through the given transport
+    /// and its endpoint will be included in our BIP21 URIs.
+    pub fn with_payjoin_transport(&mut self, transport: Arc<dyn PayjoinTransport>) {
+        self.payjoin_transport = Some(transport);
+    }
+
     pub async fn build(self) -> Result<MutinyWallet<S>, MutinyError> {
         let network = self
             .network
@@ -692,11 +866,15 @@ impl<S: MutinyStorage> MutinyWalletBuilder<S> {
             stop.clone(),
             self.storage.clone(),
             self.session_id,
+            config.log_retention,
         ));
 
+        let event_notifier = EventNotifier::default();
+
         let mut nm_builder = NodeManagerBuilder::new(self.xprivkey, self.storage.clone())
             .with_config(config.clone());
         nm_builder.with_stop(stop.clone());
+        nm_builder.with_event_notifier(event_notifier.clone());
         nm_builder.with_logger(logger.clone());
         let node_manager = Arc::new(nm_builder.build().await?);
 
@@ -718,7 +896,14 @@ impl<S: MutinyStorage> MutinyWalletBuilder<S> {
         // create federation module if any exist
         let federation_storage = self.storage.get_federations()?;
         let federations = if !federation_storage.federations.is_empty() {
-            create_federations(federation_storage.clone(), &config, &self.storage, &logger).await?
+            create_federations(
+                federation_storage.clone(),
+                &config,
+                &self.storage,
+                &event_notifier,
+                &logger,
+            )
+            .await?
         } else {
             Arc::new(RwLock::new(HashMap::new()))
         };
@@ -730,11 +915,10 @@ impl<S: MutinyStorage> MutinyWalletBuilder<S> {
             );
         }
 
-        let lnurl_client = Arc::new(
-            lnurl::Builder::default()
-                .build_async()
-                .expect("failed to make lnurl client"),
-        );
+        // LNURL-pay needs requests the lnurl client can't make (payer data, success actions),
+        // so we keep the HTTP client it is built on to make those with
+        let lnurl_http_client = reqwest::Client::new();
+        let lnurl_client = Arc::new(LnUrlClient::from_client(lnurl_http_client.clone()));
 
         let (subscription_client, auth) = if let Some(auth_client) = self.auth_client.clone() {
             if let Some(subscription_url) = self.subscription_url {
@@ -762,8 +946,12 @@ impl<S: MutinyStorage> MutinyWalletBuilder<S> {
             federation_storage: Arc::new(RwLock::new(federation_storage)),
             federations,
             lnurl_client,
+            lnurl_http_client,
             subscription_client,
             auth,
+            event_notifier,
+            payjoin_transport: self.payjoin_transport,
+            lnurl_pay_running: Arc::new(AtomicBool::new(false)),
             stop,
             logger,
             network,
@@ -797,9 +985,15 @@ impl<S: MutinyStorage> MutinyWalletBuilder<S> {
         // start the nostr background process
         mw.start_nostr().await;
 
+        // notify NWC clients about payments
+        mw.start_nwc_notifications();
+
         // start the federation background processor
         mw.start_fedimint_background_checker().await;
 
+        // start handling payjoin requests
+        mw.start_payjoin_receiver();
+
         Ok(mw)
     }
 }
@@ -817,8 +1011,13 @@ pub struct MutinyWallet<S: MutinyStorage> {
     pub federation_storage: Arc<RwLock<FederationStorage>>,
     pub(crate) federations: Arc<RwLock<HashMap<FederationId, Arc<FederationClient<S>>>>>,
     lnurl_client: Arc<LnUrlClient>,
+    /// The HTTP client behind `lnurl_client`
+    lnurl_http_client: reqwest::Client,
     auth: AuthManager,
     subscription_client: Option<Arc<MutinySubscriptionClient>>,
+    event_notifier: EventNotifier,
+    payjoin_transport: Option<Arc<dyn PayjoinTransport>>,
+    lnurl_pay_running: Arc<AtomicBool>,
     pub stop: Arc<AtomicBool>,
     pub logger: Arc<MutinyLogger>,
     network: Network,
@@ -835,6 +1034,7 @@ impl<S: MutinyStorage> MutinyWallet<S> {
         let mut nm_builder = NodeManagerBuilder::new(self.xprivkey, self.storage.clone())
             .with_config(self.config.clone());
         nm_builder.with_stop(self.stop.clone());
+        nm_builder.with_event_notifier(self.event_notifier.clone());
         nm_builder.with_logger(self.logger.clone());
 
         // when we restart, gen a new session id
@@ -844,6 +1044,66 @@ impl<S: MutinyStorage> MutinyWallet<S> {
         Ok(())
     }
 
+    /// Starts a background process that answers payjoin requests, if we have a transport
+    pub(crate) fn start_payjoin_receiver(&self) {
+        let Some(transport) = self.payjoin_transport.clone() else {
+            return;
+        };
+
+        let receiver = PayjoinReceiver::new(
+            self.node_manager.wallet.clone(),
+            self.storage.clone(),
+            self.logger.clone(),
+        );
+        let stop = self.stop.clone();
+        utils::spawn(async move {
+            receiver.run(transport, stop).await;
+        });
+    }
+
+    /// Starts answering LNURL-pay requests for our lightning address,
+    /// requests from payers come through the given transport.
+    pub fn start_lnurl_pay_server(
+        &self,
+        config: LnUrlPayConfig,
+        transport: Arc<dyn LnUrlPayTransport>,
+    ) -> Result<(), MutinyError> {
+        if self.safe_mode {
+            return Err(MutinyError::NotRunning);
+        }
+        if self.lnurl_pay_running.swap(true, Ordering::Relaxed) {
+            return Err(MutinyError::AlreadyRunning);
+        }
+
+        let keys = match self.nostr.derive_lnurl_keys() {
+            Ok(keys) => keys,
+            Err(e) => {
+                self.lnurl_pay_running.store(false, Ordering::Relaxed);
+                return Err(e);
+            }
+        };
+        log_info!(
+            self.logger,
+            "Starting LNURL-pay server for {}",
+            config.lightning_address()
+        );
+
+        let server = LnUrlPayServer::new(self.clone(), config, keys, self.logger.clone());
+        let stop = self.stop.clone();
+        let running = self.lnurl_pay_running.clone();
+        utils::spawn(async move {
+            server.run(transport, stop).await;
+            running.store(false, Ordering::Relaxed);
+        });
+
+        Ok(())
+    }
+
+    /// Lists the payments made to our lightning address, with their comments and payer data.
+    pub fn list_lnurl_pay_received(&self) -> Result<Vec<LnUrlPayReceived>, MutinyError> {
+        lnurlpay::list_lnurl_pay_received(&self.storage)
+    }
+
     /// Starts a background process that will watch for nostr events
     pub(crate) async fn start_nostr(&self) {
         let nostr = self.nostr.clone();
@@ -919,12 +1179,13 @@ impl<S: MutinyStorage> MutinyWallet<S> {
                                         match event.kind {
                                             Kind::WalletConnectRequest => {
                                                 match nostr.handle_nwc_request(event, &self_clone).await {
-                                                    Ok(Some(event)) => {
-                                                        if let Err(e) = client.send_event(event).await {
-                                                            log_warn!(logger, "Error sending NWC event: {e}");
+                                                    Ok(events) => {
+                                                        for event in events {
+                                                            if let Err(e) = client.send_event(event).await {
+                                                                log_warn!(logger, "Error sending NWC event: {e}");
+                                                            }
                                                         }
                                                     }
-                                                    Ok(None) => {} // no response
                                                     Err(e) => {
                                                         log_error!(logger, "Error handling NWC request: {e}");
                                                     }
@@ -973,7 +1234,59 @@ impl<S: MutinyStorage> MutinyWallet<S> {
         });
     }
 
-    /// Pays a lightning invoice from a federation (preferred) or node.
+    /// Starts a background process that sends NIP-47 notifications to
+    /// NWC profiles when a lightning or federation payment completes
+    pub(crate) fn start_nwc_notifications(&self) {
+        let mut events = self.event_notifier.subscribe();
+        let logger = self.logger.clone();
+        let stop = self.stop.clone();
+        let self_clone = self.clone();
+        utils::spawn(async move {
+            loop {
+                let read_fut = events.next().fuse();
+                let delay_fut = Box::pin(utils::sleep(1_000)).fuse();
+
+                pin_mut!(read_fut, delay_fut);
+                let payment_hash = select! {
+                    event = read_fut => match event {
+                        Some(MutinyEvent::PaymentReceived { payment_hash, .. })
+                        | Some(MutinyEvent::PaymentSent { payment_hash, .. })
+                        | Some(MutinyEvent::FederationPayment {
+                            payment_hash,
+                            status: HTLCStatus::Succeeded,
+                            ..
+                        }) => payment_hash,
+                        Some(_) => continue,
+                        None => break,
+                    },
+                    _ = delay_fut => {
+                        if stop.load(Ordering::Relaxed) {
+                            break;
+                        }
+                        continue;
+                    }
+                };
+
+                let invoice = match self_clone.get_invoice_by_hash(&payment_hash).await {
+                    Ok(invoice) => invoice,
+                    Err(e) => {
+                        log_warn!(logger, "Could not find payment for NWC notification: {e}");
+                        continue;
+                    }
+                };
+
+                if let Err(e) = self_clone.nostr.send_nwc_notifications(&invoice).await {
+                    log_warn!(logger, "Failed to send NWC notifications: {e}");
+                }
+            }
+        });
+    }
+
+    /// Pays a lightning invoice from a federation or node, in the order
+    /// set by the [`RoutingPolicy`]. The next source is only tried when the
+    /// payment definitely failed, not when it timed out.
+    /// An invoice can't be split between sources, if only their combined
+    /// balance is enough this fails with [`MutinyError::SplitPaymentUnsupported`].
     /// An amount should only be provided if the invoice does not have an amount.
     /// Amountless invoices cannot be paid by a federation.
     /// The amount should be in satoshis.
@@ -982,6 +1295,34 @@ impl<S: MutinyStorage> MutinyWallet<S> {
         inv: &Bolt11Invoice,
         amt_sats: Option<u64>,
         labels: Vec<String>,
+    ) -> Result<MutinyInvoice, MutinyError> {
+        let policy = self.get_routing_policy()?;
+        let sources = self.payment_sources(&policy).await?;
+        self.pay_invoice_from_sources(inv, amt_sats, labels, &policy, sources)
+            .await
+    }
+
+    /// Pays a lightning invoice from the given source only.
+    /// The limits of the [`RoutingPolicy`] still apply.
+    pub async fn pay_invoice_from_source(
+        &self,
+        inv: &Bolt11Invoice,
+        amt_sats: Option<u64>,
+        labels: Vec<String>,
+        source: PaymentSource,
+    ) -> Result<MutinyInvoice, MutinyError> {
+        let policy = self.get_routing_policy()?;
+        self.pay_invoice_from_sources(inv, amt_sats, labels, &policy, vec![source])
+            .await
+    }
+
+    async fn pay_invoice_from_sources(
+        &self,
+        inv: &Bolt11Invoice,
+        amt_sats: Option<u64>,
+        labels: Vec<String>,
+        policy: &RoutingPolicy,
+        sources: Vec<PaymentSource>,
     ) -> Result<MutinyInvoice, MutinyError> {
         if inv.network() != self.network {
             return Err(MutinyError::IncorrectNetwork(inv.network()));
@@ -997,68 +1338,205 @@ impl<S: MutinyStorage> MutinyWallet<S> {
             .amount_milli_satoshis()
             .or(amt_sats.map(|x| x * 1_000))
             .ok_or(MutinyError::InvoiceInvalid)?;
+        let send_sats = send_msat / 1_000;
 
-        // Try each federation first
-        let federation_ids = self.list_federation_ids().await?;
-        let mut last_federation_error = None;
-        for federation_id in federation_ids {
-            if let Some(fedimint_client) = self.federations.read().await.get(&federation_id) {
-                // Check if the federation has enough balance
-                let balance = fedimint_client.get_balance().await?;
-                if balance >= send_msat / 1_000 {
-                    // Try to pay the invoice using the federation
-                    let payment_result = fedimint_client
-                        .pay_invoice(inv.clone(), labels.clone())
-                        .await;
-                    match payment_result {
-                        Ok(r) => {
-                            self.storage.set_invoice_labels(inv.clone(), labels)?;
-                            return Ok(r);
+        let mut last_error = None;
+        // an invoice can only be paid from a single source, so keep track of
+        // the balances to tell the user if only their combined balance is enough
+        let mut total_balance = 0;
+        let mut largest_balance = 0;
+        for source in sources {
+            let result = match source {
+                PaymentSource::Federation(federation_id) => {
+                    let fedimint_client =
+                        self.federations.read().await.get(&federation_id).cloned();
+                    // If federation client is not found, continue to next source
+                    let Some(fedimint_client) = fedimint_client else {
+                        continue;
+                    };
+
+                    let balance = match fedimint_client.get_balance().await {
+                        Ok(balance) => balance,
+                        Err(e) => {
+                            log_warn!(
+                                self.logger,
+                                "skipping federation {federation_id}, could not get balance: {e}"
+                            );
+                            last_error = Some(e);
+                            continue;
                         }
-                        Err(e) => match e {
-                            MutinyError::PaymentTimeout => return Err(e),
-                            MutinyError::RoutingFailed => {
-                                log_debug!(
+                    };
+                    total_balance += balance;
+                    largest_balance = largest_balance.max(balance);
+                    // only ask the gateway for a fee if we need to check it
+                    let fee = match policy.limits(&source).and_then(|l| l.max_fee_sats) {
+                        Some(_) => match fedimint_client.estimate_invoice_fee(inv).await {
+                            Ok(fee) => Some(fee),
+                            Err(e) => {
+                                log_warn!(
                                     self.logger,
-                                    "could not make payment through federation: {e}"
+                                    "skipping federation {federation_id}, could not estimate fee: {e}"
                                 );
-                                last_federation_error = Some(e);
+                                last_error = Some(e);
                                 continue;
                             }
-                            _ => {
-                                log_warn!(self.logger, "unhandled error: {e}");
-                                last_federation_error = Some(e);
-                            }
                         },
+                        None => None,
+                    };
+                    if let Err(e) = policy.check_payment(&source, send_sats, fee, balance) {
+                        log_debug!(self.logger, "skipping federation {federation_id}: {e:?}");
+                        last_error = Some(e.into());
+                        continue;
                     }
+
+                    fedimint_client
+                        .pay_invoice(inv.clone(), labels.clone())
+                        .await
                 }
-                // If payment fails or invoice amount is None or balance is not sufficient, continue to next federation
+                PaymentSource::Node => {
+                    // Pay from the node with the most balance, only if it has any at all
+                    let (node_pubkey, balance) =
+                        match self.node_manager.get_largest_node_balance().await {
+                            Some((pubkey, balance)) if balance > 0 => (pubkey, balance),
+                            _ => continue,
+                        };
+                    total_balance += balance;
+                    largest_balance = largest_balance.max(balance);
+                    if let Err(e) = policy.check_payment(&source, send_sats, None, balance) {
+                        log_debug!(self.logger, "skipping node: {e:?}");
+                        last_error = Some(e.into());
+                        continue;
+                    }
+
+                    // the node enforces the fee limit while routing
+                    let max_fee_sats = policy.limits(&source).and_then(|l| l.max_fee_sats);
+                    self.node_manager
+                        .pay_invoice(
+                            Some(&node_pubkey),
+                            inv,
+                            amt_sats,
+                            max_fee_sats,
+                            labels.clone(),
+                        )
+                        .await
+                }
+            };
+
+            match result {
+                Ok(r) => {
+                    self.storage.set_invoice_labels(inv.clone(), labels)?;
+                    return Ok(r);
+                }
+                // only try the next source if this one definitely did not pay, anything
+                // else, like a timeout, could still succeed and would then pay twice
+                Err(
+                    e @ (MutinyError::RoutingFailed
+                    | MutinyError::InsufficientBalance
+                    | MutinyError::ReserveAmountError),
+                ) => {
+                    log_debug!(self.logger, "could not make payment from {source:?}: {e}");
+                    last_error = Some(e);
+                }
+                Err(e) => return Err(e),
             }
-            // If federation client is not found, continue to next federation
         }
 
-        // If any balance at all, then fallback to node manager for payment.
-        // Take the error from the node manager as the priority.
-        if self
-            .node_manager
-            .nodes
-            .lock()
-            .await
-            .iter()
-            .flat_map(|(_, n)| n.channel_manager.list_channels())
-            .map(|c| c.balance_msat)
-            .sum::<u64>()
-            > 0
-        {
-            let res = self
-                .node_manager
-                .pay_invoice(None, inv, amt_sats, labels.clone())
-                .await?;
-            self.storage.set_invoice_labels(inv.clone(), labels)?;
-            Ok(res)
-        } else {
-            Err(last_federation_error.unwrap_or(MutinyError::InsufficientBalance))
+        if largest_balance < send_sats && total_balance >= send_sats {
+            log_warn!(
+                self.logger,
+                "no single source can pay {send_sats} sats, only their combined balance of {total_balance} sats"
+            );
+            return Err(MutinyError::SplitPaymentUnsupported);
         }
+
+        Err(last_error.unwrap_or(MutinyError::InsufficientBalance))
+    }
+
+    /// Reports the expected fee for paying the invoice from each source, in the order
+    /// they would be tried, and whether the [`RoutingPolicy`] would skip them.
+    /// Nothing is paid.
+    pub async fn quote_payment(
+        &self,
+        inv: &Bolt11Invoice,
+        amt_sats: Option<u64>,
+    ) -> Result<Vec<PaymentQuote>, MutinyError> {
+        if inv.network() != self.network {
+            return Err(MutinyError::IncorrectNetwork(inv.network()));
+        }
+
+        let send_sats = inv
+            .amount_milli_satoshis()
+            .or(amt_sats.map(|x| x * 1_000))
+            .ok_or(MutinyError::InvoiceInvalid)?
+            / 1_000;
+
+        let policy = self.get_routing_policy()?;
+        let sources = self.payment_sources(&policy).await?;
+
+        let mut quotes = Vec::with_capacity(sources.len());
+        for source in sources {
+            let (balance_sats, fee) = match source {
+                PaymentSource::Federation(federation_id) => {
+                    let fedimint_client =
+                        self.federations.read().await.get(&federation_id).cloned();
+                    let Some(fedimint_client) = fedimint_client else {
+                        continue;
+                    };
+                    let balance = fedimint_client.get_balance().await?;
+                    (balance, fedimint_client.estimate_invoice_fee(inv).await)
+                }
+                PaymentSource::Node => {
+                    // the payment would be sent from the node with the most balance
+                    let largest = self.node_manager.get_largest_node_balance().await;
+                    let balance = largest.map(|(_, b)| b).unwrap_or_default();
+                    let fee = self
+                        .node_manager
+                        .estimate_invoice_fee(largest.map(|(pk, _)| pk).as_ref(), inv, amt_sats)
+                        .await;
+                    (balance, fee)
+                }
+            };
+
+            let fee_sats = fee.ok();
+            let error = policy
+                .check_payment(&source, send_sats, fee_sats, balance_sats)
+                .err()
+                .or(fee_sats.is_none().then_some(QuoteError::NoRoute));
+            quotes.push(PaymentQuote {
+                source,
+                balance_sats,
+                fee_sats,
+                error,
+            });
+        }
+
+        Ok(quotes)
+    }
+
+    /// All federations and then our node, ordered by the routing policy
+    async fn payment_sources(
+        &self,
+        policy: &RoutingPolicy,
+    ) -> Result<Vec<PaymentSource>, MutinyError> {
+        let mut available: Vec<PaymentSource> = self
+            .list_federation_ids()
+            .await?
+            .into_iter()
+            .map(PaymentSource::Federation)
+            .collect();
+        available.push(PaymentSource::Node);
+
+        Ok(policy.order_sources(&available))
+    }
+
+    /// Gets the policy used to choose where payments are made from and invoices are created.
+    pub fn get_routing_policy(&self) -> Result<RoutingPolicy, MutinyError> {
+        get_routing_policy(&self.storage)
+    }
+
+    /// Sets the policy used to choose where payments are made from and invoices are created.
+    pub fn set_routing_policy(&self, policy: RoutingPolicy) -> Result<(), MutinyError> {
+        set_routing_policy(&self.storage, policy)
     }
 
     /// Creates a BIP 21 invoice. This creates a new address and a lightning invoice.
@@ -1107,11 +1585,19 @@ impl<S: MutinyStorage> MutinyWallet<S> {
             return Err(MutinyError::WalletOperationFailed);
         };
 
+        // we don't answer payjoin requests in safe mode
+        let payjoin_endpoint = if self.safe_mode {
+            None
+        } else {
+            self.payjoin_transport.as_ref().map(|t| t.endpoint())
+        };
+
         Ok(MutinyBip21RawMaterials {
             address,
             invoice,
             btc_amount: amount.map(|amount| bitcoin::Amount::from_sat(amount).to_btc().to_string()),
             labels,
+            payjoin_endpoint,
         })
     }
 
@@ -1120,31 +1606,142 @@ impl<S: MutinyStorage> MutinyWallet<S> {
         amount: Option<u64>,
         labels: Vec<String>,
     ) -> Result<MutinyInvoice, MutinyError> {
-        let federation_ids = self.list_federation_ids().await?;
+        self.create_lightning_invoice_with_options(amount, labels, InvoiceOptions::default())
+            .await
+    }
 
-        // Attempt to create federation invoice
-        if !federation_ids.is_empty() {
-            let federation_id = &federation_ids[0];
-            let fedimint_client = self.federations.read().await.get(federation_id).cloned();
+    /// Creates a lightning invoice with the given description or description hash and expiry.
+    /// Federations can't commit to a description hash so only our node is used for those.
+    pub async fn create_lightning_invoice_with_options(
+        &self,
+        amount: Option<u64>,
+        labels: Vec<String>,
+        options: InvoiceOptions,
+    ) -> Result<MutinyInvoice, MutinyError> {
+        let policy = self.get_routing_policy()?;
+        let sources = self.payment_sources(&policy).await?;
+        self.create_lightning_invoice_from_sources(amount, labels, &policy, sources, options)
+            .await
+    }
 
-            if let Some(client) = fedimint_client {
-                if let Ok(inv) = client
-                    .get_invoice(amount.unwrap_or_default(), labels.clone())
-                    .await
-                {
+    /// Creates the invoice for an LSP fee quote from [`NodeManager::quote_lsp_fee`],
+    /// this is how the user approves the fee before the invoice is given out.
+    pub async fn approve_lsp_fee_quote(
+        &self,
+        quote_id: &str,
+        labels: Vec<String>,
+    ) -> Result<MutinyInvoice, MutinyError> {
+        let inv = self.node_manager.approve_lsp_fee_quote(quote_id).await?;
+        self.storage
+            .set_invoice_labels(inv.bolt11.clone().expect("just created"), labels)?;
+
+        Ok(inv)
+    }
+
+    /// Creates a lightning invoice with the given source only.
+    /// The amount should be in satoshis.
+    pub async fn create_invoice_with_source(
+        &self,
+        amount: Option<u64>,
+        labels: Vec<String>,
+        source: PaymentSource,
+    ) -> Result<MutinyInvoice, MutinyError> {
+        let policy = self.get_routing_policy()?;
+        self.create_lightning_invoice_from_sources(
+            amount,
+            labels,
+            &policy,
+            vec![source],
+            InvoiceOptions::default(),
+        )
+        .await
+    }
+
+    async fn create_lightning_invoice_from_sources(
+        &self,
+        amount: Option<u64>,
+        labels: Vec<String>,
+        policy: &RoutingPolicy,
+        sources: Vec<PaymentSource>,
+        options: InvoiceOptions,
+    ) -> Result<MutinyInvoice, MutinyError> {
+        let mut last_error = None;
+        for source in sources {
+            let result = match source {
+                PaymentSource::Federation(_) if options.description_hash.is_some() => continue,
+                PaymentSource::Federation(federation_id) => {
+                    let fedimint_client =
+                        self.federations.read().await.get(&federation_id).cloned();
+                    let Some(client) = fedimint_client else {
+                        continue;
+                    };
+                    if !policy.can_receive(&source, client.get_balance().await?) {
+                        continue;
+                    }
+
+                    client
+                        .get_invoice(amount.unwrap_or_default(), labels.clone(), options.clone())
+                        .await
+                }
+                PaymentSource::Node => {
+                    if !policy.can_receive(&source, self.node_manager.get_lightning_balance().await)
+                    {
+                        continue;
+                    }
+
+                    self.node_manager
+                        .create_invoice_with_options(amount, options.clone())
+                        .await
+                }
+            };
+
+            match result {
+                Ok(inv) => {
                     self.storage
                         .set_invoice_labels(inv.bolt11.clone().expect("just created"), labels)?;
                     return Ok(inv);
                 }
+                Err(e) => {
+                    log_debug!(self.logger, "could not create invoice with {source:?}: {e}");
+                    last_error = Some(e);
+                }
             }
         }
 
-        // Fallback to node_manager invoice creation if no federation invoice created
-        let inv = self.node_manager.create_invoice(amount).await?;
-        self.storage
-            .set_invoice_labels(inv.bolt11.clone().expect("just created"), labels)?;
+        Err(last_error.unwrap_or(MutinyError::InvoiceCreationFailed))
+    }
 
-        Ok(inv)
+    /// Creates a reusable BOLT12 offer that can be paid multiple times.
+    /// If no amount is provided, the payer will choose how much to pay.
+    /// The amount should be in satoshis.
+    ///
+    /// Offers are always paid to our lightning node, federations do not support them.
+    pub async fn create_offer(
+        &self,
+        amount: Option<u64>,
+        description: Option<String>,
+    ) -> Result<Offer, MutinyError> {
+        self.node_manager.create_offer(amount, description).await
+    }
+
+    /// Pays a BOLT12 offer from our lightning node.
+    /// An amount should only be provided if the offer does not have an amount.
+    /// The amount should be in satoshis.
+    pub async fn pay_offer(
+        &self,
+        offer: &Offer,
+        amt_sats: Option<u64>,
+        payer_note: Option<String>,
+        labels: Vec<String>,
+    ) -> Result<MutinyInvoice, MutinyError> {
+        self.node_manager
+            .pay_offer(None, offer, amt_sats, payer_note, labels)
+            .await
+    }
+
+    /// Requests payment for a BOLT12 refund, the creator of the refund will pay our node.
+    pub async fn request_refund(&self, refund: &Refund) -> Result<(), MutinyError> {
+        self.node_manager.request_refund(None, refund).await
     }
 
     /// Gets the current balance of the wallet.
@@ -1189,6 +1786,16 @@ impl<S: MutinyStorage> MutinyWallet<S> {
             activities.push(ActivityItem::ChannelClosed(chan));
         }
 
+        // Only show federation deposits that have been seen on-chain
+        for tx in list_federation_onchain_txs(&self.storage)? {
+            match tx.status {
+                HTLCStatus::Succeeded | HTLCStatus::InFlight => {
+                    activities.push(ActivityItem::FederationOnchain(tx));
+                }
+                HTLCStatus::Pending | HTLCStatus::Failed => {}
+            }
+        }
+
         // Sort all activities, newest first
         activities.sort_by(|a, b| b.cmp(a));
 
@@ -1216,7 +1823,19 @@ impl<S: MutinyStorage> MutinyWallet<S> {
                     None => vec![],
                     Some(i) => labels_map.get(&i).cloned().unwrap_or_default(),
                 };
-                let mutiny_invoice = MutinyInvoice::from(i.clone(), h, inbound, labels).ok();
+                let mutiny_invoice =
+                    MutinyInvoice::from(i.clone(), h, inbound, labels)
+                        .ok()
+                        .map(|mut invoice| {
+                            if !inbound {
+                                invoice.success_action = lnurlpay::read_success_action(
+                                    &self.storage,
+                                    &invoice.payment_hash,
+                                )
+                                .unwrap_or_default();
+                            }
+                            invoice
+                        });
 
                 // filter out expired invoices
                 mutiny_invoice.filter(|invoice| {
@@ -1238,6 +1857,15 @@ impl<S: MutinyStorage> MutinyWallet<S> {
     pub async fn get_invoice_by_hash(
         &self,
         hash: &sha256::Hash,
+    ) -> Result<MutinyInvoice, MutinyError> {
+        let mut invoice = self.find_invoice_by_hash(hash).await?;
+        invoice.success_action = lnurlpay::read_success_action(&self.storage, hash)?;
+        Ok(invoice)
+    }
+
+    async fn find_invoice_by_hash(
+        &self,
+        hash: &sha256::Hash,
     ) -> Result<MutinyInvoice, MutinyError> {
         // First, try to find the invoice in the node manager
         if let Ok(invoice) = self.node_manager.get_invoice_by_hash(hash).await {
@@ -1543,6 +2171,12 @@ impl<S: MutinyStorage> MutinyWallet<S> {
         Ok(messages)
     }
 
+    /// Subscribes to updates about payments, channels and syncing, so they
+    /// don't need to be polled for. Events are only sent while the wallet is running.
+    pub fn subscribe_events(&self) -> UnboundedReceiver<MutinyEvent> {
+        self.event_notifier.subscribe()
+    }
+
     /// Stops all of the nodes and background processes.
     /// Returns after node has been stopped.
     pub async fn stop(&self) -> Result<(), MutinyError> {
@@ -1616,7 +2250,7 @@ impl<S: MutinyStorage> MutinyWallet<S> {
     pub async fn restore_mnemonic(mut storage: S, m: Mnemonic) -> Result<(), MutinyError> {
         // Delete our storage but insert some device specific data
         let device_id = storage.get_device_id()?;
-        let logs: Option<Vec<String>> = storage.get_data(LOGGING_KEY)?;
+        let logs: Option<serde_json::Value> = storage.get_data(LOGGING_KEY)?;
         storage.stop();
         S::clear().await?;
         storage.start().await?;
@@ -1651,6 +2285,7 @@ impl<S: MutinyStorage> MutinyWallet<S> {
             self.xprivkey,
             self.storage.clone(),
             self.network,
+            self.event_notifier.clone(),
             self.logger.clone(),
             self.federation_storage.clone(),
             self.federations.clone(),
@@ -1739,6 +2374,69 @@ impl<S: MutinyStorage> MutinyWallet<S> {
         Ok(FederationBalances { balances })
     }
 
+    /// Creates an address to deposit bitcoin into the given federation.
+    /// Once the transaction confirms, the federation gives us ecash for it.
+    pub async fn get_federation_deposit_address(
+        &self,
+        federation_id: FederationId,
+        labels: Vec<String>,
+    ) -> Result<FederationOnchainTx, MutinyError> {
+        let federations = self.federations.read().await;
+        let fedimint_client = federations
+            .get(&federation_id)
+            .ok_or(MutinyError::NotFound)?;
+
+        fedimint_client.get_deposit_address(labels).await
+    }
+
+    /// Estimates the fee the federation charges to withdraw to the given address.
+    /// The amount is in satoshis.
+    pub async fn estimate_federation_withdrawal_fee(
+        &self,
+        federation_id: FederationId,
+        address: Address,
+        amount: u64,
+    ) -> Result<u64, MutinyError> {
+        if !address.is_valid_for_network(self.network) {
+            return Err(MutinyError::IncorrectNetwork(address.network));
+        }
+
+        let federations = self.federations.read().await;
+        let fedimint_client = federations
+            .get(&federation_id)
+            .ok_or(MutinyError::NotFound)?;
+
+        fedimint_client
+            .estimate_withdrawal_fee(address, amount)
+            .await
+    }
+
+    /// Withdraws from the given federation to an on-chain address.
+    /// The amount is in satoshis, the federation's fee is paid on top of it.
+    pub async fn withdraw_from_federation(
+        &self,
+        federation_id: FederationId,
+        address: Address,
+        amount: u64,
+        labels: Vec<String>,
+    ) -> Result<FederationOnchainTx, MutinyError> {
+        if !address.is_valid_for_network(self.network) {
+            return Err(MutinyError::IncorrectNetwork(address.network));
+        }
+
+        let federations = self.federations.read().await;
+        let fedimint_client = federations
+            .get(&federation_id)
+            .ok_or(MutinyError::NotFound)?;
+
+        fedimint_client.withdraw(address, amount, labels).await
+    }
+
+    /// Lists all deposits into and withdrawals from our federations.
+    pub fn list_federation_onchain_txs(&self) -> Result<Vec<FederationOnchainTx>, MutinyError> {
+        list_federation_onchain_txs(&self.storage)
+    }
+
     /// Starts a background process that will check pending fedimint operations
     pub(crate) async fn start_fedimint_background_checker(&self) {
         let logger = self.logger.clone();
@@ -1823,63 +2521,230 @@ impl<S: MutinyStorage> MutinyWallet<S> {
         lnurl: &LnUrl,
         amount_sats: u64,
         zap_npub: Option<XOnlyPublicKey>,
-        mut labels: Vec<String>,
+        labels: Vec<String>,
         comment: Option<String>,
+        payer_data: Option<PayerData>,
     ) -> Result<MutinyInvoice, MutinyError> {
-        let response = self.lnurl_client.make_request(&lnurl.url).await?;
+        let pay_request =
+            lnurlpay::get_lnurl_pay_request(&self.lnurl_http_client, &lnurl.url).await?;
+
+        // if user's npub is given, do an anon zap
+        match zap_npub {
+            Some(zap_npub) => {
+                let zap_request = self
+                    .nostr
+                    .create_zap_request(
+                        zap_npub,
+                        None,
+                        amount_sats * 1000,
+                        lnurl.encode(),
+                        comment,
+                        ZapPrivacy::Anonymous,
+                    )
+                    .await?;
 
-        match response {
-            LnUrlResponse::LnUrlPayResponse(pay) => {
-                let msats = amount_sats * 1000;
-
-                // if user's npub is given, do an anon zap
-                let (zap_request, comment) = match zap_npub {
-                    Some(zap_npub) => {
-                        let data = ZapRequestData {
-                            public_key: zap_npub,
-                            relays: vec![
-                                "wss://relay.mutinywallet.com".into(),
-                                "wss://relay.primal.net".into(),
-                            ],
-                            message: comment.unwrap_or_default(),
-                            amount: Some(msats),
-                            lnurl: Some(lnurl.encode()),
-                            event_id: None,
-                            event_coordinate: None,
-                        };
-                        let event = nip57::anonymous_zap_request(data)?;
+                self.lnurl_pay_with_zap_request(
+                    lnurl,
+                    &pay_request,
+                    amount_sats,
+                    Some(zap_request.as_json()),
+                    labels,
+                    None,
+                    payer_data,
+                )
+                .await
+            }
+            None => {
+                let comment = comment.filter(|c| !c.is_empty());
+                self.lnurl_pay_with_zap_request(
+                    lnurl,
+                    &pay_request,
+                    amount_sats,
+                    None,
+                    labels,
+                    comment,
+                    payer_data,
+                )
+                .await
+            }
+        }
+    }
 
-                        (Some(event.as_json()), None)
-                    }
-                    None => (None, comment.filter(|c| !c.is_empty())),
-                };
+    /// Zaps a nostr profile, or one of their events, through their lightning address (NIP-57).
+    ///
+    /// Waits a bit for the recipient to publish the zap receipt and checks it is for the paid invoice.
+    pub async fn zap(
+        &self,
+        npub: XOnlyPublicKey,
+        amount_sats: u64,
+        event_id: Option<EventId>,
+        comment: Option<String>,
+        privacy: ZapPrivacy,
+        labels: Vec<String>,
+    ) -> Result<SentZap, MutinyError> {
+        let lnurl = self.get_zap_lnurl(npub).await?;
+
+        // the receipt has to be signed by the key the service gives us
+        let pay_request =
+            lnurlpay::get_lnurl_pay_request(&self.lnurl_http_client, &lnurl.url).await?;
+        let allows_nostr = pay_request
+            .get("allowsNostr")
+            .and_then(|a| a.as_bool())
+            .unwrap_or(false);
+        let nostr_pubkey = pay_request
+            .get("nostrPubkey")
+            .and_then(|p| p.as_str())
+            .and_then(|p| XOnlyPublicKey::from_str(p).ok());
+        let nostr_pubkey = match nostr_pubkey {
+            Some(nostr_pubkey) if allows_nostr => nostr_pubkey,
+            _ => return Err(MutinyError::IncorrectLnUrlFunction),
+        };
 
-                let invoice = self
-                    .lnurl_client
-                    .get_invoice(&pay, msats, zap_request, comment.as_deref())
-                    .await?;
+        let zap_request = self
+            .nostr
+            .create_zap_request(
+                npub,
+                event_id,
+                amount_sats * 1000,
+                lnurl.encode(),
+                comment.filter(|c| !c.is_empty()),
+                privacy,
+            )
+            .await?;
+
+        let invoice = self
+            .lnurl_pay_with_zap_request(
+                &lnurl,
+                &pay_request,
+                amount_sats,
+                Some(zap_request.as_json()),
+                labels,
+                None,
+                None,
+            )
+            .await?;
+
+        let receipt = match invoice.bolt11.as_ref() {
+            Some(bolt11) => self
+                .nostr
+                .wait_for_zap_receipt(&zap_request, bolt11, nostr_pubkey)
+                .await
+                .unwrap_or_else(|e| {
+                    log_warn!(self.logger, "Failed to get zap receipt: {e}");
+                    None
+                }),
+            None => None,
+        };
+
+        Ok(SentZap {
+            invoice,
+            zap_request: zap_request.id,
+            receipt: receipt.map(|r| r.id),
+        })
+    }
 
-                let invoice = Bolt11Invoice::from_str(invoice.invoice())?;
+    /// Finds the LNURL to zap a nostr profile with, from our contacts or their profile metadata.
+    async fn get_zap_lnurl(&self, npub: XOnlyPublicKey) -> Result<LnUrl, MutinyError> {
+        let contact = self
+            .storage
+            .get_contacts()?
+            .into_values()
+            .find(|c| c.npub == Some(npub) && (c.ln_address.is_some() || c.lnurl.is_some()));
 
-                if invoice
-                    .amount_milli_satoshis()
-                    .is_some_and(|amt| msats == amt)
-                {
-                    // If we don't have any labels, see if this matches a contact
-                    if labels.is_empty() {
-                        if let Some(label) = self.storage.get_contact_for_lnurl(lnurl)? {
-                            labels.insert(0, label)
-                        }
-                    }
+        let contact = match contact {
+            Some(contact) => contact,
+            None => {
+                let url = self
+                    .config
+                    .primal_url
+                    .as_deref()
+                    .unwrap_or("https://primal-cache.mutinywallet.com/api");
+                let client = reqwest::Client::new();
+
+                let body = json!(["user_infos", {"pubkeys": [npub.to_hex()] }]);
+                let data: Vec<Value> = Self::primal_request(&client, url, body).await?;
+                let metadata = parse_profile_metadata(data)
+                    .remove(&npub)
+                    .ok_or(MutinyError::NotFound)?;
+                Contact::create_from_metadata(npub, metadata)
+            }
+        };
 
-                    self.pay_invoice(&invoice, None, labels).await
-                } else {
-                    log_error!(self.logger, "LNURL return invoice with incorrect amount");
-                    Err(MutinyError::LnUrlFailure)
+        // NIP-57 prefers the lightning address
+        contact
+            .ln_address
+            .map(|address| address.lnurl())
+            .or(contact.lnurl)
+            .ok_or(MutinyError::NotFound)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn lnurl_pay_with_zap_request(
+        &self,
+        lnurl: &LnUrl,
+        pay_request: &Value,
+        amount_sats: u64,
+        zap_request: Option<String>,
+        mut labels: Vec<String>,
+        comment: Option<String>,
+        payer_data: Option<PayerData>,
+    ) -> Result<MutinyInvoice, MutinyError> {
+        let msats = amount_sats * 1000;
+
+        let lnurl_invoice = lnurlpay::get_lnurl_pay_invoice(
+            &self.lnurl_http_client,
+            pay_request,
+            msats,
+            zap_request.clone(),
+            comment,
+            payer_data.as_ref(),
+        )
+        .await?;
+        let invoice = lnurl_invoice.bolt11.clone();
+
+        // a zap's invoice has to commit to the zap request, or its receipt can't be verified
+        if let Some(zap_request) = zap_request.as_ref() {
+            if !invoice_commits_to(&invoice, zap_request) {
+                log_error!(
+                    self.logger,
+                    "LNURL returned zap invoice without the zap request's description hash"
+                );
+                return Err(MutinyError::LnUrlFailure);
+            }
+        }
+
+        if invoice
+            .amount_milli_satoshis()
+            .is_some_and(|amt| msats == amt)
+        {
+            // If we don't have any labels, see if this matches a contact
+            if labels.is_empty() {
+                if let Some(label) = self.storage.get_contact_for_lnurl(lnurl)? {
+                    labels.insert(0, label)
                 }
             }
-            LnUrlResponse::LnUrlWithdrawResponse(_) => Err(MutinyError::IncorrectLnUrlFunction),
-            LnUrlResponse::LnUrlChannelResponse(_) => Err(MutinyError::IncorrectLnUrlFunction),
+
+            let mut paid = self.pay_invoice(&invoice, None, labels).await?;
+
+            let preimage = paid
+                .preimage
+                .as_ref()
+                .and_then(|p| <[u8; 32]>::from_hex(p).ok());
+            if let Some(preimage) = preimage {
+                paid.success_action = lnurl_invoice.success_action(&preimage, self.logger.as_ref());
+            }
+            if let Some(success_action) = paid.success_action.as_ref() {
+                lnurlpay::persist_success_action(
+                    &self.storage,
+                    &paid.payment_hash,
+                    success_action,
+                )?;
+            }
+
+            Ok(paid)
+        } else {
+            log_error!(self.logger, "LNURL return invoice with incorrect amount");
+            Err(MutinyError::LnUrlFailure)
         }
     }
 
@@ -1928,6 +2793,11 @@ impl<S: MutinyStorage> MutinyWallet<S> {
     pub fn is_safe_mode(&self) -> bool {
         self.safe_mode
     }
+
+    /// Returns true if the on-chain wallet has no keys, so its PSBTs must be signed externally.
+    pub fn is_watch_only(&self) -> bool {
+        self.node_manager.is_watch_only()
+    }
 }
 
 impl<S: MutinyStorage> InvoiceHandler for MutinyWallet<S> {
@@ -1962,12 +2832,56 @@ impl<S: MutinyStorage> InvoiceHandler for MutinyWallet<S> {
     ) -> Result<MutinyInvoice, MutinyError> {
         self.create_lightning_invoice(amount, labels).await
     }
+
+    async fn create_invoice_with_options(
+        &self,
+        amount: Option<u64>,
+        labels: Vec<String>,
+        options: InvoiceOptions,
+    ) -> Result<MutinyInvoice, MutinyError> {
+        self.create_lightning_invoice_with_options(amount, labels, options)
+            .await
+    }
+
+    async fn keysend(
+        &self,
+        to_node: PublicKey,
+        amt_sats: u64,
+        message: Option<String>,
+        custom_tlvs: Vec<CustomTlv>,
+        labels: Vec<String>,
+    ) -> Result<MutinyInvoice, MutinyError> {
+        self.node_manager
+            .keysend(None, to_node, amt_sats, message, custom_tlvs, labels)
+            .await
+    }
+
+    async fn create_bip21(
+        &self,
+        amount: Option<u64>,
+        labels: Vec<String>,
+    ) -> Result<MutinyBip21RawMaterials, MutinyError> {
+        self.create_bip21(amount, labels).await
+    }
+
+    async fn get_invoice_by_hash(&self, hash: &sha256::Hash) -> Result<MutinyInvoice, MutinyError> {
+        self.get_invoice_by_hash(hash).await
+    }
+
+    async fn get_balance(&self) -> Result<MutinyBalance, MutinyError> {
+        self.get_balance().await
+    }
+
+    async fn get_activity(&self) -> Result<Vec<ActivityItem>, MutinyError> {
+        self.get_activity().await
+    }
 }
 
 async fn create_federations<S: MutinyStorage>(
     federation_storage: FederationStorage,
     c: &MutinyWalletConfig,
     storage: &S,
+    event_notifier: &EventNotifier,
     logger: &Arc<MutinyLogger>,
 ) -> Result<Arc<RwLock<HashMap<FederationId, Arc<FederationClient<S>>>>>, MutinyError> {
     let federations = federation_storage.federations.into_iter();
@@ -1979,6 +2893,7 @@ async fn create_federations<S: MutinyStorage>(
             c.xprivkey,
             storage,
             c.network,
+            event_notifier.clone(),
             logger.clone(),
         )
         .await?;
@@ -1997,6 +2912,7 @@ pub(crate) async fn create_new_federation<S: MutinyStorage>(
     xprivkey: ExtendedPrivKey,
     storage: S,
     network: Network,
+    event_notifier: EventNotifier,
     logger: Arc<MutinyLogger>,
     federation_storage: Arc<RwLock<FederationStorage>>,
     federations: Arc<RwLock<HashMap<FederationId, Arc<FederationClient<S>>>>>,
@@ -2042,6 +2958,7 @@ pub(crate) async fn create_new_federation<S: MutinyStorage>(
         xprivkey,
         &storage,
         network,
+        event_notifier,
         logger.clone(),
     )
     .await?;
@@ -2069,8 +2986,8 @@ pub(crate) async fn create_new_federation<S: MutinyStorage>(
 #[cfg(test)]
 mod tests {
     use crate::{
-        encrypt::encryption_key_from_pass, generate_seed, nodemanager::NodeManager, MutinyWallet,
-        MutinyWalletBuilder, MutinyWalletConfigBuilder,
+        encrypt::encryption_key_from_pass, error::MutinyError, generate_seed,
+        nodemanager::NodeManager, MutinyWallet, MutinyWalletBuilder, MutinyWalletConfigBuilder,
     };
     use bitcoin::util::bip32::ExtendedPrivKey;
     use bitcoin::Network;
@@ -2328,6 +3245,35 @@ mod tests {
         assert_ne!(contact.name, incorrect_name);
     }
 
+    #[test]
+    async fn pay_invoice_insufficient_balance() {
+        let test_name = "pay_invoice_insufficient_balance";
+        log!("{}", test_name);
+
+        let mnemonic = generate_seed(12).unwrap();
+        let network = Network::Regtest;
+        let xpriv = ExtendedPrivKey::new_master(network, &mnemonic.to_seed("")).unwrap();
+        let storage = MemoryStorage::new(None, None, None);
+        let config = MutinyWalletConfigBuilder::new(xpriv)
+            .with_network(network)
+            .build();
+        let mw = MutinyWalletBuilder::new(xpriv, storage.clone())
+            .with_config(config)
+            .build()
+            .await
+            .expect("mutiny wallet should initialize");
+
+        // no node with balance and no federations, so the combined balance isn't enough either
+        let (invoice, _) = create_dummy_invoice(Some(10_000_000), network, None);
+        let result = mw.pay_invoice(&invoice, None, vec![]).await;
+        assert_eq!(result, Err(MutinyError::InsufficientBalance));
+
+        // invoices for other networks are rejected before trying any source
+        let (invoice, _) = create_dummy_invoice(Some(10_000_000), Network::Bitcoin, None);
+        let result = mw.pay_invoice(&invoice, None, vec![]).await;
+        assert_eq!(result, Err(MutinyError::IncorrectNetwork(Network::Bitcoin)));
+    }
+
     #[test]
     async fn get_dm_conversation_test() {
         // test nsec I made and sent dms to
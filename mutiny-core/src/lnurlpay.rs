@@ -0,0 +1,1527 @@
+use crate::encrypt::decrypt_with_iv;
+use crate::error::MutinyError;
+use crate::logging::MutinyLogger;
+use crate::storage::MutinyStorage;
+use crate::{utils, HTLCStatus, InvoiceOptions, MutinyWallet};
+use async_lock::Mutex;
+use async_trait::async_trait;
+use bitcoin::hashes::hex::ToHex;
+use bitcoin::hashes::{sha256, Hash};
+use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use futures::{pin_mut, select, FutureExt, StreamExt};
+use lightning::util::logger::Logger;
+use lightning::{log_debug, log_error, log_info, log_warn};
+use lightning_invoice::Bolt11Invoice;
+use nostr::key::XOnlyPublicKey;
+use nostr::prelude::{decrypt, encrypt};
+use nostr::{Event, EventBuilder, EventId, Filter, JsonUtil, Keys, Kind, Tag, Timestamp};
+use nostr_sdk::{Client, RelayPoolNotification};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+const LNURL_PAY_RECEIVED_PREFIX_KEY: &str = "lnurlp_received/";
+const LNURL_PAY_SUCCESS_ACTION_PREFIX_KEY: &str = "lnurlp_success_action/";
+
+/// How often we check if zapped invoices were paid so we can publish their receipts
+const ZAP_RECEIPT_INTERVAL_SECS: u64 = 10;
+/// How long after a zapped invoice expires we stop checking if it was paid
+const ZAP_RECEIPT_GRACE_SECS: u64 = 60 * 60;
+
+/// Most HTTP connections we handle at once, others are turned away
+#[cfg(not(target_arch = "wasm32"))]
+const MAX_HTTP_CONNECTIONS: usize = 64;
+/// How long we wait on a payer's socket, or for our response to their request
+#[cfg(not(target_arch = "wasm32"))]
+const HTTP_TIMEOUT_SECS: u64 = 30;
+/// Largest request we read, LNURL-pay requests are a single line and a few headers
+#[cfg(not(target_arch = "wasm32"))]
+const MAX_HTTP_REQUEST_BYTES: u64 = 16 * 1024;
+
+/// Kind of the events an LNURL proxy forwards HTTP requests to us with
+pub const LNURL_PROXY_REQUEST_KIND: u64 = 21_001;
+/// Kind of the events we answer an LNURL proxy with
+pub const LNURL_PROXY_RESPONSE_KIND: u64 = 21_002;
+
+/// A payer data field we ask for, see LUD-18
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PayerDataField {
+    pub mandatory: bool,
+}
+
+/// The payer data we ask payers for, see LUD-18
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct PayerDataConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<PayerDataField>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pubkey: Option<PayerDataField>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identifier: Option<PayerDataField>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<PayerDataField>,
+}
+
+impl PayerDataConfig {
+    fn field(&self, name: &str) -> Option<PayerDataField> {
+        match name {
+            "name" => self.name,
+            "pubkey" => self.pubkey,
+            "identifier" => self.identifier,
+            "email" => self.email,
+            _ => None,
+        }
+    }
+
+    /// Checks the payer data given to the callback, returning the reason if it is not valid
+    fn validate(&self, payer_data: &Value) -> Result<(), String> {
+        let fields = payer_data
+            .as_object()
+            .ok_or("Payer data must be an object".to_string())?;
+
+        if let Some(name) = fields.keys().find(|k| self.field(k).is_none()) {
+            return Err(format!("Payer data field {name} was not requested"));
+        }
+
+        for name in ["name", "pubkey", "identifier", "email"] {
+            let mandatory = self.field(name).is_some_and(|f| f.mandatory);
+            if mandatory && !fields.contains_key(name) {
+                return Err(format!("Payer data field {name} is mandatory"));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Settings for our LNURL-pay server, this is what payers see when
+/// they pay to our lightning address.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct LnUrlPayConfig {
+    pub username: String,
+    pub domain: String,
+    pub min_sendable_msat: u64,
+    pub max_sendable_msat: u64,
+    /// Longest comment a payer can include, 0 disables comments (LUD-12)
+    pub comment_allowed: u16,
+    /// Shown to payers, defaults to "Pay to {lightning address}"
+    pub description: Option<String>,
+    /// Payer data to ask for (LUD-18)
+    pub payer_data: Option<PayerDataConfig>,
+    /// If we accept NIP-57 zaps and publish receipts for them
+    pub allow_zaps: bool,
+    /// Shown to the payer after the payment succeeds (LUD-09)
+    pub success_message: Option<String>,
+}
+
+impl LnUrlPayConfig {
+    pub fn new(username: String, domain: String) -> Self {
+        Self {
+            username,
+            domain,
+            min_sendable_msat: 1_000,
+            max_sendable_msat: 1_000_000_000,
+            comment_allowed: 255,
+            description: None,
+            payer_data: None,
+            allow_zaps: true,
+            success_message: None,
+        }
+    }
+
+    pub fn lightning_address(&self) -> String {
+        format!("{}@{}", self.username, self.domain)
+    }
+
+    /// The metadata payers commit to in the invoice's description hash (LUD-06)
+    fn metadata(&self) -> String {
+        let description = self
+            .description
+            .clone()
+            .unwrap_or_else(|| format!("Pay to {}", self.lightning_address()));
+
+        json!([
+            ["text/plain", description],
+            ["text/identifier", self.lightning_address()]
+        ])
+        .to_string()
+    }
+
+    fn metadata_path(&self) -> String {
+        format!("/.well-known/lnurlp/{}", self.username)
+    }
+
+    fn callback_path(&self) -> String {
+        format!("/lnurlp/{}/callback", self.username)
+    }
+}
+
+/// A request from a payer's wallet to our LNURL-pay server.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct LnUrlPayRequest {
+    /// Identifier set by the transport so it can match a response to its request
+    pub id: String,
+    pub path: String,
+    /// The query string of the request, without the leading `?`
+    pub query: String,
+}
+
+/// Our response to a [`LnUrlPayRequest`], the body is always JSON.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct LnUrlPayResponse {
+    pub status: u16,
+    pub body: String,
+}
+
+impl LnUrlPayResponse {
+    fn ok(body: Value) -> Self {
+        Self {
+            status: 200,
+            body: body.to_string(),
+        }
+    }
+
+    fn error(status: u16, reason: impl Into<String>) -> Self {
+        let reason: String = reason.into();
+        Self {
+            status,
+            body: json!({ "status": "ERROR", "reason": reason }).to_string(),
+        }
+    }
+}
+
+/// Carries LNURL-pay requests between payers and our server.
+///
+/// This could be a local HTTP server, or a proxy on our domain that forwards requests over nostr.
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+pub trait LnUrlPayTransport: Send + Sync {
+    /// The URL payers reach us at, e.g. `https://example.com`
+    fn base_url(&self) -> String;
+
+    /// Waits for the next request from a payer.
+    /// Returns `None` once the transport is closed.
+    async fn next_request(&self) -> Option<LnUrlPayRequest>;
+
+    /// Sends the response for a request given by [`LnUrlPayTransport::next_request`].
+    async fn respond(
+        &self,
+        request: &LnUrlPayRequest,
+        response: LnUrlPayResponse,
+    ) -> Result<(), MutinyError>;
+}
+
+/// A payment made to our lightning address.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct LnUrlPayReceived {
+    pub payment_hash: sha256::Hash,
+    pub bolt11: Bolt11Invoice,
+    pub amount_msat: u64,
+    /// The payer's comment (LUD-12)
+    pub comment: Option<String>,
+    /// The payer data given by the payer (LUD-18)
+    pub payer_data: Option<Value>,
+    /// The raw NIP-57 zap request, if this payment is a zap
+    pub zap_request: Option<String>,
+    /// Our zap receipt, once the invoice was paid and the receipt published
+    pub zap_receipt: Option<EventId>,
+    pub created_at: u64,
+}
+
+fn lnurl_pay_received_key(payment_hash: &sha256::Hash) -> String {
+    format!("{LNURL_PAY_RECEIVED_PREFIX_KEY}{}", payment_hash.to_hex())
+}
+
+pub(crate) fn persist_lnurl_pay_received<S: MutinyStorage>(
+    storage: &S,
+    received: &LnUrlPayReceived,
+) -> Result<(), MutinyError> {
+    storage.set_data(
+        lnurl_pay_received_key(&received.payment_hash),
+        received,
+        None,
+    )
+}
+
+pub(crate) fn list_lnurl_pay_received<S: MutinyStorage>(
+    storage: &S,
+) -> Result<Vec<LnUrlPayReceived>, MutinyError> {
+    let map: HashMap<String, LnUrlPayReceived> =
+        storage.scan(LNURL_PAY_RECEIVED_PREFIX_KEY, None)?;
+    Ok(map.into_values().collect())
+}
+
+/// Checks a NIP-57 zap request given to the callback,
+/// returning the reason if it is not valid.
+fn validate_zap_request(raw: &str, amount_msat: u64) -> Result<Event, String> {
+    let event = Event::from_json(raw).map_err(|_| "Invalid zap request".to_string())?;
+    if event.verify().is_err() {
+        return Err("Invalid zap request signature".to_string());
+    }
+    if event.kind != Kind::ZapRequest {
+        return Err("Zap request must be kind 9734".to_string());
+    }
+
+    let count_tags = |name: &str| {
+        event
+            .tags
+            .iter()
+            .filter(|t| t.as_vec().first().is_some_and(|n| n == name))
+            .count()
+    };
+    if count_tags("p") != 1 {
+        return Err("Zap request must have exactly one p tag".to_string());
+    }
+    if count_tags("e") > 1 {
+        return Err("Zap request must have at most one e tag".to_string());
+    }
+    if zap_request_relays(&event).is_empty() {
+        return Err("Zap request must have relays".to_string());
+    }
+
+    let amount = event
+        .tags
+        .iter()
+        .map(|t| t.as_vec())
+        .find(|t| t.first().is_some_and(|n| n == "amount"))
+        .and_then(|t| t.get(1).cloned());
+    if let Some(amount) = amount {
+        if amount.parse::<u64>().ok() != Some(amount_msat) {
+            return Err("Zap request amount does not match".to_string());
+        }
+    }
+
+    Ok(event)
+}
+
+fn zap_request_relays(zap_request: &Event) -> Vec<String> {
+    zap_request
+        .tags
+        .iter()
+        .map(|t| t.as_vec())
+        .find(|t| t.first().is_some_and(|n| n == "relays"))
+        .map(|t| t.into_iter().skip(1).collect())
+        .unwrap_or_default()
+}
+
+/// Creates the NIP-57 zap receipt for a paid zap request.
+fn create_zap_receipt(
+    keys: &Keys,
+    raw_zap_request: &str,
+    bolt11: &Bolt11Invoice,
+    preimage: Option<String>,
+) -> Result<Event, MutinyError> {
+    let zap_request = Event::from_json(raw_zap_request)
+        .map_err(|e| MutinyError::Other(anyhow::anyhow!("Invalid zap request: {e}")))?;
+
+    // the receipt references the same things the request did
+    let mut tags: Vec<Tag> = zap_request
+        .tags
+        .iter()
+        .filter(|t| {
+            t.as_vec()
+                .first()
+                .is_some_and(|n| n == "p" || n == "e" || n == "a")
+        })
+        .cloned()
+        .collect();
+    tags.push(Tag::PublicKey {
+        public_key: zap_request.pubkey,
+        relay_url: None,
+        alias: None,
+        uppercase: true,
+    });
+    tags.push(Tag::Bolt11(bolt11.to_string()));
+    tags.push(Tag::Description(raw_zap_request.to_string()));
+    if let Some(preimage) = preimage {
+        tags.push(Tag::Preimage(preimage));
+    }
+
+    let receipt = EventBuilder::new(Kind::ZapReceipt, "", tags).to_event(keys)?;
+    Ok(receipt)
+}
+
+/// Answers LNURL-pay requests for our lightning address and
+/// publishes receipts for zaps paid to it.
+pub struct LnUrlPayServer<S: MutinyStorage> {
+    wallet: MutinyWallet<S>,
+    config: LnUrlPayConfig,
+    /// Signs our zap receipts, its public key is given to zappers as `nostrPubkey`
+    keys: Keys,
+    logger: Arc<MutinyLogger>,
+}
+
+impl<S: MutinyStorage> LnUrlPayServer<S> {
+    pub fn new(
+        wallet: MutinyWallet<S>,
+        config: LnUrlPayConfig,
+        keys: Keys,
+        logger: Arc<MutinyLogger>,
+    ) -> Self {
+        Self {
+            wallet,
+            config,
+            keys,
+            logger,
+        }
+    }
+
+    /// Handles requests from the transport until it is closed or we are stopped.
+    pub(crate) async fn run(&self, transport: Arc<dyn LnUrlPayTransport>, stop: Arc<AtomicBool>) {
+        let base_url = transport.base_url();
+        let mut last_zap_check = 0;
+        loop {
+            if stop.load(Ordering::Relaxed) {
+                break;
+            }
+
+            let now = utils::now().as_secs();
+            if self.config.allow_zaps
+                && now.saturating_sub(last_zap_check) >= ZAP_RECEIPT_INTERVAL_SECS
+            {
+                last_zap_check = now;
+                if let Err(e) = self.publish_zap_receipts().await {
+                    log_warn!(self.logger, "Failed to publish zap receipts: {e}");
+                }
+            }
+
+            let read_fut = transport.next_request().fuse();
+            let delay_fut = Box::pin(utils::sleep(1_000)).fuse();
+            pin_mut!(read_fut, delay_fut);
+            select! {
+                request = read_fut => {
+                    let request = match request {
+                        Some(request) => request,
+                        None => {
+                            log_info!(self.logger, "LNURL-pay transport closed");
+                            break;
+                        }
+                    };
+
+                    let response = self.handle_request(&base_url, &request).await;
+                    if let Err(e) = transport.respond(&request, response).await {
+                        log_warn!(self.logger, "Failed to send LNURL-pay response: {e}");
+                    }
+                }
+                _ = delay_fut => {}
+            }
+        }
+    }
+
+    /// Answers a payer's request, `base_url` is where the transport is reachable.
+    pub async fn handle_request(
+        &self,
+        base_url: &str,
+        request: &LnUrlPayRequest,
+    ) -> LnUrlPayResponse {
+        let path = request.path.trim_end_matches('/');
+        if path == self.config.metadata_path() {
+            self.pay_request(base_url)
+        } else if path == self.config.callback_path() {
+            self.callback(&request.query).await
+        } else {
+            LnUrlPayResponse::error(404, "Not found")
+        }
+    }
+
+    /// The LUD-06 pay request, this is what payers get for our lightning address
+    fn pay_request(&self, base_url: &str) -> LnUrlPayResponse {
+        let mut response = json!({
+            "tag": "payRequest",
+            "callback": format!("{}{}", base_url.trim_end_matches('/'), self.config.callback_path()),
+            "minSendable": self.config.min_sendable_msat,
+            "maxSendable": self.config.max_sendable_msat,
+            "metadata": self.config.metadata(),
+        });
+
+        if self.config.comment_allowed > 0 {
+            response["commentAllowed"] = json!(self.config.comment_allowed);
+        }
+        if let Some(payer_data) = self.config.payer_data.as_ref() {
+            response["payerData"] = json!(payer_data);
+        }
+        if self.config.allow_zaps {
+            response["allowsNostr"] = json!(true);
+            response["nostrPubkey"] = json!(self.keys.public_key().to_string());
+        }
+
+        LnUrlPayResponse::ok(response)
+    }
+
+    /// The LUD-06 callback, creates the invoice for the payer
+    async fn callback(&self, query: &str) -> LnUrlPayResponse {
+        let params: HashMap<String, String> = url::form_urlencoded::parse(query.as_bytes())
+            .into_owned()
+            .collect();
+
+        let amount_msat = match params.get("amount").and_then(|a| a.parse::<u64>().ok()) {
+            Some(amount) => amount,
+            None => return LnUrlPayResponse::error(400, "Missing amount"),
+        };
+        if amount_msat < self.config.min_sendable_msat
+            || amount_msat > self.config.max_sendable_msat
+        {
+            return LnUrlPayResponse::error(400, "Amount out of range");
+        }
+        // our invoices are for whole sats
+        if amount_msat % 1_000 != 0 {
+            return LnUrlPayResponse::error(400, "Amount must be a whole number of sats");
+        }
+
+        let comment = params.get("comment").filter(|c| !c.is_empty()).cloned();
+        if let Some(comment) = comment.as_ref() {
+            if comment.chars().count() > self.config.comment_allowed as usize {
+                return LnUrlPayResponse::error(400, "Comment too long");
+            }
+        }
+
+        let metadata = self.config.metadata();
+        let mut payer_data = None;
+        let mut zap_request = None;
+        let description_hash = match (params.get("nostr"), params.get("payerdata")) {
+            (Some(raw), _) if self.config.allow_zaps => {
+                if let Err(reason) = validate_zap_request(raw, amount_msat) {
+                    return LnUrlPayResponse::error(400, reason);
+                }
+                zap_request = Some(raw.clone());
+                sha256::Hash::hash(raw.as_bytes())
+            }
+            (Some(_), _) => return LnUrlPayResponse::error(400, "Zaps are not allowed"),
+            (None, Some(raw)) => {
+                let config = match self.config.payer_data.as_ref() {
+                    Some(config) => config,
+                    None => return LnUrlPayResponse::error(400, "Payer data is not allowed"),
+                };
+                let value: Value = match serde_json::from_str(raw) {
+                    Ok(value) => value,
+                    Err(_) => return LnUrlPayResponse::error(400, "Invalid payer data"),
+                };
+                if let Err(reason) = config.validate(&value) {
+                    return LnUrlPayResponse::error(400, reason);
+                }
+                payer_data = Some(value);
+                sha256::Hash::hash(format!("{metadata}{raw}").as_bytes())
+            }
+            (None, None) => {
+                let mandatory = self
+                    .config
+                    .payer_data
+                    .as_ref()
+                    .is_some_and(|c| c.validate(&json!({})).is_err());
+                if mandatory {
+                    return LnUrlPayResponse::error(400, "Missing payer data");
+                }
+                sha256::Hash::hash(metadata.as_bytes())
+            }
+        };
+
+        let invoice = match self
+            .wallet
+            .create_lightning_invoice_with_options(
+                Some(amount_msat / 1_000),
+                vec![],
+                InvoiceOptions {
+                    description_hash: Some(description_hash),
+                    ..Default::default()
+                },
+            )
+            .await
+        {
+            Ok(invoice) => invoice,
+            Err(e) => {
+                log_error!(self.logger, "Failed to create LNURL-pay invoice: {e}");
+                return LnUrlPayResponse::error(500, "Failed to create invoice");
+            }
+        };
+        let bolt11 = match invoice.bolt11 {
+            Some(bolt11) => bolt11,
+            None => return LnUrlPayResponse::error(500, "Failed to create invoice"),
+        };
+
+        let received = LnUrlPayReceived {
+            payment_hash: invoice.payment_hash,
+            bolt11: bolt11.clone(),
+            amount_msat,
+            comment,
+            payer_data,
+            zap_request,
+            zap_receipt: None,
+            created_at: utils::now().as_secs(),
+        };
+        if let Err(e) = persist_lnurl_pay_received(&self.wallet.storage, &received) {
+            log_error!(self.logger, "Failed to save LNURL-pay invoice: {e}");
+            return LnUrlPayResponse::error(500, "Failed to create invoice");
+        }
+        log_debug!(
+            self.logger,
+            "Created LNURL-pay invoice for {amount_msat} msats"
+        );
+
+        let mut response = json!({ "pr": bolt11.to_string(), "routes": [] });
+        if let Some(message) = self.config.success_message.as_ref() {
+            response["successAction"] = json!({ "tag": "message", "message": message });
+        }
+
+        LnUrlPayResponse::ok(response)
+    }
+
+    /// Publishes receipts for zaps that have been paid since we last checked.
+    /// Zaps whose invoice expired a while ago can no longer be paid, so they are skipped.
+    async fn publish_zap_receipts(&self) -> Result<(), MutinyError> {
+        let now = utils::now().as_secs();
+        let pending = list_lnurl_pay_received(&self.wallet.storage)?
+            .into_iter()
+            .filter(|r| r.zap_request.is_some() && r.zap_receipt.is_none())
+            .filter(|r| {
+                let expires_at =
+                    (r.bolt11.duration_since_epoch() + r.bolt11.expiry_time()).as_secs();
+                expires_at.saturating_add(ZAP_RECEIPT_GRACE_SECS) >= now
+            });
+
+        for mut received in pending {
+            let invoice = match self
+                .wallet
+                .get_invoice_by_hash(&received.payment_hash)
+                .await
+            {
+                Ok(invoice) => invoice,
+                Err(_) => continue,
+            };
+            if invoice.status != HTLCStatus::Succeeded {
+                continue;
+            }
+
+            let raw = received.zap_request.clone().unwrap_or_default();
+            let receipt = create_zap_receipt(&self.keys, &raw, &received.bolt11, invoice.preimage)?;
+            let relays = match Event::from_json(&raw) {
+                Ok(zap_request) => zap_request_relays(&zap_request),
+                Err(_) => vec![],
+            };
+
+            let client = Client::new(&self.keys);
+            client.add_relays(relays).await?;
+            client.connect().await;
+            let sent = client.send_event(receipt.clone()).await;
+            client.disconnect().await?;
+            sent?;
+
+            log_info!(self.logger, "Published zap receipt {}", receipt.id);
+            received.zap_receipt = Some(receipt.id);
+            persist_lnurl_pay_received(&self.wallet.storage, &received)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Payer data we give to a service that asks for it (LUD-18)
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct PayerData {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pubkey: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identifier: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+}
+
+impl PayerData {
+    /// Only gives the fields the service asked for, fails if we are missing a mandatory one.
+    fn for_request(&self, requested: &PayerDataConfig) -> Result<String, MutinyError> {
+        let mut fields = serde_json::Map::new();
+        let values = [
+            ("name", &self.name),
+            ("pubkey", &self.pubkey),
+            ("identifier", &self.identifier),
+            ("email", &self.email),
+        ];
+        for (name, value) in values {
+            if let (Some(_), Some(value)) = (requested.field(name), value) {
+                fields.insert(name.to_string(), json!(value));
+            }
+        }
+
+        let payer_data = Value::Object(fields);
+        requested
+            .validate(&payer_data)
+            .map_err(|_| MutinyError::InvalidArgumentsError)?;
+
+        Ok(payer_data.to_string())
+    }
+}
+
+/// What a service shows the payer after a successful payment (LUD-09)
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "tag", rename_all = "lowercase")]
+pub enum SuccessAction {
+    Message {
+        message: String,
+    },
+    Url {
+        description: String,
+        url: String,
+    },
+    /// Decrypted with the payment's preimage (LUD-10)
+    Aes {
+        description: String,
+        plaintext: String,
+    },
+}
+
+/// A success action as the service gives it with the invoice
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "tag", rename_all = "lowercase")]
+enum RawSuccessAction {
+    Message {
+        message: String,
+    },
+    Url {
+        description: String,
+        url: String,
+    },
+    Aes {
+        description: String,
+        ciphertext: String,
+        iv: String,
+    },
+}
+
+impl RawSuccessAction {
+    /// Checks the success action and decrypts it if needed,
+    /// `callback` is the URL that gave it to us.
+    fn resolve(self, callback: &str, preimage: &[u8; 32]) -> Result<SuccessAction, MutinyError> {
+        match self {
+            RawSuccessAction::Message { message } => Ok(SuccessAction::Message { message }),
+            RawSuccessAction::Url { description, url } => {
+                // the url has to be on the same domain as the callback
+                let domain = url::Url::parse(&url)?.domain().map(|d| d.to_string());
+                let callback_domain = url::Url::parse(callback)?.domain().map(|d| d.to_string());
+                if domain.is_none() || domain != callback_domain {
+                    return Err(MutinyError::LnUrlFailure);
+                }
+                Ok(SuccessAction::Url { description, url })
+            }
+            RawSuccessAction::Aes {
+                description,
+                ciphertext,
+                iv,
+            } => {
+                let ciphertext = base64::decode(ciphertext)?;
+                let iv = base64::decode(iv)?;
+                let plaintext = decrypt_with_iv(preimage, &iv, &ciphertext)?;
+                Ok(SuccessAction::Aes {
+                    description,
+                    plaintext: String::from_utf8(plaintext)?,
+                })
+            }
+        }
+    }
+}
+
+/// The callback's response, with the invoice to pay
+#[derive(Deserialize, Clone, Debug)]
+struct CallbackResponse {
+    pr: String,
+    #[serde(default, rename = "successAction")]
+    success_action: Option<RawSuccessAction>,
+}
+
+/// An invoice from an LNURL-pay service, and what to show once it is paid.
+pub(crate) struct LnUrlPayInvoice {
+    pub bolt11: Bolt11Invoice,
+    callback: String,
+    success_action: Option<RawSuccessAction>,
+}
+
+impl LnUrlPayInvoice {
+    /// The success action of the paid invoice, errors are logged and ignored
+    /// because the payment went through regardless.
+    pub fn success_action(
+        &self,
+        preimage: &[u8; 32],
+        logger: &MutinyLogger,
+    ) -> Option<SuccessAction> {
+        let action = self.success_action.clone()?;
+        match action.resolve(&self.callback, preimage) {
+            Ok(action) => Some(action),
+            Err(e) => {
+                log_warn!(logger, "Invalid LNURL-pay success action: {e}");
+                None
+            }
+        }
+    }
+}
+
+/// Gets the pay request (LUD-06) of an LNURL, fails if it is not an LNURL-pay.
+pub(crate) async fn get_lnurl_pay_request(
+    client: &reqwest::Client,
+    lnurl: &str,
+) -> Result<Value, MutinyError> {
+    let pay_request = fetch_json(client, lnurl).await?;
+    if pay_request.get("status").and_then(|s| s.as_str()) == Some("ERROR") {
+        return Err(MutinyError::LnUrlFailure);
+    }
+    if pay_request.get("tag").and_then(|t| t.as_str()) != Some("payRequest") {
+        return Err(MutinyError::IncorrectLnUrlFunction);
+    }
+
+    Ok(pay_request)
+}
+
+/// Gets an invoice from the callback of a pay request given by [`get_lnurl_pay_request`].
+///
+/// The payer data is only given to services that ask for it in their pay request.
+pub(crate) async fn get_lnurl_pay_invoice(
+    client: &reqwest::Client,
+    pay_request: &Value,
+    amount_msat: u64,
+    zap_request: Option<String>,
+    comment: Option<String>,
+    payer_data: Option<&PayerData>,
+) -> Result<LnUrlPayInvoice, MutinyError> {
+    let callback = pay_request
+        .get("callback")
+        .and_then(|c| c.as_str())
+        .ok_or(MutinyError::LnUrlFailure)?;
+
+    let payer_data = match (payer_data, pay_request.get("payerData")) {
+        (Some(payer_data), Some(requested)) => {
+            let requested: PayerDataConfig = serde_json::from_value(requested.clone())?;
+            Some(payer_data.for_request(&requested)?)
+        }
+        _ => None,
+    };
+
+    let mut url = url::Url::parse(callback)?;
+    {
+        let mut query = url.query_pairs_mut();
+        query.append_pair("amount", &amount_msat.to_string());
+        if let Some(zap_request) = zap_request.as_ref() {
+            query.append_pair("nostr", zap_request);
+        }
+        if let Some(comment) = comment.as_ref() {
+            query.append_pair("comment", comment);
+        }
+        if let Some(payer_data) = payer_data.as_ref() {
+            query.append_pair("payerdata", payer_data);
+        }
+    }
+
+    let response: Value = fetch_json(client, url.as_str()).await?;
+    if response.get("status").and_then(|s| s.as_str()) == Some("ERROR") {
+        return Err(MutinyError::LnUrlFailure);
+    }
+    let response: CallbackResponse = serde_json::from_value(response)?;
+
+    Ok(LnUrlPayInvoice {
+        bolt11: Bolt11Invoice::from_str(&response.pr)?,
+        callback: callback.to_string(),
+        success_action: response.success_action,
+    })
+}
+
+async fn fetch_json(client: &reqwest::Client, url: &str) -> Result<Value, MutinyError> {
+    client
+        .get(url)
+        .send()
+        .await
+        .map_err(|_| MutinyError::LnUrlFailure)?
+        .json()
+        .await
+        .map_err(|_| MutinyError::LnUrlFailure)
+}
+
+fn success_action_key(payment_hash: &sha256::Hash) -> String {
+    format!(
+        "{LNURL_PAY_SUCCESS_ACTION_PREFIX_KEY}{}",
+        payment_hash.to_hex()
+    )
+}
+
+pub(crate) fn persist_success_action<S: MutinyStorage>(
+    storage: &S,
+    payment_hash: &sha256::Hash,
+    success_action: &SuccessAction,
+) -> Result<(), MutinyError> {
+    storage.set_data(success_action_key(payment_hash), success_action, None)
+}
+
+pub(crate) fn read_success_action<S: MutinyStorage>(
+    storage: &S,
+    payment_hash: &sha256::Hash,
+) -> Result<Option<SuccessAction>, MutinyError> {
+    storage.get_data(success_action_key(payment_hash))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+type PendingRequest = (
+    LnUrlPayRequest,
+    std::sync::mpsc::SyncSender<LnUrlPayResponse>,
+);
+
+/// A [`LnUrlPayTransport`] that listens for HTTP requests on a local socket.
+///
+/// It is meant to be put behind a reverse proxy that terminates TLS for our domain.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct HttpTransport {
+    base_url: String,
+    requests: Mutex<UnboundedReceiver<PendingRequest>>,
+    pending: Mutex<HashMap<String, std::sync::mpsc::SyncSender<LnUrlPayResponse>>>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl HttpTransport {
+    /// Starts listening on the given address, `base_url` is the public URL we are reachable at.
+    pub fn bind(addr: &str, base_url: String) -> Result<Self, MutinyError> {
+        use std::sync::atomic::AtomicUsize;
+        use std::time::Duration;
+
+        let listener = std::net::TcpListener::bind(addr)?;
+        let (sender, receiver) = unbounded();
+
+        std::thread::spawn(move || {
+            let connections = Arc::new(AtomicUsize::new(0));
+            let mut next_id: u64 = 0;
+            for mut stream in listener.incoming().flatten() {
+                let timeout = Some(Duration::from_secs(HTTP_TIMEOUT_SECS));
+                if stream.set_read_timeout(timeout).is_err()
+                    || stream.set_write_timeout(timeout).is_err()
+                {
+                    continue;
+                }
+
+                if connections.fetch_add(1, Ordering::SeqCst) >= MAX_HTTP_CONNECTIONS {
+                    connections.fetch_sub(1, Ordering::SeqCst);
+                    Self::write_response(&mut stream, LnUrlPayResponse::error(503, "Busy"));
+                    continue;
+                }
+
+                next_id += 1;
+                let id = next_id.to_string();
+                let sender = sender.clone();
+                let connections = connections.clone();
+                std::thread::spawn(move || {
+                    Self::handle_connection(stream, id, sender);
+                    connections.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+        });
+
+        Ok(Self {
+            base_url,
+            requests: Mutex::new(receiver),
+            pending: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn handle_connection(
+        mut stream: std::net::TcpStream,
+        id: String,
+        sender: UnboundedSender<PendingRequest>,
+    ) {
+        use std::io::{BufRead, BufReader, Read};
+
+        let mut request_line = String::new();
+        let mut reader = BufReader::new((&stream).take(MAX_HTTP_REQUEST_BYTES));
+        if reader.read_line(&mut request_line).is_err() {
+            return;
+        }
+        // drain the headers, we don't need any of them
+        let mut header = String::new();
+        while reader.read_line(&mut header).is_ok_and(|n| n > 2) {
+            header.clear();
+        }
+
+        let mut parts = request_line.split_whitespace();
+        let response = match (parts.next(), parts.next()) {
+            (Some("GET"), Some(target)) => {
+                let (path, query) = target.split_once('?').unwrap_or((target, ""));
+                let request = LnUrlPayRequest {
+                    id,
+                    path: path.to_string(),
+                    query: query.to_string(),
+                };
+                let (responder, receiver) = std::sync::mpsc::sync_channel(1);
+                if sender.unbounded_send((request, responder)).is_err() {
+                    return;
+                }
+                receiver
+                    .recv_timeout(std::time::Duration::from_secs(HTTP_TIMEOUT_SECS))
+                    .unwrap_or_else(|_| LnUrlPayResponse::error(503, "Unavailable"))
+            }
+            _ => LnUrlPayResponse::error(405, "Method not allowed"),
+        };
+
+        Self::write_response(&mut stream, response);
+    }
+
+    fn write_response(stream: &mut std::net::TcpStream, response: LnUrlPayResponse) {
+        use std::io::Write;
+
+        let http = format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nAccess-Control-Allow-Origin: *\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            response.status,
+            if response.status == 200 { "OK" } else { "Error" },
+            response.body.len(),
+            response.body
+        );
+        let _ = stream.write_all(http.as_bytes());
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[async_trait]
+impl LnUrlPayTransport for HttpTransport {
+    fn base_url(&self) -> String {
+        self.base_url.clone()
+    }
+
+    async fn next_request(&self) -> Option<LnUrlPayRequest> {
+        let (request, responder) = self.requests.lock().await.next().await?;
+        self.pending
+            .lock()
+            .await
+            .insert(request.id.clone(), responder);
+        Some(request)
+    }
+
+    async fn respond(
+        &self,
+        request: &LnUrlPayRequest,
+        response: LnUrlPayResponse,
+    ) -> Result<(), MutinyError> {
+        let responder = self
+            .pending
+            .lock()
+            .await
+            .remove(&request.id)
+            .ok_or(MutinyError::NotFound)?;
+
+        // the payer may have given up waiting, nothing to do then
+        let _ = responder.try_send(response);
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct ProxyRequest {
+    path: String,
+    #[serde(default)]
+    query: String,
+}
+
+/// A [`LnUrlPayTransport`] for when we can't listen for HTTP requests, like in the browser.
+///
+/// A proxy on our domain forwards each request to us as an encrypted nostr event
+/// and returns our encrypted response to the payer.
+pub struct NostrProxyTransport {
+    keys: Keys,
+    proxy_pubkey: XOnlyPublicKey,
+    base_url: String,
+    client: Client,
+    requests: Mutex<UnboundedReceiver<LnUrlPayRequest>>,
+}
+
+impl NostrProxyTransport {
+    /// Connects to the relays and starts listening for requests from the proxy.
+    pub async fn new(
+        keys: Keys,
+        proxy_pubkey: XOnlyPublicKey,
+        relays: Vec<String>,
+        base_url: String,
+        logger: Arc<MutinyLogger>,
+    ) -> Result<Self, MutinyError> {
+        let client = Client::new(&keys);
+        client.add_relays(relays).await?;
+        client.connect().await;
+
+        let filter = Filter::new()
+            .kind(Kind::from(LNURL_PROXY_REQUEST_KIND))
+            .author(proxy_pubkey)
+            .pubkey(keys.public_key())
+            .since(Timestamp::now());
+        client.subscribe(vec![filter]).await;
+
+        let (sender, receiver) = unbounded();
+        let mut notifications = client.notifications();
+        let secret = keys.secret_key()?;
+        utils::spawn(async move {
+            while let Ok(notification) = notifications.recv().await {
+                let event = match notification {
+                    RelayPoolNotification::Event { event, .. } => event,
+                    RelayPoolNotification::Shutdown => break,
+                    _ => continue,
+                };
+                if event.pubkey != proxy_pubkey || event.verify().is_err() {
+                    continue;
+                }
+
+                let request = decrypt(&secret, &proxy_pubkey, &event.content)
+                    .ok()
+                    .and_then(|c| serde_json::from_str::<ProxyRequest>(&c).ok());
+                match request {
+                    Some(request) => {
+                        let request = LnUrlPayRequest {
+                            id: event.id.to_hex(),
+                            path: request.path,
+                            query: request.query,
+                        };
+                        if sender.unbounded_send(request).is_err() {
+                            break;
+                        }
+                    }
+                    None => log_warn!(logger, "Invalid LNURL proxy request {}", event.id),
+                }
+            }
+        });
+
+        Ok(Self {
+            keys,
+            proxy_pubkey,
+            base_url,
+            client,
+            requests: Mutex::new(receiver),
+        })
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl LnUrlPayTransport for NostrProxyTransport {
+    fn base_url(&self) -> String {
+        self.base_url.clone()
+    }
+
+    async fn next_request(&self) -> Option<LnUrlPayRequest> {
+        self.requests.lock().await.next().await
+    }
+
+    async fn respond(
+        &self,
+        request: &LnUrlPayRequest,
+        response: LnUrlPayResponse,
+    ) -> Result<(), MutinyError> {
+        let event_id =
+            EventId::from_hex(&request.id).map_err(|_| MutinyError::InvalidArgumentsError)?;
+        let content = encrypt(
+            &self.keys.secret_key()?,
+            &self.proxy_pubkey,
+            serde_json::to_string(&response)?,
+        )?;
+        let p_tag = Tag::PublicKey {
+            public_key: self.proxy_pubkey,
+            relay_url: None,
+            alias: None,
+            uppercase: false,
+        };
+        let e_tag = Tag::Event {
+            event_id,
+            relay_url: None,
+            marker: None,
+        };
+        let event = EventBuilder::new(
+            Kind::from(LNURL_PROXY_RESPONSE_KIND),
+            content,
+            [p_tag, e_tag],
+        )
+        .to_event(&self.keys)?;
+
+        self.client.send_event(event).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::encrypt::encryption_key_from_pass;
+    use crate::storage::MemoryStorage;
+    use crate::test_utils::*;
+    use crate::{MutinyWalletBuilder, MutinyWalletConfigBuilder};
+    use bitcoin::util::bip32::ExtendedPrivKey;
+    use bitcoin::Network;
+    use lightning_invoice::Bolt11InvoiceDescription;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    fn zap_request(keys: &Keys, amount_msat: u64) -> Event {
+        let tags = vec![
+            Tag::PublicKey {
+                public_key: Keys::generate().public_key(),
+                relay_url: None,
+                alias: None,
+                uppercase: false,
+            },
+            Tag::parse(vec!["relays", "wss://relay.example.com"]).unwrap(),
+            Tag::parse(vec!["amount".to_string(), amount_msat.to_string()]).unwrap(),
+        ];
+        EventBuilder::new(Kind::ZapRequest, "", tags)
+            .to_event(keys)
+            .unwrap()
+    }
+
+    async fn create_server(config: LnUrlPayConfig) -> LnUrlPayServer<MemoryStorage> {
+        let network = Network::Regtest;
+        let xpriv = ExtendedPrivKey::new_master(network, &[0; 32]).unwrap();
+        let pass = uuid::Uuid::new_v4().to_string();
+        let cipher = encryption_key_from_pass(&pass).unwrap();
+        let storage = MemoryStorage::new(Some(pass), Some(cipher), None);
+        let wallet_config = MutinyWalletConfigBuilder::new(xpriv)
+            .with_network(network)
+            .build();
+        let wallet = MutinyWalletBuilder::new(xpriv, storage.clone())
+            .with_config(wallet_config)
+            .build()
+            .await
+            .unwrap();
+        storage.set_done_first_sync().unwrap();
+
+        let logger = Arc::new(MutinyLogger::default());
+        LnUrlPayServer::new(wallet, config, Keys::generate(), logger)
+    }
+
+    async fn request(
+        server: &LnUrlPayServer<MemoryStorage>,
+        path: &str,
+        params: &[(&str, &str)],
+    ) -> (u16, Value) {
+        let query = url::form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(params)
+            .finish();
+        let request = LnUrlPayRequest {
+            id: "1".to_string(),
+            path: path.to_string(),
+            query,
+        };
+        let response = server
+            .handle_request("https://example.com/", &request)
+            .await;
+        (
+            response.status,
+            serde_json::from_str(&response.body).unwrap(),
+        )
+    }
+
+    /// Checks the callback's invoice and returns what we saved for it
+    fn check_invoice(
+        server: &LnUrlPayServer<MemoryStorage>,
+        body: &Value,
+        amount_msat: u64,
+        description_hash: sha256::Hash,
+    ) -> LnUrlPayReceived {
+        let bolt11 = Bolt11Invoice::from_str(body["pr"].as_str().unwrap()).unwrap();
+        assert_eq!(bolt11.amount_milli_satoshis(), Some(amount_msat));
+        match bolt11.description() {
+            Bolt11InvoiceDescription::Hash(hash) => assert_eq!(hash.0, description_hash),
+            _ => panic!("invoice should have a description hash"),
+        }
+
+        list_lnurl_pay_received(&server.wallet.storage)
+            .unwrap()
+            .into_iter()
+            .find(|r| r.bolt11 == bolt11)
+            .unwrap()
+    }
+
+    #[test]
+    async fn test_lnurl_pay_server() {
+        let test_name = "test_lnurl_pay_server";
+        log!("{}", test_name);
+
+        let mut config = LnUrlPayConfig::new("alice".to_string(), "example.com".to_string());
+        config.comment_allowed = 10;
+        config.payer_data = Some(PayerDataConfig {
+            name: Some(PayerDataField { mandatory: false }),
+            ..Default::default()
+        });
+        config.success_message = Some("Thanks!".to_string());
+        let metadata = config.metadata();
+        let server = create_server(config).await;
+
+        let (status, body) = request(&server, "/.well-known/lnurlp/alice", &[]).await;
+        assert_eq!(status, 200);
+        assert_eq!(
+            body,
+            json!({
+                "tag": "payRequest",
+                "callback": "https://example.com/lnurlp/alice/callback",
+                "minSendable": 1_000,
+                "maxSendable": 1_000_000_000,
+                "metadata": metadata,
+                "commentAllowed": 10,
+                "payerData": { "name": { "mandatory": false } },
+                "allowsNostr": true,
+                "nostrPubkey": server.keys.public_key().to_string(),
+            })
+        );
+
+        let (status, _) = request(&server, "/.well-known/lnurlp/bob", &[]).await;
+        assert_eq!(status, 404);
+
+        let callback = "/lnurlp/alice/callback";
+        let (status, body) = request(&server, callback, &[]).await;
+        assert_eq!(status, 400);
+        assert_eq!(
+            body,
+            json!({ "status": "ERROR", "reason": "Missing amount" })
+        );
+        let (status, _) = request(&server, callback, &[("amount", "1")]).await;
+        assert_eq!(status, 400);
+        let (status, _) = request(&server, callback, &[("amount", "1500")]).await;
+        assert_eq!(status, 400);
+        let (status, body) = request(
+            &server,
+            callback,
+            &[("amount", "5000"), ("comment", "this is too long")],
+        )
+        .await;
+        assert_eq!(status, 400);
+        assert_eq!(body["reason"], "Comment too long");
+
+        // a plain payment with a comment
+        let (status, body) =
+            request(&server, callback, &[("amount", "5000"), ("comment", "hi")]).await;
+        assert_eq!(status, 200);
+        assert_eq!(body["routes"], json!([]));
+        assert_eq!(
+            body["successAction"],
+            json!({ "tag": "message", "message": "Thanks!" })
+        );
+        let received = check_invoice(
+            &server,
+            &body,
+            5_000,
+            sha256::Hash::hash(metadata.as_bytes()),
+        );
+        assert_eq!(received.amount_msat, 5_000);
+        assert_eq!(received.comment, Some("hi".to_string()));
+        assert_eq!(received.payer_data, None);
+        assert_eq!(received.zap_request, None);
+
+        // payer data is committed to along with the metadata
+        let payer_data = json!({ "name": "Bob" }).to_string();
+        let (status, body) = request(
+            &server,
+            callback,
+            &[("amount", "6000"), ("payerdata", &payer_data)],
+        )
+        .await;
+        assert_eq!(status, 200);
+        let received = check_invoice(
+            &server,
+            &body,
+            6_000,
+            sha256::Hash::hash(format!("{metadata}{payer_data}").as_bytes()),
+        );
+        assert_eq!(received.payer_data, Some(json!({ "name": "Bob" })));
+        let (status, _) = request(
+            &server,
+            callback,
+            &[("amount", "6000"), ("payerdata", "{\"email\":1}")],
+        )
+        .await;
+        assert_eq!(status, 400);
+
+        // zaps commit to the zap request
+        let zap = zap_request(&Keys::generate(), 7_000).as_json();
+        let (status, body) =
+            request(&server, callback, &[("amount", "7000"), ("nostr", &zap)]).await;
+        assert_eq!(status, 200);
+        let received = check_invoice(&server, &body, 7_000, sha256::Hash::hash(zap.as_bytes()));
+        assert_eq!(received.zap_request, Some(zap.clone()));
+        assert_eq!(received.zap_receipt, None);
+
+        let (status, body) =
+            request(&server, callback, &[("amount", "8000"), ("nostr", &zap)]).await;
+        assert_eq!(status, 400);
+        assert_eq!(body["reason"], "Zap request amount does not match");
+    }
+
+    #[test]
+    async fn test_lnurl_pay_metadata() {
+        let test_name = "test_lnurl_pay_metadata";
+        log!("{}", test_name);
+
+        let mut config = LnUrlPayConfig::new("alice".to_string(), "example.com".to_string());
+        assert_eq!(config.lightning_address(), "alice@example.com");
+        assert_eq!(
+            config.metadata(),
+            r#"[["text/plain","Pay to alice@example.com"],["text/identifier","alice@example.com"]]"#
+        );
+
+        config.description = Some("Tips".to_string());
+        assert_eq!(
+            config.metadata(),
+            r#"[["text/plain","Tips"],["text/identifier","alice@example.com"]]"#
+        );
+        assert_eq!(config.metadata_path(), "/.well-known/lnurlp/alice");
+        assert_eq!(config.callback_path(), "/lnurlp/alice/callback");
+    }
+
+    #[test]
+    async fn test_validate_payer_data() {
+        let test_name = "test_validate_payer_data";
+        log!("{}", test_name);
+
+        let config = PayerDataConfig {
+            name: Some(PayerDataField { mandatory: true }),
+            email: Some(PayerDataField { mandatory: false }),
+            ..Default::default()
+        };
+
+        assert!(config.validate(&json!({ "name": "Bob" })).is_ok());
+        assert!(config
+            .validate(&json!({ "name": "Bob", "email": "bob@example.com" }))
+            .is_ok());
+        // missing a mandatory field
+        assert!(config
+            .validate(&json!({ "email": "bob@example.com" }))
+            .is_err());
+        // a field we didn't ask for
+        assert!(config
+            .validate(&json!({ "name": "Bob", "pubkey": "02aa" }))
+            .is_err());
+        assert!(config.validate(&json!("Bob")).is_err());
+    }
+
+    #[test]
+    async fn test_validate_zap_request() {
+        let test_name = "test_validate_zap_request";
+        log!("{}", test_name);
+
+        let keys = Keys::generate();
+        let request = zap_request(&keys, 21_000);
+        let event = validate_zap_request(&request.as_json(), 21_000).unwrap();
+        assert_eq!(event.id, request.id);
+        assert_eq!(
+            zap_request_relays(&event),
+            vec!["wss://relay.example.com".to_string()]
+        );
+
+        // amount doesn't match the callback's
+        assert!(validate_zap_request(&request.as_json(), 42_000).is_err());
+
+        // not a zap request
+        let note = EventBuilder::new(Kind::TextNote, "", [])
+            .to_event(&keys)
+            .unwrap();
+        assert!(validate_zap_request(&note.as_json(), 21_000).is_err());
+        assert!(validate_zap_request("not json", 21_000).is_err());
+    }
+
+    #[test]
+    async fn test_lnurl_pay_received_storage() {
+        let test_name = "test_lnurl_pay_received_storage";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::default();
+        let bolt11 = create_dummy_invoice(Some(1_000), bitcoin::Network::Regtest, None).0;
+        let received = LnUrlPayReceived {
+            payment_hash: *bolt11.payment_hash(),
+            bolt11,
+            amount_msat: 1_000_000,
+            comment: Some("thanks".to_string()),
+            payer_data: None,
+            zap_request: None,
+            zap_receipt: None,
+            created_at: 0,
+        };
+
+        assert!(list_lnurl_pay_received(&storage).unwrap().is_empty());
+        persist_lnurl_pay_received(&storage, &received).unwrap();
+        assert_eq!(list_lnurl_pay_received(&storage).unwrap(), vec![received]);
+    }
+
+    #[test]
+    async fn test_payer_data_for_request() {
+        let test_name = "test_payer_data_for_request";
+        log!("{}", test_name);
+
+        let payer_data = PayerData {
+            name: Some("Bob".to_string()),
+            email: Some("bob@example.com".to_string()),
+            ..Default::default()
+        };
+
+        // only the requested fields are given
+        let requested = PayerDataConfig {
+            name: Some(PayerDataField { mandatory: true }),
+            identifier: Some(PayerDataField { mandatory: false }),
+            ..Default::default()
+        };
+        assert_eq!(
+            payer_data.for_request(&requested).unwrap(),
+            r#"{"name":"Bob"}"#
+        );
+
+        // we don't have a mandatory field
+        let requested = PayerDataConfig {
+            identifier: Some(PayerDataField { mandatory: true }),
+            ..Default::default()
+        };
+        assert_eq!(
+            payer_data.for_request(&requested),
+            Err(MutinyError::InvalidArgumentsError)
+        );
+    }
+
+    #[test]
+    async fn test_resolve_success_action() {
+        let test_name = "test_resolve_success_action";
+        log!("{}", test_name);
+
+        let callback = "https://example.com/lnurlp/alice/callback";
+        let preimage = [7u8; 32];
+
+        let message: RawSuccessAction =
+            serde_json::from_str(r#"{"tag":"message","message":"Thanks!"}"#).unwrap();
+        assert_eq!(
+            message.resolve(callback, &preimage).unwrap(),
+            SuccessAction::Message {
+                message: "Thanks!".to_string()
+            }
+        );
+
+        let url = RawSuccessAction::Url {
+            description: "Receipt".to_string(),
+            url: "https://example.com/receipt/1".to_string(),
+        };
+        assert!(url.resolve(callback, &preimage).is_ok());
+
+        // urls on another domain are not allowed
+        let url = RawSuccessAction::Url {
+            description: "Receipt".to_string(),
+            url: "https://evil.com/receipt/1".to_string(),
+        };
+        assert!(url.resolve(callback, &preimage).is_err());
+
+        // the AES action is encrypted with the preimage
+        let iv = [3u8; 16];
+        let ciphertext = {
+            use aes::cipher::block_padding::Pkcs7;
+            use aes::cipher::{BlockEncryptMut, KeyIvInit};
+            cbc::Encryptor::<aes::Aes256>::new(&preimage.into(), &iv.into())
+                .encrypt_padded_vec_mut::<Pkcs7>(b"voucher 1234")
+        };
+        let aes = RawSuccessAction::Aes {
+            description: "Your voucher".to_string(),
+            ciphertext: base64::encode(ciphertext),
+            iv: base64::encode(iv),
+        };
+        assert_eq!(
+            aes.clone().resolve(callback, &preimage).unwrap(),
+            SuccessAction::Aes {
+                description: "Your voucher".to_string(),
+                plaintext: "voucher 1234".to_string()
+            }
+        );
+        assert!(aes.resolve(callback, &[8u8; 32]).is_err());
+    }
+}
@@ -14,17 +14,19 @@ use lightning_liquidity::events::Event;
 use lightning_liquidity::lsps2::event::LSPS2ClientEvent;
 use lightning_liquidity::lsps2::msgs::OpeningFeeParams;
 use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
 use std::collections::HashMap;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use crate::{
     error::MutinyError,
     keymanager::PhantomKeysManager,
     ldkstorage::PhantomChannelManager,
     logging::MutinyLogger,
-    lsp::{FeeRequest, InvoiceRequest, Lsp, LspConfig},
-    node::{parse_peer_info, LiquidityManager},
+    lsp::{FeeRequest, InvoiceRequest, Lsp, LspConfig, LSP_FEE_QUOTE_EXPIRY_SECS},
+    node::{parse_peer_info, InvoiceOptions, LiquidityManager},
     storage::MutinyStorage,
     utils,
 };
@@ -56,6 +58,9 @@ pub(crate) struct PendingPaymentInfo {
 
 type PendingFeeRequestSender = oneshot::Sender<Result<GetInfoResponse, MutinyError>>;
 type PendingBuyRequestSender = oneshot::Sender<Result<Bolt11Invoice, MutinyError>>;
+/// The sender for the invoice, the options it should be created with
+/// and route hints through our other LSPs to add to it
+type PendingBuyRequest = (PendingBuyRequestSender, InvoiceOptions, Vec<RouteHint>);
 
 #[derive(Clone)]
 pub struct LspsClient<S: MutinyStorage> {
@@ -68,10 +73,9 @@ pub struct LspsClient<S: MutinyStorage> {
     network: Network,
     logger: Arc<MutinyLogger>,
     pending_fee_requests: Arc<Mutex<HashMap<u128, PendingFeeRequestSender>>>,
-    pending_buy_requests: Arc<Mutex<HashMap<u128, PendingBuyRequestSender>>>,
+    pending_buy_requests: Arc<Mutex<HashMap<u128, PendingBuyRequest>>>,
     pending_channel_info: Arc<Mutex<HashMap<u128, JitChannelInfo>>>,
     pending_payments: Arc<Mutex<HashMap<PaymentHash, PendingPaymentInfo>>>,
-    stop: Arc<AtomicBool>,
 }
 
 impl<S: MutinyStorage> LspsClient<S> {
@@ -84,7 +88,6 @@ impl<S: MutinyStorage> LspsClient<S> {
         keys_manager: Arc<PhantomKeysManager<S>>,
         network: Network,
         logger: Arc<MutinyLogger>,
-        stop: Arc<AtomicBool>,
     ) -> Result<Self, MutinyError> {
         let (lsp_pubkey, _) = parse_peer_info(&lsp_connection_string)?;
 
@@ -101,18 +104,14 @@ impl<S: MutinyStorage> LspsClient<S> {
             pending_buy_requests: Arc::new(Mutex::new(HashMap::new())),
             pending_channel_info: Arc::new(Mutex::new(HashMap::new())),
             pending_payments: Arc::new(Mutex::new(HashMap::new())),
-            stop,
         };
 
-        let events_client = client.clone();
-        utils::spawn(async move {
-            events_client.handle_events().await;
-        });
-
         Ok(client)
     }
 
-    pub(crate) async fn handle_event(&self, event: Event) {
+    /// Handles an event from the liquidity manager, ignoring
+    /// the ones for requests this client did not make.
+    pub(crate) async fn handle_event(&self, event: &Event) {
         match event {
             Event::LSPS2Client(LSPS2ClientEvent::GetInfoResponse {
                 jit_channel_id,
@@ -129,11 +128,11 @@ impl<S: MutinyStorage> LspsClient<S> {
 
                 let mut pending_fee_requests = self.pending_fee_requests.lock().unwrap();
 
-                if let Some(fee_response_sender) = pending_fee_requests.remove(&user_channel_id) {
+                if let Some(fee_response_sender) = pending_fee_requests.remove(user_channel_id) {
                     if fee_response_sender
                         .send(Ok(GetInfoResponse {
-                            jit_channel_id,
-                            opening_fee_params_menu,
+                            jit_channel_id: *jit_channel_id,
+                            opening_fee_params_menu: opening_fee_params_menu.clone(),
                         }))
                         .is_err()
                     {
@@ -153,8 +152,10 @@ impl<S: MutinyStorage> LspsClient<S> {
 
                 let mut pending_buy_requests = self.pending_buy_requests.lock().unwrap();
 
-                if let Some(buy_response_sender) = pending_buy_requests.remove(&user_channel_id) {
-                    let invoice_expiry_delta_secs = 3600;
+                if let Some((buy_response_sender, options, route_hints)) =
+                    pending_buy_requests.remove(user_channel_id)
+                {
+                    let invoice_expiry_delta_secs = options.expiry_secs();
                     let (payment_hash, payment_secret) = match self
                         .channel_manager
                         .create_inbound_payment(None, invoice_expiry_delta_secs, None)
@@ -175,7 +176,7 @@ impl<S: MutinyStorage> LspsClient<S> {
                         }
                     };
 
-                    let cltv_expiry_delta: u16 = match cltv_expiry_delta.try_into() {
+                    let cltv_expiry_delta: u16 = match (*cltv_expiry_delta).try_into() {
                         Ok(cltv_expiry_delta) => cltv_expiry_delta,
                         Err(e) => {
                             log_error!(
@@ -197,8 +198,8 @@ impl<S: MutinyStorage> LspsClient<S> {
                     };
 
                     let lsp_route_hint = RouteHint(vec![RouteHintHop {
-                        src_node_id: counterparty_node_id,
-                        short_channel_id: intercept_scid,
+                        src_node_id: *counterparty_node_id,
+                        short_channel_id: *intercept_scid,
                         fees: RoutingFees {
                             base_msat: 0,
                             proportional_millionths: 0,
@@ -231,17 +232,25 @@ impl<S: MutinyStorage> LspsClient<S> {
 
                     let secp = Secp256k1::new();
                     let payee_pub_key = self.keys_manager.get_node_secret_key().public_key(&secp);
-                    let mut invoice = InvoiceBuilder::new(self.network.into())
-                        .description("".into())
+                    let invoice = InvoiceBuilder::new(self.network.into());
+                    let invoice = match options.description_hash {
+                        Some(description_hash) => invoice.description_hash(description_hash),
+                        None => invoice.description(options.description.unwrap_or_default()),
+                    };
+                    let mut invoice = invoice
                         .payment_hash(payment_hash)
                         .payment_secret(payment_secret)
                         .duration_since_epoch(utils::now())
+                        .expiry_time(Duration::from_secs(invoice_expiry_delta_secs.into()))
                         .payee_pub_key(payee_pub_key)
                         .basic_mpp()
                         .min_final_cltv_expiry_delta(MIN_FINAL_CLTV_EXPIRY_DELTA.into())
                         .private_route(lsp_route_hint);
+                    for route_hint in route_hints {
+                        invoice = invoice.private_route(route_hint);
+                    }
 
-                    let payment_size_msat = match payment_size_msat {
+                    let payment_size_msat = match *payment_size_msat {
                         Some(payment_size_msat) => payment_size_msat,
                         None => {
                             log_error!(self.logger, "payment_size_msat was not specified but is required to create an invoice");
@@ -298,19 +307,26 @@ impl<S: MutinyStorage> LspsClient<S> {
             _ => {}
         }
     }
+}
 
-    pub(crate) async fn handle_events(&self) {
-        loop {
-            for event in self.liquidity_manager.get_and_clear_pending_events() {
-                self.handle_event(event).await;
-            }
-
-            if self.stop.load(Ordering::Relaxed) {
-                break;
+/// Passes the liquidity manager's events to each of the node's LSPS clients.
+pub(crate) async fn handle_lsps_events<S: MutinyStorage>(
+    liquidity_manager: Arc<LiquidityManager<S>>,
+    clients: Vec<LspsClient<S>>,
+    stop: Arc<AtomicBool>,
+) {
+    loop {
+        for event in liquidity_manager.get_and_clear_pending_events() {
+            for client in clients.iter() {
+                client.handle_event(&event).await;
             }
+        }
 
-            utils::sleep(1000).await;
+        if stop.load(Ordering::Relaxed) {
+            break;
         }
+
+        utils::sleep(1000).await;
     }
 }
 
@@ -332,6 +348,27 @@ pub fn compute_opening_fee(
         .map(|f| std::cmp::max(f, opening_fee_min_fee_msat))
 }
 
+/// Picks the cheapest opening fee params for the payment from the LSP's menu,
+/// skipping ones that expire before `valid_until`. Ties go to the one valid the longest.
+fn select_opening_fee_params(
+    menu: &[OpeningFeeParams],
+    payment_size_msat: u64,
+    valid_until: u64,
+) -> Option<OpeningFeeParams> {
+    menu.iter()
+        .filter(|params| params.valid_until.timestamp() >= valid_until as i64)
+        .filter_map(|params| {
+            compute_opening_fee(
+                payment_size_msat,
+                params.min_fee_msat,
+                params.proportional.into(),
+            )
+            .map(|fee| (fee, params))
+        })
+        .min_by_key(|(fee, params)| (*fee, Reverse(params.valid_until)))
+        .map(|(_, params)| params.clone())
+}
+
 #[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
 #[cfg_attr(not(target_arch = "wasm32"), async_trait)]
 impl<S: MutinyStorage> Lsp for LspsClient<S> {
@@ -388,7 +425,18 @@ impl<S: MutinyStorage> Lsp for LspsClient<S> {
             MutinyError::LspGenericError
         })??;
 
-        let fee_params = get_info_response.opening_fee_params_menu[0].clone();
+        let fee_params = select_opening_fee_params(
+            &get_info_response.opening_fee_params_menu,
+            fee_request.amount_msat,
+            utils::now().as_secs() + LSP_FEE_QUOTE_EXPIRY_SECS,
+        )
+        .ok_or_else(|| {
+            log_error!(
+                self.logger,
+                "LSP did not offer any opening fee params that are valid long enough"
+            );
+            MutinyError::LspGenericError
+        })?;
 
         let min_fee_msat = fee_params.min_fee_msat;
         let proportional_fee = fee_params.proportional;
@@ -437,7 +485,14 @@ impl<S: MutinyStorage> Lsp for LspsClient<S> {
 
         {
             let mut pending_buy_requests = self.pending_buy_requests.lock().unwrap();
-            pending_buy_requests.insert(user_channel_id, pending_buy_request_sender);
+            pending_buy_requests.insert(
+                user_channel_id,
+                (
+                    pending_buy_request_sender,
+                    invoice_request.options,
+                    invoice_request.route_hints,
+                ),
+            );
         }
 
         let (channel_id, fee_params) = {
@@ -520,3 +575,64 @@ impl<S: MutinyStorage> Lsp for LspsClient<S> {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::*;
+    use chrono::{TimeZone, Utc};
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    fn fee_params(min_fee_msat: u64, proportional: u32, valid_until: i64) -> OpeningFeeParams {
+        OpeningFeeParams {
+            min_fee_msat,
+            proportional,
+            valid_until: Utc.timestamp_opt(valid_until, 0).unwrap(),
+            min_lifetime: 1_000,
+            max_client_to_self_delay: 2_016,
+            promise: "promise".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_select_opening_fee_params() {
+        let test_name = "test_select_opening_fee_params";
+        log!("{}", test_name);
+
+        let select = |menu: &[OpeningFeeParams], payment_size_msat: u64| {
+            select_opening_fee_params(menu, payment_size_msat, 1_000)
+                .map(|p| (p.min_fee_msat, p.valid_until.timestamp()))
+        };
+
+        let high_min = fee_params(1_000_000, 1_000, 2_000);
+        let high_rate = fee_params(500_000, 10_000, 2_000);
+        let expiring = fee_params(100_000, 1_000, 500);
+        let high_rate_longer = fee_params(500_000, 10_000, 3_000);
+
+        // for 10k sats the minimum fees decide
+        assert_eq!(
+            select(&[high_min.clone(), high_rate.clone()], 10_000_000),
+            Some((500_000, 2_000))
+        );
+        // for 1m sats the proportional fees decide
+        assert_eq!(
+            select(&[high_min.clone(), high_rate.clone()], 1_000_000_000),
+            Some((1_000_000, 2_000))
+        );
+        // params that expire too soon are skipped even if they are cheaper
+        assert_eq!(
+            select(&[high_rate.clone(), expiring.clone()], 10_000_000),
+            Some((500_000, 2_000))
+        );
+        assert_eq!(select(&[expiring], 10_000_000), None);
+        // ties go to the params valid the longest
+        assert_eq!(
+            select(&[high_rate, high_rate_longer], 10_000_000),
+            Some((500_000, 3_000))
+        );
+        assert_eq!(select(&[], 10_000_000), None);
+    }
+}
@@ -0,0 +1,403 @@
+use crate::{error::MutinyError, storage::MutinyStorage, utils};
+use bitcoin::hashes::sha256;
+use bitcoin::secp256k1::PublicKey;
+use bitcoin::Txid;
+use reqwest::{Client, Response};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+const GET_INFO_PATH: &str = "/api/v1/get_info";
+const CREATE_ORDER_PATH: &str = "/api/v1/create_order";
+const GET_ORDER_PATH: &str = "/api/v1/get_order";
+
+const CHANNEL_ORDER_PREFIX_KEY: &str = "lsps1_order/";
+
+/// The limits an LSP puts on channels bought from it.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Lsps1Options {
+    pub min_required_channel_confirmations: u16,
+    pub min_funding_confirms_within_blocks: u16,
+    pub min_onchain_payment_confirmations: Option<u16>,
+    pub supports_zero_channel_reserve: bool,
+    #[serde(default, with = "sat_opt")]
+    pub min_onchain_payment_size_sat: Option<u64>,
+    pub max_channel_expiry_blocks: u32,
+    #[serde(with = "sat")]
+    pub min_initial_client_balance_sat: u64,
+    #[serde(with = "sat")]
+    pub max_initial_client_balance_sat: u64,
+    #[serde(with = "sat")]
+    pub min_initial_lsp_balance_sat: u64,
+    #[serde(with = "sat")]
+    pub max_initial_lsp_balance_sat: u64,
+    #[serde(with = "sat")]
+    pub min_channel_balance_sat: u64,
+    #[serde(with = "sat")]
+    pub max_channel_balance_sat: u64,
+}
+
+impl Lsps1Options {
+    /// Checks that an order for these balances could be accepted by the LSP.
+    pub(crate) fn validate(
+        &self,
+        lsp_balance_sat: u64,
+        client_balance_sat: u64,
+        channel_expiry_blocks: u32,
+    ) -> bool {
+        let channel_balance_sat = lsp_balance_sat + client_balance_sat;
+        (self.min_initial_lsp_balance_sat..=self.max_initial_lsp_balance_sat)
+            .contains(&lsp_balance_sat)
+            && (self.min_initial_client_balance_sat..=self.max_initial_client_balance_sat)
+                .contains(&client_balance_sat)
+            && (self.min_channel_balance_sat..=self.max_channel_balance_sat)
+                .contains(&channel_balance_sat)
+            && channel_expiry_blocks <= self.max_channel_expiry_blocks
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Lsps1Info {
+    /// Connection strings of the LSP's node
+    #[serde(default)]
+    pub uris: Vec<String>,
+    pub options: Lsps1Options,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct CreateOrderRequest {
+    pub public_key: PublicKey,
+    #[serde(with = "sat")]
+    pub lsp_balance_sat: u64,
+    #[serde(with = "sat")]
+    pub client_balance_sat: u64,
+    pub required_channel_confirmations: u16,
+    pub funding_confirms_within_blocks: u16,
+    pub channel_expiry_blocks: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refund_onchain_address: Option<String>,
+    pub announce_channel: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Lsps1OrderState {
+    Created,
+    Completed,
+    Failed,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Lsps1PaymentState {
+    ExpectPayment,
+    Hold,
+    Paid,
+    Refunded,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Lsps1Payment {
+    pub state: Lsps1PaymentState,
+    #[serde(with = "sat")]
+    pub fee_total_sat: u64,
+    /// The fee plus the balance pushed to us
+    #[serde(with = "sat")]
+    pub order_total_sat: u64,
+    pub bolt11_invoice: String,
+    pub onchain_address: Option<String>,
+    pub min_onchain_payment_confirmations: Option<u16>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Lsps1Channel {
+    pub funded_at: String,
+    pub funding_outpoint: String,
+    pub expires_at: String,
+}
+
+/// An order for a channel as returned by the LSP.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Lsps1Order {
+    pub order_id: String,
+    #[serde(with = "sat")]
+    pub lsp_balance_sat: u64,
+    #[serde(with = "sat")]
+    pub client_balance_sat: u64,
+    pub required_channel_confirmations: u16,
+    pub funding_confirms_within_blocks: u16,
+    pub channel_expiry_blocks: u32,
+    pub token: Option<String>,
+    pub created_at: String,
+    pub expires_at: String,
+    pub announce_channel: bool,
+    pub order_state: Lsps1OrderState,
+    pub payment: Lsps1Payment,
+    pub channel: Option<Lsps1Channel>,
+}
+
+/// How we paid for a channel order.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum ChannelOrderPayment {
+    Lightning { payment_hash: sha256::Hash },
+    Onchain { txid: Txid },
+}
+
+/// A channel order we made, saved so it can be tracked until the channel is opened.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ChannelOrder {
+    pub lsp_url: String,
+    pub node_id: PublicKey,
+    pub order: Lsps1Order,
+    pub paid_with: Option<ChannelOrderPayment>,
+    pub last_update: u64,
+}
+
+impl ChannelOrder {
+    /// If the order can still be paid for.
+    pub fn is_payable(&self) -> bool {
+        self.paid_with.is_none()
+            && self.order.order_state == Lsps1OrderState::Created
+            && self.order.payment.state == Lsps1PaymentState::ExpectPayment
+    }
+
+    /// If the LSP can still change the order.
+    pub fn is_pending(&self) -> bool {
+        self.order.order_state == Lsps1OrderState::Created
+    }
+}
+
+/// A client for buying channels from an LSP with the LSPS1 protocol over HTTP.
+#[derive(Clone, Debug)]
+pub(crate) struct Lsps1Client {
+    pub url: String,
+    pub http_client: Client,
+}
+
+impl Lsps1Client {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.trim_end_matches('/').to_string(),
+            http_client: Client::new(),
+        }
+    }
+
+    pub async fn get_info(&self) -> Result<Lsps1Info, MutinyError> {
+        let request = self
+            .http_client
+            .get(format!("{}{}", self.url, GET_INFO_PATH))
+            .build()
+            .map_err(|_| MutinyError::LspGenericError)?;
+        let response = utils::fetch_with_timeout(&self.http_client, request).await?;
+        parse_response(response).await
+    }
+
+    pub async fn create_order(
+        &self,
+        order_request: &CreateOrderRequest,
+    ) -> Result<Lsps1Order, MutinyError> {
+        let request = self
+            .http_client
+            .post(format!("{}{}", self.url, CREATE_ORDER_PATH))
+            .json(order_request)
+            .build()
+            .map_err(|_| MutinyError::LspGenericError)?;
+        let response = utils::fetch_with_timeout(&self.http_client, request).await?;
+        parse_response(response).await
+    }
+
+    pub async fn get_order(&self, order_id: &str) -> Result<Lsps1Order, MutinyError> {
+        let request = self
+            .http_client
+            .get(format!("{}{}", self.url, GET_ORDER_PATH))
+            .query(&[("order_id", order_id)])
+            .build()
+            .map_err(|_| MutinyError::LspGenericError)?;
+        let response = utils::fetch_with_timeout(&self.http_client, request).await?;
+        parse_response(response).await
+    }
+}
+
+async fn parse_response<T: serde::de::DeserializeOwned>(
+    response: Response,
+) -> Result<T, MutinyError> {
+    let status = response.status().as_u16();
+    if !(200..300).contains(&status) {
+        let body = response.text().await.unwrap_or_default();
+        return Err(anyhow::anyhow!("LSPS1 request failed ({status}): {body}").into());
+    }
+
+    response
+        .json()
+        .await
+        .map_err(|_| MutinyError::LspGenericError)
+}
+
+fn channel_order_key(order_id: &str) -> String {
+    format!("{CHANNEL_ORDER_PREFIX_KEY}{order_id}")
+}
+
+pub(crate) fn persist_channel_order<S: MutinyStorage>(
+    storage: &S,
+    order: &ChannelOrder,
+) -> Result<(), MutinyError> {
+    storage.set_data(channel_order_key(&order.order.order_id), order, None)
+}
+
+pub(crate) fn read_channel_order<S: MutinyStorage>(
+    storage: &S,
+    order_id: &str,
+) -> Result<Option<ChannelOrder>, MutinyError> {
+    storage.get_data(channel_order_key(order_id))
+}
+
+pub(crate) fn list_channel_orders<S: MutinyStorage>(
+    storage: &S,
+) -> Result<Vec<ChannelOrder>, MutinyError> {
+    let map: HashMap<String, ChannelOrder> = storage.scan(CHANNEL_ORDER_PREFIX_KEY, None)?;
+    Ok(map.into_values().collect())
+}
+
+/// LSPS0 encodes satoshi amounts as strings, some LSPs use numbers.
+mod sat {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StringOrNumber {
+        String(String),
+        Number(u64),
+    }
+
+    pub fn serialize<S: Serializer>(amount: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&amount.to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        match StringOrNumber::deserialize(deserializer)? {
+            StringOrNumber::String(s) => s.parse().map_err(serde::de::Error::custom),
+            StringOrNumber::Number(n) => Ok(n),
+        }
+    }
+
+    pub(super) fn deserialize_opt<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<u64>, D::Error> {
+        match Option::<StringOrNumber>::deserialize(deserializer)? {
+            Some(StringOrNumber::String(s)) => {
+                s.parse().map(Some).map_err(serde::de::Error::custom)
+            }
+            Some(StringOrNumber::Number(n)) => Ok(Some(n)),
+            None => Ok(None),
+        }
+    }
+}
+
+mod sat_opt {
+    use serde::{Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        amount: &Option<u64>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match amount {
+            Some(amount) => super::sat::serialize(amount, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<u64>, D::Error> {
+        super::sat::deserialize_opt(deserializer)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use crate::test_utils::*;
+    use std::str::FromStr;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    const ORDER: &str = r#"{
+        "order_id": "bb4b5d0a-8334-49d8-9463-90a6d413af7c",
+        "lsp_balance_sat": "5000000",
+        "client_balance_sat": "2000000",
+        "required_channel_confirmations": 0,
+        "funding_confirms_within_blocks": 1,
+        "channel_expiry_blocks": 12,
+        "token": "",
+        "created_at": "2012-04-23T18:25:43.511Z",
+        "expires_at": "2015-01-25T19:29:44.612Z",
+        "announce_channel": false,
+        "order_state": "CREATED",
+        "payment": {
+            "state": "EXPECT_PAYMENT",
+            "fee_total_sat": 8888,
+            "order_total_sat": "2008888",
+            "bolt11_invoice": "lnbc252u1p3aht9ysp580g4633gd2x9lc5al0wd8wx0mpn9748jeyz46kqjrpxn52uhfpjqpp5qgf67tcqmuqehzgjm8mzya90h73deafvr4m5705l5u5l4r05l8cqdpud3h8ymm4w3jhytnpwpczqmt0de6xsmre2pkxzm3qydmkzdjrdev9s7zhgfaqxqyjw5qcqpjrzjqt6xptnd85lpqnu2lefq4cx070v5cdwzh2xlvmdgnu7gqp4zvkus5zapryqqx9qqqyqqqqqqqqqqqcsq9q9qyysgqen77vu8xqjelum24hgjpgfdgfgx4q0nehhalcmuggt32japhjuksq9jv6eksjfnppm4hrzsgyxt8y8xacxut9qv3fpyetz8t7tsymygq8yzn05",
+            "onchain_address": "bc1p5uvtaxzkjwvey2tfy49k5vtqfpjmrgm09cvs88ezyy8h2zv7jhas9tu4yr",
+            "min_onchain_payment_confirmations": 1
+        },
+        "channel": null
+    }"#;
+
+    #[test]
+    fn test_parse_lsps1_order() {
+        let test_name = "test_parse_lsps1_order";
+        log!("{}", test_name);
+
+        let order: Lsps1Order = serde_json::from_str(ORDER).unwrap();
+        assert_eq!(order.lsp_balance_sat, 5_000_000);
+        assert_eq!(order.payment.fee_total_sat, 8_888);
+        assert_eq!(order.payment.order_total_sat, 2_008_888);
+        assert_eq!(order.order_state, Lsps1OrderState::Created);
+        assert_eq!(order.payment.state, Lsps1PaymentState::ExpectPayment);
+
+        // amounts are written back as strings
+        let json = serde_json::to_value(&order).unwrap();
+        assert_eq!(json["payment"]["fee_total_sat"], "8888");
+        let round_trip: Lsps1Order = serde_json::from_value(json).unwrap();
+        assert_eq!(round_trip, order);
+    }
+
+    #[test]
+    fn test_channel_order_storage() {
+        let test_name = "test_channel_order_storage";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::default();
+        let order: Lsps1Order = serde_json::from_str(ORDER).unwrap();
+        let mut channel_order = ChannelOrder {
+            lsp_url: "https://lsp.example.com".to_string(),
+            node_id: PublicKey::from_str(
+                "02465ed5be53d04fde66c9418ff14a5f2267723810176c9212b722e542dc1afb1b",
+            )
+            .unwrap(),
+            order,
+            paid_with: None,
+            last_update: 0,
+        };
+        assert!(channel_order.is_payable());
+        assert!(read_channel_order(&storage, &channel_order.order.order_id)
+            .unwrap()
+            .is_none());
+
+        persist_channel_order(&storage, &channel_order).unwrap();
+        assert_eq!(
+            read_channel_order(&storage, &channel_order.order.order_id).unwrap(),
+            Some(channel_order.clone())
+        );
+
+        channel_order.order.payment.state = Lsps1PaymentState::Hold;
+        assert!(!channel_order.is_payable());
+        persist_channel_order(&storage, &channel_order).unwrap();
+        assert_eq!(list_channel_orders(&storage).unwrap(), vec![channel_order]);
+    }
+}
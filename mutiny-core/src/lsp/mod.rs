@@ -2,20 +2,24 @@ use crate::error::MutinyError;
 use crate::keymanager::PhantomKeysManager;
 use crate::ldkstorage::PhantomChannelManager;
 use crate::logging::MutinyLogger;
-use crate::node::LiquidityManager;
+use crate::node::{InvoiceOptions, LiquidityManager};
 use crate::storage::MutinyStorage;
 use async_trait::async_trait;
 use bitcoin::secp256k1::PublicKey;
 use bitcoin::Network;
 use lightning::ln::PaymentHash;
+use lightning::routing::router::RouteHint;
 use lightning_invoice::Bolt11Invoice;
 use lsps::{LspsClient, LspsConfig};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::sync::{atomic::AtomicBool, Arc};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
 use voltage::LspClient;
 
 pub mod lsps;
+pub mod lsps1;
 pub mod voltage;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -62,6 +66,133 @@ where
     }
 }
 
+/// How a node picks which of its LSPs to use for an invoice.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum LspSelection {
+    /// The LSP with the lowest fee, ties go to the most reliable one
+    #[default]
+    Cheapest,
+    /// The LSP that has failed the least, ties go to the cheapest one
+    MostReliable,
+}
+
+impl FromStr for LspSelection {
+    type Err = MutinyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "cheapest" => Ok(Self::Cheapest),
+            "most_reliable" | "mostreliable" => Ok(Self::MostReliable),
+            _ => Err(MutinyError::InvalidArgumentsError),
+        }
+    }
+}
+
+const LSP_STATS_KEY_PREFIX: &str = "lsp_stats/";
+
+/// How often requests to an LSP have worked, kept across restarts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct LspStats {
+    pub successes: u32,
+    pub failures: u32,
+}
+
+impl LspStats {
+    pub fn record(&mut self, success: bool) {
+        if success {
+            self.successes = self.successes.saturating_add(1);
+        } else {
+            self.failures = self.failures.saturating_add(1);
+        }
+    }
+
+    /// Failures per thousand requests, LSPs we haven't used yet count as reliable.
+    pub fn failure_rate(&self) -> u32 {
+        let total = self.successes as u64 + self.failures as u64;
+        if total == 0 {
+            0
+        } else {
+            (self.failures as u64 * 1_000 / total) as u32
+        }
+    }
+}
+
+/// Reads the stats a node has recorded for each of its LSPs.
+pub(crate) fn read_lsp_stats<S: MutinyStorage>(
+    storage: &S,
+    node_pubkey: &PublicKey,
+) -> Result<HashMap<PublicKey, LspStats>, MutinyError> {
+    let key = format!("{LSP_STATS_KEY_PREFIX}{node_pubkey}");
+    Ok(storage.get_data(key)?.unwrap_or_default())
+}
+
+pub(crate) fn persist_lsp_stats<S: MutinyStorage>(
+    storage: &S,
+    node_pubkey: &PublicKey,
+    stats: &HashMap<PublicKey, LspStats>,
+) -> Result<(), MutinyError> {
+    let key = format!("{LSP_STATS_KEY_PREFIX}{node_pubkey}");
+    storage.set_data(key, stats, None)
+}
+
+/// An LSP that quoted a fee for an invoice.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct LspCandidate {
+    /// Index of the LSP in the node's list
+    pub index: usize,
+    pub fee_msat: u64,
+    pub stats: LspStats,
+}
+
+/// Orders the LSPs by which should be tried first.
+pub(crate) fn rank_lsp_candidates(candidates: &mut [LspCandidate], selection: LspSelection) {
+    match selection {
+        LspSelection::Cheapest => {
+            candidates.sort_by_key(|c| (c.fee_msat, c.stats.failure_rate(), c.index))
+        }
+        LspSelection::MostReliable => {
+            candidates.sort_by_key(|c| (c.stats.failure_rate(), c.fee_msat, c.index))
+        }
+    }
+}
+
+/// How long a quoted LSP fee can be approved for.
+pub(crate) const LSP_FEE_QUOTE_EXPIRY_SECS: u64 = 10 * 60;
+
+/// The most we are willing to pay an LSP to open a channel for an invoice.
+/// When both are set the fee has to be under both of them.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct LspFeeLimits {
+    /// Maximum fee in msats
+    pub max_fee_msat: Option<u64>,
+    /// Maximum fee in parts per million of the invoice amount
+    pub max_fee_ppm: Option<u32>,
+}
+
+impl LspFeeLimits {
+    pub fn allows(&self, amount_msat: u64, fee_msat: u64) -> bool {
+        let under_absolute = self.max_fee_msat.map_or(true, |max| fee_msat <= max);
+        let under_proportional = self.max_fee_ppm.map_or(true, |ppm| {
+            fee_msat as u128 * 1_000_000 <= amount_msat as u128 * ppm as u128
+        });
+
+        under_absolute && under_proportional
+    }
+}
+
+/// A fee quoted by an LSP for receiving a payment, the invoice is only
+/// created once the quote is approved.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct LspFeeQuote {
+    pub id: String,
+    pub amount_sat: u64,
+    pub fee_msat: u64,
+    /// The LSP that quoted the fee, none when no channel needs to be opened
+    pub lsp_pubkey: Option<PublicKey>,
+    /// Unix timestamp the quote can be approved until
+    pub expires_at: u64,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct InvoiceRequest {
     // Used only for VoltageFlow
@@ -70,6 +201,11 @@ pub struct InvoiceRequest {
     pub fee_id: Option<String>,
     // Used only for LSPS to track channel creation
     pub user_channel_id: Option<u128>,
+    // Used only for LSPS, the VoltageFlow invoice keeps the ones from the bolt11
+    pub options: InvoiceOptions,
+    // Used only for LSPS, hints through our other LSPs to add to the invoice
+    #[serde(skip)]
+    pub route_hints: Vec<RouteHint>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -112,7 +248,6 @@ impl<S: MutinyStorage> AnyLsp<S> {
         Ok(Self::VoltageFlow(LspClient::new(url).await?))
     }
 
-    #[allow(clippy::too_many_arguments)]
     pub fn new_lsps(
         connection_string: String,
         token: Option<String>,
@@ -121,7 +256,6 @@ impl<S: MutinyStorage> AnyLsp<S> {
         keys_manager: Arc<PhantomKeysManager<S>>,
         network: Network,
         logger: Arc<MutinyLogger>,
-        stop: Arc<AtomicBool>,
     ) -> Result<Self, MutinyError> {
         let lsps_client = LspsClient::new(
             connection_string,
@@ -131,7 +265,6 @@ impl<S: MutinyStorage> AnyLsp<S> {
             keys_manager,
             network,
             logger,
-            stop,
         )?;
         Ok(Self::Lsps(lsps_client))
     }
@@ -196,3 +329,113 @@ impl<S: MutinyStorage> Lsp for AnyLsp<S> {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use crate::test_utils::*;
+    use bitcoin::secp256k1::{Secp256k1, ONE_KEY};
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    fn candidate(index: usize, fee_msat: u64, successes: u32, failures: u32) -> LspCandidate {
+        LspCandidate {
+            index,
+            fee_msat,
+            stats: LspStats {
+                successes,
+                failures,
+            },
+        }
+    }
+
+    #[test]
+    fn test_rank_lsp_candidates() {
+        let test_name = "test_rank_lsp_candidates";
+        log!("{}", test_name);
+
+        let cheap_flaky = candidate(0, 1_000, 1, 3);
+        let pricey_reliable = candidate(1, 5_000, 10, 0);
+        let cheap_unused = candidate(2, 1_000, 0, 0);
+
+        let mut candidates = vec![cheap_flaky, pricey_reliable, cheap_unused];
+        rank_lsp_candidates(&mut candidates, LspSelection::Cheapest);
+        assert_eq!(candidates, vec![cheap_unused, cheap_flaky, pricey_reliable]);
+
+        rank_lsp_candidates(&mut candidates, LspSelection::MostReliable);
+        assert_eq!(candidates, vec![cheap_unused, pricey_reliable, cheap_flaky]);
+    }
+
+    #[test]
+    fn test_lsp_stats_storage() {
+        let test_name = "test_lsp_stats_storage";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::default();
+        let node = PublicKey::from_str(
+            "02465ed5be53d04fde66c9418ff14a5f2267723810176c9212b722e542dc1afb1b",
+        )
+        .unwrap();
+        let lsp = PublicKey::from_secret_key(&Secp256k1::new(), &ONE_KEY);
+        assert!(read_lsp_stats(&storage, &node).unwrap().is_empty());
+
+        let mut stats = HashMap::new();
+        stats
+            .entry(lsp)
+            .or_insert_with(LspStats::default)
+            .record(false);
+        persist_lsp_stats(&storage, &node, &stats).unwrap();
+        assert_eq!(read_lsp_stats(&storage, &node).unwrap(), stats);
+    }
+
+    #[test]
+    fn test_lsp_fee_limits() {
+        let test_name = "test_lsp_fee_limits";
+        log!("{}", test_name);
+
+        assert!(LspFeeLimits::default().allows(10_000_000, 10_000_000));
+
+        let limits = LspFeeLimits {
+            max_fee_msat: Some(5_000_000),
+            max_fee_ppm: Some(10_000),
+        };
+        // 1% of 100k sats is 1k sats, under both limits
+        assert!(limits.allows(100_000_000, 1_000_000));
+        // above 1% of the amount
+        assert!(!limits.allows(100_000_000, 1_000_001));
+        // under 1% of the amount but above the absolute limit
+        assert!(!limits.allows(1_000_000_000, 5_000_001));
+
+        let absolute_only = LspFeeLimits {
+            max_fee_msat: Some(5_000_000),
+            max_fee_ppm: None,
+        };
+        assert!(absolute_only.allows(1_000, 5_000_000));
+    }
+
+    #[test]
+    fn test_lsp_stats_failure_rate() {
+        let test_name = "test_lsp_stats_failure_rate";
+        log!("{}", test_name);
+
+        let mut stats = LspStats::default();
+        assert_eq!(stats.failure_rate(), 0);
+
+        stats.record(false);
+        assert_eq!(stats.failure_rate(), 1_000);
+
+        stats.record(true);
+        stats.record(true);
+        stats.record(true);
+        assert_eq!(stats.failure_rate(), 250);
+
+        assert_eq!(
+            LspSelection::from_str("most_reliable").unwrap(),
+            LspSelection::MostReliable
+        );
+        assert!(LspSelection::from_str("random").is_err());
+    }
+}
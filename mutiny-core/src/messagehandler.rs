@@ -1,28 +1,77 @@
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use bitcoin::secp256k1::PublicKey;
 use lightning::io::{Error, Read};
 use lightning::ln::features::{InitFeatures, NodeFeatures};
-use lightning::ln::msgs::{DecodeError, LightningError};
+use lightning::ln::msgs::{ChannelReestablish, DecodeError, LightningError};
 use lightning::ln::peer_handler::CustomMessageHandler;
 use lightning::ln::wire::{CustomMessageReader, Type};
+use lightning::ln::ChannelId;
+use lightning::log_error;
+use lightning::offers::invoice::Bolt12Invoice;
+use lightning::onion_message::{OffersMessage, OffersMessageHandler, PendingOnionMessage};
+use lightning::util::logger::Logger;
 use lightning::util::ser::{Writeable, Writer};
 
+use crate::ldkstorage::PhantomChannelManager;
+use crate::logging::MutinyLogger;
 use crate::node::LiquidityManager;
-use crate::storage::MutinyStorage;
+use crate::storage::{persist_bolt12_invoice_receive, read_bolt12_receive, MutinyStorage};
+
+/// The BOLT 1 message type of `channel_reestablish`
+const CHANNEL_REESTABLISH_TYPE: u16 = 136;
 
 pub struct MutinyMessageHandler<S: MutinyStorage> {
     pub liquidity: Option<Arc<LiquidityManager<S>>>,
+    pub pending_msgs: Mutex<Vec<(PublicKey, MutinyMessage<S>)>>,
 }
 
 pub enum MutinyMessage<S: MutinyStorage> {
     Liquidity(<LiquidityManager<S> as CustomMessageReader>::CustomMessage),
+    /// Only ever sent, channel manager handles the ones we receive
+    ChannelReestablish(ChannelReestablish),
+}
+
+impl<S: MutinyStorage> MutinyMessageHandler<S> {
+    pub fn new(liquidity: Option<Arc<LiquidityManager<S>>>) -> Self {
+        Self {
+            liquidity,
+            pending_msgs: Mutex::new(vec![]),
+        }
+    }
+
+    /// Queues a `channel_reestablish` for a channel we have lost the state of.
+    ///
+    /// It claims we are on the very first commitment, which makes a peer that
+    /// supports `option_data_loss_protect` force close the channel so we can
+    /// sweep our balance. The message is sent the next time the peer manager
+    /// processes events.
+    pub fn request_force_close(&self, peer: PublicKey, channel_id: ChannelId) {
+        // any valid point, the peer can't check it
+        let dummy_point = PublicKey::from_slice(&[2; 33]).expect("valid point");
+        let msg = ChannelReestablish {
+            channel_id,
+            next_local_commitment_number: 0,
+            next_remote_commitment_number: 0,
+            your_last_per_commitment_secret: [1; 32],
+            my_current_per_commitment_point: dummy_point,
+            next_funding_txid: None,
+        };
+
+        self.pending_msgs
+            .lock()
+            .expect("pending msgs lock")
+            .push((peer, MutinyMessage::ChannelReestablish(msg)));
+    }
 }
 
 impl<S: MutinyStorage> std::fmt::Debug for MutinyMessage<S> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::Liquidity(arg0) => f.debug_tuple("Liquidity").field(arg0).finish(),
+            Self::ChannelReestablish(arg0) => {
+                f.debug_tuple("ChannelReestablish").field(arg0).finish()
+            }
         }
     }
 }
@@ -43,21 +92,31 @@ impl<S: MutinyStorage> CustomMessageHandler for MutinyMessageHandler<S> {
                     );
                 }
             }
+            // we never read these as custom messages
+            MutinyMessage::ChannelReestablish(_) => {}
         }
 
         Ok(())
     }
 
     fn get_and_clear_pending_msg(&self) -> Vec<(PublicKey, Self::CustomMessage)> {
+        let mut msgs: Vec<(PublicKey, Self::CustomMessage)> = self
+            .pending_msgs
+            .lock()
+            .expect("pending msgs lock")
+            .drain(..)
+            .collect();
+
         if let Some(liquidity) = &self.liquidity {
-            liquidity
-                .get_and_clear_pending_msg()
-                .into_iter()
-                .map(|(pubkey, message)| (pubkey, MutinyMessage::Liquidity(message)))
-                .collect()
-        } else {
-            vec![]
+            msgs.extend(
+                liquidity
+                    .get_and_clear_pending_msg()
+                    .into_iter()
+                    .map(|(pubkey, message)| (pubkey, MutinyMessage::Liquidity(message))),
+            );
         }
+
+        msgs
     }
 
     fn provided_node_features(&self) -> NodeFeatures {
@@ -101,6 +160,7 @@ impl<S: MutinyStorage> Type for MutinyMessage<S> {
     fn type_id(&self) -> u16 {
         match self {
             MutinyMessage::Liquidity(message) => message.type_id(),
+            MutinyMessage::ChannelReestablish(_) => CHANNEL_REESTABLISH_TYPE,
         }
     }
 }
@@ -109,6 +169,69 @@ impl<S: MutinyStorage> Writeable for MutinyMessage<S> {
     fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
         match self {
             MutinyMessage::Liquidity(message) => message.write(writer),
+            MutinyMessage::ChannelReestablish(message) => message.write(writer),
         }
     }
 }
+
+/// Passes offers messages to the channel manager and remembers which of our
+/// offers or refunds each invoice we send is for.
+///
+/// LDK doesn't tell us this when the payment is received, so we match
+/// inbound BOLT12 payments by the payment hash of the invoice instead.
+pub struct MutinyOffersHandler<S: MutinyStorage> {
+    channel_manager: Arc<PhantomChannelManager<S>>,
+    storage: S,
+    logger: Arc<MutinyLogger>,
+}
+
+impl<S: MutinyStorage> MutinyOffersHandler<S> {
+    pub fn new(
+        channel_manager: Arc<PhantomChannelManager<S>>,
+        storage: S,
+        logger: Arc<MutinyLogger>,
+    ) -> Self {
+        Self {
+            channel_manager,
+            storage,
+            logger,
+        }
+    }
+
+    fn track_invoice(&self, invoice: &Bolt12Invoice) {
+        // offer invoices are signed with the offer's signing pubkey,
+        // refund invoices are for the refund's payer id
+        let bolt12 = read_bolt12_receive(&self.storage, &invoice.signing_pubkey())
+            .or_else(|| read_bolt12_receive(&self.storage, &invoice.payer_id()));
+
+        if let Some(bolt12) = bolt12 {
+            let payment_hash = invoice.payment_hash();
+            if let Err(e) = persist_bolt12_invoice_receive(&self.storage, &payment_hash.0, bolt12) {
+                log_error!(self.logger, "ERROR: could not persist BOLT12 invoice: {e}");
+            }
+        }
+    }
+}
+
+impl<S: MutinyStorage> OffersMessageHandler for MutinyOffersHandler<S> {
+    fn handle_message(&self, message: OffersMessage) -> Option<OffersMessage> {
+        let response = self.channel_manager.handle_message(message);
+        if let Some(OffersMessage::Invoice(invoice)) = &response {
+            self.track_invoice(invoice);
+        }
+
+        response
+    }
+
+    fn release_pending_messages(&self) -> Vec<PendingOnionMessage<OffersMessage>> {
+        // includes the invoices for refunds we requested payment for
+        let messages = self.channel_manager.release_pending_messages();
+        for message in messages.iter() {
+            if let OffersMessage::Invoice(invoice) = &message.contents {
+                self.track_invoice(invoice);
+            }
+        }
+
+        messages
+    }
+}
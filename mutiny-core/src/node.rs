@@ -1,14 +1,24 @@
-use crate::lsp::{InvoiceRequest, LspConfig};
+use crate::lsp::lsps::handle_lsps_events;
+use crate::lsp::{
+    persist_lsp_stats, rank_lsp_candidates, read_lsp_stats, FeeResponse, InvoiceRequest,
+    LspCandidate, LspConfig, LspFeeLimits, LspFeeQuote, LspSelection, LspStats,
+    LSP_FEE_QUOTE_EXPIRY_SECS,
+};
 use crate::nodemanager::ChannelClosure;
 use crate::peermanager::LspMessageRouter;
+use crate::scb::{ChannelBackup, ChannelRecovery, NodeChannelBackup};
 use crate::storage::MutinyStorage;
 use crate::utils::get_monitor_version;
 use crate::{
     chain::MutinyChain,
     error::{MutinyError, MutinyStorageError},
-    event::{EventHandler, HTLCStatus, MillisatAmount, PaymentInfo},
+    event::{CustomTlv, EventHandler, EventNotifier, HTLCStatus, MillisatAmount, PaymentInfo},
     fees::MutinyFeeEstimator,
     gossip::{get_all_peers, read_peer_info, save_peer_connection_info},
+    holdinvoice::{
+        list_hold_invoices, persist_hold_invoice, read_hold_invoice, HoldInvoice,
+        HoldInvoiceStatus, HOLD_INVOICE_MIN_FINAL_CLTV_EXPIRY_DELTA,
+    },
     keymanager::{create_keys_manager, pubkey_from_keys_manager},
     ldkstorage::{MutinyNodePersister, PhantomChannelManager},
     logging::MutinyLogger,
@@ -26,18 +36,24 @@ use crate::{
     ldkstorage::{persist_monitor, ChannelOpenParams},
     storage::persist_payment_info,
 };
-use crate::{messagehandler::MutinyMessageHandler, storage::read_payment_info};
+use crate::{
+    messagehandler::{MutinyMessageHandler, MutinyOffersHandler},
+    storage::{persist_bolt12_receive, read_offer_payment_hash, read_payment_info},
+};
 use anyhow::{anyhow, Context};
 use bdk::FeeRate;
 use bitcoin::hashes::{hex::ToHex, sha256::Hash as Sha256};
+use bitcoin::psbt::PartiallySignedTransaction;
 use bitcoin::util::bip32::ExtendedPrivKey;
 use bitcoin::{hashes::Hash, secp256k1::PublicKey, Network, OutPoint};
 use core::time::Duration;
-use esplora_client::AsyncClient;
+use futures::future::join_all;
 use futures_util::lock::Mutex;
 use lightning::events::bump_transaction::{BumpTransactionEventHandler, Wallet};
 use lightning::ln::channelmanager::ChannelDetails;
 use lightning::ln::PaymentSecret;
+use lightning::offers::offer::{Amount, Offer};
+use lightning::offers::refund::Refund;
 use lightning::onion_message::OnionMessenger as LdkOnionMessenger;
 use lightning::routing::scoring::ProbabilisticScoringDecayParameters;
 use lightning::sign::{EntropySource, InMemorySigner, NodeSigner, Recipient};
@@ -48,13 +64,15 @@ use lightning::{
     ln::{
         channelmanager::{PaymentId, PhantomRouteHints, Retry},
         peer_handler::{IgnoringMessageHandler, MessageHandler as LdkMessageHandler},
-        PaymentHash, PaymentPreimage,
+        ChannelId, PaymentHash, PaymentPreimage,
     },
     log_debug, log_error, log_info, log_trace, log_warn,
     routing::{
         gossip,
-        gossip::NodeId,
-        router::{DefaultRouter, PaymentParameters, RouteParameters},
+        gossip::{NodeId, RoutingFees},
+        router::{
+            DefaultRouter, PaymentParameters, RouteHint, RouteHintHop, RouteParameters, Router as _,
+        },
     },
     util::{
         config::{ChannelHandshakeConfig, ChannelHandshakeLimits, UserConfig},
@@ -69,11 +87,17 @@ use lightning::{
 use lightning_background_processor::process_events_async;
 use lightning_invoice::payment::PaymentError;
 use lightning_invoice::{
-    utils::{create_invoice_from_channelmanager_and_duration_since_epoch, create_phantom_invoice},
-    Bolt11Invoice,
+    utils::{
+        create_invoice_from_channelmanager_and_duration_since_epoch,
+        create_invoice_from_channelmanager_and_duration_since_epoch_with_payment_hash,
+        create_invoice_from_channelmanager_with_description_hash_and_duration_since_epoch,
+        create_phantom_invoice, create_phantom_invoice_with_description_hash,
+    },
+    Bolt11Invoice, Bolt11InvoiceDescription,
 };
 use lightning_liquidity::lsps2::client::LSPS2ClientConfig;
 use lightning_liquidity::{LiquidityClientConfig, LiquidityManager as LDKLSPLiquidityManager};
+use serde::{Deserialize, Serialize};
 
 #[cfg(test)]
 use mockall::predicate::*;
@@ -88,6 +112,8 @@ use std::{
 
 const INITIAL_RECONNECTION_DELAY: u64 = 10;
 const MAX_RECONNECTION_DELAY: u64 = 60;
+/// How long our invoices can be paid for unless asked otherwise
+pub(crate) const DEFAULT_INVOICE_EXPIRY_SECS: u32 = 3600;
 
 pub(crate) type BumpTxEventHandler<S: MutinyStorage> = BumpTransactionEventHandler<
     Arc<MutinyChain<S>>,
@@ -106,7 +132,7 @@ pub(crate) type OnionMessenger<S: MutinyStorage> = LdkOnionMessenger<
     Arc<PhantomKeysManager<S>>,
     Arc<MutinyLogger>,
     Arc<LspMessageRouter>,
-    Arc<PhantomChannelManager<S>>,
+    Arc<MutinyOffersHandler<S>>,
     IgnoringMessageHandler,
 >;
 
@@ -152,6 +178,23 @@ pub struct PubkeyConnectionInfo {
     pub original_connection_string: String,
 }
 
+/// The optional details of an invoice we create.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, Eq, PartialEq)]
+pub struct InvoiceOptions {
+    /// Included in the invoice, it is left empty otherwise to keep invoices small
+    pub description: Option<String>,
+    /// Committed to instead of a description, as used by LNURL-pay and zaps
+    pub description_hash: Option<Sha256>,
+    /// How long the invoice can be paid for, an hour if not set
+    pub expiry_secs: Option<u32>,
+}
+
+impl InvoiceOptions {
+    pub(crate) fn expiry_secs(&self) -> u32 {
+        self.expiry_secs.unwrap_or(DEFAULT_INVOICE_EXPIRY_SECS)
+    }
+}
+
 impl PubkeyConnectionInfo {
     pub fn new(connection: &str) -> Result<Self, MutinyError> {
         if connection.is_empty() {
@@ -179,13 +222,15 @@ pub struct NodeBuilder<S: MutinyStorage> {
     chain: Option<Arc<MutinyChain<S>>>,
     fee_estimator: Option<Arc<MutinyFeeEstimator<S>>>,
     wallet: Option<Arc<OnChainWallet<S>>>,
-    esplora: Option<Arc<AsyncClient>>,
     #[cfg(target_arch = "wasm32")]
     websocket_proxy_addr: Option<String>,
     network: Option<Network>,
 
     // optional
-    lsp_config: Option<LspConfig>,
+    lsp_configs: Vec<LspConfig>,
+    lsp_selection: LspSelection,
+    lsp_fee_limits: LspFeeLimits,
+    event_notifier: Option<EventNotifier>,
     logger: Option<Arc<MutinyLogger>>,
     do_not_connect_peers: bool,
 }
@@ -202,10 +247,12 @@ impl<S: MutinyStorage> NodeBuilder<S> {
             chain: None,
             fee_estimator: None,
             wallet: None,
-            esplora: None,
             #[cfg(target_arch = "wasm32")]
             websocket_proxy_addr: None,
-            lsp_config: None,
+            lsp_configs: vec![],
+            lsp_selection: LspSelection::default(),
+            lsp_fee_limits: LspFeeLimits::default(),
+            event_notifier: None,
             logger: None,
             network: None,
             do_not_connect_peers: false,
@@ -260,12 +307,6 @@ impl<S: MutinyStorage> NodeBuilder<S> {
         self
     }
 
-    /// Required
-    pub fn with_esplora(mut self, esplora: Arc<AsyncClient>) -> NodeBuilder<S> {
-        self.esplora = Some(esplora);
-        self
-    }
-
     pub fn with_network(mut self, network: Network) -> NodeBuilder<S> {
         self.network = Some(network);
         self
@@ -277,8 +318,21 @@ impl<S: MutinyStorage> NodeBuilder<S> {
         self.websocket_proxy_addr = Some(websocket_proxy_addr);
     }
 
-    pub fn with_lsp_config(&mut self, lsp_config: LspConfig) {
-        self.lsp_config = Some(lsp_config);
+    /// The LSPs the node can use, the first one is its primary LSP.
+    pub fn with_lsp_configs(&mut self, lsp_configs: Vec<LspConfig>) {
+        self.lsp_configs = lsp_configs;
+    }
+
+    pub fn with_lsp_selection(&mut self, lsp_selection: LspSelection) {
+        self.lsp_selection = lsp_selection;
+    }
+
+    pub fn with_lsp_fee_limits(&mut self, lsp_fee_limits: LspFeeLimits) {
+        self.lsp_fee_limits = lsp_fee_limits;
+    }
+
+    pub fn with_event_notifier(&mut self, event_notifier: EventNotifier) {
+        self.event_notifier = Some(event_notifier);
     }
 
     pub fn with_logger(&mut self, logger: Arc<MutinyLogger>) {
@@ -319,10 +373,6 @@ impl<S: MutinyStorage> NodeBuilder<S> {
             || Err(MutinyError::InvalidArgumentsError),
             |v| Ok(v.clone()),
         )?;
-        let esplora = self.esplora.as_ref().map_or_else(
-            || Err(MutinyError::InvalidArgumentsError),
-            |v| Ok(v.clone()),
-        )?;
         let network = self
             .network
             .map_or_else(|| Err(MutinyError::InvalidArgumentsError), Ok)?;
@@ -346,6 +396,7 @@ impl<S: MutinyStorage> NodeBuilder<S> {
             logger.clone(),
         )?);
         let pubkey = pubkey_from_keys_manager(&keys_manager);
+        let logger = Arc::new(logger.for_node(&pubkey));
 
         // init the persister
         let persister = Arc::new(MutinyNodePersister::new(
@@ -356,7 +407,7 @@ impl<S: MutinyStorage> NodeBuilder<S> {
 
         // init chain monitor
         let chain_monitor: Arc<ChainMonitor<S>> = Arc::new(ChainMonitor::new(
-            Some(chain.tx_sync.clone()),
+            Some(chain.clone()),
             chain.clone(),
             logger.clone(),
             fee_estimator.clone(),
@@ -387,27 +438,32 @@ impl<S: MutinyStorage> NodeBuilder<S> {
             scoring_params(),
         ));
 
-        log_info!(logger, "creating lsp client");
-        let lsp_config: Option<LspConfig> = match node_index.lsp {
+        log_info!(logger, "creating lsp clients");
+        let lsp_configs: Vec<LspConfig> = match node_index.lsp {
             None => {
-                log_info!(logger, "no lsp saved, using configured one if present");
-                self.lsp_config
+                log_info!(logger, "no lsp saved, using configured ones if present");
+                self.lsp_configs
             }
             Some(ref lsp) => {
-                if self.lsp_config.as_ref() == Some(lsp) {
+                if self.lsp_configs.first() == Some(lsp) {
                     log_info!(logger, "lsp config matches saved lsp config");
-                    self.lsp_config
+                    // keep any LSPs that were added to the node
+                    let mut lsp_configs = self.lsp_configs;
+                    for additional in node_index.additional_lsps.iter() {
+                        if !lsp_configs.contains(additional) {
+                            lsp_configs.push(additional.clone());
+                        }
+                    }
+                    lsp_configs
                 } else {
                     log_info!(logger, "lsp config does not match saved lsp config");
-                    None
+                    vec![]
                 }
             }
         };
 
         // init channel manager
-        let accept_underpaying_htlcs = lsp_config
-            .as_ref()
-            .is_some_and(|l| l.accept_underpaying_htlcs());
+        let accept_underpaying_htlcs = lsp_configs.iter().any(|l| l.accept_underpaying_htlcs());
         let mut read_channel_manager = persister
             .read_channel_manager(
                 network,
@@ -419,7 +475,7 @@ impl<S: MutinyStorage> NodeBuilder<S> {
                 keys_manager.clone(),
                 router.clone(),
                 channel_monitors,
-                &esplora,
+                chain.chain_source.as_ref(),
             )
             .await?;
 
@@ -428,47 +484,89 @@ impl<S: MutinyStorage> NodeBuilder<S> {
 
         let stop = Arc::new(AtomicBool::new(false));
 
-        let (lsp_client, liquidity) = match lsp_config {
-            Some(LspConfig::VoltageFlow(url)) => {
-                (Some(AnyLsp::new_voltage_flow(&url).await?), None)
-            }
-            Some(LspConfig::Lsps(lsps_config)) => {
-                let liquidity_manager = Arc::new(LiquidityManager::new(
-                    keys_manager.clone(),
+        // all the LSPS LSPs share one liquidity manager
+        let liquidity = if lsp_configs.iter().any(|l| matches!(l, LspConfig::Lsps(_))) {
+            Some(Arc::new(LiquidityManager::new(
+                keys_manager.clone(),
+                channel_manager.clone(),
+                None,
+                None,
+                None,
+                Some(LiquidityClientConfig {
+                    lsps2_client_config: Some(LSPS2ClientConfig {}),
+                }),
+            )))
+        } else {
+            None
+        };
+
+        let mut lsp_clients: Vec<AnyLsp<S>> = Vec::with_capacity(lsp_configs.len());
+        let mut lsp_error = None;
+        for lsp_config in lsp_configs.clone() {
+            let lsp_client = match (lsp_config, liquidity.as_ref()) {
+                (LspConfig::VoltageFlow(url), _) => AnyLsp::new_voltage_flow(&url).await,
+                (LspConfig::Lsps(lsps_config), Some(liquidity_manager)) => AnyLsp::new_lsps(
+                    lsps_config.connection_string,
+                    lsps_config.token,
+                    liquidity_manager.clone(),
                     channel_manager.clone(),
-                    None,
-                    None,
-                    None,
-                    Some(LiquidityClientConfig {
-                        lsps2_client_config: Some(LSPS2ClientConfig {}),
-                    }),
-                ));
+                    keys_manager.clone(),
+                    network,
+                    logger.clone(),
+                ),
+                (LspConfig::Lsps(_), None) => unreachable!("liquidity manager is created for lsps"),
+            };
 
-                (
-                    Some(AnyLsp::new_lsps(
-                        lsps_config.connection_string.clone(),
-                        lsps_config.token.clone(),
-                        liquidity_manager.clone(),
-                        channel_manager.clone(),
-                        keys_manager.clone(),
-                        network,
-                        logger.clone(),
-                        stop.clone(),
-                    )?),
-                    Some(liquidity_manager),
-                )
+            match lsp_client {
+                Ok(lsp_client) => lsp_clients.push(lsp_client),
+                // any LSP being down, even the primary, should not stop the node from starting
+                Err(e) => {
+                    log_warn!(logger, "could not create lsp client, skipping it: {e}");
+                    lsp_error = Some(e);
+                }
+            }
+        }
+        if lsp_clients.is_empty() {
+            if let Some(e) = lsp_error {
+                return Err(e);
+            }
+        }
+        let lsp_stats = match read_lsp_stats(&persister.storage, &pubkey) {
+            Ok(lsp_stats) => lsp_stats,
+            Err(e) => {
+                log_warn!(logger, "could not read lsp stats: {e}");
+                HashMap::new()
             }
-            None => (None, None),
         };
 
-        let lsp_client_pubkey = lsp_client.clone().map(|lsp| lsp.get_lsp_pubkey());
+        if let Some(liquidity_manager) = liquidity.clone() {
+            let lsps_clients = lsp_clients
+                .iter()
+                .filter_map(|l| match l {
+                    AnyLsp::Lsps(client) => Some(client.clone()),
+                    AnyLsp::VoltageFlow(_) => None,
+                })
+                .collect();
+            let lsps_stop = stop.clone();
+            utils::spawn(async move {
+                handle_lsps_events(liquidity_manager, lsps_clients, lsps_stop).await;
+            });
+        }
+
+        // onion messages are routed through the primary LSP
+        let lsp_client_pubkey = lsp_clients.first().map(|lsp| lsp.get_lsp_pubkey());
         let message_router = Arc::new(LspMessageRouter::new(lsp_client_pubkey));
+        let offers_handler = Arc::new(MutinyOffersHandler::new(
+            channel_manager.clone(),
+            persister.storage.clone(),
+            logger.clone(),
+        ));
         let onion_message_handler = Arc::new(OnionMessenger::new(
             keys_manager.clone(),
             keys_manager.clone(),
             logger.clone(),
             message_router,
-            channel_manager.clone(),
+            offers_handler,
             IgnoringMessageHandler {},
         ));
 
@@ -478,14 +576,14 @@ impl<S: MutinyStorage> NodeBuilder<S> {
             logger: logger.clone(),
         });
 
+        let message_handler = Arc::new(MutinyMessageHandler::new(liquidity.clone()));
+
         // init peer manager
         let ln_msg_handler = MessageHandler {
             chan_handler: channel_manager.clone(),
             route_handler,
             onion_message_handler,
-            custom_message_handler: Arc::new(MutinyMessageHandler {
-                liquidity: liquidity.clone(),
-            }),
+            custom_message_handler: message_handler.clone(),
         };
 
         let bump_tx_event_handler = Arc::new(BumpTransactionEventHandler::new(
@@ -503,7 +601,8 @@ impl<S: MutinyStorage> NodeBuilder<S> {
             keys_manager.clone(),
             persister.clone(),
             bump_tx_event_handler,
-            lsp_client.clone(),
+            lsp_clients.clone(),
+            self.event_notifier.clone().unwrap_or_default(),
             logger.clone(),
         );
 
@@ -700,7 +799,7 @@ impl<S: MutinyStorage> NodeBuilder<S> {
             let reconnection_fee = fee_estimator.clone();
             let reconnection_logger = logger.clone();
             let reconnection_uuid = uuid.clone();
-            let reconnection_lsp_client = lsp_client.clone();
+            let reconnection_lsp_clients = lsp_clients.clone();
             let reconnection_stop = stop.clone();
             let reconnection_stopped_comp = stopped_components.clone();
             reconnection_stopped_comp.try_write()?.push(false);
@@ -714,7 +813,7 @@ impl<S: MutinyStorage> NodeBuilder<S> {
                     reconnection_fee,
                     &reconnection_logger,
                     reconnection_uuid,
-                    reconnection_lsp_client.as_ref(),
+                    &reconnection_lsp_clients,
                     reconnection_stop,
                     reconnection_stopped_comp,
                     network == Network::Regtest,
@@ -820,7 +919,7 @@ impl<S: MutinyStorage> NodeBuilder<S> {
             }
         });
 
-        Ok(Node {
+        let node = Node {
             _uuid: uuid,
             stopped_components,
             child_index: node_index.child_index,
@@ -834,15 +933,47 @@ impl<S: MutinyStorage> NodeBuilder<S> {
             persister,
             wallet,
             logger,
-            lsp_client,
+            lsp_clients,
+            lsp_configs,
+            lsp_selection: self.lsp_selection,
+            lsp_stats: Arc::new(RwLock::new(lsp_stats)),
+            lsp_fee_limits: self.lsp_fee_limits,
+            pending_lsp_quotes: Arc::new(RwLock::new(HashMap::new())),
+            router,
+            event_handler,
+            message_handler,
+            channel_recovery_running: Arc::new(AtomicBool::new(false)),
             sync_lock,
             stop,
             #[cfg(target_arch = "wasm32")]
             websocket_proxy_addr,
-        })
+        };
+
+        // pick back up any channel recovery that was in progress
+        node.resume_channel_recovery();
+
+        Ok(node)
     }
 }
 
+/// A fee from one of our LSPs for receiving a payment.
+struct QuotedLsp {
+    /// Index of the LSP in the node's list
+    index: usize,
+    user_channel_id: Option<u128>,
+    has_inbound_capacity: bool,
+    lsp_fee: FeeResponse,
+}
+
+/// A fee quote waiting for the user to approve it.
+struct PendingLspQuote {
+    quote: LspFeeQuote,
+    /// The LSPs that quoted, in the order to try them.
+    /// Empty if no LSP is needed to receive the payment
+    lsps: Vec<QuotedLsp>,
+    route_hints: Option<Vec<PhantomRouteHints>>,
+}
+
 pub(crate) struct Node<S: MutinyStorage> {
     pub _uuid: String,
     pub child_index: u32,
@@ -857,7 +988,18 @@ pub(crate) struct Node<S: MutinyStorage> {
     pub persister: Arc<MutinyNodePersister<S>>,
     wallet: Arc<OnChainWallet<S>>,
     pub(crate) logger: Arc<MutinyLogger>,
-    pub(crate) lsp_client: Option<AnyLsp<S>>,
+    /// The node's LSPs, the first one is its primary LSP
+    pub(crate) lsp_clients: Vec<AnyLsp<S>>,
+    /// The configs of all of the node's LSPs, including ones that were down at startup
+    lsp_configs: Vec<LspConfig>,
+    lsp_selection: LspSelection,
+    lsp_stats: Arc<RwLock<HashMap<PublicKey, LspStats>>>,
+    lsp_fee_limits: LspFeeLimits,
+    pending_lsp_quotes: Arc<RwLock<HashMap<String, PendingLspQuote>>>,
+    router: Arc<Router>,
+    event_handler: EventHandler<S>,
+    message_handler: Arc<MutinyMessageHandler<S>>,
+    channel_recovery_running: Arc<AtomicBool>,
     pub(crate) sync_lock: Arc<Mutex<()>>,
     stop: Arc<AtomicBool>,
     #[cfg(target_arch = "wasm32")]
@@ -894,7 +1036,8 @@ impl<S: MutinyStorage> Node<S> {
     pub fn node_index(&self) -> NodeIndex {
         NodeIndex {
             child_index: self.child_index,
-            lsp: self.lsp_client.as_ref().map(|l| l.get_config()),
+            lsp: self.lsp_configs.first().cloned(),
+            additional_lsps: self.lsp_configs.iter().skip(1).cloned().collect(),
             archived: Some(false),
         }
     }
@@ -961,6 +1104,124 @@ impl<S: MutinyStorage> Node<S> {
         }
     }
 
+    /// Creates a static backup of the channels this node has state for.
+    pub fn channel_backup(&self) -> Result<NodeChannelBackup, MutinyError> {
+        let monitors = self
+            .persister
+            .read_channel_monitors(self.keys_manager.clone())?;
+        let closures = self.persister.list_channel_closures()?;
+
+        let mut channels = Vec::with_capacity(monitors.len());
+        for (_, monitor) in monitors {
+            let (funding_txo, _) = monitor.get_funding_txo();
+            let channel_id = ChannelId::v1_from_funding_outpoint(funding_txo);
+            let closure = closures
+                .iter()
+                .find(|(_, c)| c.channel_id == Some(channel_id.0))
+                .map(|(_, c)| c);
+
+            // older monitors don't know the counterparty, fall back to the closure
+            let Some(counterparty) = monitor
+                .get_counterparty_node_id()
+                .or(closure.and_then(|c| c.node_id))
+            else {
+                log_warn!(
+                    self.logger,
+                    "unknown counterparty for channel {}, not backing up",
+                    channel_id.0.to_hex()
+                );
+                continue;
+            };
+
+            let peer_connection_string =
+                read_peer_info(&self.persister.storage, &NodeId::from_pubkey(&counterparty))?
+                    .and_then(|p| p.connection_string);
+
+            channels.push(ChannelBackup {
+                channel_id,
+                funding_txo,
+                counterparty,
+                peer_connection_string,
+                channel_keys_id: monitor.channel_keys_id(),
+                closed: closure.is_some(),
+            });
+        }
+
+        Ok(NodeChannelBackup {
+            child_index: self.child_index,
+            pubkey: self.pubkey,
+            channels,
+        })
+    }
+
+    /// Starts recovering the funds of the given channels.
+    /// Channels we still have the state of are skipped.
+    ///
+    /// Returns the number of channels added to the recovery.
+    pub fn start_channel_recovery(
+        &self,
+        channels: Vec<ChannelBackup>,
+    ) -> Result<usize, MutinyError> {
+        let monitors = self.chain_monitor.list_monitors();
+        let mut pending = self.persister.get_channel_recovery()?;
+
+        let mut added = 0;
+        for channel in channels {
+            if monitors.contains(&channel.funding_txo)
+                || pending.iter().any(|c| c.channel_id == channel.channel_id)
+            {
+                continue;
+            }
+
+            // save the peer so the reconnection loop connects to it
+            if let Some(connection_string) = channel.peer_connection_string.as_ref() {
+                save_peer_connection_info(
+                    &self.persister.storage,
+                    &self._uuid,
+                    &NodeId::from_pubkey(&channel.counterparty),
+                    connection_string,
+                    None,
+                )?;
+            }
+
+            pending.push(channel);
+            added += 1;
+        }
+
+        self.persister.set_channel_recovery(pending)?;
+        self.resume_channel_recovery();
+
+        Ok(added)
+    }
+
+    /// Spawns the channel recovery if there is one pending and it is not already running
+    fn resume_channel_recovery(&self) {
+        let has_pending = match self.persister.get_channel_recovery() {
+            Ok(pending) => !pending.is_empty(),
+            Err(e) => {
+                log_error!(self.logger, "could not read channel recovery: {e}");
+                false
+            }
+        };
+
+        if !has_pending || self.channel_recovery_running.swap(true, Ordering::Relaxed) {
+            return;
+        }
+
+        let recovery = ChannelRecovery {
+            persister: self.persister.clone(),
+            keys_manager: self.keys_manager.clone(),
+            event_handler: self.event_handler.clone(),
+            message_handler: self.message_handler.clone(),
+            peer_manager: self.peer_manager.clone(),
+            chain_source: self.wallet.blockchain.clone(),
+            logger: self.logger.clone(),
+            running: self.channel_recovery_running.clone(),
+            stop: self.stop.clone(),
+        };
+        utils::spawn(recovery.run());
+    }
+
     pub fn disconnect_peer(&self, peer_id: PublicKey) {
         self.peer_manager.disconnect_by_node_id(peer_id);
     }
@@ -974,28 +1235,223 @@ impl<S: MutinyStorage> Node<S> {
         amount_sat: Option<u64>,
         route_hints: Option<Vec<PhantomRouteHints>>,
     ) -> Result<Bolt11Invoice, MutinyError> {
-        match self.lsp_client.as_ref() {
-            Some(lsp) => {
-                self.connect_peer(
-                    PubkeyConnectionInfo::new(&lsp.get_lsp_connection_string())?,
+        self.create_invoice_with_options(amount_sat, InvoiceOptions::default(), route_hints)
+            .await
+    }
+
+    /// Creates an invoice with the given description or description hash and expiry.
+    pub async fn create_invoice_with_options(
+        &self,
+        amount_sat: Option<u64>,
+        options: InvoiceOptions,
+        route_hints: Option<Vec<PhantomRouteHints>>,
+    ) -> Result<Bolt11Invoice, MutinyError> {
+        if self.lsp_clients.is_empty() {
+            return self
+                .create_internal_invoice(amount_sat, None, route_hints, None, options)
+                .await;
+        }
+
+        // LSP requires an amount:
+        let amount_sat = amount_sat.ok_or(MutinyError::BadAmountError)?;
+
+        let quotes = match self.quote_lsps(amount_sat).await? {
+            Some(quotes) => quotes,
+            None => {
+                return self
+                    .create_internal_invoice(Some(amount_sat), None, route_hints, None, options)
+                    .await
+            }
+        };
+
+        self.create_invoice_with_lsps(quotes, amount_sat, route_hints, options)
+            .await
+    }
+
+    /// Tries the quoted LSPs in order until one of them creates the invoice.
+    async fn create_invoice_with_lsps(
+        &self,
+        quotes: Vec<QuotedLsp>,
+        amount_sat: u64,
+        route_hints: Option<Vec<PhantomRouteHints>>,
+        options: InvoiceOptions,
+    ) -> Result<Bolt11Invoice, MutinyError> {
+        let mut last_error = None;
+        for quote in quotes {
+            let lsp = &self.lsp_clients[quote.index];
+            match self
+                .create_lsp_invoice(
+                    lsp,
+                    amount_sat,
+                    quote.lsp_fee,
+                    quote.user_channel_id,
+                    quote.has_inbound_capacity,
+                    route_hints.clone(),
+                    options.clone(),
+                )
+                .await
+            {
+                Ok(invoice) => {
+                    self.record_lsp_result(lsp, true);
+                    return Ok(invoice);
+                }
+                Err(e) => {
+                    log_warn!(
+                        self.logger,
+                        "Could not create invoice with LSP {}: {e}",
+                        lsp.get_lsp_pubkey()
+                    );
+                    self.record_lsp_result(lsp, false);
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        Err(last_error.unwrap_or(MutinyError::LspGenericError))
+    }
+
+    /// Gets the fee for receiving the amount without creating an invoice yet.
+    /// The invoice is created once the quote is approved with [`Node::approve_lsp_fee_quote`].
+    pub async fn quote_lsp_fee(
+        &self,
+        amount_sat: u64,
+        route_hints: Option<Vec<PhantomRouteHints>>,
+    ) -> Result<LspFeeQuote, MutinyError> {
+        let quotes = if self.lsp_clients.is_empty() {
+            vec![]
+        } else {
+            self.quote_lsps(amount_sat).await?.unwrap_or_default()
+        };
+        let quote = quotes.first();
+
+        let mut id_bytes = [0u8; 16];
+        getrandom::getrandom(&mut id_bytes).map_err(|_| MutinyError::SeedGenerationFailed)?;
+
+        let lsp_quote = LspFeeQuote {
+            id: id_bytes.to_hex(),
+            amount_sat,
+            fee_msat: quote.map_or(0, |q| q.lsp_fee.fee_amount_msat),
+            lsp_pubkey: quote.map(|q| self.lsp_clients[q.index].get_lsp_pubkey()),
+            expires_at: utils::now().as_secs() + LSP_FEE_QUOTE_EXPIRY_SECS,
+        };
+
+        let mut pending_quotes = self.pending_lsp_quotes.write().unwrap();
+        let now = utils::now().as_secs();
+        pending_quotes.retain(|_, p| p.quote.expires_at >= now);
+        pending_quotes.insert(
+            lsp_quote.id.clone(),
+            PendingLspQuote {
+                quote: lsp_quote.clone(),
+                lsps: quotes,
+                route_hints,
+            },
+        );
+
+        Ok(lsp_quote)
+    }
+
+    /// Creates the invoice for a fee quote from [`Node::quote_lsp_fee`].
+    /// If the quoted LSP fails, the other LSPs that quoted no more than it are tried.
+    pub async fn approve_lsp_fee_quote(
+        &self,
+        quote_id: &str,
+    ) -> Result<Bolt11Invoice, MutinyError> {
+        let pending = self
+            .pending_lsp_quotes
+            .write()
+            .unwrap()
+            .remove(quote_id)
+            .filter(|p| p.quote.expires_at >= utils::now().as_secs())
+            .ok_or(MutinyError::LspFeeQuoteNotFound)?;
+
+        let amount_sat = pending.quote.amount_sat;
+        if pending.lsps.is_empty() {
+            return self
+                .create_internal_invoice(
+                    Some(amount_sat),
+                    None,
+                    pending.route_hints,
                     None,
+                    InvoiceOptions::default(),
                 )
-                .await?;
+                .await;
+        }
+
+        // never charge more than the fee that was approved
+        let approved_fee_msat = pending.quote.fee_msat;
+        let quotes = pending
+            .lsps
+            .into_iter()
+            .filter(|q| q.lsp_fee.fee_amount_msat <= approved_fee_msat)
+            .collect();
+        self.create_invoice_with_lsps(
+            quotes,
+            amount_sat,
+            pending.route_hints,
+            InvoiceOptions::default(),
+        )
+        .await
+    }
+
+    /// Gets fees from all of our LSPs that can take the amount, ordered by our
+    /// selection policy. LSPs charging more than our fee limits are left out.
+    ///
+    /// Returns none if a normal invoice should be used instead.
+    async fn quote_lsps(&self, amount_sat: u64) -> Result<Option<Vec<QuotedLsp>>, MutinyError> {
+        // connect to our LSPs, one being down should not stop us from receiving
+        let mut last_error = None;
+        let mut lsps = Vec::with_capacity(self.lsp_clients.len());
+        for (index, lsp) in self.lsp_clients.iter().enumerate() {
+            let connect_res = match PubkeyConnectionInfo::new(&lsp.get_lsp_connection_string()) {
+                Ok(connect_info) => self.connect_peer(connect_info, None).await,
+                Err(e) => Err(e),
+            };
 
-                // LSP requires an amount:
-                let amount_sat = amount_sat.ok_or(MutinyError::BadAmountError)?;
+            match connect_res {
+                Ok(_) => lsps.push((index, lsp)),
+                Err(e) => {
+                    log_warn!(
+                        self.logger,
+                        "Could not connect to LSP {}: {e}",
+                        lsp.get_lsp_pubkey()
+                    );
+                    self.record_lsp_result(lsp, false);
+                    last_error = Some(e);
+                }
+            }
+        }
+        if lsps.is_empty() {
+            return Err(last_error.unwrap_or(MutinyError::LspConnectionError));
+        }
 
-                // Needs any amount over 0 if channel exists
-                // Needs amount over minimum if no channel
-                let inbound_capacity_msat: u64 = self
-                    .channel_manager
+        // Needs any amount over 0 if channel exists
+        // Needs amount over minimum if no channel
+        let inbound_capacities: Vec<u64> = lsps
+            .iter()
+            .map(|(_, lsp)| {
+                self.channel_manager
                     .list_channels_with_counterparty(&lsp.get_lsp_pubkey())
                     .iter()
                     .map(|c| c.inbound_capacity_msat)
-                    .sum();
+                    .sum()
+            })
+            .collect();
+        let total_inbound_capacity_msat: u64 = inbound_capacities.iter().sum();
+
+        log_debug!(self.logger, "Current inbound liquidity {total_inbound_capacity_msat}msats from {} LSPs, creating invoice for {}msats", lsps.len(), amount_sat * 1000);
 
-                log_debug!(self.logger, "Current inbound liquidity {inbound_capacity_msat}msats, creating invoice for {}msats", amount_sat * 1000);
+        // if no single LSP can take the payment but they can together, use a normal
+        // invoice, it has route hints through each of them so the payment can be split
+        if lsps.len() > 1
+            && inbound_capacities.iter().all(|c| *c <= amount_sat * 1_000)
+            && total_inbound_capacity_msat > amount_sat * 1_000
+        {
+            return Ok(None);
+        }
 
+        // check the fee from each LSP that can take the amount
+        let quotes = join_all(lsps.into_iter().zip(inbound_capacities).filter_map(
+            |((index, lsp), inbound_capacity_msat)| {
                 let has_inbound_capacity = inbound_capacity_msat > amount_sat * 1_000;
 
                 let min_amount_sat = if has_inbound_capacity {
@@ -1005,127 +1461,306 @@ impl<S: MutinyStorage> Node<S> {
                 };
 
                 if amount_sat < min_amount_sat {
-                    return Err(MutinyError::BadAmountError);
+                    return None;
                 }
 
                 let user_channel_id = match lsp {
                     AnyLsp::VoltageFlow(_) => None,
-                    AnyLsp::Lsps(_) => Some(utils::now().as_secs().into()),
+                    AnyLsp::Lsps(_) => {
+                        let mut user_channel_id_bytes = [0u8; 16];
+                        getrandom::getrandom(&mut user_channel_id_bytes).unwrap();
+                        Some(u128::from_be_bytes(user_channel_id_bytes))
+                    }
                 };
 
-                // check the fee from the LSP
-                let lsp_fee = lsp
-                    .get_lsp_fee_msat(FeeRequest {
-                        pubkey: self.pubkey.to_hex(),
-                        amount_msat: amount_sat * 1000,
+                Some(async move {
+                    let lsp_fee = lsp
+                        .get_lsp_fee_msat(FeeRequest {
+                            pubkey: self.pubkey.to_hex(),
+                            amount_msat: amount_sat * 1000,
+                            user_channel_id,
+                        })
+                        .await;
+                    (index, lsp, user_channel_id, has_inbound_capacity, lsp_fee)
+                })
+            },
+        ))
+        .await;
+
+        if quotes.is_empty() {
+            return Err(MutinyError::BadAmountError);
+        }
+
+        let mut candidates = Vec::with_capacity(quotes.len());
+        let mut quoted = HashMap::with_capacity(quotes.len());
+        for (index, lsp, user_channel_id, has_inbound_capacity, lsp_fee) in quotes {
+            match lsp_fee {
+                Ok(lsp_fee)
+                    if !self
+                        .lsp_fee_limits
+                        .allows(amount_sat * 1_000, lsp_fee.fee_amount_msat) =>
+                {
+                    log_warn!(
+                        self.logger,
+                        "LSP {} fee of {}msats is above our limits, skipping it",
+                        lsp.get_lsp_pubkey(),
+                        lsp_fee.fee_amount_msat
+                    );
+                    last_error = Some(MutinyError::LspFeeTooHigh);
+                }
+                Ok(lsp_fee) => {
+                    candidates.push(LspCandidate {
+                        index,
+                        fee_msat: lsp_fee.fee_amount_msat,
+                        stats: self.lsp_stats(lsp),
+                    });
+                    quoted.insert(
+                        index,
+                        QuotedLsp {
+                            index,
+                            user_channel_id,
+                            has_inbound_capacity,
+                            lsp_fee,
+                        },
+                    );
+                }
+                Err(e) => {
+                    log_warn!(
+                        self.logger,
+                        "Could not get fee from LSP {}: {e}",
+                        lsp.get_lsp_pubkey()
+                    );
+                    self.record_lsp_result(lsp, false);
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        if candidates.is_empty() {
+            return Err(last_error.unwrap_or(MutinyError::LspGenericError));
+        }
+
+        rank_lsp_candidates(&mut candidates, self.lsp_selection);
+        Ok(Some(
+            candidates
+                .into_iter()
+                .filter_map(|c| quoted.remove(&c.index))
+                .collect(),
+        ))
+    }
+
+    /// Creates an invoice that goes through the given LSP, with the fee it quoted.
+    async fn create_lsp_invoice(
+        &self,
+        lsp: &AnyLsp<S>,
+        amount_sat: u64,
+        lsp_fee: FeeResponse,
+        user_channel_id: Option<u128>,
+        has_inbound_capacity: bool,
+        route_hints: Option<Vec<PhantomRouteHints>>,
+        options: InvoiceOptions,
+    ) -> Result<Bolt11Invoice, MutinyError> {
+        // Convert the fee from msat to sat for comparison and subtraction
+        let lsp_fee_sat = lsp_fee.fee_amount_msat / 1000;
+
+        // Ensure that the fee is less than the amount being requested.
+        // If it isn't, we don't subtract it.
+        // This prevents amount from being subtracted down to 0.
+        // This will mean that the LSP fee will be paid by the payer instead.
+        let amount_minus_fee = if lsp_fee_sat < amount_sat {
+            amount_sat
+                .checked_sub(lsp_fee_sat)
+                .ok_or(MutinyError::BadAmountError)?
+        } else {
+            amount_sat
+        };
+
+        match lsp {
+            AnyLsp::VoltageFlow(client) => {
+                let description_hash = options.description_hash;
+                let invoice = self
+                    .create_internal_invoice(
+                        Some(amount_minus_fee),
+                        Some(lsp_fee.fee_amount_msat),
+                        route_hints,
+                        None,
+                        options.clone(),
+                    )
+                    .await?;
+
+                let lsp_invoice = client
+                    .get_lsp_invoice(InvoiceRequest {
+                        bolt11: Some(invoice.to_string()),
                         user_channel_id,
+                        fee_id: lsp_fee.id,
+                        options,
+                        route_hints: vec![],
                     })
                     .await?;
 
-                // Convert the fee from msat to sat for comparison and subtraction
-                let lsp_fee_sat = lsp_fee.fee_amount_msat / 1000;
-
-                // Ensure that the fee is less than the amount being requested.
-                // If it isn't, we don't subtract it.
-                // This prevents amount from being subtracted down to 0.
-                // This will mean that the LSP fee will be paid by the payer instead.
-                let amount_minus_fee = if lsp_fee_sat < amount_sat {
-                    amount_sat
-                        .checked_sub(lsp_fee_sat)
-                        .ok_or(MutinyError::BadAmountError)?
-                } else {
-                    amount_sat
+                if invoice.network() != self.network {
+                    return Err(MutinyError::IncorrectNetwork(invoice.network()));
+                }
+
+                if lsp_invoice.payment_hash() != invoice.payment_hash()
+                    || lsp_invoice.recover_payee_pub_key() != client.get_lsp_pubkey()
+                    || (lsp_invoice.amount_milli_satoshis() != Some(amount_sat * 1_000)
+                        && amount_sat != amount_minus_fee)
+                {
+                    log_error!(
+                        self.logger,
+                        "Received unexpected invoice from LSP: {:?} when amount was {}",
+                        lsp_invoice.amount_milli_satoshis(),
+                        amount_sat * 1_000
+                    );
+                    return Err(MutinyError::InvoiceCreationFailed);
+                }
+
+                // the wrapped invoice must commit to the same description as ours
+                let description_matches = match (lsp_invoice.description(), invoice.description()) {
+                    (Bolt11InvoiceDescription::Hash(theirs), Bolt11InvoiceDescription::Hash(_)) => {
+                        Some(theirs.0) == description_hash
+                    }
+                    (
+                        Bolt11InvoiceDescription::Direct(theirs),
+                        Bolt11InvoiceDescription::Direct(ours),
+                    ) => description_hash.is_none() && theirs.to_string() == ours.to_string(),
+                    _ => false,
                 };
+                if !description_matches {
+                    log_error!(
+                        self.logger,
+                        "Received invoice from LSP with a different description"
+                    );
+                    return Err(MutinyError::InvoiceCreationFailed);
+                }
+
+                log_debug!(self.logger, "Got wrapped invoice from LSP: {lsp_invoice}");
 
-                match lsp {
-                    AnyLsp::VoltageFlow(client) => {
-                        let invoice = self
-                            .create_internal_invoice(
-                                Some(amount_minus_fee),
+                Ok(lsp_invoice)
+            }
+            AnyLsp::Lsps(client) => {
+                if has_inbound_capacity {
+                    Ok(self
+                        .create_internal_invoice(Some(amount_sat), None, route_hints, None, options)
+                        .await?)
+                } else {
+                    let lsp_invoice = match client
+                        .get_lsp_invoice(InvoiceRequest {
+                            bolt11: None,
+                            user_channel_id,
+                            fee_id: lsp_fee.id,
+                            options,
+                            route_hints: self.other_lsp_route_hints(lsp, amount_sat * 1_000),
+                        })
+                        .await
+                    {
+                        Ok(invoice) => {
+                            self.save_invoice_payment_info(
+                                invoice.clone(),
+                                Some(amount_sat * 1_000),
                                 Some(lsp_fee.fee_amount_msat),
-                                route_hints,
                             )
                             .await?;
 
-                        let lsp_invoice = client
-                            .get_lsp_invoice(InvoiceRequest {
-                                bolt11: Some(invoice.to_string()),
-                                user_channel_id,
-                                fee_id: lsp_fee.id,
-                            })
-                            .await?;
-
-                        if invoice.network() != self.network {
-                            return Err(MutinyError::IncorrectNetwork(invoice.network()));
+                            invoice
                         }
-
-                        if lsp_invoice.payment_hash() != invoice.payment_hash()
-                            || lsp_invoice.recover_payee_pub_key() != client.get_lsp_pubkey()
-                            || (lsp_invoice.amount_milli_satoshis() != Some(amount_sat * 1_000)
-                                && amount_sat != amount_minus_fee)
-                        {
-                            log_error!(
-                                self.logger,
-                                "Received unexpected invoice from LSP: {:?} when amount was {}",
-                                lsp_invoice.amount_milli_satoshis(),
-                                amount_sat * 1_000
-                            );
-                            return Err(MutinyError::InvoiceCreationFailed);
+                        Err(e) => {
+                            log_error!(self.logger, "Failed to get invoice from LSP: {e}");
+                            return Err(e);
                         }
+                    };
+                    Ok(lsp_invoice)
+                }
+            }
+        }
+    }
+
+    /// If the pubkey is one of our LSPs.
+    pub(crate) fn is_lsp(&self, pubkey: &PublicKey) -> bool {
+        self.lsp_clients
+            .iter()
+            .any(|lsp| &lsp.get_lsp_pubkey() == pubkey)
+    }
 
-                        log_debug!(self.logger, "Got wrapped invoice from LSP: {lsp_invoice}");
+    fn lsp_stats(&self, lsp: &AnyLsp<S>) -> LspStats {
+        self.lsp_stats
+            .read()
+            .ok()
+            .and_then(|stats| stats.get(&lsp.get_lsp_pubkey()).copied())
+            .unwrap_or_default()
+    }
 
-                        Ok(lsp_invoice)
-                    }
-                    AnyLsp::Lsps(client) => {
-                        if has_inbound_capacity {
-                            Ok(self
-                                .create_internal_invoice(Some(amount_sat), None, route_hints)
-                                .await?)
-                        } else {
-                            let lsp_invoice = match client
-                                .get_lsp_invoice(InvoiceRequest {
-                                    bolt11: None,
-                                    user_channel_id,
-                                    fee_id: lsp_fee.id,
-                                })
-                                .await
-                            {
-                                Ok(invoice) => {
-                                    self.save_invoice_payment_info(
-                                        invoice.clone(),
-                                        Some(amount_sat * 1_000),
-                                        Some(lsp_fee.fee_amount_msat),
-                                    )
-                                    .await?;
-
-                                    invoice
-                                }
-                                Err(e) => {
-                                    log_error!(self.logger, "Failed to get invoice from LSP: {e}");
-                                    return Err(e);
-                                }
-                            };
-                            Ok(lsp_invoice)
-                        }
-                    }
-                }
+    fn record_lsp_result(&self, lsp: &AnyLsp<S>, success: bool) {
+        if let Ok(mut stats) = self.lsp_stats.write() {
+            stats
+                .entry(lsp.get_lsp_pubkey())
+                .or_default()
+                .record(success);
+            if let Err(e) = persist_lsp_stats(&self.persister.storage, &self.pubkey, &stats) {
+                log_warn!(self.logger, "Could not save LSP stats: {e}");
             }
-            None => Ok(self
-                .create_internal_invoice(amount_sat, None, route_hints)
-                .await?),
         }
     }
 
+    /// Route hints through our channels with LSPs other than the given one,
+    /// so an invoice from one LSP can also be paid through the others.
+    /// Only channels that can receive the whole amount are used.
+    fn other_lsp_route_hints(&self, lsp: &AnyLsp<S>, amount_msat: u64) -> Vec<RouteHint> {
+        let lsp_pubkey = lsp.get_lsp_pubkey();
+        self.channel_manager
+            .list_usable_channels()
+            .into_iter()
+            .filter(|c| {
+                c.counterparty.node_id != lsp_pubkey
+                    && self.is_lsp(&c.counterparty.node_id)
+                    && c.inbound_capacity_msat >= amount_msat
+            })
+            .filter_map(|c| {
+                let forwarding_info = c.counterparty.forwarding_info.as_ref()?;
+                Some(RouteHint(vec![RouteHintHop {
+                    src_node_id: c.counterparty.node_id,
+                    short_channel_id: c.get_inbound_payment_scid()?,
+                    fees: RoutingFees {
+                        base_msat: forwarding_info.fee_base_msat,
+                        proportional_millionths: forwarding_info.fee_proportional_millionths,
+                    },
+                    cltv_expiry_delta: forwarding_info.cltv_expiry_delta,
+                    htlc_minimum_msat: c.inbound_htlc_minimum_msat,
+                    htlc_maximum_msat: c.inbound_htlc_maximum_msat,
+                }]))
+            })
+            .collect()
+    }
+
+    /// Creates an invoice from our channel manager. If a payment hash is given
+    /// it is a hold invoice, payments to it are held until settled or cancelled.
     async fn create_internal_invoice(
         &self,
         amount_sat: Option<u64>,
         fee_amount_msat: Option<u64>,
         route_hints: Option<Vec<PhantomRouteHints>>,
+        payment_hash: Option<PaymentHash>,
+        options: InvoiceOptions,
     ) -> Result<Bolt11Invoice, MutinyError> {
+        // hold invoices always have an empty description
+        if payment_hash.is_some() && options.description_hash.is_some() {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+        // an invoice can have either a description or its hash
+        if options.description.is_some() && options.description_hash.is_some() {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+
         let amount_msat = amount_sat.map(|s| s * 1_000);
-        // Set description to empty string to make smallest possible invoice/QR code
-        let description = "".to_string();
+        let expiry_secs = options.expiry_secs();
+        // Default to an empty description to make smallest possible invoice/QR code
+        let description = options.description.unwrap_or_default();
+        let description_hash = options.description_hash;
+        let min_final_cltv_expiry_delta = match payment_hash {
+            Some(_) => HOLD_INVOICE_MIN_FINAL_CLTV_EXPIRY_DELTA,
+            None => 40,
+        };
 
         // wait for first sync to complete
         for _ in 0..60 {
@@ -1141,32 +1776,74 @@ impl<S: MutinyStorage> Node<S> {
             sleep(1_000).await;
         }
 
-        let invoice_res = match route_hints {
-            None => {
-                let now = crate::utils::now();
-                create_invoice_from_channelmanager_and_duration_since_epoch(
+        let invoice_res = match (route_hints, description_hash) {
+            (None, Some(description_hash)) => {
+                create_invoice_from_channelmanager_with_description_hash_and_duration_since_epoch(
                     &self.channel_manager.clone(),
                     self.keys_manager.clone(),
                     self.logger.clone(),
                     self.network.into(),
                     amount_msat,
-                    description,
-                    now,
-                    3600,
-                    Some(40),
+                    lightning_invoice::Sha256(description_hash),
+                    crate::utils::now(),
+                    expiry_secs,
+                    Some(min_final_cltv_expiry_delta),
                 )
             }
-            Some(r) => create_phantom_invoice(
+            (Some(r), Some(description_hash)) => create_phantom_invoice_with_description_hash(
                 amount_msat,
                 None,
+                expiry_secs,
+                lightning_invoice::Sha256(description_hash),
+                r,
+                self.keys_manager.clone(),
+                self.keys_manager.clone(),
+                self.logger.clone(),
+                self.network.into(),
+                Some(min_final_cltv_expiry_delta),
+                crate::utils::now(),
+            ),
+            (None, None) => {
+                let now = crate::utils::now();
+                match payment_hash {
+                    Some(payment_hash) => {
+                        create_invoice_from_channelmanager_and_duration_since_epoch_with_payment_hash(
+                            &self.channel_manager.clone(),
+                            self.keys_manager.clone(),
+                            self.logger.clone(),
+                            self.network.into(),
+                            amount_msat,
+                            description,
+                            now,
+                            expiry_secs,
+                            payment_hash,
+                            Some(min_final_cltv_expiry_delta),
+                        )
+                    }
+                    None => create_invoice_from_channelmanager_and_duration_since_epoch(
+                        &self.channel_manager.clone(),
+                        self.keys_manager.clone(),
+                        self.logger.clone(),
+                        self.network.into(),
+                        amount_msat,
+                        description,
+                        now,
+                        expiry_secs,
+                        Some(min_final_cltv_expiry_delta),
+                    ),
+                }
+            }
+            (Some(r), None) => create_phantom_invoice(
+                amount_msat,
+                payment_hash,
                 description,
-                3600,
+                expiry_secs,
                 r,
                 self.keys_manager.clone(),
                 self.keys_manager.clone(),
                 self.logger.clone(),
                 self.network.into(),
-                Some(40),
+                Some(min_final_cltv_expiry_delta),
                 crate::utils::now(),
             ),
         };
@@ -1198,6 +1875,8 @@ impl<S: MutinyStorage> Node<S> {
             amt_msat: MillisatAmount(amount_msat),
             fee_paid_msat: fee_amount_msat,
             bolt11: Some(invoice.clone()),
+            bolt12: None,
+            custom_tlvs: vec![],
             payee_pubkey: None,
             last_update,
         };
@@ -1215,6 +1894,116 @@ impl<S: MutinyStorage> Node<S> {
         Ok(())
     }
 
+    /// Creates a hold invoice for the given payment hash. Payments to it are held
+    /// until they are settled with the preimage or cancelled.
+    pub async fn create_hold_invoice(
+        &self,
+        payment_hash: Sha256,
+        amount_sat: Option<u64>,
+        route_hints: Option<Vec<PhantomRouteHints>>,
+    ) -> Result<Bolt11Invoice, MutinyError> {
+        let storage = &self.persister.storage;
+        if read_hold_invoice(storage, &payment_hash)?.is_some()
+            || read_payment_info(storage, payment_hash.as_inner(), true, &self.logger).is_some()
+        {
+            return Err(MutinyError::NonUniquePaymentHash);
+        }
+
+        let invoice = self
+            .create_internal_invoice(
+                amount_sat,
+                None,
+                route_hints,
+                Some(PaymentHash(payment_hash.into_inner())),
+                InvoiceOptions::default(),
+            )
+            .await?;
+
+        let hold_invoice = HoldInvoice::new(invoice.clone(), utils::now().as_secs());
+        persist_hold_invoice(storage, &hold_invoice)?;
+
+        Ok(invoice)
+    }
+
+    /// Claims the payment held for a hold invoice.
+    pub fn settle_hold_invoice(
+        &self,
+        mut hold_invoice: HoldInvoice,
+        preimage: [u8; 32],
+    ) -> Result<(), MutinyError> {
+        // save the preimage now, we don't get it back when the payment is claimed
+        let payment_hash = hold_invoice.payment_hash.into_inner();
+        if let Some(mut payment_info) =
+            read_payment_info(&self.persister.storage, &payment_hash, true, &self.logger)
+        {
+            payment_info.preimage = Some(preimage);
+            payment_info.last_update = utils::now().as_secs();
+            persist_payment_info(&self.persister.storage, &payment_hash, &payment_info, true)
+                .map_err(|e| MutinyError::PersistenceFailed {
+                    source: MutinyStorageError::Other(e.into()),
+                })?;
+        }
+
+        self.channel_manager.claim_funds(PaymentPreimage(preimage));
+        log_info!(
+            self.logger,
+            "Settled hold invoice {}",
+            hold_invoice.payment_hash
+        );
+
+        hold_invoice.status = HoldInvoiceStatus::Settled;
+        hold_invoice.last_update = utils::now().as_secs();
+        persist_hold_invoice(&self.persister.storage, &hold_invoice)
+    }
+
+    /// Fails back any payment held for a hold invoice and stops accepting new ones.
+    pub fn cancel_hold_invoice(&self, mut hold_invoice: HoldInvoice) -> Result<(), MutinyError> {
+        let payment_hash = hold_invoice.payment_hash.into_inner();
+        self.channel_manager
+            .fail_htlc_backwards(&PaymentHash(payment_hash));
+
+        if let Some(mut payment_info) =
+            read_payment_info(&self.persister.storage, &payment_hash, true, &self.logger)
+        {
+            payment_info.status = HTLCStatus::Failed;
+            payment_info.last_update = utils::now().as_secs();
+            persist_payment_info(&self.persister.storage, &payment_hash, &payment_info, true)
+                .map_err(|e| MutinyError::PersistenceFailed {
+                    source: MutinyStorageError::Other(e.into()),
+                })?;
+        }
+        log_info!(
+            self.logger,
+            "Cancelled hold invoice {}",
+            hold_invoice.payment_hash
+        );
+
+        hold_invoice.status = HoldInvoiceStatus::Cancelled;
+        hold_invoice.last_update = utils::now().as_secs();
+        persist_hold_invoice(&self.persister.storage, &hold_invoice)
+    }
+
+    /// Cancels the payments this node is holding that are close to their claim deadline,
+    /// returning the payment hashes of the cancelled hold invoices.
+    pub(crate) fn cancel_expiring_hold_invoices(&self) -> Result<Vec<Sha256>, MutinyError> {
+        let height = self.channel_manager.current_best_block().height();
+        let mut cancelled = vec![];
+        for hold_invoice in list_hold_invoices(&self.persister.storage)? {
+            if hold_invoice.node_id == Some(self.pubkey) && hold_invoice.should_cancel(height) {
+                log_warn!(
+                    self.logger,
+                    "Hold invoice {} is close to its claim deadline, cancelling",
+                    hold_invoice.payment_hash
+                );
+                let payment_hash = hold_invoice.payment_hash;
+                self.cancel_hold_invoice(hold_invoice)?;
+                cancelled.push(payment_hash);
+            }
+        }
+
+        Ok(cancelled)
+    }
+
     pub fn get_invoice_by_hash(&self, payment_hash: &Sha256) -> Result<MutinyInvoice, MutinyError> {
         let (payment_info, inbound) = self.get_payment_info_from_persisters(payment_hash)?;
         let labels_map = self.persister.storage.get_invoice_labels()?;
@@ -1287,12 +2076,46 @@ impl<S: MutinyStorage> Node<S> {
         Retry::Attempts(15)
     }
 
+    /// Checks that we have enough balance to send the given amount and
+    /// waits until we have a usable channel to make the payment with.
+    async fn prepare_outbound_payment(&self, send_msats: u64) -> Result<(), MutinyError> {
+        // check if we have enough balance to send
+        let channels = self.channel_manager.list_channels();
+        if channels
+            .iter()
+            // only consider channels that are confirmed
+            .filter(|c| c.is_channel_ready)
+            .map(|c| c.balance_msat)
+            .sum::<u64>()
+            < send_msats
+        {
+            // Channels exist but not enough capacity
+            return Err(MutinyError::InsufficientBalance);
+        }
+
+        // make sure node at least has one connection before attempting payment
+        // wait for connection before paying, or otherwise instant fail anyways
+        for _ in 0..DEFAULT_PAYMENT_TIMEOUT {
+            // check if we've been stopped
+            if self.stop.load(Ordering::Relaxed) {
+                return Err(MutinyError::NotRunning);
+            }
+            if !self.channel_manager.list_usable_channels().is_empty() {
+                break;
+            }
+            sleep(1_000).await;
+        }
+
+        Ok(())
+    }
+
     /// init_invoice_payment sends off the payment but does not wait for results
     /// use pay_invoice_with_timeout to wait for results
     pub async fn init_invoice_payment(
         &self,
         invoice: &Bolt11Invoice,
         amt_sats: Option<u64>,
+        max_fee_sats: Option<u64>,
     ) -> Result<(PaymentId, PaymentHash), MutinyError> {
         let payment_hash = invoice.payment_hash().as_inner();
 
@@ -1314,32 +2137,7 @@ impl<S: MutinyStorage> Node<S> {
             .or(amt_sats.map(|x| x * 1_000))
             .ok_or(MutinyError::InvoiceInvalid)?;
 
-        // check if we have enough balance to send
-        let channels = self.channel_manager.list_channels();
-        if channels
-            .iter()
-            // only consider channels that are confirmed
-            .filter(|c| c.is_channel_ready)
-            .map(|c| c.balance_msat)
-            .sum::<u64>()
-            < send_msats
-        {
-            // Channels exist but not enough capacity
-            return Err(MutinyError::InsufficientBalance);
-        }
-
-        // make sure node at least has one connection before attempting payment
-        // wait for connection before paying, or otherwise instant fail anyways
-        for _ in 0..DEFAULT_PAYMENT_TIMEOUT {
-            // check if we've been stopped
-            if self.stop.load(Ordering::Relaxed) {
-                return Err(MutinyError::NotRunning);
-            }
-            if !self.channel_manager.list_usable_channels().is_empty() {
-                break;
-            }
-            sleep(1_000).await;
-        }
+        self.prepare_outbound_payment(send_msats).await?;
 
         let (pay_result, amt_msat) = if invoice.amount_milli_satoshis().is_none() {
             if amt_sats.is_none() {
@@ -1347,7 +2145,7 @@ impl<S: MutinyStorage> Node<S> {
             }
             let amount_msats = amt_sats.unwrap() * 1_000;
             (
-                self.pay_invoice_internal(invoice, amount_msats),
+                self.pay_invoice_internal(invoice, amount_msats, max_fee_sats),
                 amount_msats,
             )
         } else {
@@ -1356,7 +2154,7 @@ impl<S: MutinyStorage> Node<S> {
             }
             let amount_msats = invoice.amount_milli_satoshis().unwrap();
             (
-                self.pay_invoice_internal(invoice, amount_msats),
+                self.pay_invoice_internal(invoice, amount_msats, max_fee_sats),
                 amount_msats,
             )
         };
@@ -1369,6 +2167,8 @@ impl<S: MutinyStorage> Node<S> {
             amt_msat: MillisatAmount(Some(amt_msat)),
             fee_paid_msat: None,
             bolt11: Some(invoice.clone()),
+            bolt12: None,
+            custom_tlvs: vec![],
             payee_pubkey: None,
             last_update,
         };
@@ -1396,15 +2196,11 @@ impl<S: MutinyStorage> Node<S> {
     }
 
     // copied from LDK, modified to change a couple params
-    fn pay_invoice_internal(
-        &self,
+    fn invoice_route_params(
         invoice: &Bolt11Invoice,
         amount_msats: u64,
-    ) -> Result<PaymentId, PaymentError> {
-        let payment_id = PaymentId(invoice.payment_hash().into_inner());
-        let payment_hash = PaymentHash((*invoice.payment_hash()).into_inner());
-        let mut recipient_onion = RecipientOnionFields::secret_only(*invoice.payment_secret());
-        recipient_onion.payment_metadata = invoice.payment_metadata().cloned();
+        max_fee_sats: Option<u64>,
+    ) -> RouteParameters {
         let mut payment_params = PaymentParameters::from_node_id(
             invoice.recover_payee_pub_key(),
             invoice.min_final_cltv_expiry_delta() as u32,
@@ -1417,11 +2213,25 @@ impl<S: MutinyStorage> Node<S> {
                 .with_bolt11_features(features.clone())
                 .unwrap();
         }
-        let route_params = RouteParameters {
+        RouteParameters {
             payment_params,
             final_value_msat: amount_msats,
-            max_total_routing_fee_msat: None, // main change from LDK, we just want payment to succeed
-        };
+            // main change from LDK, unless limited we just want payment to succeed
+            max_total_routing_fee_msat: max_fee_sats.map(|fee| fee * 1_000),
+        }
+    }
+
+    fn pay_invoice_internal(
+        &self,
+        invoice: &Bolt11Invoice,
+        amount_msats: u64,
+        max_fee_sats: Option<u64>,
+    ) -> Result<PaymentId, PaymentError> {
+        let payment_id = PaymentId(invoice.payment_hash().into_inner());
+        let payment_hash = PaymentHash((*invoice.payment_hash()).into_inner());
+        let mut recipient_onion = RecipientOnionFields::secret_only(*invoice.payment_secret());
+        recipient_onion.payment_metadata = invoice.payment_metadata().cloned();
+        let route_params = Self::invoice_route_params(invoice, amount_msats, max_fee_sats);
 
         match self.channel_manager.as_ref().send_payment(
             payment_hash,
@@ -1435,6 +2245,36 @@ impl<S: MutinyStorage> Node<S> {
         }
     }
 
+    /// Finds a route for the invoice without paying it and returns its fee in sats.
+    pub fn estimate_invoice_fee(
+        &self,
+        invoice: &Bolt11Invoice,
+        amt_sats: Option<u64>,
+    ) -> Result<u64, MutinyError> {
+        let amount_msats = invoice
+            .amount_milli_satoshis()
+            .or(amt_sats.map(|x| x * 1_000))
+            .ok_or(MutinyError::InvoiceInvalid)?;
+        let route_params = Self::invoice_route_params(invoice, amount_msats, None);
+
+        let first_hops = self.channel_manager.list_usable_channels();
+        let route = self
+            .router
+            .find_route(
+                &self.pubkey,
+                &route_params,
+                Some(&first_hops.iter().collect::<Vec<_>>()),
+                self.channel_manager.compute_inflight_htlcs(),
+            )
+            .map_err(|e| {
+                log_debug!(self.logger, "could not find route: {}", e.err);
+                MutinyError::RoutingFailed
+            })?;
+
+        // round up so we never under estimate
+        Ok((route.get_total_fees() + 999) / 1_000)
+    }
+
     async fn await_payment(
         &self,
         payment_id: PaymentId,
@@ -1452,6 +2292,11 @@ impl<S: MutinyStorage> Node<S> {
                 return Err(MutinyError::PaymentTimeout);
             }
 
+            // offer payments are moved from their payment id to
+            // their payment hash once we receive the invoice
+            let payment_hash = read_offer_payment_hash(&self.persister.storage, &payment_id.0)
+                .map(PaymentHash)
+                .unwrap_or(payment_hash);
             let payment_info = read_payment_info(
                 &self.persister.storage,
                 &payment_hash.0,
@@ -1479,11 +2324,14 @@ impl<S: MutinyStorage> Node<S> {
         &self,
         invoice: &Bolt11Invoice,
         amt_sats: Option<u64>,
+        max_fee_sats: Option<u64>,
         timeout_secs: Option<u64>,
         labels: Vec<String>,
     ) -> Result<MutinyInvoice, MutinyError> {
         // initiate payment
-        let (payment_id, payment_hash) = self.init_invoice_payment(invoice, amt_sats).await?;
+        let (payment_id, payment_hash) = self
+            .init_invoice_payment(invoice, amt_sats, max_fee_sats)
+            .await?;
         let timeout: u64 = timeout_secs.unwrap_or(DEFAULT_PAYMENT_TIMEOUT);
 
         self.await_payment(payment_id, payment_hash, timeout, labels)
@@ -1497,6 +2345,7 @@ impl<S: MutinyStorage> Node<S> {
         to_node: PublicKey,
         amt_sats: u64,
         message: Option<String>,
+        custom_tlvs: Vec<CustomTlv>,
         labels: Vec<String>,
         payment_id: PaymentId,
     ) -> Result<MutinyInvoice, MutinyError> {
@@ -1517,16 +2366,29 @@ impl<S: MutinyStorage> Node<S> {
             max_total_routing_fee_msat: None,
         };
 
-        let recipient_onion = if let Some(msg) = message {
+        let mut custom_tlvs = custom_tlvs;
+        if let Some(msg) = message {
             // keysend messages are encoded as TLV type 34349334
+            custom_tlvs.push(CustomTlv {
+                tlv_type: 34349334,
+                value: msg.encode(),
+            });
+        }
+
+        let recipient_onion = if custom_tlvs.is_empty() {
             RecipientOnionFields::secret_only(payment_secret)
-                .with_custom_tlvs(vec![(34349334, msg.encode())])
-                .map_err(|_| {
-                    log_error!(self.logger, "could not encode keysend message");
-                    MutinyError::InvoiceCreationFailed
-                })?
         } else {
+            let tlvs = custom_tlvs
+                .iter()
+                .map(|tlv| (tlv.tlv_type, tlv.value.clone()))
+                .collect();
+            // types must be unique and at least 2^16, and not the keysend preimage type
             RecipientOnionFields::secret_only(payment_secret)
+                .with_custom_tlvs(tlvs)
+                .map_err(|_| {
+                    log_error!(self.logger, "invalid custom TLV records for keysend");
+                    MutinyError::InvalidArgumentsError
+                })?
         };
 
         let pay_result = self.channel_manager.send_spontaneous_payment_with_retry(
@@ -1547,6 +2409,8 @@ impl<S: MutinyStorage> Node<S> {
             amt_msat: MillisatAmount(Some(amt_msats)),
             fee_paid_msat: None,
             bolt11: None,
+            bolt12: None,
+            custom_tlvs,
             payee_pubkey: Some(to_node),
             last_update,
         };
@@ -1587,6 +2451,7 @@ impl<S: MutinyStorage> Node<S> {
         to_node: PublicKey,
         amt_sats: u64,
         message: Option<String>,
+        custom_tlvs: Vec<CustomTlv>,
         labels: Vec<String>,
         timeout_secs: Option<u64>,
     ) -> Result<MutinyInvoice, MutinyError> {
@@ -1595,8 +2460,14 @@ impl<S: MutinyStorage> Node<S> {
         let payment_id = PaymentId(entropy);
 
         // initiate payment
-        let pay =
-            self.init_keysend_payment(to_node, amt_sats, message, labels.clone(), payment_id)?;
+        let pay = self.init_keysend_payment(
+            to_node,
+            amt_sats,
+            message,
+            custom_tlvs,
+            labels.clone(),
+            payment_id,
+        )?;
 
         let timeout: u64 = timeout_secs.unwrap_or(DEFAULT_PAYMENT_TIMEOUT);
         let payment_hash = PaymentHash(pay.payment_hash.into_inner());
@@ -1605,6 +2476,157 @@ impl<S: MutinyStorage> Node<S> {
             .await
     }
 
+    /// Creates a reusable BOLT12 offer that can be paid to this node.
+    /// If no amount is provided, the payer will choose how much to pay.
+    pub fn create_offer(
+        &self,
+        amount_sat: Option<u64>,
+        description: Option<String>,
+    ) -> Result<Offer, MutinyError> {
+        let mut builder = self
+            .channel_manager
+            .create_offer_builder(description.unwrap_or_default());
+        if let Some(amount_sat) = amount_sat {
+            builder = builder.amount_msats(amount_sat * 1_000);
+        }
+
+        let offer = builder.build().map_err(|e| {
+            log_error!(self.logger, "ERROR: could not generate offer: {e:?}");
+            MutinyError::InvoiceCreationFailed
+        })?;
+
+        // invoices for the offer are signed with its signing pubkey
+        persist_bolt12_receive(
+            &self.persister.storage,
+            &offer.signing_pubkey(),
+            offer.to_string(),
+        )?;
+
+        log_info!(self.logger, "SUCCESS: generated offer: {offer}");
+
+        Ok(offer)
+    }
+
+    /// init_offer_payment requests an invoice for the offer and pays it once received,
+    /// but does not wait for results. Use pay_offer_with_timeout to wait for results.
+    ///
+    /// The payment is tracked by its payment id because we do not know the
+    /// payment hash until we receive the invoice.
+    pub async fn init_offer_payment(
+        &self,
+        offer: &Offer,
+        amt_sats: Option<u64>,
+        payer_note: Option<String>,
+        payment_id: PaymentId,
+    ) -> Result<(), MutinyError> {
+        if offer.is_expired() {
+            return Err(MutinyError::InvoiceExpired);
+        }
+
+        if read_payment_info(&self.persister.storage, &payment_id.0, false, &self.logger).is_some()
+        {
+            return Err(MutinyError::NonUniquePaymentHash);
+        }
+
+        // get offer amount or use amt_sats
+        let amt_msat = match (offer.amount(), amt_sats) {
+            (Some(Amount::Bitcoin { amount_msats }), None) => *amount_msats,
+            (None, Some(amt_sats)) => amt_sats * 1_000,
+            // an amount should only be provided if the offer does not have one,
+            // and we can't pay offers denominated in other currencies
+            _ => return Err(MutinyError::InvoiceInvalid),
+        };
+
+        self.prepare_outbound_payment(amt_msat).await?;
+
+        let pay_result = self.channel_manager.pay_for_offer(
+            offer,
+            None,
+            amt_sats.map(|a| a * 1_000),
+            payer_note,
+            payment_id,
+            Self::retry_strategy(),
+            None,
+        );
+
+        let last_update = utils::now().as_secs();
+        let mut payment_info = PaymentInfo {
+            preimage: None,
+            secret: None,
+            status: HTLCStatus::InFlight,
+            amt_msat: MillisatAmount(Some(amt_msat)),
+            fee_paid_msat: None,
+            bolt11: None,
+            bolt12: Some(offer.to_string()),
+            custom_tlvs: vec![],
+            payee_pubkey: Some(offer.signing_pubkey()),
+            last_update,
+        };
+
+        persist_payment_info(&self.persister.storage, &payment_id.0, &payment_info, false)?;
+
+        match pay_result {
+            Ok(()) => Ok(()),
+            Err(error) => {
+                log_error!(self.logger, "failed to pay offer: {error:?}");
+
+                payment_info.status = HTLCStatus::Failed;
+                persist_payment_info(&self.persister.storage, &payment_id.0, &payment_info, false)?;
+
+                Err(error.into())
+            }
+        }
+    }
+
+    pub async fn pay_offer_with_timeout(
+        &self,
+        offer: &Offer,
+        amt_sats: Option<u64>,
+        payer_note: Option<String>,
+        labels: Vec<String>,
+        timeout_secs: Option<u64>,
+    ) -> Result<MutinyInvoice, MutinyError> {
+        let mut entropy = [0u8; 32];
+        getrandom::getrandom(&mut entropy).map_err(|_| MutinyError::SeedGenerationFailed)?;
+        let payment_id = PaymentId(entropy);
+
+        // initiate payment
+        self.init_offer_payment(offer, amt_sats, payer_note, payment_id)
+            .await?;
+
+        let timeout: u64 = timeout_secs.unwrap_or(DEFAULT_PAYMENT_TIMEOUT);
+        // offer payments are stored under their payment id until we know the payment hash
+        let payment_hash = PaymentHash(payment_id.0);
+
+        self.await_payment(payment_id, payment_hash, timeout, labels)
+            .await
+    }
+
+    /// Sends an invoice for the given BOLT12 refund so the creator of the refund can pay us.
+    /// The payment will show up in our activity once it has been received.
+    pub fn request_refund(&self, refund: &Refund) -> Result<(), MutinyError> {
+        if refund.is_expired() {
+            return Err(MutinyError::InvoiceExpired);
+        }
+
+        // saved first so the invoice we send for it can be matched to it
+        persist_bolt12_receive(
+            &self.persister.storage,
+            &refund.payer_id(),
+            refund.to_string(),
+        )?;
+
+        self.channel_manager
+            .request_refund_payment(refund)
+            .map_err(|e| {
+                log_error!(
+                    self.logger,
+                    "ERROR: could not request refund payment: {e:?}"
+                );
+                MutinyError::from(e)
+            })
+    }
+
     async fn await_chan_funding_tx(
         &self,
         user_channel_id: u128,
@@ -1674,17 +2696,15 @@ impl<S: MutinyStorage> Node<S> {
         user_channel_id: Option<u128>,
     ) -> Result<u128, MutinyError> {
         let accept_underpaying_htlcs = self
-            .lsp_client
-            .as_ref()
-            .is_some_and(|l| l.accept_underpaying_htlcs());
+            .lsp_clients
+            .iter()
+            .any(|l| l.accept_underpaying_htlcs());
         let mut config = default_user_config(accept_underpaying_htlcs);
 
         // if we are opening channel to LSP, turn off SCID alias until CLN is updated
         // LSP protects all invoice information anyways, so no UTXO leakage
-        if let Some(lsp) = self.lsp_client.clone() {
-            if pubkey == lsp.get_lsp_pubkey() {
-                config.channel_handshake_config.negotiate_scid_privacy = false;
-            }
+        if self.is_lsp(&pubkey) {
+            config.channel_handshake_config.negotiate_scid_privacy = false;
         }
 
         let user_channel_id = user_channel_id.unwrap_or_else(|| {
@@ -1782,16 +2802,14 @@ impl<S: MutinyStorage> Node<S> {
         let channel_value_satoshis = utxo_value - expected_fee;
 
         let accept_underpaying_htlcs = self
-            .lsp_client
-            .as_ref()
-            .is_some_and(|l| l.accept_underpaying_htlcs());
+            .lsp_clients
+            .iter()
+            .any(|l| l.accept_underpaying_htlcs());
         let mut config = default_user_config(accept_underpaying_htlcs);
         // if we are opening channel to LSP, turn off SCID alias until CLN is updated
         // LSP protects all invoice information anyways, so no UTXO leakage
-        if let Some(lsp) = self.lsp_client.clone() {
-            if pubkey == lsp.get_lsp_pubkey() {
-                config.channel_handshake_config.negotiate_scid_privacy = false;
-            }
+        if self.is_lsp(&pubkey) {
+            config.channel_handshake_config.negotiate_scid_privacy = false;
         }
 
         let user_channel_id = user_chan_id.unwrap_or_else(|| {
@@ -1846,6 +2864,98 @@ impl<S: MutinyStorage> Node<S> {
 
         self.await_chan_funding_tx(init, &pubkey, timeout).await
     }
+
+    /// Starts opening a channel funded by a watch-only wallet. Returns the user channel id
+    /// and the unsigned funding PSBT, once it is signed externally the channel is funded
+    /// with [`Node::fund_channel_with_signed_psbt`].
+    pub async fn open_channel_unsigned_with_timeout(
+        &self,
+        pubkey: PublicKey,
+        amount_sat: u64,
+        fee_rate: Option<f32>,
+        user_channel_id: Option<u128>,
+        timeout: u64,
+    ) -> Result<(u128, PartiallySignedTransaction), MutinyError> {
+        // a wallet with keys funds the channel itself
+        if !self.wallet.is_watch_only() {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+
+        let user_channel_id = self
+            .init_open_channel(pubkey, amount_sat, fee_rate, user_channel_id)
+            .await?;
+
+        let start = utils::now().as_secs();
+        loop {
+            if self.stop.load(Ordering::Relaxed) {
+                return Err(MutinyError::NotRunning);
+            }
+
+            if let Ok(Some(_closure)) = self.persister.get_channel_closure(user_channel_id) {
+                return Err(MutinyError::ChannelCreationFailed);
+            }
+
+            // the event handler saves the funding PSBT when the channel is ready to be funded
+            let funding = self
+                .persister
+                .get_channel_open_params(user_channel_id)?
+                .and_then(|p| p.unsigned_funding);
+            if let Some(funding) = funding {
+                return Ok((user_channel_id, funding.psbt));
+            }
+
+            if utils::now().as_secs().saturating_sub(start) > timeout {
+                return Err(MutinyError::ChannelCreationFailed);
+            }
+
+            sleep(250).await;
+        }
+    }
+
+    /// Funds a channel started with [`Node::open_channel_unsigned_with_timeout`]
+    /// using its funding PSBT after it was signed externally.
+    pub async fn fund_channel_with_signed_psbt(
+        &self,
+        user_channel_id: u128,
+        psbt: PartiallySignedTransaction,
+        timeout: u64,
+    ) -> Result<OutPoint, MutinyError> {
+        let mut params = self
+            .persister
+            .get_channel_open_params(user_channel_id)?
+            .ok_or(MutinyError::NotFound)?;
+        let funding = params
+            .unsigned_funding
+            .take()
+            .ok_or(MutinyError::NotFound)?;
+
+        // the signer must not have changed the transaction
+        if psbt.unsigned_tx.txid() != funding.psbt.unsigned_tx.txid() {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+
+        let tx = self.wallet.finalize_signed_psbt(psbt)?.extract_tx();
+
+        // save before funding so the channel pending event can clean up the params
+        params.opening_tx = Some(tx.clone());
+        self.persister
+            .persist_channel_open_params(user_channel_id, params)?;
+
+        if let Err(e) = self.channel_manager.funding_transaction_generated(
+            &ChannelId(funding.temporary_channel_id),
+            &funding.counterparty_node_id,
+            tx,
+        ) {
+            log_error!(
+                self.logger,
+                "ERROR: Could not send funding transaction to channel manager: {e:?}"
+            );
+            return Err(MutinyError::ChannelCreationFailed);
+        }
+
+        self.await_chan_funding_tx(user_channel_id, &funding.counterparty_node_id, timeout)
+            .await
+    }
 }
 
 pub(crate) fn scoring_params() -> ProbabilisticScoringFeeParameters {
@@ -1920,7 +3030,7 @@ async fn start_reconnection_handling<S: MutinyStorage>(
     fee_estimator: Arc<MutinyFeeEstimator<S>>,
     logger: &Arc<MutinyLogger>,
     uuid: String,
-    lsp_client: Option<&AnyLsp<S>>,
+    lsp_clients: &[AnyLsp<S>],
     stop: Arc<AtomicBool>,
     stopped_components: Arc<RwLock<Vec<bool>>>,
     skip_fee_estimates: bool,
@@ -1951,14 +3061,14 @@ async fn start_reconnection_handling<S: MutinyStorage>(
     let proxy_logger = logger.clone();
     let peer_man_proxy = peer_man.clone();
     let proxy_fee_estimator = fee_estimator.clone();
-    let lsp_client_copy = lsp_client.cloned();
+    let lsp_clients_copy = lsp_clients.to_vec();
     let storage_copy = storage.clone();
     let uuid_copy = uuid.clone();
     let stop_copy = stop.clone();
     utils::spawn(async move {
-        // Now try to connect to the client's LSP
+        // Now try to connect to the client's LSPs
         // This is here in case the LSP client node info has not saved to storage yet
-        if let Some(lsp) = lsp_client_copy.as_ref() {
+        for lsp in lsp_clients_copy.iter() {
             let node_id = NodeId::from_pubkey(&lsp.get_lsp_pubkey());
 
             let connect_res = connect_peer_if_necessary(
@@ -1990,18 +3100,21 @@ async fn start_reconnection_handling<S: MutinyStorage>(
             ) {
                 log_error!(proxy_logger, "could not save connection to lsp: {e}");
             }
-        };
+        }
 
-        // Now try to connect to other nodes the client might have, skipping the LSP if necessary
+        // Now try to connect to other nodes the client might have, skipping the LSPs if necessary
         let stored_peers = get_all_peers(&storage_copy).unwrap_or_default();
-        let lsp_node_id = lsp_client_copy.map(|lsp| NodeId::from_pubkey(&lsp.get_lsp_pubkey()));
+        let lsp_node_ids: Vec<NodeId> = lsp_clients_copy
+            .iter()
+            .map(|lsp| NodeId::from_pubkey(&lsp.get_lsp_pubkey()))
+            .collect();
         let initial_peers: Vec<(NodeId, String)> = stored_peers
             .into_iter()
             .filter(|(_, d)| {
                 d.connection_string.is_some() && d.nodes.binary_search(&uuid.to_string()).is_ok()
             })
             .map(|(n, d)| (n, d.connection_string.unwrap()))
-            .filter(|(n, _)| lsp_node_id != Some(*n))
+            .filter(|(n, _)| !lsp_node_ids.contains(n))
             .collect();
         for (pubkey, conn_str) in initial_peers.into_iter() {
             log_trace!(
@@ -2246,7 +3359,9 @@ pub(crate) fn default_user_config(accept_underpaying_htlcs: bool) -> UserConfig
 mod tests {
     use super::*;
     use crate::node::{map_sending_failure, parse_peer_info};
-    use crate::storage::MemoryStorage;
+    use crate::storage::{
+        delete_payment_info, persist_offer_payment_hash, read_bolt12_receive, MemoryStorage,
+    };
     use crate::test_utils::*;
     use bitcoin::secp256k1::PublicKey;
     use lightning::ln::channelmanager::ChannelCounterparty;
@@ -2446,6 +3561,114 @@ mod tests {
         assert!(from_storage.last_updated >= now);
     }
 
+    #[tokio::test]
+    async fn test_lsp_fee_quote() {
+        let storage = MemoryStorage::default();
+        let node = create_node(storage).await;
+
+        // without an LSP there is nothing to pay
+        let quote = node.quote_lsp_fee(1_000, None).await.unwrap();
+        assert_eq!(quote.amount_sat, 1_000);
+        assert_eq!(quote.fee_msat, 0);
+        assert_eq!(quote.lsp_pubkey, None);
+
+        let invoice = node.approve_lsp_fee_quote(&quote.id).await.unwrap();
+        assert_eq!(invoice.amount_milli_satoshis(), Some(1_000_000));
+
+        // a quote can only be approved once
+        assert_eq!(
+            node.approve_lsp_fee_quote(&quote.id).await,
+            Err(MutinyError::LspFeeQuoteNotFound)
+        );
+        assert_eq!(
+            node.approve_lsp_fee_quote("unknown").await,
+            Err(MutinyError::LspFeeQuoteNotFound)
+        );
+
+        // expired quotes are not honored
+        let quote = node.quote_lsp_fee(2_000, None).await.unwrap();
+        node.pending_lsp_quotes
+            .write()
+            .unwrap()
+            .get_mut(&quote.id)
+            .unwrap()
+            .quote
+            .expires_at = utils::now().as_secs() - 1;
+        assert_eq!(
+            node.approve_lsp_fee_quote(&quote.id).await,
+            Err(MutinyError::LspFeeQuoteNotFound)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_create_offer() {
+        let storage = MemoryStorage::default();
+        let node = create_node(storage).await;
+
+        let amount_sats = 1_000;
+
+        let offer = node
+            .create_offer(Some(amount_sats), Some("test".to_string()))
+            .unwrap();
+
+        assert_eq!(
+            offer.amount(),
+            Some(&Amount::Bitcoin {
+                amount_msats: amount_sats * 1_000
+            })
+        );
+        assert_eq!(offer.description().to_string(), "test");
+
+        // offers are reusable so they need to survive encoding
+        let parsed = Offer::from_str(&offer.to_string()).unwrap();
+        assert_eq!(parsed.to_string(), offer.to_string());
+
+        // offers are saved so payments to them can be matched
+        let receive = read_bolt12_receive(&node.persister.storage, &offer.signing_pubkey());
+        assert_eq!(receive, Some(offer.to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_pay_offer_without_channels() {
+        let storage = MemoryStorage::default();
+        let node = create_node(storage).await;
+
+        let offer = node.create_offer(Some(10_000), None).unwrap();
+
+        let result = node
+            .pay_offer_with_timeout(&offer, None, None, vec![], None)
+            .await;
+
+        assert_eq!(result.unwrap_err(), MutinyError::InsufficientBalance);
+    }
+
+    #[tokio::test]
+    async fn test_channel_funding_psbt_needs_watch_only_wallet() {
+        let storage = MemoryStorage::default();
+        let node = create_node(storage).await;
+        let pubkey = PublicKey::from_str(
+            "02cae09cf2c8842ace44068a5bf3117a494ebbf69a99e79712483c36f97cdb7b54",
+        )
+        .unwrap();
+
+        // a wallet with keys funds its channels itself
+        let result = node
+            .open_channel_unsigned_with_timeout(pubkey, 100_000, None, None, 1)
+            .await;
+        assert_eq!(result.unwrap_err(), MutinyError::InvalidArgumentsError);
+
+        // nothing is waiting to be funded
+        let psbt = PartiallySignedTransaction::from_unsigned_tx(bitcoin::Transaction {
+            version: 2,
+            lock_time: bitcoin::PackedLockTime::ZERO,
+            input: vec![],
+            output: vec![],
+        })
+        .unwrap();
+        let result = node.fund_channel_with_signed_psbt(1, psbt, 1).await;
+        assert_eq!(result.unwrap_err(), MutinyError::NotFound);
+    }
+
     #[tokio::test]
     async fn test_fail_own_invoice() {
         let storage = MemoryStorage::default();
@@ -2454,7 +3677,7 @@ mod tests {
         let invoice = node.create_invoice(Some(10_000), None).await.unwrap();
 
         let result = node
-            .pay_invoice_with_timeout(&invoice, None, None, vec![])
+            .pay_invoice_with_timeout(&invoice, None, None, None, vec![])
             .await;
 
         match result {
@@ -2486,6 +3709,8 @@ mod tests {
             amt_msat: MillisatAmount(Some(1000)),
             fee_paid_msat: None,
             bolt11: None,
+            bolt12: None,
+            custom_tlvs: vec![],
             payee_pubkey: None,
             last_update: crate::utils::now().as_secs(),
         };
@@ -2540,6 +3765,63 @@ mod tests {
 
         assert!(result.is_ok());
     }
+
+    #[tokio::test]
+    async fn test_await_offer_payment() {
+        let storage = MemoryStorage::default();
+        let node = create_node(storage).await;
+        let payment_id = PaymentId([1; 32]);
+        let preimage = [2; 32];
+        let payment_hash = PaymentHash(Sha256::hash(&preimage).into_inner());
+
+        let mut payment_info = PaymentInfo {
+            preimage: None,
+            secret: None,
+            status: HTLCStatus::InFlight,
+            amt_msat: MillisatAmount(Some(1000)),
+            fee_paid_msat: None,
+            bolt11: None,
+            bolt12: Some("lno1".to_string()),
+            custom_tlvs: vec![],
+            payee_pubkey: None,
+            last_update: crate::utils::now().as_secs(),
+        };
+
+        // offer payments are stored under their payment id until we get the invoice
+        persist_payment_info(&node.persister.storage, &payment_id.0, &payment_info, false).unwrap();
+
+        let result = node
+            .await_payment(payment_id, PaymentHash(payment_id.0), 1, vec![])
+            .await;
+
+        assert_eq!(result.unwrap_err(), MutinyError::PaymentTimeout);
+
+        // then they are moved to their payment hash
+        payment_info.status = HTLCStatus::Succeeded;
+        payment_info.preimage = Some(preimage);
+        persist_payment_info(
+            &node.persister.storage,
+            &payment_hash.0,
+            &payment_info,
+            false,
+        )
+        .unwrap();
+        persist_offer_payment_hash(&node.persister.storage, &payment_id.0, &payment_hash.0)
+            .unwrap();
+        delete_payment_info(&node.persister.storage, &payment_id.0, false).unwrap();
+
+        let invoice = node
+            .await_payment(payment_id, PaymentHash(payment_id.0), 1, vec![])
+            .await
+            .unwrap();
+        assert_eq!(invoice.payment_hash.into_inner(), payment_hash.0);
+        assert_eq!(invoice.status, HTLCStatus::Succeeded);
+
+        let by_hash = node
+            .get_invoice_by_hash(&Sha256::from_inner(payment_hash.0))
+            .unwrap();
+        assert_eq!(by_hash.bolt12, Some("lno1".to_string()));
+    }
 }
 
 #[cfg(test)]
@@ -2554,6 +3836,7 @@ mod wasm_test {
     use lightning::ln::channelmanager::PaymentId;
     use lightning::ln::PaymentHash;
     use lightning_invoice::Bolt11InvoiceDescription;
+    use std::str::FromStr;
     use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
 
     wasm_bindgen_test_configure!(run_in_browser);
@@ -2608,7 +3891,7 @@ mod wasm_test {
         let invoice = node.create_invoice(Some(10_000), None).await.unwrap();
 
         let result = node
-            .pay_invoice_with_timeout(&invoice, None, None, vec![])
+            .pay_invoice_with_timeout(&invoice, None, None, None, vec![])
             .await;
 
         match result {
@@ -2640,6 +3923,8 @@ mod wasm_test {
             amt_msat: MillisatAmount(Some(1000)),
             fee_paid_msat: None,
             bolt11: None,
+            bolt12: None,
+            custom_tlvs: vec![],
             payee_pubkey: None,
             last_update: crate::utils::now().as_secs(),
         };
@@ -1,21 +1,31 @@
-use crate::event::HTLCStatus;
+use crate::event::{CustomTlv, EventNotifier, HTLCStatus, MutinyEvent};
 use crate::labels::LabelStorage;
-use crate::logging::LOGGING_KEY;
+use crate::logging::{LogFilter, LogRecord, LOGGING_KEY};
 use crate::utils::{sleep, spawn};
 use crate::ActivityItem;
 use crate::MutinyInvoice;
 use crate::MutinyWalletConfig;
 use crate::{
     chain::MutinyChain,
+    chainsource::build_chain_source,
     error::MutinyError,
+    esplorapool::EndpointStats,
     fees::MutinyFeeEstimator,
     gossip,
     gossip::{fetch_updated_gossip, get_rgs_url},
+    holdinvoice::{list_hold_invoices, read_hold_invoice, HoldInvoice, HoldInvoiceStatus},
     logging::MutinyLogger,
-    lsp::{deserialize_lsp_config, Lsp, LspConfig},
-    node::{Node, PubkeyConnectionInfo, RapidGossipSync},
-    onchain::get_esplora_url,
-    onchain::OnChainWallet,
+    lsp::{
+        deserialize_lsp_config,
+        lsps1::{
+            list_channel_orders, persist_channel_order, read_channel_order, ChannelOrder,
+            ChannelOrderPayment, CreateOrderRequest, Lsps1Client, Lsps1Info, Lsps1PaymentState,
+        },
+        Lsp, LspConfig, LspFeeLimits, LspFeeQuote, LspSelection,
+    },
+    node::{InvoiceOptions, Node, PubkeyConnectionInfo, RapidGossipSync},
+    onchain::{get_tr_descriptors_for_xpub, OnChainWallet},
+    scb::StaticChannelBackup,
     utils,
 };
 use crate::{gossip::*, scorer::HubPreferentialScorer};
@@ -29,24 +39,25 @@ use bdk::{wallet::AddressIndex, FeeRate, LocalUtxo};
 use bitcoin::blockdata::script;
 use bitcoin::hashes::hex::ToHex;
 use bitcoin::hashes::sha256;
+use bitcoin::hashes::Hash;
 use bitcoin::psbt::PartiallySignedTransaction;
 use bitcoin::secp256k1::PublicKey;
 use bitcoin::util::bip32::ExtendedPrivKey;
 use bitcoin::{Address, Network, OutPoint, Transaction, Txid};
 use core::time::Duration;
-use esplora_client::{AsyncClient, Builder};
 use futures::{future::join_all, lock::Mutex};
 use lightning::chain::Confirm;
 use lightning::events::ClosureReason;
 use lightning::ln::channelmanager::{ChannelDetails, PhantomRouteHints};
 use lightning::ln::script::ShutdownScript;
 use lightning::ln::ChannelId;
+use lightning::offers::offer::Offer;
+use lightning::offers::refund::Refund;
 use lightning::routing::gossip::NodeId;
 use lightning::sign::{NodeSigner, Recipient};
 use lightning::util::logger::*;
 use lightning::{log_debug, log_error, log_info, log_warn};
 use lightning_invoice::Bolt11Invoice;
-use lightning_transaction_sync::EsploraSyncClient;
 use payjoin::Uri;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
@@ -75,6 +86,9 @@ pub struct NodeIndex {
     pub child_index: u32,
     #[serde(deserialize_with = "deserialize_lsp_config")]
     pub lsp: Option<LspConfig>,
+    /// LSPs used alongside the primary one
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub additional_lsps: Vec<LspConfig>,
     pub archived: Option<bool>,
 }
 
@@ -82,6 +96,15 @@ impl NodeIndex {
     pub fn is_archived(&self) -> bool {
         self.archived.unwrap_or(false)
     }
+
+    /// All of the node's LSPs, starting with the primary one.
+    pub fn lsp_configs(&self) -> Vec<LspConfig> {
+        self.lsp
+            .iter()
+            .chain(self.additional_lsps.iter())
+            .cloned()
+            .collect()
+    }
 }
 
 // This is the NodeIdentity that refer to a specific node
@@ -97,6 +120,9 @@ pub struct MutinyBip21RawMaterials {
     pub invoice: Option<Bolt11Invoice>,
     pub btc_amount: Option<String>,
     pub labels: Vec<String>,
+    /// The endpoint to use as the `pj=` parameter if we can receive a payjoin
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payjoin_endpoint: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Eq, PartialEq)]
@@ -261,6 +287,14 @@ impl Ord for ChannelClosure {
     }
 }
 
+/// A channel waiting for its funding transaction to be signed by an external signer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingChannelFunding {
+    pub user_channel_id: u128,
+    /// The unsigned funding transaction
+    pub psbt: PartiallySignedTransaction,
+}
+
 pub struct NodeBalance {
     pub confirmed: u64,
     pub unconfirmed: u64,
@@ -273,6 +307,7 @@ pub struct NodeManagerBuilder<S: MutinyStorage> {
     storage: S,
     config: Option<MutinyWalletConfig>,
     stop: Option<Arc<AtomicBool>>,
+    event_notifier: Option<EventNotifier>,
     logger: Option<Arc<MutinyLogger>>,
 }
 
@@ -283,6 +318,7 @@ impl<S: MutinyStorage> NodeManagerBuilder<S> {
             storage,
             config: None,
             stop: None,
+            event_notifier: None,
             logger: None,
         }
     }
@@ -296,6 +332,10 @@ impl<S: MutinyStorage> NodeManagerBuilder<S> {
         self.stop = Some(stop);
     }
 
+    pub fn with_event_notifier(&mut self, event_notifier: EventNotifier) {
+        self.event_notifier = Some(event_notifier);
+    }
+
     pub fn with_logger(&mut self, logger: Arc<MutinyLogger>) {
         self.logger = Some(logger);
     }
@@ -310,6 +350,7 @@ impl<S: MutinyStorage> NodeManagerBuilder<S> {
             .map_or_else(|| Err(MutinyError::InvalidArgumentsError), Ok)?;
         let logger = self.logger.unwrap_or(Arc::new(MutinyLogger::default()));
         let stop = self.stop.unwrap_or(Arc::new(AtomicBool::new(false)));
+        let event_notifier = self.event_notifier.unwrap_or_default();
 
         #[cfg(target_arch = "wasm32")]
         let websocket_proxy_addr = c
@@ -339,32 +380,53 @@ impl<S: MutinyStorage> NodeManagerBuilder<S> {
             }
         });
 
-        let esplora_server_url = get_esplora_url(c.network, c.user_esplora_url);
-        let esplora = Builder::new(&esplora_server_url).build_async()?;
-        let tx_sync = Arc::new(EsploraSyncClient::from_client(
-            esplora.clone(),
+        let chain_source = build_chain_source(
+            &c.chain_source,
+            c.network,
+            c.user_esplora_urls,
+            self.storage.clone(),
             logger.clone(),
-        ));
+        )?;
 
-        let esplora = Arc::new(esplora);
         let fee_estimator = Arc::new(MutinyFeeEstimator::new(
             self.storage.clone(),
             c.network,
-            esplora.clone(),
+            chain_source.clone(),
             logger.clone(),
         ));
 
-        let wallet = Arc::new(OnChainWallet::new(
-            self.xprivkey,
-            self.storage.clone(),
-            c.network,
-            esplora.clone(),
-            fee_estimator.clone(),
-            stop.clone(),
-            logger.clone(),
-        )?);
+        let wallet = match c.watch_only_xpub {
+            Some((account_xpub, master_fingerprint)) => {
+                let (receive, change) =
+                    get_tr_descriptors_for_xpub(account_xpub, master_fingerprint, c.network, 0);
+                OnChainWallet::new_watch_only(
+                    &receive,
+                    Some(&change),
+                    self.storage.clone(),
+                    c.network,
+                    chain_source.clone(),
+                    fee_estimator.clone(),
+                    stop.clone(),
+                    logger.clone(),
+                )?
+            }
+            None => OnChainWallet::new(
+                self.xprivkey,
+                self.storage.clone(),
+                c.network,
+                chain_source.clone(),
+                fee_estimator.clone(),
+                stop.clone(),
+                logger.clone(),
+            )?,
+        };
+        let wallet = Arc::new(wallet);
 
-        let chain = Arc::new(MutinyChain::new(tx_sync, wallet.clone(), logger.clone()));
+        let chain = Arc::new(MutinyChain::new(
+            chain_source,
+            wallet.clone(),
+            logger.clone(),
+        ));
 
         let (gossip_sync, scorer) = get_gossip_sync(
             &self.storage,
@@ -379,10 +441,19 @@ impl<S: MutinyStorage> NodeManagerBuilder<S> {
 
         let gossip_sync = Arc::new(gossip_sync);
 
-        let lsp_config = if c.safe_mode {
-            None
+        let lsp_configs: Vec<LspConfig> = if c.safe_mode {
+            vec![]
         } else {
-            create_lsp_config(c.lsp_url, c.lsp_connection_string, c.lsp_token)?
+            let mut lsp_configs: Vec<LspConfig> =
+                create_lsp_config(c.lsp_url, c.lsp_connection_string, c.lsp_token)?
+                    .into_iter()
+                    .collect();
+            for lsp in c.additional_lsps {
+                if !lsp_configs.contains(&lsp) {
+                    lsp_configs.push(lsp);
+                }
+            }
+            lsp_configs
         };
 
         let node_storage = self.storage.get_nodes()?;
@@ -410,16 +481,16 @@ impl<S: MutinyStorage> NodeManagerBuilder<S> {
                     .with_chain(chain.clone())
                     .with_fee_estimator(fee_estimator.clone())
                     .with_wallet(wallet.clone())
-                    .with_esplora(esplora.clone())
                     .with_network(c.network);
+                node_builder.with_event_notifier(event_notifier.clone());
                 node_builder.with_logger(logger.clone());
 
                 #[cfg(target_arch = "wasm32")]
                 node_builder.with_websocket_proxy_addr(websocket_proxy_addr.clone());
 
-                if let Some(l) = lsp_config.clone() {
-                    node_builder.with_lsp_config(l);
-                }
+                node_builder.with_lsp_configs(lsp_configs.clone());
+                node_builder.with_lsp_selection(c.lsp_selection);
+                node_builder.with_lsp_fee_limits(c.lsp_fee_limits);
                 if c.do_not_connect_peers {
                     node_builder.do_not_connect_peers();
                 }
@@ -478,8 +549,10 @@ impl<S: MutinyStorage> NodeManagerBuilder<S> {
             #[cfg(target_arch = "wasm32")]
             websocket_proxy_addr,
             user_rgs_url: c.user_rgs_url,
-            esplora,
-            lsp_config,
+            lsp_configs,
+            lsp_selection: c.lsp_selection,
+            lsp_fee_limits: c.lsp_fee_limits,
+            event_notifier,
             logger,
             bitcoin_price_cache: Arc::new(Mutex::new(price_cache)),
             do_not_connect_peers: c.do_not_connect_peers,
@@ -504,7 +577,6 @@ pub struct NodeManager<S: MutinyStorage> {
     #[cfg(target_arch = "wasm32")]
     websocket_proxy_addr: String,
     user_rgs_url: Option<String>,
-    esplora: Arc<AsyncClient>,
     pub(crate) wallet: Arc<OnChainWallet<S>>,
     gossip_sync: Arc<RapidGossipSync>,
     scorer: Arc<utils::Mutex<HubPreferentialScorer>>,
@@ -513,7 +585,10 @@ pub struct NodeManager<S: MutinyStorage> {
     pub(crate) storage: S,
     pub(crate) node_storage: Mutex<NodeStorage>,
     pub(crate) nodes: Arc<Mutex<HashMap<PublicKey, Arc<Node<S>>>>>,
-    pub(crate) lsp_config: Option<LspConfig>,
+    pub(crate) lsp_configs: Vec<LspConfig>,
+    lsp_selection: LspSelection,
+    lsp_fee_limits: LspFeeLimits,
+    pub(crate) event_notifier: EventNotifier,
     pub(crate) logger: Arc<MutinyLogger>,
     bitcoin_price_cache: Arc<Mutex<HashMap<String, (f32, Duration)>>>,
     do_not_connect_peers: bool,
@@ -608,10 +683,13 @@ impl<S: MutinyStorage> NodeManager<S> {
 
                 if let Err(e) = nm.sync().await {
                     log_error!(nm.logger, "Failed to sync: {e}");
-                } else if !synced {
-                    // if this is the first sync, set the done_first_sync flag
-                    let _ = nm.storage.set_done_first_sync();
-                    synced = true;
+                } else {
+                    if !synced {
+                        // if this is the first sync, set the done_first_sync flag
+                        let _ = nm.storage.set_done_first_sync();
+                        synced = true;
+                    }
+                    nm.event_notifier.notify(MutinyEvent::OnchainSyncCompleted);
                 }
 
                 // sleep for 1 minute, checking graceful shutdown check each 1s.
@@ -626,7 +704,7 @@ impl<S: MutinyStorage> NodeManager<S> {
     }
 
     /// Broadcast a transaction to the network.
-    /// The transaction is broadcast through the configured esplora server.
+    /// The transaction is broadcast through the configured chain source.
     pub async fn broadcast_transaction(&self, tx: Transaction) -> Result<(), MutinyError> {
         self.wallet.broadcast_transaction(tx).await
     }
@@ -752,6 +830,28 @@ impl<S: MutinyStorage> NodeManager<S> {
         self.wallet.send(send_to, amount, labels, fee_rate).await
     }
 
+    /// Sends an on-chain transaction to the given address, only spending the given UTXOs.
+    /// Any change goes back to the wallet.
+    /// The amount is in satoshis and the fee rate is in sat/vbyte.
+    ///
+    /// If a fee rate is not provided, one will be used from the fee estimator.
+    pub async fn send_to_address_with_utxos(
+        &self,
+        send_to: Address,
+        amount: u64,
+        utxos: &[OutPoint],
+        labels: Vec<String>,
+        fee_rate: Option<f32>,
+    ) -> Result<Txid, MutinyError> {
+        if !send_to.is_valid_for_network(self.network) {
+            return Err(MutinyError::IncorrectNetwork(send_to.network));
+        }
+
+        self.wallet
+            .send_with_utxos(send_to, amount, utxos, labels, fee_rate)
+            .await
+    }
+
     /// Sweeps all the funds from the wallet to the given address.
     /// The fee rate is in sat/vbyte.
     ///
@@ -769,6 +869,49 @@ impl<S: MutinyStorage> NodeManager<S> {
         self.wallet.sweep(send_to, labels, fee_rate).await
     }
 
+    /// Creates an unsigned PSBT sending to the given address so it can be signed
+    /// by an external signer. If utxos are given, only those will be spent.
+    /// The amount is in satoshis and the fee rate is in sat/vbyte.
+    pub fn create_unsigned_psbt(
+        &self,
+        send_to: Address,
+        amount: u64,
+        utxos: Option<&[OutPoint]>,
+        fee_rate: Option<f32>,
+    ) -> Result<PartiallySignedTransaction, MutinyError> {
+        self.wallet
+            .create_unsigned_psbt(send_to, amount, utxos, fee_rate)
+    }
+
+    /// Creates an unsigned PSBT sweeping all the funds from the wallet to the given address,
+    /// so it can be signed by an external signer. The fee rate is in sat/vbyte.
+    pub fn create_unsigned_sweep_psbt(
+        &self,
+        send_to: Address,
+        fee_rate: Option<f32>,
+    ) -> Result<PartiallySignedTransaction, MutinyError> {
+        if !send_to.is_valid_for_network(self.network) {
+            return Err(MutinyError::IncorrectNetwork(send_to.network));
+        }
+
+        self.wallet
+            .create_unsigned_sweep_psbt(send_to.script_pubkey(), fee_rate)
+    }
+
+    /// Returns true if the on-chain wallet has no keys, so its PSBTs must be signed externally.
+    pub fn is_watch_only(&self) -> bool {
+        self.wallet.is_watch_only()
+    }
+
+    /// Finalizes and broadcasts a PSBT created by this wallet and signed elsewhere.
+    pub async fn broadcast_signed_psbt(
+        &self,
+        psbt: PartiallySignedTransaction,
+        labels: Vec<String>,
+    ) -> Result<Txid, MutinyError> {
+        self.wallet.broadcast_signed_psbt(psbt, labels).await
+    }
+
     /// Estimates the onchain fee for a transaction sending to the given address.
     /// The amount is in satoshis and the fee rate is in sat/vbyte.
     pub fn estimate_tx_fee(
@@ -852,21 +995,20 @@ impl<S: MutinyStorage> NodeManager<S> {
         }
 
         let script = address.payload.script_pubkey();
-        let txs = self.esplora.scripthash_txs(&script, None).await?;
+        let txs = self.wallet.blockchain.get_script_txs(&script).await?;
 
-        let details_opt = txs.first().map(|tx| {
+        let details_opt = txs.first().map(|(tx, confirmation)| {
             let received: u64 = tx
-                .vout
+                .output
                 .iter()
-                .filter(|v| v.scriptpubkey == script)
-                .map(|v| v.value)
+                .filter(|o| o.script_pubkey == script)
+                .map(|o| o.value)
                 .sum();
 
-            let confirmation_time = tx
-                .confirmation_time()
+            let confirmation_time = confirmation
                 .map(|c| ConfirmationTime::Confirmed {
                     height: c.height,
-                    time: c.timestamp,
+                    time: c.time,
                 })
                 .unwrap_or(ConfirmationTime::Unconfirmed {
                     last_seen: utils::now().as_secs(),
@@ -879,8 +1021,8 @@ impl<S: MutinyStorage> NodeManager<S> {
                 .unwrap_or_default();
 
             let details = TransactionDetails {
-                transaction: Some(tx.to_tx()),
-                txid: tx.txid,
+                transaction: Some(tx.clone()),
+                txid: tx.txid(),
                 received,
                 sent: 0,
                 fee: None,
@@ -888,16 +1030,10 @@ impl<S: MutinyStorage> NodeManager<S> {
                 labels,
             };
 
-            let block_id = match tx.status.block_hash {
-                Some(hash) => {
-                    let height = tx
-                        .status
-                        .block_height
-                        .expect("block height must be present");
-                    Some(BlockId { hash, height })
-                }
-                None => None,
-            };
+            let block_id = confirmation.map(|c| BlockId {
+                hash: c.block_hash,
+                height: c.height,
+            });
 
             (details, block_id)
         });
@@ -1090,6 +1226,22 @@ impl<S: MutinyStorage> NodeManager<S> {
         self.wallet.list_utxos()
     }
 
+    /// Lists the UTXOs that are frozen and will not be spent by the wallet.
+    pub fn list_frozen_utxos(&self) -> Result<Vec<OutPoint>, MutinyError> {
+        Ok(self.wallet.get_frozen_utxos()?.into_iter().collect())
+    }
+
+    /// Freezes the given UTXOs so they are never used when creating transactions,
+    /// unless they are unfrozen.
+    pub fn freeze_utxos(&self, utxos: &[OutPoint]) -> Result<(), MutinyError> {
+        self.wallet.freeze_utxos(utxos)
+    }
+
+    /// Unfreezes the given UTXOs so they can be spent again.
+    pub fn unfreeze_utxos(&self, utxos: &[OutPoint]) -> Result<(), MutinyError> {
+        self.wallet.unfreeze_utxos(utxos)
+    }
+
     /// Syncs the lightning wallet with the blockchain.
     /// This will update the wallet with any lightning channels
     /// that have been opened or closed.
@@ -1117,8 +1269,8 @@ impl<S: MutinyStorage> NodeManager<S> {
             .collect();
 
         self.chain
-            .tx_sync
-            .sync(confirmables)
+            .chain_source
+            .sync_lightning(confirmables)
             .await
             .map_err(|_e| MutinyError::ChainAccessFailed)?;
 
@@ -1180,6 +1332,9 @@ impl<S: MutinyStorage> NodeManager<S> {
         } else if let Err(e) = self.sync_ldk().await {
             log_error!(self.logger, "Failed to sync ldk: {e}");
             return Err(e);
+        } else {
+            self.cancel_expiring_hold_invoices().await;
+            self.refresh_channel_orders().await;
         }
 
         // sync bdk wallet
@@ -1210,6 +1365,11 @@ impl<S: MutinyStorage> NodeManager<S> {
         max(self.fee_estimator.get_high_fee_rate() / 250, 1)
     }
 
+    /// Gets the request stats for each of the esplora servers being used.
+    pub fn get_chain_endpoint_stats(&self) -> Vec<EndpointStats> {
+        self.chain.chain_source.endpoint_stats()
+    }
+
     /// Creates a new lightning node and adds it to the manager.
     pub async fn new_node(&self) -> Result<NodeIdentity, MutinyError> {
         if self.safe_mode {
@@ -1272,22 +1432,31 @@ impl<S: MutinyStorage> NodeManager<S> {
     ///
     /// Requires a restart of the node manager to take effect.
     pub async fn change_lsp(&self, lsp_config: Option<LspConfig>) -> Result<(), MutinyError> {
+        self.change_lsps(lsp_config.into_iter().collect()).await
+    }
+
+    /// Changes all the node's LSPs to the given configs, the first one is the primary LSP.
+    /// If any of the nodes have an active channel with an LSP that is removed,
+    /// it will fail to change the LSPs.
+    ///
+    /// Requires a restart of the node manager to take effect.
+    pub async fn change_lsps(&self, lsp_configs: Vec<LspConfig>) -> Result<(), MutinyError> {
         // if we are in safe mode we don't load the lightning state so we can't know if it is safe to change the LSP.
         if self.safe_mode {
             return Err(MutinyError::NotRunning);
         }
 
-        // check if any nodes have active channels with the current LSP
-        // if they do, we can't change the LSP
+        // check if any nodes have active channels with an LSP being removed
+        // if they do, we can't change the LSPs
         let nodes = self.nodes.lock().await;
         if nodes.iter().any(|(_, n)| {
-            if let Some(lsp_pk) = n.lsp_client.as_ref().map(|x| x.get_lsp_pubkey()) {
-                !n.channel_manager
-                    .list_channels_with_counterparty(&lsp_pk)
-                    .is_empty()
-            } else {
-                false
-            }
+            n.lsp_clients.iter().any(|lsp| {
+                !lsp_configs.contains(&lsp.get_config())
+                    && !n
+                        .channel_manager
+                        .list_channels_with_counterparty(&lsp.get_lsp_pubkey())
+                        .is_empty()
+            })
         }) {
             return Err(MutinyError::LspGenericError);
         }
@@ -1296,7 +1465,8 @@ impl<S: MutinyStorage> NodeManager<S> {
         // edit node storage
         let mut node_storage = self.node_storage.lock().await;
         node_storage.nodes.iter_mut().for_each(|(_, n)| {
-            n.lsp = lsp_config.clone();
+            n.lsp = lsp_configs.first().cloned();
+            n.additional_lsps = lsp_configs.iter().skip(1).cloned().collect();
         });
         node_storage.version += 1; // update version for VSS
 
@@ -1306,6 +1476,26 @@ impl<S: MutinyStorage> NodeManager<S> {
         Ok(())
     }
 
+    /// Adds an LSP to all the nodes, it is used alongside their current LSPs.
+    /// If a node has no LSP, it becomes its primary LSP.
+    ///
+    /// Requires a restart of the node manager to take effect.
+    pub async fn add_lsp(&self, lsp_config: LspConfig) -> Result<(), MutinyError> {
+        let mut node_storage = self.node_storage.lock().await;
+        node_storage.nodes.iter_mut().for_each(|(_, n)| {
+            if n.lsp.is_none() {
+                n.lsp = Some(lsp_config.clone());
+            } else if !n.lsp_configs().contains(&lsp_config) {
+                n.additional_lsps.push(lsp_config.clone());
+            }
+        });
+        node_storage.version += 1; // update version for VSS
+
+        self.storage.insert_nodes(&node_storage).await?;
+
+        Ok(())
+    }
+
     /// Attempts to connect to a peer using either a specified node or the first available node.
     pub async fn connect_to_peer(
         &self,
@@ -1371,12 +1561,74 @@ impl<S: MutinyStorage> NodeManager<S> {
     /// If the manager has more than one node it will create a phantom invoice.
     /// If there is only one node it will create an invoice just for that node.
     pub async fn create_invoice(&self, amount: Option<u64>) -> Result<MutinyInvoice, MutinyError> {
+        self.create_invoice_with_options(amount, InvoiceOptions::default())
+            .await
+    }
+
+    /// Creates a lightning invoice with the given description or description hash,
+    /// as used by LNURL-pay, and expiry. The amount should be in satoshis.
+    pub async fn create_invoice_with_options(
+        &self,
+        amount: Option<u64>,
+        options: InvoiceOptions,
+    ) -> Result<MutinyInvoice, MutinyError> {
+        let nodes = self.nodes.lock().await;
+        let route_hints = self.phantom_route_hints(&nodes)?;
+
+        // just create a normal invoice from the first node
+        let first_node = if let Some(node) = nodes.values().next() {
+            node
+        } else {
+            return Err(MutinyError::WalletOperationFailed);
+        };
+        let invoice = first_node
+            .create_invoice_with_options(amount, options, route_hints)
+            .await?;
+
+        Ok(invoice.into())
+    }
+
+    /// Gets the LSP fee for receiving the amount in satoshis, without creating an invoice.
+    /// The invoice is created once the fee is approved with [`NodeManager::approve_lsp_fee_quote`].
+    pub async fn quote_lsp_fee(&self, amount: u64) -> Result<LspFeeQuote, MutinyError> {
+        let nodes = self.nodes.lock().await;
+        let route_hints = self.phantom_route_hints(&nodes)?;
+
+        let first_node = if let Some(node) = nodes.values().next() {
+            node
+        } else {
+            return Err(MutinyError::WalletOperationFailed);
+        };
+        first_node.quote_lsp_fee(amount, route_hints).await
+    }
+
+    /// Creates the invoice for an LSP fee quote from [`NodeManager::quote_lsp_fee`].
+    pub async fn approve_lsp_fee_quote(
+        &self,
+        quote_id: &str,
+    ) -> Result<MutinyInvoice, MutinyError> {
         let nodes = self.nodes.lock().await;
-        let use_phantom = nodes.len() > 1 && self.lsp_config.is_none();
+        let first_node = if let Some(node) = nodes.values().next() {
+            node
+        } else {
+            return Err(MutinyError::WalletOperationFailed);
+        };
+        let invoice = first_node.approve_lsp_fee_quote(quote_id).await?;
+
+        Ok(invoice.into())
+    }
+
+    /// Route hints so any of our nodes can receive a payment to our invoice,
+    /// only used when we have several nodes and no LSP.
+    fn phantom_route_hints(
+        &self,
+        nodes: &HashMap<PublicKey, Arc<Node<S>>>,
+    ) -> Result<Option<Vec<PhantomRouteHints>>, MutinyError> {
         if nodes.len() == 0 {
             return Err(MutinyError::InvoiceCreationFailed);
         }
-        let route_hints: Option<Vec<PhantomRouteHints>> = if use_phantom {
+        let use_phantom = nodes.len() > 1 && self.lsp_configs.is_empty();
+        let route_hints = if use_phantom {
             Some(
                 nodes
                     .iter()
@@ -1387,48 +1639,390 @@ impl<S: MutinyStorage> NodeManager<S> {
             None
         };
 
-        // just create a normal invoice from the first node
+        Ok(route_hints)
+    }
+
+    /// Creates a hold invoice for a payment hash chosen by the caller, the amount is in satoshis.
+    /// Payments to it are held until [`NodeManager::settle_hold_invoice`] is called with the
+    /// preimage or [`NodeManager::cancel_hold_invoice`] is called. Held payments are cancelled
+    /// automatically shortly before they would expire.
+    pub async fn create_hold_invoice(
+        &self,
+        payment_hash: sha256::Hash,
+        amount: Option<u64>,
+    ) -> Result<MutinyInvoice, MutinyError> {
+        let nodes = self.nodes.lock().await;
+        let route_hints = self.phantom_route_hints(&nodes)?;
+
         let first_node = if let Some(node) = nodes.values().next() {
             node
         } else {
             return Err(MutinyError::WalletOperationFailed);
         };
-        let invoice = first_node.create_invoice(amount, route_hints).await?;
+        let invoice = first_node
+            .create_hold_invoice(payment_hash, amount, route_hints)
+            .await?;
 
         Ok(invoice.into())
     }
 
+    /// Claims the payment held for the hold invoice of the preimage's payment hash.
+    pub async fn settle_hold_invoice(&self, preimage: [u8; 32]) -> Result<(), MutinyError> {
+        let payment_hash = sha256::Hash::hash(&preimage);
+        let hold_invoice =
+            read_hold_invoice(&self.storage, &payment_hash)?.ok_or(MutinyError::NotFound)?;
+        match hold_invoice.status {
+            HoldInvoiceStatus::Accepted => {}
+            HoldInvoiceStatus::Settled => return Ok(()),
+            HoldInvoiceStatus::Open | HoldInvoiceStatus::Cancelled => {
+                return Err(MutinyError::HoldInvoiceNotAccepted)
+            }
+        }
+
+        let node = self
+            .get_node_by_key_or_first(hold_invoice.node_id.as_ref())
+            .await?;
+        node.settle_hold_invoice(hold_invoice, preimage)
+    }
+
+    /// Cancels a hold invoice, failing back the payment if one is being held.
+    pub async fn cancel_hold_invoice(&self, payment_hash: sha256::Hash) -> Result<(), MutinyError> {
+        let hold_invoice =
+            read_hold_invoice(&self.storage, &payment_hash)?.ok_or(MutinyError::NotFound)?;
+        match hold_invoice.status {
+            HoldInvoiceStatus::Open | HoldInvoiceStatus::Accepted => {}
+            HoldInvoiceStatus::Cancelled => return Ok(()),
+            HoldInvoiceStatus::Settled => return Err(MutinyError::InvalidArgumentsError),
+        }
+
+        let node = self
+            .get_node_by_key_or_first(hold_invoice.node_id.as_ref())
+            .await?;
+        node.cancel_hold_invoice(hold_invoice)?;
+        self.event_notifier
+            .notify(MutinyEvent::HoldInvoiceCancelled { payment_hash });
+
+        Ok(())
+    }
+
+    /// Lists our hold invoices, most recently updated first.
+    pub fn list_hold_invoices(&self) -> Result<Vec<HoldInvoice>, MutinyError> {
+        let mut hold_invoices = list_hold_invoices(&self.storage)?;
+        hold_invoices.sort_by(|a, b| b.last_update.cmp(&a.last_update));
+        Ok(hold_invoices)
+    }
+
+    /// Cancels held payments that are about to expire so they don't cause a force close.
+    async fn cancel_expiring_hold_invoices(&self) {
+        let nodes = self.nodes.lock().await;
+        for node in nodes.values() {
+            match node.cancel_expiring_hold_invoices() {
+                Ok(cancelled) => {
+                    for payment_hash in cancelled {
+                        self.event_notifier
+                            .notify(MutinyEvent::HoldInvoiceCancelled { payment_hash });
+                    }
+                }
+                Err(e) => log_error!(self.logger, "Failed to cancel expiring hold invoices: {e}"),
+            }
+        }
+    }
+
+    /// Gets the channel options of an LSP that sells channels with LSPS1.
+    pub async fn get_channel_order_info(&self, lsp_url: &str) -> Result<Lsps1Info, MutinyError> {
+        Lsps1Client::new(lsp_url).get_info().await
+    }
+
+    /// Buys a channel from an LSP with LSPS1, so we have inbound liquidity ahead of time.
+    /// The LSP opens a channel to our first node with `lsp_balance_sat` on its side
+    /// and `client_balance_sat` on ours, once the order is paid with [`Self::pay_channel_order`].
+    pub async fn create_channel_order(
+        &self,
+        lsp_url: String,
+        lsp_balance_sat: u64,
+        client_balance_sat: u64,
+        channel_expiry_blocks: u32,
+        token: Option<String>,
+    ) -> Result<ChannelOrder, MutinyError> {
+        let client = Lsps1Client::new(&lsp_url);
+        let info = client.get_info().await?;
+        if !info
+            .options
+            .validate(lsp_balance_sat, client_balance_sat, channel_expiry_blocks)
+        {
+            log_error!(
+                self.logger,
+                "Channel order is outside of the LSP's limits: {:?}",
+                info.options
+            );
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+
+        let node = self.get_node_by_key_or_first(None).await?;
+        // the LSP needs to be able to reach us to open the channel
+        if let Some(uri) = info.uris.first() {
+            if let Err(e) = self.connect_to_peer(Some(&node.pubkey), uri, None).await {
+                log_warn!(self.logger, "Could not connect to LSP {uri}: {e}");
+            }
+        }
+
+        let refund_address = self.get_new_address(vec![])?;
+        let request = CreateOrderRequest {
+            public_key: node.pubkey,
+            lsp_balance_sat,
+            client_balance_sat,
+            required_channel_confirmations: info.options.min_required_channel_confirmations,
+            funding_confirms_within_blocks: info.options.min_funding_confirms_within_blocks,
+            channel_expiry_blocks,
+            token,
+            refund_onchain_address: Some(refund_address.to_string()),
+            announce_channel: false,
+        };
+        let order = client.create_order(&request).await?;
+        log_info!(self.logger, "Created channel order {}", order.order_id);
+
+        let channel_order = ChannelOrder {
+            lsp_url,
+            node_id: node.pubkey,
+            order,
+            paid_with: None,
+            last_update: utils::now().as_secs(),
+        };
+        persist_channel_order(&self.storage, &channel_order)?;
+
+        Ok(channel_order)
+    }
+
+    /// Gets the latest state of a channel order from its LSP.
+    /// If our lightning payment for it failed, the order can be paid again.
+    pub async fn get_channel_order(&self, order_id: &str) -> Result<ChannelOrder, MutinyError> {
+        let mut channel_order =
+            read_channel_order(&self.storage, order_id)?.ok_or(MutinyError::NotFound)?;
+        let order = Lsps1Client::new(&channel_order.lsp_url)
+            .get_order(order_id)
+            .await?;
+
+        let mut changed = false;
+        if order != channel_order.order {
+            channel_order.order = order;
+            changed = true;
+        }
+
+        if let Some(ChannelOrderPayment::Lightning { payment_hash }) = &channel_order.paid_with {
+            let node = self
+                .get_node_by_key_or_first(Some(&channel_order.node_id))
+                .await?;
+            let failed = node
+                .get_invoice_by_hash(payment_hash)
+                .is_ok_and(|i| i.status == HTLCStatus::Failed);
+            if failed && channel_order.order.payment.state == Lsps1PaymentState::ExpectPayment {
+                log_warn!(self.logger, "Payment for channel order {order_id} failed");
+                channel_order.paid_with = None;
+                changed = true;
+            }
+        }
+
+        if changed {
+            channel_order.last_update = utils::now().as_secs();
+            persist_channel_order(&self.storage, &channel_order)?;
+        }
+
+        Ok(channel_order)
+    }
+
+    /// Lists our channel orders, most recently updated first.
+    pub fn list_channel_orders(&self) -> Result<Vec<ChannelOrder>, MutinyError> {
+        let mut orders = list_channel_orders(&self.storage)?;
+        orders.sort_by(|a, b| b.last_update.cmp(&a.last_update));
+        Ok(orders)
+    }
+
+    /// Pays for a channel order, with lightning from the node the channel is for
+    /// or on-chain from the wallet.
+    ///
+    /// A lightning payment is only started here, the LSP may hold it until the channel
+    /// is open, so [`Self::get_channel_order`] should be used to follow its progress.
+    pub async fn pay_channel_order(
+        &self,
+        order_id: &str,
+        onchain: bool,
+    ) -> Result<ChannelOrder, MutinyError> {
+        let mut channel_order = self.get_channel_order(order_id).await?;
+        if !channel_order.is_payable() {
+            return Err(MutinyError::ChannelOrderNotPayable);
+        }
+
+        let payment = &channel_order.order.payment;
+        let paid_with = if onchain {
+            let address = payment
+                .onchain_address
+                .as_ref()
+                .ok_or(MutinyError::ChannelOrderNotPayable)?;
+            let address = Address::from_str(address).map_err(|_| MutinyError::LspGenericError)?;
+            let txid = self
+                .send_to_address(address, payment.order_total_sat, vec![], None)
+                .await?;
+            ChannelOrderPayment::Onchain { txid }
+        } else {
+            let invoice = Bolt11Invoice::from_str(&payment.bolt11_invoice)?;
+            if invoice.amount_milli_satoshis() != Some(payment.order_total_sat * 1_000) {
+                log_error!(
+                    self.logger,
+                    "Channel order invoice amount does not match order"
+                );
+                return Err(MutinyError::InvoiceInvalid);
+            }
+            let node = self
+                .get_node_by_key_or_first(Some(&channel_order.node_id))
+                .await?;
+            node.init_invoice_payment(&invoice, None, None).await?;
+            ChannelOrderPayment::Lightning {
+                payment_hash: *invoice.payment_hash(),
+            }
+        };
+
+        channel_order.paid_with = Some(paid_with);
+        channel_order.last_update = utils::now().as_secs();
+        persist_channel_order(&self.storage, &channel_order)?;
+
+        Ok(channel_order)
+    }
+
+    /// Updates the channel orders that the LSP has not finished yet.
+    async fn refresh_channel_orders(&self) {
+        let orders = match list_channel_orders(&self.storage) {
+            Ok(orders) => orders,
+            Err(e) => {
+                log_error!(self.logger, "Failed to read channel orders: {e}");
+                return;
+            }
+        };
+
+        for order in orders.iter().filter(|o| o.is_pending()) {
+            if let Err(e) = self.get_channel_order(&order.order.order_id).await {
+                log_warn!(
+                    self.logger,
+                    "Failed to refresh channel order {}: {e}",
+                    order.order.order_id
+                );
+            }
+        }
+    }
+
     /// Pays a lightning invoice from either a specified node or the first available node.
     /// An amount should only be provided if the invoice does not have an amount.
-    /// The amount should be in satoshis.
+    /// The amount and max fee should be in satoshis.
     pub(crate) async fn pay_invoice(
         &self,
         self_node_pubkey: Option<&PublicKey>,
         invoice: &Bolt11Invoice,
         amt_sats: Option<u64>,
+        max_fee_sats: Option<u64>,
         labels: Vec<String>,
     ) -> Result<MutinyInvoice, MutinyError> {
         let node = self.get_node_by_key_or_first(self_node_pubkey).await?;
-        node.pay_invoice_with_timeout(invoice, amt_sats, None, labels)
+        node.pay_invoice_with_timeout(invoice, amt_sats, max_fee_sats, None, labels)
             .await
     }
 
+    /// Estimates the routing fee in satoshis for paying the invoice
+    /// from either a specified node or the first available node.
+    pub(crate) async fn estimate_invoice_fee(
+        &self,
+        self_node_pubkey: Option<&PublicKey>,
+        invoice: &Bolt11Invoice,
+        amt_sats: Option<u64>,
+    ) -> Result<u64, MutinyError> {
+        let node = self.get_node_by_key_or_first(self_node_pubkey).await?;
+        node.estimate_invoice_fee(invoice, amt_sats)
+    }
+
+    /// The node with the largest lightning balance, with that balance in satoshis.
+    /// A payment is sent from a single node so this is the most we can pay.
+    pub(crate) async fn get_largest_node_balance(&self) -> Option<(PublicKey, u64)> {
+        self.nodes
+            .lock()
+            .await
+            .values()
+            .map(|n| {
+                let balance_msat: u64 = n
+                    .channel_manager
+                    .list_channels()
+                    .iter()
+                    .map(|c| c.balance_msat)
+                    .sum();
+                (n.pubkey, balance_msat / 1_000)
+            })
+            .max_by_key(|(_, balance)| *balance)
+    }
+
+    /// The total balance of our usable lightning channels in satoshis.
+    pub(crate) async fn get_lightning_balance(&self) -> u64 {
+        self.nodes
+            .lock()
+            .await
+            .iter()
+            .flat_map(|(_, n)| n.channel_manager.list_channels())
+            .map(|c| c.balance_msat)
+            .sum::<u64>()
+            / 1_000
+    }
+
     /// Sends a spontaneous payment to a node from either a specified node or the first available node.
-    /// The amount should be in satoshis.
+    /// The amount should be in satoshis. Custom TLV records are sent along with the message, if any.
     pub async fn keysend(
         &self,
         self_node_pubkey: Option<&PublicKey>,
         to_node: PublicKey,
         amt_sats: u64,
         message: Option<String>,
+        custom_tlvs: Vec<CustomTlv>,
         labels: Vec<String>,
     ) -> Result<MutinyInvoice, MutinyError> {
         let node = self.get_node_by_key_or_first(self_node_pubkey).await?;
         log_debug!(self.logger, "Keysending to {to_node}");
-        node.keysend_with_timeout(to_node, amt_sats, message, labels, None)
+        node.keysend_with_timeout(to_node, amt_sats, message, custom_tlvs, labels, None)
             .await
     }
 
+    /// Creates a reusable BOLT12 offer from the first available node.
+    /// If no amount is provided, the payer will choose how much to pay.
+    /// The amount should be in satoshis.
+    pub async fn create_offer(
+        &self,
+        amount: Option<u64>,
+        description: Option<String>,
+    ) -> Result<Offer, MutinyError> {
+        let node = self.get_node_by_key_or_first(None).await?;
+        node.create_offer(amount, description)
+    }
+
+    /// Pays a BOLT12 offer from either a specified node or the first available node.
+    /// An amount should only be provided if the offer does not have an amount.
+    /// The amount should be in satoshis.
+    pub async fn pay_offer(
+        &self,
+        self_node_pubkey: Option<&PublicKey>,
+        offer: &Offer,
+        amt_sats: Option<u64>,
+        payer_note: Option<String>,
+        labels: Vec<String>,
+    ) -> Result<MutinyInvoice, MutinyError> {
+        let node = self.get_node_by_key_or_first(self_node_pubkey).await?;
+        node.pay_offer_with_timeout(offer, amt_sats, payer_note, labels, None)
+            .await
+    }
+
+    /// Requests payment for a BOLT12 refund to either a specified node or the first available node.
+    pub async fn request_refund(
+        &self,
+        self_node_pubkey: Option<&PublicKey>,
+        refund: &Refund,
+    ) -> Result<(), MutinyError> {
+        let node = self.get_node_by_key_or_first(self_node_pubkey).await?;
+        node.request_refund(refund)
+    }
+
     /// Gets an invoice from the node manager.
     /// This includes sent and received invoices.
     pub(crate) async fn get_invoice_by_hash(
@@ -1487,8 +2081,8 @@ impl<S: MutinyStorage> NodeManager<S> {
         let to_pubkey = match to_pubkey {
             Some(pubkey) => pubkey,
             None => node
-                .lsp_client
-                .as_ref()
+                .lsp_clients
+                .first()
                 .ok_or(MutinyError::PubkeyInvalid)?
                 .get_lsp_pubkey(),
         };
@@ -1508,6 +2102,65 @@ impl<S: MutinyStorage> NodeManager<S> {
         }
     }
 
+    /// Starts opening a channel funded by the watch-only on-chain wallet, from either a
+    /// specified node or the first available node to the given pubkey.
+    /// The amount is in satoshis.
+    ///
+    /// The returned funding PSBT needs to be signed externally and given to
+    /// [`NodeManager::fund_channel`] to finish opening the channel.
+    pub async fn open_channel_unsigned(
+        &self,
+        self_node_pubkey: Option<&PublicKey>,
+        to_pubkey: Option<PublicKey>,
+        amount: u64,
+        fee_rate: Option<f32>,
+        user_channel_id: Option<u128>,
+    ) -> Result<PendingChannelFunding, MutinyError> {
+        let node = self.get_node_by_key_or_first(self_node_pubkey).await?;
+        let to_pubkey = match to_pubkey {
+            Some(pubkey) => pubkey,
+            None => node
+                .lsp_clients
+                .first()
+                .ok_or(MutinyError::PubkeyInvalid)?
+                .get_lsp_pubkey(),
+        };
+
+        let (user_channel_id, psbt) = node
+            .open_channel_unsigned_with_timeout(to_pubkey, amount, fee_rate, user_channel_id, 60)
+            .await?;
+
+        Ok(PendingChannelFunding {
+            user_channel_id,
+            psbt,
+        })
+    }
+
+    /// Funds a channel started with [`NodeManager::open_channel_unsigned`]
+    /// with its funding PSBT after it was signed externally.
+    pub async fn fund_channel(
+        &self,
+        self_node_pubkey: Option<&PublicKey>,
+        user_channel_id: u128,
+        psbt: PartiallySignedTransaction,
+    ) -> Result<MutinyChannel, MutinyError> {
+        let node = self.get_node_by_key_or_first(self_node_pubkey).await?;
+
+        let outpoint = node
+            .fund_channel_with_signed_psbt(user_channel_id, psbt, 60)
+            .await?;
+
+        let all_channels = node.channel_manager.list_channels();
+        let found_channel = all_channels
+            .iter()
+            .find(|chan| chan.funding_txo.map(|a| a.into_bitcoin_outpoint()) == Some(outpoint));
+
+        match found_channel {
+            Some(channel) => Ok(channel.into()),
+            None => Err(MutinyError::ChannelCreationFailed),
+        }
+    }
+
     /// Opens a channel from either a specified node or the first available node to the given pubkey.
     /// It will spend the given utxos in full to fund the channel.
     ///
@@ -1524,8 +2177,8 @@ impl<S: MutinyStorage> NodeManager<S> {
         let to_pubkey = match to_pubkey {
             Some(pubkey) => pubkey,
             None => node
-                .lsp_client
-                .as_ref()
+                .lsp_clients
+                .first()
                 .ok_or(MutinyError::PubkeyInvalid)?
                 .get_lsp_pubkey(),
         };
@@ -1547,6 +2200,7 @@ impl<S: MutinyStorage> NodeManager<S> {
 
     /// Opens a channel from our selected node to the given pubkey.
     /// It will spend the all the on-chain utxo in full to fund the channel.
+    /// Frozen utxos are not spent.
     ///
     /// The node must be online and have a connection to the peer.
     pub async fn sweep_all_to_channel(
@@ -1555,10 +2209,12 @@ impl<S: MutinyStorage> NodeManager<S> {
         from_node: Option<&PublicKey>,
         to_pubkey: Option<PublicKey>,
     ) -> Result<MutinyChannel, MutinyError> {
+        let frozen = self.wallet.get_frozen_utxos()?;
         let utxos = self
             .list_utxos()?
             .iter()
             .map(|u| u.outpoint)
+            .filter(|outpoint| !frozen.contains(outpoint))
             .collect::<Vec<_>>();
 
         self.sweep_utxos_to_channel(user_chan_id, from_node, &utxos, to_pubkey)
@@ -1683,6 +2339,64 @@ impl<S: MutinyStorage> NodeManager<S> {
         Ok(mutiny_channels)
     }
 
+    /// Creates a static backup of the channels of all our nodes.
+    ///
+    /// The backup does not contain any channel state, so it only needs to be
+    /// updated after opening channels. It can be used with
+    /// [`NodeManager::recover_from_static_channel_backup`] when the channel
+    /// state has been lost.
+    pub async fn create_static_channel_backup(&self) -> Result<StaticChannelBackup, MutinyError> {
+        if self.safe_mode {
+            return Err(MutinyError::NotRunning);
+        }
+
+        let nodes = self.nodes.lock().await;
+        let nodes = nodes
+            .values()
+            .map(|n| n.channel_backup())
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(StaticChannelBackup {
+            created_at: utils::now().as_secs(),
+            nodes,
+        })
+    }
+
+    /// Recovers the funds of channels from a static channel backup.
+    ///
+    /// Our peers are asked to force close the channels we no longer have the state of,
+    /// afterwards our balance is swept to the on-chain wallet. This happens in the
+    /// background and continues across restarts until all channels are swept.
+    ///
+    /// Returns the number of channels being recovered.
+    pub async fn recover_from_static_channel_backup(
+        &self,
+        backup: StaticChannelBackup,
+    ) -> Result<usize, MutinyError> {
+        if self.safe_mode {
+            return Err(MutinyError::NotRunning);
+        }
+
+        let nodes = self.nodes.lock().await;
+        let mut count = 0;
+        for node_backup in backup.nodes {
+            let Some(node) = nodes.values().find(|n| n.pubkey == node_backup.pubkey) else {
+                log_warn!(
+                    self.logger,
+                    "node {} (index {}) from backup not found, skipping",
+                    node_backup.pubkey,
+                    node_backup.child_index
+                );
+                continue;
+            };
+
+            count += node.start_channel_recovery(node_backup.channels)?;
+        }
+
+        log_info!(self.logger, "recovering {count} channels from backup");
+        Ok(count)
+    }
+
     /// Lists all the peers for all the nodes in the node manager.
     pub async fn list_peers(&self) -> Result<Vec<MutinyPeer>, MutinyError> {
         let peer_data = gossip::get_all_peers(&self.storage)?;
@@ -1851,12 +2565,33 @@ impl<S: MutinyStorage> NodeManager<S> {
         Ok(response.price)
     }
 
-    /// Retrieves the logs from storage.
+    /// Retrieves the logs from storage as lines of text.
     pub fn get_logs(
         storage: S,
         logger: Arc<MutinyLogger>,
     ) -> Result<Option<Vec<String>>, MutinyError> {
-        logger.get_logs(&storage)
+        let logs = logger.get_logs(&storage, &LogFilter::default())?;
+        Ok(logs.map(|logs| logs.iter().map(|r| r.to_string()).collect()))
+    }
+
+    /// Retrieves the log records from storage that match the filter.
+    pub fn query_logs(
+        storage: S,
+        logger: Arc<MutinyLogger>,
+        filter: &LogFilter,
+    ) -> Result<Option<Vec<LogRecord>>, MutinyError> {
+        logger.get_logs(&storage, filter)
+    }
+
+    /// Exports the logs that match the filter as text for a support bundle.
+    /// Secrets are redacted before logs are saved, so they are never included.
+    pub fn export_logs(
+        storage: S,
+        logger: Arc<MutinyLogger>,
+        filter: &LogFilter,
+    ) -> Result<Option<String>, MutinyError> {
+        let logs = logger.get_logs(&storage, filter)?;
+        Ok(logs.map(|logs| logs.iter().map(|r| format!("{r}\n")).collect::<String>()))
     }
 
     /// Resets the scorer and network graph. This can be useful if you get stuck in a bad state.
@@ -1961,11 +2696,10 @@ pub(crate) async fn create_new_node_from_node_manager<S: MutinyStorage>(
     // Create and save a new node using the next child index
     let next_node_uuid = Uuid::new_v4().to_string();
 
-    let lsp = node_manager.lsp_config.clone();
-
     let next_node = NodeIndex {
         child_index: next_node_index,
-        lsp,
+        lsp: node_manager.lsp_configs.first().cloned(),
+        additional_lsps: node_manager.lsp_configs.iter().skip(1).cloned().collect(),
         archived: Some(false),
     };
 
@@ -1985,16 +2719,16 @@ pub(crate) async fn create_new_node_from_node_manager<S: MutinyStorage>(
         .with_chain(node_manager.chain.clone())
         .with_fee_estimator(node_manager.fee_estimator.clone())
         .with_wallet(node_manager.wallet.clone())
-        .with_esplora(node_manager.esplora.clone())
         .with_network(node_manager.network);
+    node_builder.with_event_notifier(node_manager.event_notifier.clone());
     node_builder.with_logger(node_manager.logger.clone());
 
     #[cfg(target_arch = "wasm32")]
     node_builder.with_websocket_proxy_addr(node_manager.websocket_proxy_addr.clone());
 
-    if let Some(l) = node_manager.lsp_config.clone() {
-        node_builder.with_lsp_config(l);
-    }
+    node_builder.with_lsp_configs(node_manager.lsp_configs.clone());
+    node_builder.with_lsp_selection(node_manager.lsp_selection);
+    node_builder.with_lsp_fee_limits(node_manager.lsp_fee_limits);
     if node_manager.do_not_connect_peers {
         node_builder.do_not_connect_peers();
     }
@@ -2063,7 +2797,7 @@ mod tests {
 
     use crate::test_utils::*;
 
-    use crate::event::{HTLCStatus, MillisatAmount, PaymentInfo};
+    use crate::event::{CustomTlv, HTLCStatus, MillisatAmount, PaymentInfo};
     use crate::nodemanager::{LspConfig, NodeIndex, NodeStorage};
     use crate::storage::{MemoryStorage, MutinyStorage};
     use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
@@ -2139,6 +2873,11 @@ mod tests {
             let retrieved_node = node_storage.nodes.get(&node_identity.uuid).unwrap();
             assert_eq!(1, retrieved_node.child_index);
         }
+
+        // payments are sent from a single node, so its balance is reported on its own
+        let (pubkey, balance) = nm.get_largest_node_balance().await.unwrap();
+        assert!(nm.nodes.lock().await.values().any(|n| n.pubkey == pubkey));
+        assert_eq!(balance, 0);
     }
 
     #[test]
@@ -2230,12 +2969,15 @@ mod tests {
             amt_msat: MillisatAmount(Some(100_000_000)),
             fee_paid_msat: None,
             bolt11: Some(invoice.clone()),
+            bolt12: None,
+            custom_tlvs: vec![],
             payee_pubkey: None,
             last_update: 1681781585,
         };
 
         let expected: MutinyInvoice = MutinyInvoice {
             bolt11: Some(invoice),
+            bolt12: None,
             description: None,
             payment_hash,
             preimage: Some(preimage.to_hex()),
@@ -2247,6 +2989,8 @@ mod tests {
             inbound: true,
             labels: labels.clone(),
             last_updated: 1681781585,
+            custom_tlvs: vec![],
+            success_action: None,
         };
 
         let actual = MutinyInvoice::from(
@@ -2276,6 +3020,11 @@ mod tests {
         )
         .unwrap();
 
+        let custom_tlvs = vec![CustomTlv {
+            tlv_type: 7629169,
+            value: b"{\"podcast\":\"mutiny\"}".to_vec(),
+        }];
+
         let payment_info = PaymentInfo {
             preimage: Some(preimage),
             secret: None,
@@ -2283,12 +3032,15 @@ mod tests {
             amt_msat: MillisatAmount(Some(100_000)),
             fee_paid_msat: Some(1_000),
             bolt11: None,
+            bolt12: None,
+            custom_tlvs: custom_tlvs.clone(),
             payee_pubkey: Some(pubkey),
             last_update: 1681781585,
         };
 
         let expected: MutinyInvoice = MutinyInvoice {
             bolt11: None,
+            bolt12: None,
             description: None,
             payment_hash,
             preimage: Some(preimage.to_hex()),
@@ -2300,6 +3052,8 @@ mod tests {
             inbound: false,
             labels: vec![],
             last_updated: 1681781585,
+            custom_tlvs,
+            success_action: None,
         };
 
         let actual = MutinyInvoice::from(
@@ -2313,6 +3067,62 @@ mod tests {
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn test_offer_payment_info_into_mutiny_invoice() {
+        let preimage: [u8; 32] =
+            FromHex::from_hex("7600f5a9ad72452dea7ad86dabbc9cb46be96a1a2fcd961e041d066b38d93008")
+                .unwrap();
+
+        let payment_hash = sha256::Hash::from_hex(
+            "55ecf9169a6fa07e8ba181fdddf5b0bcc7860176659fa22a7cca9da2a359a33b",
+        )
+        .unwrap();
+
+        let pubkey = PublicKey::from_str(
+            "02465ed5be53d04fde66c9418ff14a5f2267723810176c9212b722e542dc1afb1b",
+        )
+        .unwrap();
+
+        let offer = "lno1pqps7sjqpgtyzm3qv4uxzmtsd3jjqer9wd3hy6tsw35k7msjzfpy7nz5yqcnygrfdej82um5wf5k2uckyypwa3eyt44h6txtxquqh7lz5djge4afgfjn7k4rgrkuag0jsd5xvxg".to_string();
+
+        let payment_info = PaymentInfo {
+            preimage: Some(preimage),
+            secret: None,
+            status: HTLCStatus::Succeeded,
+            amt_msat: MillisatAmount(Some(100_000)),
+            fee_paid_msat: Some(1_000),
+            bolt11: None,
+            bolt12: Some(offer.clone()),
+            custom_tlvs: vec![],
+            payee_pubkey: Some(pubkey),
+            last_update: 1681781585,
+        };
+
+        let expected: MutinyInvoice = MutinyInvoice {
+            bolt11: None,
+            bolt12: Some(offer),
+            description: None,
+            payment_hash,
+            preimage: Some(preimage.to_hex()),
+            payee_pubkey: Some(pubkey),
+            amount_sats: Some(100),
+            expire: 1681781585,
+            status: HTLCStatus::Succeeded,
+            fees_paid: Some(1),
+            inbound: false,
+            labels: vec![],
+            last_updated: 1681781585,
+            custom_tlvs: vec![],
+            success_action: None,
+        };
+
+        // offer payments are stored under their payment id until we get the invoice
+        let payment_id = PaymentHash([7; 32]);
+        let actual = MutinyInvoice::from(payment_info, payment_id, false, vec![]).unwrap();
+
+        assert_eq!(actual, expected);
+    }
+
     #[test]
     fn test_serialize_node_storage() {
         let old: NodeStorage = serde_json::from_str("{\"nodes\":{\"93ca1ee3-d5f1-42ed-8bd9-042b298c70dc\":{\"archived\":false,\"child_index\":0,\"lsp\":\"https://signet-lsp.mutinywallet.com\"}},\"version\":11}").unwrap();
@@ -2321,6 +3131,7 @@ mod tests {
             lsp: Some(LspConfig::VoltageFlow(
                 "https://signet-lsp.mutinywallet.com".to_string(),
             )),
+            additional_lsps: vec![],
             archived: Some(false),
         };
         let mut nodes = HashMap::new();
@@ -2383,6 +3194,7 @@ mod tests {
 
         let invoice1: MutinyInvoice = MutinyInvoice {
             bolt11: None,
+            bolt12: None,
             description: None,
             payment_hash,
             preimage: Some(preimage.to_hex()),
@@ -2394,10 +3206,13 @@ mod tests {
             inbound: false,
             labels: vec![],
             last_updated: 1681781585,
+            custom_tlvs: vec![],
+            success_action: None,
         };
 
         let invoice2: MutinyInvoice = MutinyInvoice {
             bolt11: None,
+            bolt12: None,
             description: None,
             payment_hash,
             preimage: Some(preimage.to_hex()),
@@ -2409,10 +3224,13 @@ mod tests {
             inbound: false,
             labels: vec![],
             last_updated: 1781781585,
+            custom_tlvs: vec![],
+            success_action: None,
         };
 
         let invoice3: MutinyInvoice = MutinyInvoice {
             bolt11: None,
+            bolt12: None,
             description: None,
             payment_hash,
             preimage: None,
@@ -2424,10 +3242,13 @@ mod tests {
             inbound: false,
             labels: vec![],
             last_updated: 1581781585,
+            custom_tlvs: vec![],
+            success_action: None,
         };
 
         let invoice4: MutinyInvoice = MutinyInvoice {
             bolt11: None,
+            bolt12: None,
             description: None,
             payment_hash,
             preimage: None,
@@ -2439,10 +3260,13 @@ mod tests {
             inbound: false,
             labels: vec![],
             last_updated: 1581781585,
+            custom_tlvs: vec![],
+            success_action: None,
         };
 
         let invoice5: MutinyInvoice = MutinyInvoice {
             bolt11: None,
+            bolt12: None,
             description: Some("difference".to_string()),
             payment_hash,
             preimage: Some(preimage.to_hex()),
@@ -2454,6 +3278,8 @@ mod tests {
             inbound: false,
             labels: vec![],
             last_updated: 1781781585,
+            custom_tlvs: vec![],
+            success_action: None,
         };
 
         let mut vec = vec![
@@ -0,0 +1,250 @@
+use crate::error::MutinyError;
+use nostr::key::{SecretKey, XOnlyPublicKey};
+use nostr::nips::{nip04, nip44};
+use nostr::Event;
+use serde::{Deserialize, Serialize};
+
+/// The tag a NWC info event lists its encryption schemes in,
+/// and a request picks one with.
+pub(crate) const ENCRYPTION_TAG: &str = "encryption";
+
+/// How the content of a DM or NWC event is encrypted
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum EncryptionScheme {
+    /// NIP-04, kept for clients that don't support NIP-44 yet
+    #[default]
+    Nip04,
+    /// NIP-44 version 2
+    Nip44,
+}
+
+impl EncryptionScheme {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Nip04 => "nip04",
+            Self::Nip44 => "nip44_v2",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "nip04" => Some(Self::Nip04),
+            "nip44_v2" => Some(Self::Nip44),
+            _ => None,
+        }
+    }
+
+    /// The scheme a NWC request asks for in its encryption tag.
+    /// Requests without one are NIP-04, `None` if we don't support what it asks for.
+    pub fn from_request(event: &Event) -> Option<Self> {
+        match encryption_tag(event) {
+            Some(tag) => tag.get(1).and_then(|n| Self::from_name(n)),
+            None => Some(Self::Nip04),
+        }
+    }
+
+    /// The scheme to talk to a wallet with, from the encryption tag of its NWC info event.
+    /// We prefer NIP-44, wallets without the tag only support NIP-04.
+    pub fn from_info(event: &Event) -> Self {
+        let nip44 = encryption_tag(event)
+            .and_then(|tag| tag.get(1).cloned())
+            .is_some_and(|schemes| {
+                schemes
+                    .split_whitespace()
+                    .any(|name| Self::from_name(name) == Some(Self::Nip44))
+            });
+
+        if nip44 {
+            Self::Nip44
+        } else {
+            Self::Nip04
+        }
+    }
+
+    /// Detects the scheme from an encrypted payload,
+    /// NIP-04 payloads always have the iv appended after `?iv=`.
+    pub fn from_payload(payload: &str) -> Self {
+        if payload.contains("?iv=") {
+            Self::Nip04
+        } else {
+            Self::Nip44
+        }
+    }
+}
+
+fn encryption_tag(event: &Event) -> Option<Vec<String>> {
+    event
+        .tags
+        .iter()
+        .map(|t| t.as_vec())
+        .find(|t| t.first().is_some_and(|n| n == ENCRYPTION_TAG))
+}
+
+/// The value of the encryption tag in our NWC info events, most preferred first
+pub(crate) fn supported_schemes() -> String {
+    [EncryptionScheme::Nip44, EncryptionScheme::Nip04]
+        .iter()
+        .map(|s| s.as_str())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+pub fn encrypt(
+    scheme: EncryptionScheme,
+    secret_key: &SecretKey,
+    public_key: &XOnlyPublicKey,
+    content: impl Into<String>,
+) -> Result<String, MutinyError> {
+    let content: String = content.into();
+    match scheme {
+        EncryptionScheme::Nip04 => Ok(nip04::encrypt(secret_key, public_key, content)?),
+        EncryptionScheme::Nip44 => Ok(nip44::encrypt(
+            secret_key,
+            public_key,
+            content,
+            nip44::Version::V2,
+        )?),
+    }
+}
+
+/// Decrypts a payload, the scheme is detected from it.
+pub fn decrypt(
+    secret_key: &SecretKey,
+    public_key: &XOnlyPublicKey,
+    payload: &str,
+) -> Result<String, MutinyError> {
+    match EncryptionScheme::from_payload(payload) {
+        EncryptionScheme::Nip04 => Ok(nip04::decrypt(secret_key, public_key, payload)?),
+        EncryptionScheme::Nip44 => Ok(nip44::decrypt(secret_key, public_key, payload)?),
+    }
+}
+
+/// NIP-44 through a NIP-07 browser extension, `method` is either `encrypt` or `decrypt`.
+///
+/// Extensions expose it as `window.nostr.nip44` next to the NIP-04 functions.
+#[cfg(target_arch = "wasm32")]
+pub(crate) async fn nip07_nip44(
+    method: &str,
+    public_key: &XOnlyPublicKey,
+    content: &str,
+) -> Result<String, MutinyError> {
+    use wasm_bindgen::JsCast;
+    use wasm_bindgen_futures::JsFuture;
+
+    let nostr = js_sys::Reflect::get(&js_sys::global(), &"nostr".into())
+        .map_err(|_| MutinyError::Nip07Extension)?;
+    let nip44 = js_sys::Reflect::get(&nostr, &"nip44".into())
+        .ok()
+        .filter(|n| n.is_object())
+        .ok_or(MutinyError::Nip07Extension)?;
+    let function: js_sys::Function = js_sys::Reflect::get(&nip44, &method.into())
+        .map_err(|_| MutinyError::Nip07Extension)?
+        .dyn_into()
+        .map_err(|_| MutinyError::Nip07Extension)?;
+
+    let promise: js_sys::Promise = function
+        .call2(&nip44, &public_key.to_string().into(), &content.into())
+        .map_err(|_| MutinyError::Nip07Extension)?
+        .dyn_into()
+        .map_err(|_| MutinyError::Nip07Extension)?;
+    let result = JsFuture::from(promise)
+        .await
+        .map_err(|_| MutinyError::Nip07Extension)?;
+
+    result.as_string().ok_or(MutinyError::Nip07Extension)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::*;
+    use nostr::{EventBuilder, Keys, Kind, Tag};
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[test]
+    async fn test_encrypt_decrypt() {
+        let test_name = "test_encrypt_decrypt";
+        log!("{}", test_name);
+
+        let alice = Keys::generate();
+        let bob = Keys::generate();
+        let message = "hello bob";
+
+        for scheme in [EncryptionScheme::Nip04, EncryptionScheme::Nip44] {
+            let payload = encrypt(
+                scheme,
+                &alice.secret_key().unwrap(),
+                &bob.public_key(),
+                message,
+            )
+            .unwrap();
+            assert_eq!(EncryptionScheme::from_payload(&payload), scheme);
+
+            let decrypted =
+                decrypt(&bob.secret_key().unwrap(), &alice.public_key(), &payload).unwrap();
+            assert_eq!(decrypted, message);
+        }
+    }
+
+    #[test]
+    async fn test_scheme_from_request() {
+        let test_name = "test_scheme_from_request";
+        log!("{}", test_name);
+
+        let keys = Keys::generate();
+        let request = |tags: Vec<Tag>| {
+            EventBuilder::new(Kind::WalletConnectRequest, "", tags)
+                .to_event(&keys)
+                .unwrap()
+        };
+
+        assert_eq!(
+            EncryptionScheme::from_request(&request(vec![])),
+            Some(EncryptionScheme::Nip04)
+        );
+
+        let tag = Tag::parse(vec![ENCRYPTION_TAG, "nip44_v2"]).unwrap();
+        assert_eq!(
+            EncryptionScheme::from_request(&request(vec![tag])),
+            Some(EncryptionScheme::Nip44)
+        );
+
+        let tag = Tag::parse(vec![ENCRYPTION_TAG, "nip99"]).unwrap();
+        assert_eq!(EncryptionScheme::from_request(&request(vec![tag])), None);
+
+        assert_eq!(supported_schemes(), "nip44_v2 nip04");
+    }
+
+    #[test]
+    async fn test_scheme_from_info() {
+        let test_name = "test_scheme_from_info";
+        log!("{}", test_name);
+
+        let keys = Keys::generate();
+        let info = |tags: Vec<Tag>| {
+            EventBuilder::new(Kind::WalletConnectInfo, "pay_invoice", tags)
+                .to_event(&keys)
+                .unwrap()
+        };
+
+        assert_eq!(
+            EncryptionScheme::from_info(&info(vec![])),
+            EncryptionScheme::Nip04
+        );
+
+        let tag = Tag::parse(vec![ENCRYPTION_TAG, "nip04 nip44_v2"]).unwrap();
+        assert_eq!(
+            EncryptionScheme::from_info(&info(vec![tag])),
+            EncryptionScheme::Nip44
+        );
+
+        let tag = Tag::parse(vec![ENCRYPTION_TAG, "nip04"]).unwrap();
+        assert_eq!(
+            EncryptionScheme::from_info(&info(vec![tag])),
+            EncryptionScheme::Nip04
+        );
+    }
+}
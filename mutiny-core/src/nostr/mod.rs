@@ -1,13 +1,19 @@
 use crate::logging::MutinyLogger;
+use crate::nostr::encryption::{EncryptionScheme, ENCRYPTION_TAG};
+use crate::nostr::nip47::*;
 use crate::nostr::nip49::{NIP49BudgetPeriod, NIP49URI};
 use crate::nostr::nwc::{
     check_valid_nwc_invoice, BudgetPeriod, BudgetedSpendingConditions, NostrWalletConnect,
-    NwcProfile, NwcProfileTag, PendingNwcInvoice, Profile, SingleUseSpendingConditions,
-    SpendingConditions, PENDING_NWC_EVENTS_KEY,
+    NwcNotificationType, NwcProfile, NwcProfileTag, PendingNwcInvoice, Profile,
+    SingleUseSpendingConditions, SpendingConditions, PENDING_NWC_EVENTS_KEY,
+    SUPPORTED_NOTIFICATIONS,
+};
+use crate::nostr::zap::{
+    verify_zap_receipt, zap_request_builder, ZapPrivacy, ZAP_RECEIPT_TIMEOUT_SECS, ZAP_RELAYS,
 };
 use crate::storage::MutinyStorage;
 use crate::{error::MutinyError, utils::get_random_bip32_child_index};
-use crate::{labels::LabelStorage, InvoiceHandler};
+use crate::{labels::LabelStorage, InvoiceHandler, MutinyInvoice};
 use crate::{utils, HTLCStatus};
 use bitcoin::hashes::{sha256, Hash};
 use bitcoin::secp256k1::{Secp256k1, Signing};
@@ -22,20 +28,23 @@ use lightning::util::logger::Logger;
 use lightning::{log_debug, log_error, log_warn};
 use lightning_invoice::Bolt11Invoice;
 use nostr::key::{SecretKey, XOnlyPublicKey};
-use nostr::nips::nip47::*;
-use nostr::prelude::{decrypt, encrypt};
-use nostr::{Event, EventBuilder, EventId, Filter, JsonUtil, Keys, Kind, Tag, Timestamp};
+use nostr::nips::nip57::{self, ZapRequestData};
+use nostr::{Event, EventBuilder, EventId, Filter, JsonUtil, Keys, Kind, Tag, TagKind, Timestamp};
 use nostr_sdk::{Client, ClientSigner, RelayPoolNotification};
 use std::collections::HashSet;
 use std::sync::{atomic::Ordering, Arc, RwLock};
 use std::time::Duration;
 use std::{str::FromStr, sync::atomic::AtomicBool};
 
+pub mod encryption;
+pub mod nip47;
 pub mod nip49;
 pub mod nwc;
+pub mod zap;
 
 const PROFILE_ACCOUNT_INDEX: u32 = 0;
 const NWC_ACCOUNT_INDEX: u32 = 1;
+const LNURL_ACCOUNT_INDEX: u32 = 2;
 
 const USER_NWC_PROFILE_START_INDEX: u32 = 1000;
 
@@ -365,6 +374,40 @@ impl<S: MutinyStorage> NostrManager<S> {
         Ok(nwc_profile)
     }
 
+    /// Sets which NIP-47 notifications the profile's client receives
+    pub fn set_nwc_profile_notifications(
+        &self,
+        profile_index: u32,
+        notifications: Vec<NwcNotificationType>,
+    ) -> Result<NwcProfile, MutinyError> {
+        let mut profiles = self.nwc.write().unwrap();
+
+        let nwc = profiles
+            .iter_mut()
+            .find(|nwc| nwc.profile.index == profile_index)
+            .ok_or(MutinyError::NotFound)?;
+
+        // keeps them in a consistent order without duplicates
+        nwc.profile.notifications = SUPPORTED_NOTIFICATIONS
+            .into_iter()
+            .filter(|n| notifications.contains(n))
+            .collect();
+
+        let nwc_profile = nwc.nwc_profile();
+
+        // save to storage
+        {
+            let profiles = profiles
+                .iter()
+                .map(|x| x.profile.clone())
+                .collect::<Vec<_>>();
+            self.storage
+                .set_data(NWC_STORAGE_KEY.to_string(), profiles, None)?;
+        }
+
+        Ok(nwc_profile)
+    }
+
     pub fn get_profile(&self, index: u32) -> Result<NwcProfile, MutinyError> {
         let profiles = self.nwc.read().unwrap();
 
@@ -438,6 +481,7 @@ impl<S: MutinyStorage> NostrManager<S> {
             spending_conditions,
             tag,
             label,
+            notifications: vec![],
         };
 
         let nwc = NostrWalletConnect::new(&Secp256k1::new(), self.xprivkey, profile)?;
@@ -480,6 +524,7 @@ impl<S: MutinyStorage> NostrManager<S> {
             tag,
             client_key: None,
             label: None,
+            notifications: vec![],
         };
         let nwc = NostrWalletConnect::new(&Secp256k1::new(), self.xprivkey, profile)?;
 
@@ -649,7 +694,8 @@ impl<S: MutinyStorage> NostrManager<S> {
         nwc: NostrWalletConnect,
         inv: PendingNwcInvoice,
     ) -> Result<EventId, MutinyError> {
-        let encrypted = encrypt(
+        let encrypted = encryption::encrypt(
+            inv.encryption,
             &nwc.server_key.secret_key().unwrap(),
             &nwc.client_pubkey(),
             resp.as_json(),
@@ -680,6 +726,36 @@ impl<S: MutinyStorage> NostrManager<S> {
         Ok(event_id)
     }
 
+    /// Sends a NIP-47 notification about a completed payment
+    /// to the client of every profile that opted in to it.
+    pub async fn send_nwc_notifications(&self, invoice: &MutinyInvoice) -> Result<(), MutinyError> {
+        let events = {
+            let profiles = self.nwc.read().unwrap();
+            profiles
+                .iter()
+                .filter_map(|nwc| match nwc.create_notification_event(invoice) {
+                    Ok(event) => event.map(|e| (nwc.profile.relay.clone(), e)),
+                    Err(e) => {
+                        log_warn!(self.logger, "Failed to create NWC notification: {e}");
+                        None
+                    }
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // one profile's relay being down shouldn't stop the others from being notified
+        for (relay, event) in events {
+            if let Err(e) = self.client.send_event_to(relay.as_str(), event).await {
+                log_warn!(
+                    self.logger,
+                    "Failed to send NWC notification to {relay}: {e:?}"
+                );
+            }
+        }
+
+        Ok(())
+    }
+
     /// Approves an invoice and sends the payment
     pub async fn approve_invoice(
         &self,
@@ -795,7 +871,8 @@ impl<S: MutinyStorage> NostrManager<S> {
                 let (nwc, inv) = self.find_nwc_data(invoice.invoice.payment_hash())?;
 
                 if let Some(nwc) = nwc {
-                    let encrypted = encrypt(
+                    let encrypted = encryption::encrypt(
+                        inv.encryption,
                         &nwc.server_key.secret_key().unwrap(),
                         &nwc.client_pubkey(),
                         resp.as_json(),
@@ -886,7 +963,8 @@ impl<S: MutinyStorage> NostrManager<S> {
                 }
             };
 
-        self.save_pending_nwc_invoice(None, event.id, event.pubkey, invoice)
+        let encryption = EncryptionScheme::from_payload(&event.content);
+        self.save_pending_nwc_invoice(None, event.id, event.pubkey, invoice, encryption)
             .await?;
 
         Ok(())
@@ -898,12 +976,14 @@ impl<S: MutinyStorage> NostrManager<S> {
         event_id: EventId,
         event_pk: XOnlyPublicKey,
         invoice: Bolt11Invoice,
+        encryption: EncryptionScheme,
     ) -> anyhow::Result<()> {
         let pending = PendingNwcInvoice {
             index: profile_index,
             invoice,
             event_id,
             pubkey: event_pk,
+            encryption,
         };
         self.pending_nwc_lock.lock().await;
 
@@ -926,7 +1006,7 @@ impl<S: MutinyStorage> NostrManager<S> {
         &self,
         event: Event,
         invoice_handler: &impl InvoiceHandler,
-    ) -> anyhow::Result<Option<Event>> {
+    ) -> anyhow::Result<Vec<Event>> {
         let nwc = {
             let vec = self.nwc.read().unwrap();
             vec.iter()
@@ -937,10 +1017,10 @@ impl<S: MutinyStorage> NostrManager<S> {
         self.storage.set_nwc_sync_time(event.created_at.as_u64())?;
 
         if let Some(mut nwc) = nwc {
-            let event = nwc.handle_nwc_request(event, invoice_handler, self).await?;
-            Ok(event)
+            let events = nwc.handle_nwc_request(event, invoice_handler, self).await?;
+            Ok(events)
         } else {
-            Ok(None)
+            Ok(vec![])
         }
     }
 
@@ -1006,15 +1086,40 @@ impl<S: MutinyStorage> NostrManager<S> {
                 invoice: bolt11.to_string(),
             }),
         };
-        let encrypted = encrypt(&nwc.secret, &nwc.public_key, req.as_json())?;
-        let p_tag = Tag::PublicKey {
+        // use what the wallet says it supports in its info event, every wallet does NIP-04
+        let info_filter = Filter::new()
+            .kind(Kind::WalletConnectInfo)
+            .author(nwc.public_key);
+        let scheme = match client
+            .get_events_of(vec![info_filter], Some(Duration::from_secs(5)))
+            .await
+        {
+            Ok(events) => events
+                .iter()
+                .max_by_key(|e| e.created_at)
+                .map(EncryptionScheme::from_info)
+                .unwrap_or_default(),
+            Err(e) => {
+                log_warn!(self.logger, "Failed to get NWC info event: {e}");
+                EncryptionScheme::Nip04
+            }
+        };
+
+        let encrypted = encryption::encrypt(scheme, &nwc.secret, &nwc.public_key, req.as_json())?;
+        let mut tags = vec![Tag::PublicKey {
             public_key: nwc.public_key,
             relay_url: None,
             alias: None,
             uppercase: false,
-        };
+        }];
+        if scheme != EncryptionScheme::Nip04 {
+            tags.push(Tag::Generic(
+                TagKind::Custom(ENCRYPTION_TAG.to_string()),
+                vec![scheme.as_str().to_string()],
+            ));
+        }
         let request_event =
-            EventBuilder::new(Kind::WalletConnectRequest, encrypted, [p_tag]).to_event(&secret)?;
+            EventBuilder::new(Kind::WalletConnectRequest, encrypted, tags).to_event(&secret)?;
 
         let filter = Filter::new()
             .kind(Kind::WalletConnectResponse)
@@ -1070,7 +1175,7 @@ impl<S: MutinyStorage> NostrManager<S> {
                                 }
                             });
                             if has_e_tag && event.kind == Kind::WalletConnectResponse && event.verify().is_ok() {
-                                let decrypted = decrypt(&nwc.secret, &nwc.public_key, &event.content)?;
+                                let decrypted = encryption::decrypt(&nwc.secret, &nwc.public_key, &event.content)?;
                                 let resp: Response = serde_json::from_str(&decrypted)?;
 
                                 if resp.result_type == Method::PayInvoice {
@@ -1112,27 +1217,30 @@ impl<S: MutinyStorage> NostrManager<S> {
         Ok(None)
     }
 
-    /// Decrypts a DM using the primary key
+    /// Decrypts a DM using the primary key, either NIP-04 or NIP-44 depending on the message
     pub async fn decrypt_dm(
         &self,
         pubkey: XOnlyPublicKey,
         message: &str,
     ) -> Result<String, MutinyError> {
-        // todo we should handle NIP-44 as well
         match &self.primary_key {
             ClientSigner::Keys(key) => {
                 let secret = key.secret_key().expect("must have");
-                let decrypted = decrypt(&secret, &pubkey, message)?;
+                let decrypted = encryption::decrypt(&secret, &pubkey, message)?;
                 Ok(decrypted)
             }
             #[cfg(target_arch = "wasm32")]
-            ClientSigner::NIP07(nip07) => {
-                let decrypted = nip07.nip04_decrypt(pubkey, message).await?;
-                Ok(decrypted)
-            }
+            ClientSigner::NIP07(nip07) => match EncryptionScheme::from_payload(message) {
+                EncryptionScheme::Nip04 => Ok(nip07.nip04_decrypt(pubkey, message).await?),
+                EncryptionScheme::Nip44 => {
+                    encryption::nip07_nip44("decrypt", &pubkey, message).await
+                }
+            },
         }
     }
 
+    /// Sends a kind 4 DM, these are always NIP-04.
+    /// NIP-44 DMs have to be gift wrapped (NIP-17), which our nostr version can't do yet.
     pub async fn send_dm(
         &self,
         pubkey: XOnlyPublicKey,
@@ -1142,6 +1250,123 @@ impl<S: MutinyStorage> NostrManager<S> {
         Ok(event_id)
     }
 
+    /// Signs an event with the primary key
+    async fn sign_event(&self, builder: EventBuilder) -> Result<Event, MutinyError> {
+        match &self.primary_key {
+            ClientSigner::Keys(keys) => Ok(builder.to_event(keys)?),
+            #[cfg(target_arch = "wasm32")]
+            ClientSigner::NIP07(nip07) => {
+                let unsigned = builder.to_unsigned_event(self.public_key);
+                Ok(nip07.sign_event(unsigned).await?)
+            }
+        }
+    }
+
+    /// Creates and signs a NIP-57 zap request, how it is signed depends on the privacy.
+    pub async fn create_zap_request(
+        &self,
+        recipient: XOnlyPublicKey,
+        event_id: Option<EventId>,
+        amount_msat: u64,
+        lnurl: String,
+        comment: Option<String>,
+        privacy: ZapPrivacy,
+    ) -> Result<Event, MutinyError> {
+        let comment = comment.unwrap_or_default();
+        match privacy {
+            ZapPrivacy::Public => {
+                let builder =
+                    zap_request_builder(recipient, event_id, amount_msat, lnurl, comment)?;
+                self.sign_event(builder).await
+            }
+            ZapPrivacy::Private | ZapPrivacy::Anonymous => {
+                let data = ZapRequestData {
+                    public_key: recipient,
+                    relays: ZAP_RELAYS.iter().map(|r| (*r).into()).collect(),
+                    message: comment,
+                    amount: Some(amount_msat),
+                    lnurl: Some(lnurl),
+                    event_id,
+                    event_coordinate: None,
+                };
+                if privacy == ZapPrivacy::Anonymous {
+                    return Ok(nip57::anonymous_zap_request(data)?);
+                }
+
+                // the recipient decrypts who it is from with our key, so we need the secret
+                match &self.primary_key {
+                    ClientSigner::Keys(keys) => Ok(nip57::private_zap_request(data, keys)?),
+                    #[cfg(target_arch = "wasm32")]
+                    ClientSigner::NIP07(_) => Err(MutinyError::InvalidArgumentsError),
+                }
+            }
+        }
+    }
+
+    /// Waits for the zap receipt of a paid zap request, returns `None` if it isn't published in time.
+    pub async fn wait_for_zap_receipt(
+        &self,
+        zap_request: &Event,
+        invoice: &Bolt11Invoice,
+        nostr_pubkey: XOnlyPublicKey,
+    ) -> Result<Option<Event>, MutinyError> {
+        let client = Client::new(&Keys::generate());
+        client
+            .add_relays(ZAP_RELAYS.iter().map(|r| r.to_string()).collect::<Vec<_>>())
+            .await?;
+        client.connect().await;
+
+        let filter = Filter::new()
+            .kind(Kind::ZapReceipt)
+            .author(nostr_pubkey)
+            .since(zap_request.created_at);
+        client.subscribe(vec![filter]).await;
+
+        let mut notifications = client.notifications();
+        let start_time = utils::now();
+
+        loop {
+            if utils::now() - start_time > Duration::from_secs(ZAP_RECEIPT_TIMEOUT_SECS) {
+                client.disconnect().await?;
+                return Ok(None);
+            }
+
+            let read_fut = notifications.recv().fuse();
+            let delay_fut = Box::pin(utils::sleep(1_000)).fuse();
+
+            pin_mut!(read_fut, delay_fut);
+            select! {
+                notification = read_fut => {
+                    match notification {
+                        Ok(RelayPoolNotification::Event { event, .. }) => {
+                            if verify_zap_receipt(&event, zap_request, invoice, nostr_pubkey) {
+                                client.disconnect().await?;
+                                return Ok(Some(event));
+                            }
+                        }
+                        Ok(RelayPoolNotification::Shutdown) | Err(_) => {
+                            return Err(MutinyError::ConnectionFailed)
+                        }
+                        Ok(_) => {}
+                    }
+                }
+                _ = delay_fut => {
+                    if self.stop.load(Ordering::Relaxed) {
+                        client.disconnect().await?;
+                        return Err(MutinyError::NotRunning);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Derives the keys of our LNURL-pay server, they sign our zap receipts
+    /// and encrypt messages with an LNURL proxy.
+    pub fn derive_lnurl_keys(&self) -> Result<Keys, MutinyError> {
+        let context = Secp256k1::new();
+        Self::derive_nostr_key(&context, self.xprivkey, LNURL_ACCOUNT_INDEX, None, None)
+    }
+
     /// Derives the client and server keys for Nostr Wallet Connect given a profile index
     /// The left key is the client key and the right key is the server key
     pub(crate) fn derive_nwc_keys<C: Signing>(
@@ -1402,7 +1627,18 @@ mod test {
         .unwrap();
         block_on(nostr_manager.handle_direct_message(dm, &inv_handler)).unwrap();
         let pending = nostr_manager.get_pending_nwc_invoices().unwrap();
-        assert!(!pending.is_empty())
+        assert!(!pending.is_empty());
+
+        // NIP-44 dms can be decrypted too
+        let payload = encryption::encrypt(
+            EncryptionScheme::Nip44,
+            &user.secret_key().unwrap(),
+            &nostr_manager.public_key,
+            "gm",
+        )
+        .unwrap();
+        let decrypted = block_on(nostr_manager.decrypt_dm(user.public_key(), &payload)).unwrap();
+        assert_eq!(decrypted, "gm");
     }
 
     #[tokio::test]
@@ -1511,6 +1747,7 @@ mod test {
             spending_conditions: Default::default(),
             tag: Default::default(),
             label: None,
+            notifications: vec![],
         };
         let mut profiles = nostr_manager.nwc.write().unwrap();
         let nwc = NostrWalletConnect::new(
@@ -1705,6 +1942,7 @@ mod test {
             invoice: Bolt11Invoice::from_str("lnbc923720n1pj9nrefpp5pczykgk37af5388n8dzynljpkzs7sje4melqgazlwv9y3apay8jqhp5rd8saxz3juve3eejq7z5fjttxmpaq88d7l92xv34n4h3mq6kwq2qcqzzsxqzfvsp5z0jwpehkuz9f2kv96h62p8x30nku76aj8yddpcust7g8ad0tr52q9qyyssqfy622q25helv8cj8hyxqltws4rdwz0xx2hw0uh575mn7a76cp3q4jcptmtjkjs4a34dqqxn8uy70d0qlxqleezv4zp84uk30pp5q3nqq4c9gkz").unwrap(),
             event_id: EventId::from_slice(&[0; 32]).unwrap(),
             pubkey: XOnlyPublicKey::from_str("552a9d06810f306bfc085cb1e1c26102554138a51fa3a7fdf98f5b03a945143a").unwrap(),
+            encryption: EncryptionScheme::Nip04,
         };
 
         // add dummy to storage
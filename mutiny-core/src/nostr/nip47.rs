@@ -0,0 +1,454 @@
+//! NIP-47 requests and responses.
+//!
+//! Our nostr version only has the original NIP-47 methods, so the ones we support are defined
+//! here following the current spec. Types that did not change are re-exported from nostr.
+
+use core::fmt;
+use nostr::{JsonUtil, Timestamp};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+use std::str::FromStr;
+
+pub use nostr::nips::nip47::{
+    Error, ErrorCode, KeysendTLVRecord, LookupInvoiceRequestParams, MakeInvoiceResponseResult,
+    NIP47Error, NostrWalletConnectURI, PayInvoiceRequestParams, PayInvoiceResponseResult,
+};
+
+/// Method
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Method {
+    #[serde(rename = "pay_invoice")]
+    PayInvoice,
+    #[serde(rename = "multi_pay_invoice")]
+    MultiPayInvoice,
+    #[serde(rename = "pay_keysend")]
+    PayKeysend,
+    #[serde(rename = "multi_pay_keysend")]
+    MultiPayKeysend,
+    #[serde(rename = "make_invoice")]
+    MakeInvoice,
+    #[serde(rename = "lookup_invoice")]
+    LookupInvoice,
+    #[serde(rename = "list_transactions")]
+    ListTransactions,
+    #[serde(rename = "get_balance")]
+    GetBalance,
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Method::PayInvoice => write!(f, "pay_invoice"),
+            Method::MultiPayInvoice => write!(f, "multi_pay_invoice"),
+            Method::PayKeysend => write!(f, "pay_keysend"),
+            Method::MultiPayKeysend => write!(f, "multi_pay_keysend"),
+            Method::MakeInvoice => write!(f, "make_invoice"),
+            Method::LookupInvoice => write!(f, "lookup_invoice"),
+            Method::ListTransactions => write!(f, "list_transactions"),
+            Method::GetBalance => write!(f, "get_balance"),
+        }
+    }
+}
+
+impl FromStr for Method {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pay_invoice" => Ok(Method::PayInvoice),
+            "multi_pay_invoice" => Ok(Method::MultiPayInvoice),
+            "pay_keysend" => Ok(Method::PayKeysend),
+            "multi_pay_keysend" => Ok(Method::MultiPayKeysend),
+            "make_invoice" => Ok(Method::MakeInvoice),
+            "lookup_invoice" => Ok(Method::LookupInvoice),
+            "list_transactions" => Ok(Method::ListTransactions),
+            "get_balance" => Ok(Method::GetBalance),
+            _ => Err(Error::UnsupportedMethod(s.to_string())),
+        }
+    }
+}
+
+/// Params of a `multi_pay_invoice` request
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MultiPayInvoiceRequestParams {
+    pub invoices: Vec<PayInvoiceRequestParams>,
+}
+
+/// Params of a `pay_keysend` request
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PayKeysendRequestParams {
+    /// Amount in msats
+    pub amount: u64,
+    /// Node id of the recipient
+    pub pubkey: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preimage: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tlv_records: Vec<KeysendTLVRecord>,
+}
+
+/// Params of a `multi_pay_keysend` request
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MultiPayKeysendRequestParams {
+    pub keysends: Vec<PayKeysendRequestParams>,
+}
+
+/// Params of a `make_invoice` request
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MakeInvoiceRequestParams {
+    /// Amount in msats
+    pub amount: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description_hash: Option<String>,
+    /// Expiry in seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expiry: Option<u64>,
+}
+
+/// If a transaction was received or sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransactionType {
+    Incoming,
+    Outgoing,
+}
+
+/// Params of a `list_transactions` request
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ListTransactionsRequestParams {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<Timestamp>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub until: Option<Timestamp>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub offset: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unpaid: Option<bool>,
+    #[serde(default, rename = "type", skip_serializing_if = "Option::is_none")]
+    pub transaction_type: Option<TransactionType>,
+}
+
+/// Params of a request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RequestParams {
+    PayInvoice(PayInvoiceRequestParams),
+    MultiPayInvoice(MultiPayInvoiceRequestParams),
+    PayKeysend(PayKeysendRequestParams),
+    MultiPayKeysend(MultiPayKeysendRequestParams),
+    MakeInvoice(MakeInvoiceRequestParams),
+    LookupInvoice(LookupInvoiceRequestParams),
+    ListTransactions(ListTransactionsRequestParams),
+    GetBalance,
+}
+
+impl Serialize for RequestParams {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            RequestParams::PayInvoice(p) => p.serialize(serializer),
+            RequestParams::MultiPayInvoice(p) => p.serialize(serializer),
+            RequestParams::PayKeysend(p) => p.serialize(serializer),
+            RequestParams::MultiPayKeysend(p) => p.serialize(serializer),
+            RequestParams::MakeInvoice(p) => p.serialize(serializer),
+            RequestParams::LookupInvoice(p) => p.serialize(serializer),
+            RequestParams::ListTransactions(p) => p.serialize(serializer),
+            RequestParams::GetBalance => Value::Object(Default::default()).serialize(serializer),
+        }
+    }
+}
+
+/// A NIP-47 request
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct Request {
+    pub method: Method,
+    pub params: RequestParams,
+}
+
+#[derive(Deserialize)]
+struct RequestTemplate {
+    method: Method,
+    #[serde(default)]
+    params: Value,
+}
+
+impl Request {
+    pub fn from_value(value: Value) -> Result<Self, Error> {
+        let template: RequestTemplate = serde_json::from_value(value)?;
+        let params = template.params;
+
+        let params = match template.method {
+            Method::PayInvoice => RequestParams::PayInvoice(serde_json::from_value(params)?),
+            Method::MultiPayInvoice => {
+                RequestParams::MultiPayInvoice(serde_json::from_value(params)?)
+            }
+            Method::PayKeysend => RequestParams::PayKeysend(serde_json::from_value(params)?),
+            Method::MultiPayKeysend => {
+                RequestParams::MultiPayKeysend(serde_json::from_value(params)?)
+            }
+            Method::MakeInvoice => RequestParams::MakeInvoice(serde_json::from_value(params)?),
+            Method::LookupInvoice => RequestParams::LookupInvoice(serde_json::from_value(params)?),
+            Method::ListTransactions => {
+                // all of the params are optional
+                let params = if params.is_null() {
+                    ListTransactionsRequestParams::default()
+                } else {
+                    serde_json::from_value(params)?
+                };
+                RequestParams::ListTransactions(params)
+            }
+            Method::GetBalance => RequestParams::GetBalance,
+        };
+
+        Ok(Self {
+            method: template.method,
+            params,
+        })
+    }
+}
+
+impl JsonUtil for Request {
+    type Err = Error;
+}
+
+impl<'de> Deserialize<'de> for Request {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = Value::deserialize(deserializer)?;
+        Self::from_value(value).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Result of a `pay_keysend` request
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PayKeysendResponseResult {
+    pub preimage: String,
+}
+
+/// A transaction, the result of a `lookup_invoice` request
+/// and the items of a `list_transactions` result. Amounts are in msats.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LookupInvoiceResponseResult {
+    #[serde(default, rename = "type", skip_serializing_if = "Option::is_none")]
+    pub transaction_type: Option<TransactionType>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub invoice: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description_hash: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preimage: Option<String>,
+    pub payment_hash: String,
+    pub amount: u64,
+    pub fees_paid: u64,
+    pub created_at: Timestamp,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<Timestamp>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub settled_at: Option<Timestamp>,
+}
+
+/// Result of a `get_balance` request
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GetBalanceResponseResult {
+    /// Balance in msats
+    pub balance: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ListTransactionsTemplate<T> {
+    transactions: T,
+}
+
+/// Result of a request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResponseResult {
+    PayInvoice(PayInvoiceResponseResult),
+    MultiPayInvoice(PayInvoiceResponseResult),
+    PayKeysend(PayKeysendResponseResult),
+    MultiPayKeysend(PayKeysendResponseResult),
+    MakeInvoice(MakeInvoiceResponseResult),
+    LookupInvoice(LookupInvoiceResponseResult),
+    ListTransactions(Vec<LookupInvoiceResponseResult>),
+    GetBalance(GetBalanceResponseResult),
+}
+
+impl Serialize for ResponseResult {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            ResponseResult::PayInvoice(p) => p.serialize(serializer),
+            ResponseResult::MultiPayInvoice(p) => p.serialize(serializer),
+            ResponseResult::PayKeysend(p) => p.serialize(serializer),
+            ResponseResult::MultiPayKeysend(p) => p.serialize(serializer),
+            ResponseResult::MakeInvoice(p) => p.serialize(serializer),
+            ResponseResult::LookupInvoice(p) => p.serialize(serializer),
+            ResponseResult::ListTransactions(p) => {
+                ListTransactionsTemplate { transactions: p }.serialize(serializer)
+            }
+            ResponseResult::GetBalance(p) => p.serialize(serializer),
+        }
+    }
+}
+
+/// A NIP-47 response
+#[derive(Debug, Clone, Serialize)]
+pub struct Response {
+    pub result_type: Method,
+    pub error: Option<NIP47Error>,
+    pub result: Option<ResponseResult>,
+}
+
+#[derive(Deserialize)]
+struct ResponseTemplate {
+    result_type: Method,
+    error: Option<NIP47Error>,
+    result: Option<Value>,
+}
+
+impl Response {
+    pub fn from_value(value: Value) -> Result<Self, Error> {
+        let template: ResponseTemplate = serde_json::from_value(value)?;
+
+        let result = match template.result {
+            Some(result) => Some(match template.result_type {
+                Method::PayInvoice => ResponseResult::PayInvoice(serde_json::from_value(result)?),
+                Method::MultiPayInvoice => {
+                    ResponseResult::MultiPayInvoice(serde_json::from_value(result)?)
+                }
+                Method::PayKeysend => ResponseResult::PayKeysend(serde_json::from_value(result)?),
+                Method::MultiPayKeysend => {
+                    ResponseResult::MultiPayKeysend(serde_json::from_value(result)?)
+                }
+                Method::MakeInvoice => ResponseResult::MakeInvoice(serde_json::from_value(result)?),
+                Method::LookupInvoice => {
+                    ResponseResult::LookupInvoice(serde_json::from_value(result)?)
+                }
+                Method::ListTransactions => {
+                    let template: ListTransactionsTemplate<Vec<LookupInvoiceResponseResult>> =
+                        serde_json::from_value(result)?;
+                    ResponseResult::ListTransactions(template.transactions)
+                }
+                Method::GetBalance => ResponseResult::GetBalance(serde_json::from_value(result)?),
+            }),
+            None => None,
+        };
+
+        Ok(Self {
+            result_type: template.result_type,
+            error: template.error,
+            result,
+        })
+    }
+}
+
+impl JsonUtil for Response {
+    type Err = Error;
+}
+
+impl<'de> Deserialize<'de> for Response {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = Value::deserialize(deserializer)?;
+        Self::from_value(value).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::*;
+    use serde_json::json;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[test]
+    async fn test_parse_requests() {
+        let test_name = "test_parse_requests";
+        log!("{}", test_name);
+
+        let json = json!({
+            "method": "multi_pay_keysend",
+            "params": {
+                "keysends": [
+                    { "id": "1", "amount": 1000, "pubkey": "02aa" },
+                    {
+                        "amount": 2000,
+                        "pubkey": "02bb",
+                        "tlv_records": [{ "type": 696969, "value": "626f6f7374" }]
+                    }
+                ]
+            }
+        });
+        let request = Request::from_json(json.to_string()).unwrap();
+        assert_eq!(request.method, Method::MultiPayKeysend);
+        match request.params {
+            RequestParams::MultiPayKeysend(params) => {
+                assert_eq!(params.keysends.len(), 2);
+                assert_eq!(params.keysends[0].amount, 1_000);
+                assert_eq!(params.keysends[1].tlv_records[0].type_, 696969);
+            }
+            _ => panic!("wrong params"),
+        }
+
+        let json = json!({ "method": "list_transactions", "params": { "type": "incoming" } });
+        let request = Request::from_json(json.to_string()).unwrap();
+        assert_eq!(
+            request.params,
+            RequestParams::ListTransactions(ListTransactionsRequestParams {
+                transaction_type: Some(TransactionType::Incoming),
+                ..Default::default()
+            })
+        );
+
+        let json = json!({ "method": "get_balance", "params": {} });
+        let request = Request::from_json(json.to_string()).unwrap();
+        assert_eq!(request.params, RequestParams::GetBalance);
+        assert_eq!(Request::from_json(request.as_json()).unwrap(), request);
+
+        let json = json!({ "method": "get_info", "params": {} });
+        assert!(Request::from_json(json.to_string()).is_err());
+        assert_eq!(
+            Method::from_str("multi_pay_invoice").unwrap(),
+            Method::MultiPayInvoice
+        );
+    }
+
+    #[test]
+    async fn test_response_round_trip() {
+        let test_name = "test_response_round_trip";
+        log!("{}", test_name);
+
+        let transaction = LookupInvoiceResponseResult {
+            transaction_type: Some(TransactionType::Outgoing),
+            invoice: None,
+            description: None,
+            description_hash: None,
+            preimage: Some("00".repeat(32)),
+            payment_hash: "11".repeat(32),
+            amount: 21_000,
+            fees_paid: 1_000,
+            created_at: Timestamp::from(100),
+            expires_at: None,
+            settled_at: Some(Timestamp::from(110)),
+        };
+        let response = Response {
+            result_type: Method::ListTransactions,
+            error: None,
+            result: Some(ResponseResult::ListTransactions(vec![transaction.clone()])),
+        };
+
+        let value: Value = serde_json::from_str(&response.as_json()).unwrap();
+        assert_eq!(value["result"]["transactions"][0]["type"], "outgoing");
+        assert_eq!(value["result"]["transactions"][0]["amount"], 21_000);
+
+        let parsed = Response::from_json(response.as_json()).unwrap();
+        assert_eq!(parsed.result_type, Method::ListTransactions);
+        assert_eq!(
+            parsed.result,
+            Some(ResponseResult::ListTransactions(vec![transaction]))
+        );
+    }
+}
@@ -1,7 +1,7 @@
+use crate::nostr::nip47::{Error, Method};
 use core::fmt;
 use itertools::Itertools;
 use nostr::key::XOnlyPublicKey;
-use nostr::nips::nip47::{Error, Method};
 use nostr::prelude::form_urlencoded::byte_serialize;
 use nostr::Url;
 use serde::{Deserialize, Serialize};
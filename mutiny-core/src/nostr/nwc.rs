@@ -1,29 +1,43 @@
 use crate::error::MutinyError;
-use crate::event::HTLCStatus;
+use crate::event::{CustomTlv, HTLCStatus};
+use crate::nostr::encryption::{self, supported_schemes, EncryptionScheme, ENCRYPTION_TAG};
+use crate::nostr::nip47::*;
 use crate::nostr::nip49::NIP49Confirmation;
 use crate::nostr::NostrManager;
 use crate::storage::MutinyStorage;
 use crate::utils;
-use crate::InvoiceHandler;
-use anyhow::anyhow;
+use crate::{ActivityItem, InvoiceHandler, InvoiceOptions, MutinyInvoice};
 use bitcoin::hashes::hex::{FromHex, ToHex};
-use bitcoin::secp256k1::{Secp256k1, Signing, ThirtyTwoByteHash};
+use bitcoin::hashes::sha256;
+use bitcoin::secp256k1::{PublicKey, Secp256k1, Signing, ThirtyTwoByteHash};
 use bitcoin::util::bip32::ExtendedPrivKey;
 use chrono::{DateTime, Datelike, Duration, NaiveDateTime, Utc};
 use core::fmt;
+use itertools::Itertools;
 use lightning::util::logger::Logger;
 use lightning::{log_error, log_warn};
-use lightning_invoice::Bolt11Invoice;
+use lightning_invoice::{Bolt11Invoice, Bolt11InvoiceDescription};
 use nostr::key::XOnlyPublicKey;
-use nostr::nips::nip47::*;
-use nostr::prelude::{decrypt, encrypt};
-use nostr::{Event, EventBuilder, EventId, Filter, JsonUtil, Keys, Kind, Tag, Timestamp};
+use nostr::prelude::encrypt;
+use nostr::{Event, EventBuilder, EventId, Filter, JsonUtil, Keys, Kind, Tag, TagKind, Timestamp};
 use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
 use std::str::FromStr;
 
 pub(crate) const PENDING_NWC_EVENTS_KEY: &str = "pending_nwc_events";
 
+/// Nostr Wallet Connect methods we support, advertised in the info event
+pub(crate) const SUPPORTED_METHODS: [Method; 8] = [
+    Method::PayInvoice,
+    Method::MultiPayInvoice,
+    Method::PayKeysend,
+    Method::MultiPayKeysend,
+    Method::MakeInvoice,
+    Method::LookupInvoice,
+    Method::ListTransactions,
+    Method::GetBalance,
+];
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct SingleUseSpendingConditions {
     pub payment_hash: Option<String>,
@@ -69,19 +83,34 @@ pub struct BudgetedSpendingConditions {
 
 impl BudgetedSpendingConditions {
     pub fn add_payment(&mut self, invoice: &Bolt11Invoice) {
+        self.add_tracked_payment(
+            invoice.amount_milli_satoshis().unwrap_or_default() / 1_000,
+            invoice.payment_hash().to_hex(),
+        );
+    }
+
+    pub fn remove_payment(&mut self, invoice: &Bolt11Invoice) {
+        self.remove_tracked_payment(&invoice.payment_hash().to_hex());
+    }
+
+    /// Adds a payment of the given amount in sats, tracked by the given hash
+    pub fn add_tracked_payment(&mut self, amt: u64, hash: String) {
         let time = utils::now().as_secs();
-        let payment = TrackedPayment {
-            time,
-            amt: invoice.amount_milli_satoshis().unwrap_or_default() / 1_000,
-            hash: invoice.payment_hash().to_hex(),
-        };
+        let payment = TrackedPayment { time, amt, hash };
 
         self.payments.push(payment);
     }
 
-    pub fn remove_payment(&mut self, invoice: &Bolt11Invoice) {
-        self.payments
-            .retain(|p| p.hash != invoice.payment_hash().to_hex());
+    pub fn remove_tracked_payment(&mut self, hash: &str) {
+        self.payments.retain(|p| p.hash != hash);
+    }
+
+    /// Replaces the hash a payment is tracked by, used once
+    /// the real payment hash of a keysend is known
+    pub fn update_tracked_payment_hash(&mut self, old_hash: &str, new_hash: String) {
+        if let Some(payment) = self.payments.iter_mut().find(|p| p.hash == old_hash) {
+            payment.hash = new_hash;
+        }
     }
 
     fn clean_old_payments(&mut self, now: DateTime<Utc>) {
@@ -162,6 +191,51 @@ impl fmt::Display for NwcProfileTag {
     }
 }
 
+/// Kind of the events NIP-47 notifications are sent in
+pub(crate) const NWC_NOTIFICATION_KIND: u64 = 23196;
+
+/// NIP-47 notifications a profile can opt in to receiving
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NwcNotificationType {
+    PaymentReceived,
+    PaymentSent,
+}
+
+/// Notifications we support, advertised in the info event
+pub(crate) const SUPPORTED_NOTIFICATIONS: [NwcNotificationType; 2] = [
+    NwcNotificationType::PaymentReceived,
+    NwcNotificationType::PaymentSent,
+];
+
+impl fmt::Display for NwcNotificationType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::PaymentReceived => write!(f, "payment_received"),
+            Self::PaymentSent => write!(f, "payment_sent"),
+        }
+    }
+}
+
+impl FromStr for NwcNotificationType {
+    type Err = MutinyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "payment_received" => Ok(Self::PaymentReceived),
+            "payment_sent" => Ok(Self::PaymentSent),
+            _ => Err(MutinyError::InvalidArgumentsError),
+        }
+    }
+}
+
+/// Content of a NIP-47 notification event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Notification {
+    notification_type: NwcNotificationType,
+    notification: LookupInvoiceResponseResult,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub(crate) struct Profile {
     pub name: String,
@@ -183,6 +257,9 @@ pub(crate) struct Profile {
     pub tag: NwcProfileTag,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub label: Option<String>,
+    /// Notifications the client wants to receive, none unless it opts in
+    #[serde(default)]
+    pub notifications: Vec<NwcNotificationType>,
 }
 
 impl Profile {
@@ -276,8 +353,22 @@ impl NostrWalletConnect {
 
     /// Create Nostr Wallet Connect Info event
     pub fn create_nwc_info_event(&self) -> anyhow::Result<Event> {
-        let info = EventBuilder::new(Kind::WalletConnectInfo, "pay_invoice".to_string(), [])
-            .to_event(&self.server_key)?;
+        let content = SUPPORTED_METHODS.iter().map(|m| m.to_string()).join(" ");
+        // advertise the encryption schemes clients can pick from
+        let encryption_tag = Tag::Generic(
+            TagKind::Custom(ENCRYPTION_TAG.to_string()),
+            vec![supported_schemes()],
+        );
+        let notifications_tag = Tag::Generic(
+            TagKind::Custom("notifications".to_string()),
+            vec![SUPPORTED_NOTIFICATIONS.iter().join(" ")],
+        );
+        let info = EventBuilder::new(
+            Kind::WalletConnectInfo,
+            content,
+            [encryption_tag, notifications_tag],
+        )
+        .to_event(&self.server_key)?;
         Ok(info)
     }
 
@@ -308,28 +399,77 @@ impl NostrWalletConnect {
         Ok(Some(event))
     }
 
+    /// Create a NIP-47 notification event about a completed payment.
+    ///
+    /// Returns `None` if the profile isn't active or hasn't opted in to this notification.
+    pub(crate) fn create_notification_event(
+        &self,
+        invoice: &MutinyInvoice,
+    ) -> anyhow::Result<Option<Event>> {
+        let notification_type = if invoice.inbound {
+            NwcNotificationType::PaymentReceived
+        } else {
+            NwcNotificationType::PaymentSent
+        };
+        if !self.profile.active() || !self.profile.notifications.contains(&notification_type) {
+            return Ok(None);
+        }
+
+        let notification = Notification {
+            notification_type,
+            notification: lookup_invoice_result(invoice.clone()),
+        };
+        // notifications of this kind are always NIP-04
+        let content = encrypt(
+            &self.server_key.secret_key()?,
+            &self.client_pubkey(),
+            serde_json::to_string(&notification)?,
+        )?;
+        let p_tag = Tag::PublicKey {
+            public_key: self.client_pubkey(),
+            relay_url: None,
+            alias: None,
+            uppercase: false,
+        };
+        let event = EventBuilder::new(Kind::Custom(NWC_NOTIFICATION_KIND), content, [p_tag])
+            .to_event(&self.server_key)?;
+
+        Ok(Some(event))
+    }
+
+    /// Label used for payments made and invoices created through this profile
+    fn payment_label(&self) -> String {
+        self.profile
+            .label
+            .clone()
+            .unwrap_or(self.profile.name.clone())
+    }
+
     pub(crate) async fn pay_nwc_invoice(
         &self,
         node: &impl InvoiceHandler,
         invoice: &Bolt11Invoice,
     ) -> Result<Response, MutinyError> {
-        let label = self
-            .profile
-            .label
-            .clone()
-            .unwrap_or(self.profile.name.clone());
+        let label = self.payment_label();
         match node.pay_invoice(invoice, None, vec![label]).await {
-            Ok(inv) => {
-                // preimage should be set after a successful payment
-                let preimage = inv.preimage.expect("preimage not set");
-                Ok(Response {
+            // preimage should be set after a successful payment
+            Ok(inv) => match inv.preimage {
+                Some(preimage) => Ok(Response {
                     result_type: Method::PayInvoice,
                     error: None,
                     result: Some(ResponseResult::PayInvoice(PayInvoiceResponseResult {
                         preimage,
                     })),
-                })
-            }
+                }),
+                None => {
+                    log_error!(node.logger(), "payment succeeded without a preimage");
+                    Ok(error_response(
+                        Method::PayInvoice,
+                        ErrorCode::Internal,
+                        "Payment preimage not found".to_string(),
+                    ))
+                }
+            },
             Err(e) => {
                 log_error!(node.logger(), "failed to pay invoice: {e}");
                 Err(e)
@@ -340,33 +480,46 @@ impl NostrWalletConnect {
     async fn save_pending_nwc_invoice<S: MutinyStorage>(
         &self,
         nostr_manager: &NostrManager<S>,
-        event_id: EventId,
-        event_pk: XOnlyPublicKey,
+        event: &Event,
         invoice: Bolt11Invoice,
     ) -> anyhow::Result<()> {
+        let encryption = EncryptionScheme::from_request(event).unwrap_or_default();
         nostr_manager
-            .save_pending_nwc_invoice(Some(self.profile.index), event_id, event_pk, invoice)
+            .save_pending_nwc_invoice(
+                Some(self.profile.index),
+                event.id,
+                event.pubkey,
+                invoice,
+                encryption,
+            )
             .await
     }
 
     fn get_skipped_error_event(
         &self,
         event: &Event,
+        result_type: Method,
         error_code: ErrorCode,
         message: String,
+    ) -> anyhow::Result<Event> {
+        let content = error_response(result_type, error_code, message);
+        self.create_response_event(event, content, None)
+    }
+
+    /// Encrypts the response, with the scheme the request asked for,
+    /// and creates the response event for the given request.
+    /// Responses to `multi_*` requests are tagged with the id of the item they answer.
+    fn create_response_event(
+        &self,
+        event: &Event,
+        content: Response,
+        d_tag: Option<String>,
     ) -> anyhow::Result<Event> {
         let server_key = self.server_key.secret_key()?;
         let client_pubkey = self.client_key.public_key();
-        let content = Response {
-            result_type: Method::PayInvoice,
-            error: Some(NIP47Error {
-                code: error_code,
-                message,
-            }),
-            result: None,
-        };
-
-        let encrypted = encrypt(&server_key, &client_pubkey, content.as_json())?;
+        let scheme = EncryptionScheme::from_request(event).unwrap_or_default();
+        let encrypted =
+            encryption::encrypt(scheme, &server_key, &client_pubkey, content.as_json())?;
 
         let p_tag = Tag::PublicKey {
             public_key: event.pubkey,
@@ -379,7 +532,12 @@ impl NostrWalletConnect {
             relay_url: None,
             marker: None,
         };
-        let response = EventBuilder::new(Kind::WalletConnectResponse, encrypted, [p_tag, e_tag])
+        let mut tags = vec![p_tag, e_tag];
+        if let Some(id) = d_tag {
+            tags.push(Tag::Identifier(id));
+        }
+
+        let response = EventBuilder::new(Kind::WalletConnectResponse, encrypted, tags)
             .to_event(&self.server_key)?;
 
         Ok(response)
@@ -387,408 +545,810 @@ impl NostrWalletConnect {
 
     /// Handle a Nostr Wallet Connect request
     ///
-    /// Returns a response event if one is needed
+    /// Returns the response events that need to be broadcast,
+    /// `multi_*` requests get a response for each of their items.
     pub async fn handle_nwc_request<S: MutinyStorage>(
         &mut self,
         event: Event,
         node: &impl InvoiceHandler,
         nostr_manager: &NostrManager<S>,
-    ) -> anyhow::Result<Option<Event>> {
+    ) -> anyhow::Result<Vec<Event>> {
         let client_pubkey = self.client_key.public_key();
-        let mut needs_save = false;
-        let mut needs_delete = false;
-        if self.profile.active()
-            && event.kind == Kind::WalletConnectRequest
-            && event.pubkey == client_pubkey
+        if !self.profile.active()
+            || event.kind != Kind::WalletConnectRequest
+            || event.pubkey != client_pubkey
         {
-            let server_key = self.server_key.secret_key()?;
+            return Ok(vec![]);
+        }
 
-            let decrypted = decrypt(&server_key, &client_pubkey, &event.content)?;
-            let req: Request = match Request::from_json(decrypted) {
-                Ok(req) => req,
-                Err(e) => {
-                    log_warn!(
-                        nostr_manager.logger,
-                        "Failed to parse request: {e}, skipping..."
-                    );
-                    return self
-                        .get_skipped_error_event(
-                            &event,
-                            ErrorCode::NotImplemented,
-                            "Failed to parse request.".to_string(),
-                        )
-                        .map(Some);
-                }
-            };
+        if EncryptionScheme::from_request(&event).is_none() {
+            log_warn!(
+                nostr_manager.logger,
+                "Request uses an unsupported encryption scheme, skipping..."
+            );
+            return self
+                .get_skipped_error_event(
+                    &event,
+                    Method::PayInvoice,
+                    ErrorCode::NotImplemented,
+                    "Unsupported encryption.".to_string(),
+                )
+                .map(|e| vec![e]);
+        }
+
+        let server_key = self.server_key.secret_key()?;
 
-            // only respond to pay invoice requests
-            if req.method != Method::PayInvoice {
+        let decrypted = encryption::decrypt(&server_key, &client_pubkey, &event.content)?;
+        let req: Request = match Request::from_json(decrypted) {
+            Ok(req) => req,
+            Err(e) => {
+                log_warn!(
+                    nostr_manager.logger,
+                    "Failed to parse request: {e}, skipping..."
+                );
                 return self
                     .get_skipped_error_event(
                         &event,
+                        Method::PayInvoice,
                         ErrorCode::NotImplemented,
-                        "Command is not supported.".to_string(),
+                        "Failed to parse request.".to_string(),
                     )
-                    .map(Some);
+                    .map(|e| vec![e]);
             }
+        };
 
-            let invoice_str = match req.params {
-                RequestParams::PayInvoice(params) => params.invoice,
-                _ => return Err(anyhow!("Invalid request params for pay invoice")),
-            };
-
-            let invoice: Bolt11Invoice = match check_valid_nwc_invoice(&invoice_str, node).await {
-                Ok(Some(invoice)) => invoice,
-                Ok(None) => return Ok(None),
-                Err(err_string) => {
-                    return self
-                        .get_skipped_error_event(&event, ErrorCode::Other, err_string)
-                        .map(Some)
+        let mut responses = vec![];
+        match req.params {
+            RequestParams::PayInvoice(params) => {
+                if let Some(content) = self
+                    .handle_pay_invoice(&event, params.invoice, node, nostr_manager)
+                    .await?
+                {
+                    responses.push(self.create_response_event(&event, content, None)?);
                 }
-            };
-
-            // if we need approval, just save in the db for later
-            match self.profile.spending_conditions.clone() {
-                SpendingConditions::SingleUse(mut single_use) => {
-                    let msats = invoice.amount_milli_satoshis().unwrap();
-
-                    // get the status of the previous payment attempt, if one exists
-                    let prev_status: Option<HTLCStatus> = match single_use.payment_hash {
-                        Some(payment_hash) => {
-                            let hash: [u8; 32] =
-                                FromHex::from_hex(&payment_hash).expect("invalid hash");
-                            node.get_outbound_payment_status(&hash).await
+            }
+            RequestParams::MultiPayInvoice(params) => {
+                for params in params.invoices {
+                    // responses are identified by the payment hash of the invoice
+                    let d_tag = Bolt11Invoice::from_str(&params.invoice)
+                        .ok()
+                        .map(|i| i.payment_hash().to_hex());
+                    if let Some(mut content) = self
+                        .handle_pay_invoice(&event, params.invoice, node, nostr_manager)
+                        .await?
+                    {
+                        content.result_type = Method::MultiPayInvoice;
+                        content.result = match content.result.take() {
+                            Some(ResponseResult::PayInvoice(res)) => {
+                                Some(ResponseResult::MultiPayInvoice(res))
+                            }
+                            res => res,
+                        };
+                        responses.push(self.create_response_event(&event, content, d_tag)?);
+                    }
+                }
+            }
+            RequestParams::PayKeysend(params) => {
+                let content = self.handle_pay_keysend(params, node, nostr_manager).await?;
+                responses.push(self.create_response_event(&event, content, None)?);
+            }
+            RequestParams::MultiPayKeysend(params) => {
+                for params in params.keysends {
+                    // responses are identified by the pubkey of the recipient
+                    let d_tag = Some(params.pubkey.clone());
+                    let mut content = self.handle_pay_keysend(params, node, nostr_manager).await?;
+                    content.result_type = Method::MultiPayKeysend;
+                    content.result = match content.result.take() {
+                        Some(ResponseResult::PayKeysend(res)) => {
+                            Some(ResponseResult::MultiPayKeysend(res))
                         }
-                        None => None,
+                        res => res,
                     };
+                    responses.push(self.create_response_event(&event, content, d_tag)?);
+                }
+            }
+            RequestParams::MakeInvoice(params) => {
+                let content = self.handle_make_invoice(params, node).await;
+                responses.push(self.create_response_event(&event, content, None)?);
+            }
+            RequestParams::LookupInvoice(params) => {
+                let content = self.handle_lookup_invoice(params, node).await;
+                responses.push(self.create_response_event(&event, content, None)?);
+            }
+            RequestParams::ListTransactions(params) => {
+                let content = self.handle_list_transactions(params, node).await;
+                responses.push(self.create_response_event(&event, content, None)?);
+            }
+            RequestParams::GetBalance => {
+                let content = self.handle_get_balance(node).await;
+                responses.push(self.create_response_event(&event, content, None)?);
+            }
+        }
 
-                    // check if we have already spent
-                    let content = match prev_status {
-                        Some(HTLCStatus::Succeeded) => {
-                            needs_delete = true;
-                            Response {
-                                result_type: Method::PayInvoice,
-                                error: Some(NIP47Error {
-                                    code: ErrorCode::QuotaExceeded,
-                                    message: "Already Claimed".to_string(),
-                                }),
-                                result: None,
-                            }
+        Ok(responses)
+    }
+
+    /// Handles a `pay_invoice` request, or a single invoice of a `multi_pay_invoice` request,
+    /// according to the profile's spending conditions.
+    ///
+    /// Returns the response content if one is needed
+    async fn handle_pay_invoice<S: MutinyStorage>(
+        &mut self,
+        event: &Event,
+        invoice_str: String,
+        node: &impl InvoiceHandler,
+        nostr_manager: &NostrManager<S>,
+    ) -> anyhow::Result<Option<Response>> {
+        let invoice: Bolt11Invoice = match check_valid_nwc_invoice(&invoice_str, node).await {
+            Ok(Some(invoice)) => invoice,
+            Ok(None) => return Ok(None),
+            Err(err_string) => {
+                return Ok(Some(error_response(
+                    Method::PayInvoice,
+                    ErrorCode::Other,
+                    err_string,
+                )))
+            }
+        };
+
+        // amountless invoices are rejected when validating the invoice
+        let msats = match invoice.amount_milli_satoshis() {
+            Some(msats) => msats,
+            None => {
+                return Ok(Some(error_response(
+                    Method::PayInvoice,
+                    ErrorCode::Other,
+                    "Invoice amount not set".to_string(),
+                )))
+            }
+        };
+
+        // if we need approval, just save in the db for later
+        match self.profile.spending_conditions.clone() {
+            SpendingConditions::SingleUse(mut single_use) => {
+                let mut needs_save = false;
+                let mut needs_delete = false;
+
+                // get the status of the previous payment attempt, if one exists
+                let prev_status: Option<HTLCStatus> = match single_use.payment_hash.as_ref() {
+                    Some(payment_hash) => {
+                        let hash: [u8; 32] = FromHex::from_hex(payment_hash).expect("invalid hash");
+                        node.get_outbound_payment_status(&hash).await
+                    }
+                    None => None,
+                };
+
+                // check if we have already spent
+                let content = match prev_status {
+                    Some(HTLCStatus::Succeeded) => {
+                        needs_delete = true;
+                        Response {
+                            result_type: Method::PayInvoice,
+                            error: Some(NIP47Error {
+                                code: ErrorCode::QuotaExceeded,
+                                message: "Already Claimed".to_string(),
+                            }),
+                            result: None,
                         }
-                        None | Some(HTLCStatus::Failed) => {
-                            if msats <= single_use.amount_sats * 1_000 {
-                                match self.pay_nwc_invoice(node, &invoice).await {
-                                    Ok(resp) => {
-                                        // after it is spent, delete the profile
-                                        // so that it cannot be used again
-                                        needs_delete = true;
-                                        resp
-                                    }
-                                    Err(e) => {
-                                        let mut code = ErrorCode::InsufficientBalance;
-                                        if let MutinyError::PaymentTimeout = e {
-                                            // if a payment times out, we should save the payment_hash
-                                            // and track if the payment settles or not. If it does not
-                                            // we can try again later.
-                                            single_use.payment_hash =
-                                                Some(invoice.payment_hash().to_hex());
-                                            self.profile.spending_conditions =
-                                                SpendingConditions::SingleUse(single_use);
-                                            needs_save = true;
-
-                                            log_error!(
-                                                nostr_manager.logger,
-                                                "Payment timeout, saving profile for later"
-                                            );
-                                            code = ErrorCode::Internal;
-                                        } else {
-                                            // for non-timeout errors, add to manual approval list
-                                            self.save_pending_nwc_invoice(
-                                                nostr_manager,
-                                                event.id,
-                                                event.pubkey,
-                                                invoice,
-                                            )
+                    }
+                    None | Some(HTLCStatus::Failed) => {
+                        if msats <= single_use.amount_sats * 1_000 {
+                            match self.pay_nwc_invoice(node, &invoice).await {
+                                Ok(resp) => {
+                                    // after it is spent, delete the profile
+                                    // so that it cannot be used again
+                                    needs_delete = true;
+                                    // remember the payment in case more invoices
+                                    // from the same request try to claim it
+                                    single_use.payment_hash = Some(invoice.payment_hash().to_hex());
+                                    self.profile.spending_conditions =
+                                        SpendingConditions::SingleUse(single_use);
+                                    resp
+                                }
+                                Err(e) => {
+                                    let mut code = ErrorCode::InsufficientBalance;
+                                    if let MutinyError::PaymentTimeout = e {
+                                        // if a payment times out, we should save the payment_hash
+                                        // and track if the payment settles or not. If it does not
+                                        // we can try again later.
+                                        single_use.payment_hash =
+                                            Some(invoice.payment_hash().to_hex());
+                                        self.profile.spending_conditions =
+                                            SpendingConditions::SingleUse(single_use);
+                                        needs_save = true;
+
+                                        log_error!(
+                                            nostr_manager.logger,
+                                            "Payment timeout, saving profile for later"
+                                        );
+                                        code = ErrorCode::Internal;
+                                    } else {
+                                        // for non-timeout errors, add to manual approval list
+                                        self.save_pending_nwc_invoice(nostr_manager, event, invoice)
                                             .await?
-                                        }
-                                        Response {
-                                            result_type: Method::PayInvoice,
-                                            error: Some(NIP47Error {
-                                                code,
-                                                message: format!("Failed to pay invoice: {e}"),
-                                            }),
-                                            result: None,
-                                        }
                                     }
-                                }
-                            } else {
-                                log_warn!(
-                                    nostr_manager.logger,
-                                    "Invoice amount too high: {msats} msats"
-                                );
-
-                                Response {
-                                    result_type: Method::PayInvoice,
-                                    error: Some(NIP47Error {
-                                        code: ErrorCode::QuotaExceeded,
-                                        message: format!("Invoice amount too high: {msats} msats"),
-                                    }),
-                                    result: None,
+                                    Response {
+                                        result_type: Method::PayInvoice,
+                                        error: Some(NIP47Error {
+                                            code,
+                                            message: format!("Failed to pay invoice: {e}"),
+                                        }),
+                                        result: None,
+                                    }
                                 }
                             }
-                        }
-                        Some(HTLCStatus::Pending) | Some(HTLCStatus::InFlight) => {
+                        } else {
                             log_warn!(
                                 nostr_manager.logger,
-                                "Previous NWC payment still in flight, cannot pay: {invoice}"
+                                "Invoice amount too high: {msats} msats"
                             );
 
                             Response {
                                 result_type: Method::PayInvoice,
                                 error: Some(NIP47Error {
-                                    code: ErrorCode::RateLimited,
-                                    message: "Previous payment still in flight, cannot pay"
-                                        .to_string(),
+                                    code: ErrorCode::QuotaExceeded,
+                                    message: format!("Invoice amount too high: {msats} msats"),
                                 }),
                                 result: None,
                             }
                         }
-                    };
-
-                    let encrypted = encrypt(&server_key, &client_pubkey, content.as_json())?;
-
-                    let p_tag = Tag::PublicKey {
-                        public_key: event.pubkey,
-                        relay_url: None,
-                        alias: None,
-                        uppercase: false,
-                    };
-                    let e_tag = Tag::Event {
-                        event_id: event.id,
-                        relay_url: None,
-                        marker: None,
-                    };
-                    let response =
-                        EventBuilder::new(Kind::WalletConnectResponse, encrypted, [p_tag, e_tag])
-                            .to_event(&self.server_key)?;
-
-                    if needs_delete {
-                        nostr_manager.delete_nwc_profile(self.profile.index)?;
-                    } else if needs_save {
-                        nostr_manager.save_nwc_profile(self.clone())?;
                     }
-
-                    return Ok(Some(response));
-                }
-                SpendingConditions::RequireApproval => {
-                    self.save_pending_nwc_invoice(nostr_manager, event.id, event.pubkey, invoice)
-                        .await?;
-
-                    if needs_save {
-                        nostr_manager.save_nwc_profile(self.clone())?;
+                    Some(HTLCStatus::Pending) | Some(HTLCStatus::InFlight) => {
+                        log_warn!(
+                            nostr_manager.logger,
+                            "Previous NWC payment still in flight, cannot pay: {invoice}"
+                        );
+
+                        Response {
+                            result_type: Method::PayInvoice,
+                            error: Some(NIP47Error {
+                                code: ErrorCode::RateLimited,
+                                message: "Previous payment still in flight, cannot pay".to_string(),
+                            }),
+                            result: None,
+                        }
                     }
+                };
 
-                    return Ok(None);
+                if needs_delete {
+                    nostr_manager.delete_nwc_profile(self.profile.index)?;
+                } else if needs_save {
+                    nostr_manager.save_nwc_profile(self.clone())?;
                 }
-                SpendingConditions::Budget(mut budget) => {
-                    let sats = invoice.amount_milli_satoshis().unwrap() / 1_000;
-
-                    let budget_err = if budget.single_max.is_some_and(|max| sats > max) {
-                        Some("Invoice amount too high.")
-                    } else if budget.sum_payments() + sats > budget.budget {
-                        // budget might not actually be exceeded, we should verify that the payments
-                        // all went through, and if not, remove them from the budget
-                        let mut indices_to_remove = Vec::new();
-                        for (index, p) in budget.payments.iter().enumerate() {
-                            let hash: [u8; 32] = FromHex::from_hex(&p.hash)?;
-                            indices_to_remove.push((index, hash));
-                        }
 
-                        let futures: Vec<_> = indices_to_remove
-                            .iter()
-                            .map(|(index, hash)| async move {
-                                match node.get_outbound_payment_status(hash).await {
-                                    Some(HTLCStatus::Failed) => Some(*index),
-                                    _ => None,
-                                }
-                            })
-                            .collect();
+                Ok(Some(content))
+            }
+            SpendingConditions::RequireApproval => {
+                self.save_pending_nwc_invoice(nostr_manager, event, invoice)
+                    .await?;
 
-                        let results = futures::future::join_all(futures).await;
+                Ok(None)
+            }
+            SpendingConditions::Budget(mut budget) => {
+                let sats = msats / 1_000;
 
-                        // Remove failed payments
-                        for index in results.into_iter().flatten().rev() {
-                            budget.payments.remove(index);
-                        }
+                let budget_err = self.check_budget(&mut budget, sats, node).await?;
 
-                        // update budget with removed payments
+                let content = match budget_err {
+                    Some(err) => {
+                        log_warn!(nostr_manager.logger, "Attempted to exceed budget: {err}");
+                        // add to manual approval list
+                        self.save_pending_nwc_invoice(nostr_manager, event, invoice)
+                            .await?;
+                        Response {
+                            result_type: Method::PayInvoice,
+                            error: Some(NIP47Error {
+                                code: ErrorCode::QuotaExceeded,
+                                message: err.to_string(),
+                            }),
+                            result: None,
+                        }
+                    }
+                    None => {
+                        // add payment to budget
+                        budget.add_payment(&invoice);
                         self.profile.spending_conditions =
                             SpendingConditions::Budget(budget.clone());
+                        // persist budget before payment to protect against it not saving after
+                        nostr_manager.save_nwc_profile(self.clone())?;
 
-                        // try again with cleaned budget
-                        if budget.sum_payments() + sats > budget.budget {
-                            Some("Budget exceeded.")
-                        } else {
-                            None
-                        }
-                    } else {
-                        None
-                    };
+                        // attempt to pay invoice
+                        match self.pay_nwc_invoice(node, &invoice).await {
+                            Ok(resp) => resp,
+                            Err(e) => {
+                                // remove payment if it failed
+                                match e {
+                                    MutinyError::PaymentTimeout => {
+                                        log_warn!(
+                                            nostr_manager.logger,
+                                            "Payment timeout, not removing payment from budget"
+                                        );
+                                    }
+                                    MutinyError::NonUniquePaymentHash => {
+                                        log_warn!(
+                                            nostr_manager.logger,
+                                            "Already paid invoice, removing payment from budget"
+                                        );
+                                        budget.remove_payment(&invoice);
+                                        self.profile.spending_conditions =
+                                            SpendingConditions::Budget(budget);
+
+                                        nostr_manager.save_nwc_profile(self.clone())?;
+
+                                        // don't save to pending list, we already paid it
+                                        return Ok(None);
+                                    }
+                                    _ => {
+                                        log_warn!(
+                                            nostr_manager.logger,
+                                            "Failed to pay invoice: {e}, removing payment from budget, adding to manual approval list"
+                                        );
 
-                    let content = match budget_err {
-                        Some(err) => {
-                            log_warn!(nostr_manager.logger, "Attempted to exceed budget: {err}");
-                            // add to manual approval list
-                            self.save_pending_nwc_invoice(
-                                nostr_manager,
-                                event.id,
-                                event.pubkey,
-                                invoice,
-                            )
-                            .await?;
-                            Response {
-                                result_type: Method::PayInvoice,
-                                error: Some(NIP47Error {
-                                    code: ErrorCode::QuotaExceeded,
-                                    message: err.to_string(),
-                                }),
-                                result: None,
-                            }
-                        }
-                        None => {
-                            // add payment to budget
-                            budget.add_payment(&invoice);
-                            self.profile.spending_conditions =
-                                SpendingConditions::Budget(budget.clone());
-                            // persist budget before payment to protect against it not saving after
-                            nostr_manager.save_nwc_profile(self.clone())?;
-
-                            // attempt to pay invoice
-                            match self.pay_nwc_invoice(node, &invoice).await {
-                                Ok(resp) => resp,
-                                Err(e) => {
-                                    // remove payment if it failed
-                                    match e {
-                                        MutinyError::PaymentTimeout => {
-                                            log_warn!(
-                                                nostr_manager.logger,
-                                                "Payment timeout, not removing payment from budget"
-                                            );
-                                        }
-                                        MutinyError::NonUniquePaymentHash => {
-                                            log_warn!(
-                                                nostr_manager.logger,
-                                                "Already paid invoice, removing payment from budget"
-                                            );
-                                            budget.remove_payment(&invoice);
-                                            self.profile.spending_conditions =
-                                                SpendingConditions::Budget(budget);
-
-                                            nostr_manager.save_nwc_profile(self.clone())?;
-
-                                            // don't save to pending list, we already paid it
-                                            return Ok(None);
-                                        }
-                                        _ => {
-                                            log_warn!(
-                                                nostr_manager.logger,
-                                                "Failed to pay invoice: {e}, removing payment from budget, adding to manual approval list"
-                                            );
-
-                                            budget.remove_payment(&invoice);
-                                            self.profile.spending_conditions =
-                                                SpendingConditions::Budget(budget.clone());
-
-                                            nostr_manager.save_nwc_profile(self.clone())?;
-
-                                            // for non-timeout errors, add to manual approval list
-                                            self.save_pending_nwc_invoice(
-                                                nostr_manager,
-                                                event.id,
-                                                event.pubkey,
-                                                invoice,
-                                            )
+                                        budget.remove_payment(&invoice);
+                                        self.profile.spending_conditions =
+                                            SpendingConditions::Budget(budget.clone());
+
+                                        nostr_manager.save_nwc_profile(self.clone())?;
+
+                                        // for non-timeout errors, add to manual approval list
+                                        self.save_pending_nwc_invoice(nostr_manager, event, invoice)
                                             .await?
-                                        }
                                     }
+                                }
 
-                                    Response {
-                                        result_type: Method::PayInvoice,
-                                        error: Some(NIP47Error {
-                                            code: ErrorCode::InsufficientBalance,
-                                            message: format!("Failed to pay invoice: {e}"),
-                                        }),
-                                        result: None,
-                                    }
+                                Response {
+                                    result_type: Method::PayInvoice,
+                                    error: Some(NIP47Error {
+                                        code: ErrorCode::InsufficientBalance,
+                                        message: format!("Failed to pay invoice: {e}"),
+                                    }),
+                                    result: None,
                                 }
                             }
                         }
-                    };
+                    }
+                };
 
-                    let encrypted = encrypt(&server_key, &client_pubkey, content.as_json())?;
+                Ok(Some(content))
+            }
+        }
+    }
 
-                    let p_tag = Tag::PublicKey {
-                        public_key: event.pubkey,
-                        relay_url: None,
-                        alias: None,
-                        uppercase: false,
-                    };
-                    let e_tag = Tag::Event {
-                        event_id: event.id,
-                        relay_url: None,
-                        marker: None,
-                    };
-                    let response =
-                        EventBuilder::new(Kind::WalletConnectResponse, encrypted, [p_tag, e_tag])
-                            .to_event(&self.server_key)?;
+    /// Checks if a payment of the given amount fits in the budget.
+    ///
+    /// Returns the reason if it does not.
+    async fn check_budget(
+        &mut self,
+        budget: &mut BudgetedSpendingConditions,
+        sats: u64,
+        node: &impl InvoiceHandler,
+    ) -> anyhow::Result<Option<&'static str>> {
+        if budget.single_max.is_some_and(|max| sats > max) {
+            return Ok(Some("Invoice amount too high."));
+        }
 
-                    return Ok(Some(response));
-                }
-            }
+        if budget.sum_payments() + sats <= budget.budget {
+            return Ok(None);
         }
 
-        if needs_delete {
-            nostr_manager.delete_nwc_profile(self.profile.index)?;
-        } else if needs_save {
-            nostr_manager.save_nwc_profile(self.clone())?;
+        // budget might not actually be exceeded, we should verify that the payments
+        // all went through, and if not, remove them from the budget
+        let mut indices_to_remove = Vec::new();
+        for (index, p) in budget.payments.iter().enumerate() {
+            let hash: [u8; 32] = FromHex::from_hex(&p.hash)?;
+            indices_to_remove.push((index, hash));
         }
 
-        Ok(None)
-    }
+        let futures: Vec<_> = indices_to_remove
+            .iter()
+            .map(|(index, hash)| async move {
+                match node.get_outbound_payment_status(hash).await {
+                    Some(HTLCStatus::Failed) => Some(*index),
+                    _ => None,
+                }
+            })
+            .collect();
 
-    pub fn nwc_profile(&self) -> NwcProfile {
-        NwcProfile {
-            name: self.profile.name.clone(),
-            index: self.profile.index,
-            client_key: self.profile.client_key,
-            relay: self.profile.relay.clone(),
-            enabled: self.profile.enabled,
-            archived: self.profile.archived,
-            nwc_uri: self
-                .get_nwc_uri()
-                .expect("failed to get nwc uri")
-                .map(|uri| uri.to_string()),
-            spending_conditions: self.profile.spending_conditions.clone(),
-            child_key_index: self.profile.child_key_index,
-            tag: self.profile.tag,
-            label: self.profile.label.clone(),
+        let results = futures::future::join_all(futures).await;
+
+        // Remove failed payments
+        for index in results.into_iter().flatten().rev() {
+            budget.payments.remove(index);
+        }
+
+        // update budget with removed payments
+        self.profile.spending_conditions = SpendingConditions::Budget(budget.clone());
+
+        // try again with cleaned budget
+        if budget.sum_payments() + sats > budget.budget {
+            Ok(Some("Budget exceeded."))
+        } else {
+            Ok(None)
         }
     }
-}
 
-/// Struct for externally exposing a nostr wallet connect profile
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
-pub struct NwcProfile {
-    pub name: String,
-    pub index: u32,
-    /// Public Key given in a Nostr Wallet Auth URI.
-    /// This will only be defined for profiles created through Nostr Wallet Auth.
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub client_key: Option<XOnlyPublicKey>,
-    pub relay: String,
-    pub enabled: Option<bool>,
-    pub archived: Option<bool>,
-    /// Nostr Wallet Connect URI
+    /// Handles a `pay_keysend` request, or a single keysend of a `multi_pay_keysend` request.
+    ///
+    /// Keysends cannot be put in the manual approval list, so they are only
+    /// allowed for profiles with a budget.
+    async fn handle_pay_keysend<S: MutinyStorage>(
+        &mut self,
+        params: PayKeysendRequestParams,
+        node: &impl InvoiceHandler,
+        nostr_manager: &NostrManager<S>,
+    ) -> anyhow::Result<Response> {
+        let Ok(pubkey) = PublicKey::from_str(&params.pubkey) else {
+            return Ok(error_response(
+                Method::PayKeysend,
+                ErrorCode::Other,
+                "Invalid pubkey".to_string(),
+            ));
+        };
+
+        if params.preimage.is_some() {
+            return Ok(error_response(
+                Method::PayKeysend,
+                ErrorCode::NotImplemented,
+                "Custom preimages are not supported.".to_string(),
+            ));
+        }
+
+        // TLV values are hex encoded
+        let custom_tlvs = match params
+            .tlv_records
+            .iter()
+            .map(|r| {
+                Vec::<u8>::from_hex(&r.value).map(|value| CustomTlv {
+                    tlv_type: r.type_,
+                    value,
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()
+        {
+            Ok(custom_tlvs) => custom_tlvs,
+            Err(_) => {
+                return Ok(error_response(
+                    Method::PayKeysend,
+                    ErrorCode::Other,
+                    "Invalid TLV record value".to_string(),
+                ));
+            }
+        };
+
+        let sats = params.amount / 1_000;
+        if sats == 0 {
+            return Ok(error_response(
+                Method::PayKeysend,
+                ErrorCode::Other,
+                "Amount too low".to_string(),
+            ));
+        }
+
+        let mut budget = match self.profile.spending_conditions.clone() {
+            SpendingConditions::Budget(budget) => budget,
+            SpendingConditions::SingleUse(_) | SpendingConditions::RequireApproval => {
+                return Ok(error_response(
+                    Method::PayKeysend,
+                    ErrorCode::Restricted,
+                    "Keysend is only available for profiles with a budget.".to_string(),
+                ));
+            }
+        };
+
+        if let Some(err) = self.check_budget(&mut budget, sats, node).await? {
+            log_warn!(nostr_manager.logger, "Attempted to exceed budget: {err}");
+            return Ok(error_response(
+                Method::PayKeysend,
+                ErrorCode::QuotaExceeded,
+                err.to_string(),
+            ));
+        }
+
+        // we don't know the payment hash until the payment is made,
+        // so track the payment under a random one until then
+        let mut entropy = [0u8; 32];
+        getrandom::getrandom(&mut entropy).map_err(|_| MutinyError::SeedGenerationFailed)?;
+        let placeholder = entropy.to_hex();
+        budget.add_tracked_payment(sats, placeholder.clone());
+        self.profile.spending_conditions = SpendingConditions::Budget(budget.clone());
+        // persist budget before payment to protect against it not saving after
+        nostr_manager.save_nwc_profile(self.clone())?;
+
+        let label = self.payment_label();
+        match node
+            .keysend(pubkey, sats, None, custom_tlvs, vec![label])
+            .await
+        {
+            Ok(inv) => {
+                budget.update_tracked_payment_hash(&placeholder, inv.payment_hash.to_hex());
+                self.profile.spending_conditions = SpendingConditions::Budget(budget);
+                nostr_manager.save_nwc_profile(self.clone())?;
+
+                // preimage should be set after a successful payment
+                match inv.preimage {
+                    Some(preimage) => Ok(Response {
+                        result_type: Method::PayKeysend,
+                        error: None,
+                        result: Some(ResponseResult::PayKeysend(PayKeysendResponseResult {
+                            preimage,
+                        })),
+                    }),
+                    None => {
+                        log_error!(nostr_manager.logger, "keysend succeeded without a preimage");
+                        Ok(error_response(
+                            Method::PayKeysend,
+                            ErrorCode::Internal,
+                            "Payment preimage not found".to_string(),
+                        ))
+                    }
+                }
+            }
+            Err(e) => {
+                if let MutinyError::PaymentTimeout = e {
+                    log_warn!(
+                        nostr_manager.logger,
+                        "Keysend timeout, not removing payment from budget"
+                    );
+                } else {
+                    log_warn!(
+                        nostr_manager.logger,
+                        "Failed to keysend: {e}, removing payment from budget"
+                    );
+                    budget.remove_tracked_payment(&placeholder);
+                    self.profile.spending_conditions = SpendingConditions::Budget(budget);
+                    nostr_manager.save_nwc_profile(self.clone())?;
+                }
+
+                Ok(error_response(
+                    Method::PayKeysend,
+                    ErrorCode::InsufficientBalance,
+                    format!("Failed to keysend: {e}"),
+                ))
+            }
+        }
+    }
+
+    /// Handles a `make_invoice` request. Receiving does not spend from the profile,
+    /// so this is allowed regardless of the spending conditions.
+    async fn handle_make_invoice(
+        &self,
+        params: MakeInvoiceRequestParams,
+        node: &impl InvoiceHandler,
+    ) -> Response {
+        let amount = Some(params.amount / 1_000).filter(|a| *a > 0);
+        let description_hash = match params.description_hash {
+            Some(hash) => match sha256::Hash::from_hex(&hash) {
+                Ok(hash) => Some(hash),
+                Err(_) => {
+                    return error_response(
+                        Method::MakeInvoice,
+                        ErrorCode::Other,
+                        "Invalid description hash".to_string(),
+                    )
+                }
+            },
+            None => None,
+        };
+        // an invoice can only have one of them, the hash commits to the description
+        let description = params.description.filter(|_| description_hash.is_none());
+        let options = InvoiceOptions {
+            description,
+            description_hash,
+            expiry_secs: params.expiry.map(|e| e.try_into().unwrap_or(u32::MAX)),
+        };
+
+        let label = self.payment_label();
+        let invoice = match node
+            .create_invoice_with_options(amount, vec![label], options)
+            .await
+        {
+            Ok(inv) => inv.bolt11,
+            Err(e) => {
+                log_error!(node.logger(), "failed to create invoice: {e}");
+                return error_response(
+                    Method::MakeInvoice,
+                    ErrorCode::Internal,
+                    format!("Failed to create invoice: {e}"),
+                );
+            }
+        };
+
+        match invoice {
+            Some(invoice) => Response {
+                result_type: Method::MakeInvoice,
+                error: None,
+                result: Some(ResponseResult::MakeInvoice(MakeInvoiceResponseResult {
+                    invoice: invoice.to_string(),
+                    payment_hash: invoice.payment_hash().to_hex(),
+                })),
+            },
+            None => error_response(
+                Method::MakeInvoice,
+                ErrorCode::Internal,
+                "Failed to create invoice".to_string(),
+            ),
+        }
+    }
+
+    /// Handles a `lookup_invoice` request.
+    /// Only payments made or received through this profile can be looked up.
+    async fn handle_lookup_invoice(
+        &self,
+        params: LookupInvoiceRequestParams,
+        node: &impl InvoiceHandler,
+    ) -> Response {
+        let hash = match (params.payment_hash, params.bolt11) {
+            (Some(hash), _) => sha256::Hash::from_hex(&hash).ok(),
+            (None, Some(bolt11)) => Bolt11Invoice::from_str(&bolt11)
+                .ok()
+                .map(|i| *i.payment_hash()),
+            (None, None) => None,
+        };
+
+        let Some(hash) = hash else {
+            return error_response(
+                Method::LookupInvoice,
+                ErrorCode::Other,
+                "Invalid payment hash or invoice".to_string(),
+            );
+        };
+
+        let label = self.payment_label();
+        match node.get_invoice_by_hash(&hash).await {
+            Ok(invoice) if invoice.labels.contains(&label) => Response {
+                result_type: Method::LookupInvoice,
+                error: None,
+                result: Some(ResponseResult::LookupInvoice(lookup_invoice_result(
+                    invoice,
+                ))),
+            },
+            _ => error_response(
+                Method::LookupInvoice,
+                ErrorCode::Other,
+                "Invoice not found".to_string(),
+            ),
+        }
+    }
+
+    /// Handles a `list_transactions` request.
+    /// Only payments made or received through this profile are listed.
+    async fn handle_list_transactions(
+        &self,
+        params: ListTransactionsRequestParams,
+        node: &impl InvoiceHandler,
+    ) -> Response {
+        let activity = match node.get_activity().await {
+            Ok(activity) => activity,
+            Err(e) => {
+                log_error!(node.logger(), "failed to get activity: {e}");
+                return error_response(
+                    Method::ListTransactions,
+                    ErrorCode::Internal,
+                    format!("Failed to list transactions: {e}"),
+                );
+            }
+        };
+
+        let label = self.payment_label();
+        let from = params.from.map(|t| t.as_u64());
+        let until = params.until.map(|t| t.as_u64());
+        let offset = params.offset.unwrap_or_default() as usize;
+        let limit = params.limit.map(|l| l as usize).unwrap_or(usize::MAX);
+
+        // activity only contains settled and in-flight payments,
+        // so unpaid invoices are never included
+        let transactions = activity
+            .into_iter()
+            .filter_map(|item| match item {
+                ActivityItem::Lightning(invoice) => Some(*invoice),
+                ActivityItem::OnChain(_)
+                | ActivityItem::ChannelClosed(_)
+                | ActivityItem::FederationOnchain(_) => None,
+            })
+            .filter(|invoice| invoice.labels.contains(&label))
+            .filter(|invoice| match params.transaction_type {
+                Some(TransactionType::Incoming) => invoice.inbound,
+                Some(TransactionType::Outgoing) => !invoice.inbound,
+                None => true,
+            })
+            .filter(|invoice| from.map_or(true, |from| invoice.last_updated >= from))
+            .filter(|invoice| until.map_or(true, |until| invoice.last_updated <= until))
+            .skip(offset)
+            .take(limit)
+            .map(lookup_invoice_result)
+            .collect();
+
+        Response {
+            result_type: Method::ListTransactions,
+            error: None,
+            result: Some(ResponseResult::ListTransactions(transactions)),
+        }
+    }
+
+    /// Handles a `get_balance` request.
+    /// The balance is what this profile is able to spend, in msats.
+    async fn handle_get_balance(&self, node: &impl InvoiceHandler) -> Response {
+        let balance = match node.get_balance().await {
+            Ok(balance) => balance,
+            Err(e) => {
+                log_error!(node.logger(), "failed to get balance: {e}");
+                return error_response(
+                    Method::GetBalance,
+                    ErrorCode::Internal,
+                    format!("Failed to get balance: {e}"),
+                );
+            }
+        };
+
+        // only lightning and federation funds can be spent over nwc
+        let wallet_msats = (balance.lightning + balance.federation) * 1_000;
+
+        let msats = match &self.profile.spending_conditions {
+            SpendingConditions::RequireApproval => wallet_msats,
+            SpendingConditions::Budget(budget) => {
+                wallet_msats.min(budget.budget_remaining() * 1_000)
+            }
+            SpendingConditions::SingleUse(single_use) => {
+                let claimed = match single_use.payment_hash.as_ref() {
+                    Some(payment_hash) => match <[u8; 32]>::from_hex(payment_hash) {
+                        Ok(hash) => node
+                            .get_outbound_payment_status(&hash)
+                            .await
+                            .is_some_and(|status| status == HTLCStatus::Succeeded),
+                        Err(_) => false,
+                    },
+                    None => false,
+                };
+
+                if claimed {
+                    0
+                } else {
+                    wallet_msats.min(single_use.amount_sats * 1_000)
+                }
+            }
+        };
+
+        Response {
+            result_type: Method::GetBalance,
+            error: None,
+            result: Some(ResponseResult::GetBalance(GetBalanceResponseResult {
+                balance: msats,
+            })),
+        }
+    }
+
+    pub fn nwc_profile(&self) -> NwcProfile {
+        NwcProfile {
+            name: self.profile.name.clone(),
+            index: self.profile.index,
+            client_key: self.profile.client_key,
+            relay: self.profile.relay.clone(),
+            enabled: self.profile.enabled,
+            archived: self.profile.archived,
+            // only fails if the relay is not a valid url
+            nwc_uri: self.get_nwc_uri().ok().flatten().map(|uri| uri.to_string()),
+            spending_conditions: self.profile.spending_conditions.clone(),
+            child_key_index: self.profile.child_key_index,
+            tag: self.profile.tag,
+            label: self.profile.label.clone(),
+            notifications: self.profile.notifications.clone(),
+        }
+    }
+}
+
+/// Struct for externally exposing a nostr wallet connect profile
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct NwcProfile {
+    pub name: String,
+    pub index: u32,
+    /// Public Key given in a Nostr Wallet Auth URI.
+    /// This will only be defined for profiles created through Nostr Wallet Auth.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_key: Option<XOnlyPublicKey>,
+    pub relay: String,
+    pub enabled: Option<bool>,
+    pub archived: Option<bool>,
+    /// Nostr Wallet Connect URI
     /// This will only be defined for profiles created manually.
     pub nwc_uri: Option<String>,
     #[serde(default)]
@@ -799,6 +1359,8 @@ pub struct NwcProfile {
     pub tag: NwcProfileTag,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub label: Option<String>,
+    #[serde(default)]
+    pub notifications: Vec<NwcNotificationType>,
 }
 
 impl NwcProfile {
@@ -814,6 +1376,7 @@ impl NwcProfile {
             child_key_index: self.child_key_index,
             tag: self.tag,
             label: self.label.clone(),
+            notifications: self.notifications.clone(),
         }
     }
 }
@@ -831,6 +1394,9 @@ pub struct PendingNwcInvoice {
     /// The nostr pubkey of the request
     /// If this is a DM, this is who sent us the request
     pub pubkey: XOnlyPublicKey,
+    /// How the response to the request is encrypted
+    #[serde(default)]
+    pub encryption: EncryptionScheme,
 }
 
 impl PartialOrd for PendingNwcInvoice {
@@ -851,6 +1417,49 @@ impl PendingNwcInvoice {
     }
 }
 
+fn error_response(result_type: Method, code: ErrorCode, message: String) -> Response {
+    Response {
+        result_type,
+        error: Some(NIP47Error { code, message }),
+        result: None,
+    }
+}
+
+/// Converts a payment into the format used by `lookup_invoice` and `list_transactions`.
+/// Amounts are in msats.
+fn lookup_invoice_result(invoice: MutinyInvoice) -> LookupInvoiceResponseResult {
+    let description_hash = invoice.bolt11.as_ref().and_then(|b| match b.description() {
+        Bolt11InvoiceDescription::Hash(h) => Some(h.0.to_hex()),
+        Bolt11InvoiceDescription::Direct(_) => None,
+    });
+    let created_at = invoice
+        .bolt11
+        .as_ref()
+        .map(|b| b.duration_since_epoch().as_secs())
+        .unwrap_or(invoice.last_updated);
+    let settled_at =
+        (invoice.status == HTLCStatus::Succeeded).then(|| Timestamp::from(invoice.last_updated));
+    let transaction_type = if invoice.inbound {
+        TransactionType::Incoming
+    } else {
+        TransactionType::Outgoing
+    };
+
+    LookupInvoiceResponseResult {
+        transaction_type: Some(transaction_type),
+        invoice: invoice.bolt11.map(|b| b.to_string()),
+        description: invoice.description,
+        description_hash,
+        preimage: invoice.preimage,
+        payment_hash: invoice.payment_hash.to_hex(),
+        amount: invoice.amount_sats.unwrap_or_default() * 1_000,
+        fees_paid: invoice.fees_paid.unwrap_or_default() * 1_000,
+        created_at: Timestamp::from(created_at),
+        expires_at: Some(Timestamp::from(invoice.expire)),
+        settled_at,
+    }
+}
+
 /// Checks if it is a valid invoice
 /// Return an error string if invalid
 /// Otherwise returns an optional invoice that should be processed
@@ -1178,14 +1787,18 @@ mod wasm_test {
     use crate::logging::MutinyLogger;
     use crate::nostr::{NostrKeySource, ProfileType};
     use crate::storage::MemoryStorage;
-    use crate::test_utils::{create_dummy_invoice, create_mutiny_wallet, create_nwc_request};
+    use crate::test_utils::{
+        create_dummy_invoice, create_mutiny_wallet, create_nwc_request, create_nwc_request_event,
+    };
     use crate::MockInvoiceHandler;
-    use crate::MutinyInvoice;
+    use crate::{MutinyBalance, MutinyInvoice};
     use bitcoin::secp256k1::ONE_KEY;
     use bitcoin::Network;
     use mockall::predicate::eq;
     use nostr::key::SecretKey;
+    use nostr::prelude::decrypt;
     use serde_json::json;
+    use std::collections::HashMap;
     use std::sync::{atomic::AtomicBool, Arc};
     use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
 
@@ -1201,10 +1814,20 @@ mod wasm_test {
         assert_eq!(pending.len(), 0);
     }
 
-    fn check_nwc_error_response(event: Event, sk: &SecretKey, expected: NIP47Error) {
+    fn single_response(result: anyhow::Result<Vec<Event>>) -> Event {
+        let mut events = result.unwrap();
+        assert_eq!(events.len(), 1);
+        events.remove(0)
+    }
+
+    fn decrypt_response(event: Event, sk: &SecretKey) -> Response {
         assert_eq!(event.kind, Kind::WalletConnectResponse);
-        let decrypted = decrypt(sk, &event.pubkey, &event.content).unwrap();
-        let resp: Response = Response::from_json(decrypted).unwrap();
+        let decrypted = encryption::decrypt(sk, &event.pubkey, &event.content).unwrap();
+        Response::from_json(decrypted).unwrap()
+    }
+
+    fn check_nwc_error_response(event: Event, sk: &SecretKey, expected: NIP47Error) {
+        let resp = decrypt_response(event, sk);
         let error = resp.error.unwrap();
         // need to compare json strings because the error code does not implement PartialEq
         assert_eq!(
@@ -1253,7 +1876,7 @@ mod wasm_test {
         let result = nwc
             .handle_nwc_request(event.clone(), &mw, &nostr_manager)
             .await;
-        assert_eq!(result.unwrap(), None);
+        assert!(result.unwrap().is_empty());
 
         let pending: Vec<PendingNwcInvoice> = storage
             .get_data(PENDING_NWC_EVENTS_KEY)
@@ -1266,6 +1889,113 @@ mod wasm_test {
         assert_eq!(pending[0].pubkey, event.pubkey);
     }
 
+    #[test]
+    async fn test_nwc_nip44_encryption() {
+        let storage = MemoryStorage::default();
+        let logger = Arc::new(MutinyLogger::default());
+        let mut node = MockInvoiceHandler::new();
+        node.expect_logger().return_const(MutinyLogger::default());
+        node.expect_get_balance().returning(|| {
+            Ok(MutinyBalance {
+                confirmed: 0,
+                unconfirmed: 0,
+                lightning: 1_000,
+                federation: 0,
+                force_close: 0,
+            })
+        });
+
+        let xprivkey = ExtendedPrivKey::new_master(Network::Regtest, &[0; 64]).unwrap();
+        let stop = Arc::new(AtomicBool::new(false));
+        let nostr_manager = NostrManager::from_mnemonic(
+            xprivkey,
+            NostrKeySource::Derived,
+            storage.clone(),
+            logger,
+            stop,
+        )
+        .unwrap();
+
+        let profile = nostr_manager
+            .create_new_profile(
+                ProfileType::Normal {
+                    name: "test".to_string(),
+                },
+                SpendingConditions::RequireApproval,
+                NwcProfileTag::General,
+            )
+            .unwrap();
+
+        let secp = Secp256k1::new();
+        let mut nwc = NostrWalletConnect::new(&secp, xprivkey, profile.profile()).unwrap();
+        let uri = nwc.get_nwc_uri().unwrap().unwrap();
+
+        // the info event advertises both schemes
+        let info = nwc.create_nwc_info_event().unwrap();
+        let tag = info
+            .tags
+            .iter()
+            .map(|t| t.as_vec())
+            .find(|t| t[0] == ENCRYPTION_TAG);
+        assert_eq!(tag.unwrap()[1], "nip44_v2 nip04");
+
+        let request = |scheme: &str| {
+            let req = Request {
+                method: Method::GetBalance,
+                params: RequestParams::GetBalance,
+            };
+            let encrypted = encryption::encrypt(
+                EncryptionScheme::Nip44,
+                &uri.secret,
+                &uri.public_key,
+                req.as_json(),
+            )
+            .unwrap();
+            let p_tag = Tag::PublicKey {
+                public_key: uri.public_key,
+                relay_url: None,
+                alias: None,
+                uppercase: false,
+            };
+            let encryption_tag = Tag::Generic(
+                TagKind::Custom(ENCRYPTION_TAG.to_string()),
+                vec![scheme.to_string()],
+            );
+            EventBuilder::new(
+                Kind::WalletConnectRequest,
+                encrypted,
+                [p_tag, encryption_tag],
+            )
+            .to_event(&Keys::new(uri.secret))
+            .unwrap()
+        };
+
+        // responses use the scheme of the request
+        let result = nwc
+            .handle_nwc_request(request("nip44_v2"), &node, &nostr_manager)
+            .await;
+        let event = single_response(result);
+        assert_eq!(
+            EncryptionScheme::from_payload(&event.content),
+            EncryptionScheme::Nip44
+        );
+        let response = decrypt_response(event, &uri.secret);
+        assert!(response.error.is_none());
+
+        // unknown schemes get an error
+        let result = nwc
+            .handle_nwc_request(request("nip99"), &node, &nostr_manager)
+            .await;
+        check_nwc_error_response(
+            single_response(result),
+            &uri.secret,
+            NIP47Error {
+                code: ErrorCode::NotImplemented,
+                message: "Unsupported encryption.".to_string(),
+            },
+        );
+    }
+
     #[test]
     async fn test_process_nwc_event_require_approval() {
         let storage = MemoryStorage::default();
@@ -1306,7 +2036,7 @@ mod wasm_test {
                 .unwrap()
         };
         let result = nwc.handle_nwc_request(event, &node, &nostr_manager).await;
-        assert_eq!(result.unwrap(), None);
+        assert!(result.unwrap().is_empty());
         check_no_pending_invoices(&storage);
 
         // test unknown command
@@ -1326,7 +2056,7 @@ mod wasm_test {
         };
         let result = nwc.handle_nwc_request(event, &node, &nostr_manager).await;
         check_nwc_error_response(
-            result.unwrap().unwrap(),
+            single_response(result),
             &uri.secret,
             NIP47Error {
                 code: ErrorCode::NotImplemented,
@@ -1335,31 +2065,55 @@ mod wasm_test {
         );
         check_no_pending_invoices(&storage);
 
-        // test unexpected command
-        let event = {
-            let req = Request {
+        // test get balance, profile can spend the whole lightning balance
+        node.expect_get_balance().returning(|| {
+            Ok(MutinyBalance {
+                confirmed: 1_000,
+                unconfirmed: 0,
+                lightning: 2_000,
+                federation: 3_000,
+                force_close: 0,
+            })
+        });
+        let event = create_nwc_request_event(
+            &uri,
+            Request {
                 method: Method::GetBalance,
                 params: RequestParams::GetBalance,
-            };
+            },
+        );
+        let result = nwc.handle_nwc_request(event, &node, &nostr_manager).await;
+        let response = decrypt_response(single_response(result), &uri.secret);
+        assert!(response.error.is_none());
+        match response.result {
+            Some(ResponseResult::GetBalance(GetBalanceResponseResult { balance })) => {
+                assert_eq!(balance, 5_000_000);
+            }
+            _ => panic!("wrong response"),
+        }
+        check_no_pending_invoices(&storage);
 
-            let encrypted = encrypt(&uri.secret, &uri.public_key, req.as_json()).unwrap();
-            let p_tag = Tag::PublicKey {
-                public_key: uri.public_key,
-                relay_url: None,
-                alias: None,
-                uppercase: false,
-            };
-            EventBuilder::new(Kind::WalletConnectRequest, encrypted, [p_tag])
-                .to_event(&Keys::new(uri.secret))
-                .unwrap()
-        };
+        // test keysend is not allowed without a budget
+        let event = create_nwc_request_event(
+            &uri,
+            Request {
+                method: Method::PayKeysend,
+                params: RequestParams::PayKeysend(PayKeysendRequestParams {
+                    amount: 1_000,
+                    pubkey: "02465ed5be53d04fde66c9418ff14a5f2267723810176c9212b722e542dc1afb1b"
+                        .to_string(),
+                    preimage: None,
+                    tlv_records: vec![],
+                }),
+            },
+        );
         let result = nwc.handle_nwc_request(event, &node, &nostr_manager).await;
         check_nwc_error_response(
-            result.unwrap().unwrap(),
+            single_response(result),
             &uri.secret,
             NIP47Error {
-                code: ErrorCode::NotImplemented,
-                message: "Command is not supported.".to_string(),
+                code: ErrorCode::Restricted,
+                message: "Keysend is only available for profiles with a budget.".to_string(),
             },
         );
         check_no_pending_invoices(&storage);
@@ -1368,7 +2122,7 @@ mod wasm_test {
         let event = create_nwc_request(&uri, "invalid invoice".to_string());
         let result = nwc.handle_nwc_request(event, &node, &nostr_manager).await;
         check_nwc_error_response(
-            result.unwrap().unwrap(),
+            single_response(result),
             &uri.secret,
             NIP47Error {
                 code: ErrorCode::Other,
@@ -1381,7 +2135,7 @@ mod wasm_test {
         let event = create_nwc_request(&uri, INVOICE.to_string());
         let result = nwc.handle_nwc_request(event, &node, &nostr_manager).await;
         check_nwc_error_response(
-            result.unwrap().unwrap(),
+            single_response(result),
             &uri.secret,
             NIP47Error {
                 code: ErrorCode::Other,
@@ -1395,7 +2149,7 @@ mod wasm_test {
         let event = create_nwc_request(&uri, invoice.to_string());
         let result = nwc.handle_nwc_request(event, &node, &nostr_manager).await;
         check_nwc_error_response(
-            result.unwrap().unwrap(),
+            single_response(result),
             &uri.secret,
             NIP47Error {
                 code: ErrorCode::Other,
@@ -1412,7 +2166,7 @@ mod wasm_test {
         let event = create_nwc_request(&uri, invoice);
         let result = nwc.handle_nwc_request(event, &node, &nostr_manager).await;
         check_nwc_error_response(
-            result.unwrap().unwrap(),
+            single_response(result),
             &uri.secret,
             NIP47Error {
                 code: ErrorCode::Other,
@@ -1428,7 +2182,7 @@ mod wasm_test {
             .returning(move |_| Some(HTLCStatus::InFlight));
         let event = create_nwc_request(&uri, invoice.to_string());
         let result = nwc.handle_nwc_request(event, &node, &nostr_manager).await;
-        assert_eq!(result.unwrap(), None);
+        assert!(result.unwrap().is_empty());
         check_no_pending_invoices(&storage);
 
         // test completed payment
@@ -1438,7 +2192,7 @@ mod wasm_test {
             .returning(move |_| Some(HTLCStatus::Succeeded));
         let event = create_nwc_request(&uri, invoice.to_string());
         let result = nwc.handle_nwc_request(event, &node, &nostr_manager).await;
-        assert_eq!(result.unwrap(), None);
+        assert!(result.unwrap().is_empty());
         check_no_pending_invoices(&storage);
 
         // test it goes to pending
@@ -1450,7 +2204,7 @@ mod wasm_test {
         let result = nwc
             .handle_nwc_request(event.clone(), &node, &nostr_manager)
             .await;
-        assert_eq!(result.unwrap(), None);
+        assert!(result.unwrap().is_empty());
 
         let pending: Vec<PendingNwcInvoice> = storage
             .get_data(PENDING_NWC_EVENTS_KEY)
@@ -1464,21 +2218,204 @@ mod wasm_test {
     }
 
     #[test]
-    async fn test_clear_expired_pending_invoices() {
+    async fn test_process_nwc_event_make_and_lookup_invoice() {
         let storage = MemoryStorage::default();
+        let logger = Arc::new(MutinyLogger::default());
+        let mut node = MockInvoiceHandler::new();
+        node.expect_logger().return_const(MutinyLogger::default());
+
+        let (invoice, _) = create_dummy_invoice(Some(10_000), Network::Regtest, None);
+        let inv = invoice.clone();
+        node.expect_create_invoice_with_options()
+            .once()
+            .with(
+                eq(Some(10)),
+                eq(vec!["test".to_string()]),
+                eq(InvoiceOptions {
+                    description: Some("coffee".to_string()),
+                    description_hash: None,
+                    expiry_secs: Some(600),
+                }),
+            )
+            .returning(move |_, labels, _| {
+                let mut mutiny_invoice: MutinyInvoice = inv.clone().into();
+                mutiny_invoice.labels = labels;
+                Ok(mutiny_invoice)
+            });
+        let inv = invoice.clone();
+        node.expect_get_invoice_by_hash()
+            .with(eq(*invoice.payment_hash()))
+            .returning(move |_| {
+                let mut mutiny_invoice: MutinyInvoice = inv.clone().into();
+                mutiny_invoice.labels = vec!["test".to_string()];
+                Ok(mutiny_invoice)
+            });
+
         let xprivkey = ExtendedPrivKey::new_master(Network::Regtest, &[0; 64]).unwrap();
         let stop = Arc::new(AtomicBool::new(false));
         let nostr_manager = NostrManager::from_mnemonic(
             xprivkey,
             NostrKeySource::Derived,
             storage.clone(),
-            Arc::new(MutinyLogger::default()),
+            logger,
             stop,
         )
         .unwrap();
 
-        // check we start with no pending invoices
-        let pending = nostr_manager.get_pending_nwc_invoices().unwrap();
+        let profile = nostr_manager
+            .create_new_profile(
+                ProfileType::Normal {
+                    name: "test".to_string(),
+                },
+                SpendingConditions::RequireApproval,
+                NwcProfileTag::General,
+            )
+            .unwrap();
+
+        let secp = Secp256k1::new();
+        let mut nwc = NostrWalletConnect::new(&secp, xprivkey, profile.profile()).unwrap();
+        let uri = nwc.get_nwc_uri().unwrap().unwrap();
+
+        // test make invoice
+        let event = create_nwc_request_event(
+            &uri,
+            Request {
+                method: Method::MakeInvoice,
+                params: RequestParams::MakeInvoice(MakeInvoiceRequestParams {
+                    amount: 10_000,
+                    description: Some("coffee".to_string()),
+                    description_hash: None,
+                    expiry: Some(600),
+                }),
+            },
+        );
+        let result = nwc.handle_nwc_request(event, &node, &nostr_manager).await;
+        let response = decrypt_response(single_response(result), &uri.secret);
+        assert!(response.error.is_none());
+        match response.result {
+            Some(ResponseResult::MakeInvoice(result)) => {
+                assert_eq!(result.invoice, invoice.to_string());
+                assert_eq!(result.payment_hash, invoice.payment_hash().to_hex());
+            }
+            _ => panic!("wrong response"),
+        }
+
+        // test lookup invoice
+        let event = create_nwc_request_event(
+            &uri,
+            Request {
+                method: Method::LookupInvoice,
+                params: RequestParams::LookupInvoice(LookupInvoiceRequestParams {
+                    payment_hash: None,
+                    bolt11: Some(invoice.to_string()),
+                }),
+            },
+        );
+        let result = nwc.handle_nwc_request(event, &node, &nostr_manager).await;
+        let response = decrypt_response(single_response(result), &uri.secret);
+        assert!(response.error.is_none());
+        match response.result {
+            Some(ResponseResult::LookupInvoice(result)) => {
+                assert_eq!(result.invoice, Some(invoice.to_string()));
+                assert_eq!(result.payment_hash, invoice.payment_hash().to_hex());
+                assert_eq!(result.amount, 10_000);
+                assert!(result.settled_at.is_none());
+            }
+            _ => panic!("wrong response"),
+        }
+    }
+
+    #[test]
+    async fn test_payment_notifications() {
+        let storage = MemoryStorage::default();
+        let logger = Arc::new(MutinyLogger::default());
+
+        let xprivkey = ExtendedPrivKey::new_master(Network::Regtest, &[0; 64]).unwrap();
+        let stop = Arc::new(AtomicBool::new(false));
+        let nostr_manager = NostrManager::from_mnemonic(
+            xprivkey,
+            NostrKeySource::Derived,
+            storage.clone(),
+            logger,
+            stop,
+        )
+        .unwrap();
+
+        let profile = nostr_manager
+            .create_new_profile(
+                ProfileType::Normal {
+                    name: "test".to_string(),
+                },
+                SpendingConditions::RequireApproval,
+                NwcProfileTag::General,
+            )
+            .unwrap();
+        assert!(profile.notifications.is_empty());
+
+        let (invoice, _) = create_dummy_invoice(Some(10_000), Network::Regtest, None);
+        let mut received: MutinyInvoice = invoice.into();
+        received.inbound = true;
+        received.status = HTLCStatus::Succeeded;
+        let mut sent = received.clone();
+        sent.inbound = false;
+
+        // profiles don't get notifications unless they opt in
+        let secp = Secp256k1::new();
+        let nwc = NostrWalletConnect::new(&secp, xprivkey, profile.profile()).unwrap();
+        assert!(nwc.create_notification_event(&received).unwrap().is_none());
+
+        let profile = nostr_manager
+            .set_nwc_profile_notifications(
+                profile.index,
+                vec![
+                    NwcNotificationType::PaymentReceived,
+                    NwcNotificationType::PaymentReceived,
+                ],
+            )
+            .unwrap();
+        assert_eq!(
+            profile.notifications,
+            vec![NwcNotificationType::PaymentReceived]
+        );
+
+        let nwc = NostrWalletConnect::new(&secp, xprivkey, profile.profile()).unwrap();
+        let uri = nwc.get_nwc_uri().unwrap().unwrap();
+        let event = nwc.create_notification_event(&received).unwrap().unwrap();
+        assert_eq!(event.kind, Kind::Custom(NWC_NOTIFICATION_KIND));
+        assert_eq!(event.pubkey, uri.public_key);
+
+        let decrypted = decrypt(&uri.secret, &event.pubkey, &event.content).unwrap();
+        let notification: Notification = serde_json::from_str(&decrypted).unwrap();
+        assert_eq!(
+            notification.notification_type,
+            NwcNotificationType::PaymentReceived
+        );
+        assert_eq!(
+            notification.notification.payment_hash,
+            received.payment_hash.to_hex()
+        );
+        assert_eq!(notification.notification.amount, 10_000);
+
+        // not opted in to sent payments
+        assert!(nwc.create_notification_event(&sent).unwrap().is_none());
+    }
+
+    #[test]
+    async fn test_clear_expired_pending_invoices() {
+        let storage = MemoryStorage::default();
+        let xprivkey = ExtendedPrivKey::new_master(Network::Regtest, &[0; 64]).unwrap();
+        let stop = Arc::new(AtomicBool::new(false));
+        let nostr_manager = NostrManager::from_mnemonic(
+            xprivkey,
+            NostrKeySource::Derived,
+            storage.clone(),
+            Arc::new(MutinyLogger::default()),
+            stop,
+        )
+        .unwrap();
+
+        // check we start with no pending invoices
+        let pending = nostr_manager.get_pending_nwc_invoices().unwrap();
         assert_eq!(pending.len(), 0);
 
         // add an expired invoice
@@ -1487,6 +2424,7 @@ mod wasm_test {
             invoice: Bolt11Invoice::from_str(INVOICE).unwrap(),
             event_id: EventId::all_zeros(),
             pubkey: nostr_manager.public_key,
+            encryption: EncryptionScheme::Nip04,
         };
         // add an unexpired invoice
         let unexpired = PendingNwcInvoice {
@@ -1494,6 +2432,7 @@ mod wasm_test {
             invoice: create_dummy_invoice(Some(1_000), Network::Regtest, None).0,
             event_id: EventId::all_zeros(),
             pubkey: nostr_manager.public_key,
+            encryption: EncryptionScheme::Nip04,
         };
         storage
             .set_data(
@@ -1555,7 +2494,7 @@ mod wasm_test {
         let result = nwc
             .handle_nwc_request(event.clone(), &mw, &nostr_manager)
             .await;
-        assert!(result.unwrap().is_some()); // should get a error response
+        assert_eq!(result.unwrap().len(), 1); // should get a error response
         let pending = nostr_manager.get_pending_nwc_invoices().unwrap();
         assert_eq!(pending.len(), 1);
         assert_eq!(pending[0].invoice, invoice);
@@ -1572,7 +2511,7 @@ mod wasm_test {
         let result = nwc
             .handle_nwc_request(event.clone(), &mw, &nostr_manager)
             .await;
-        assert!(result.unwrap().is_some()); // should get a error response
+        assert_eq!(result.unwrap().len(), 1); // should get a error response
         let pending = nostr_manager.get_pending_nwc_invoices().unwrap();
         assert_eq!(pending.len(), 1);
         assert_eq!(pending[0].invoice, invoice);
@@ -1641,7 +2580,7 @@ mod wasm_test {
         let result = nwc
             .handle_nwc_request(event.clone(), &node, &nostr_manager)
             .await;
-        let event = result.unwrap().unwrap();
+        let event = single_response(result);
         let content = decrypt(&uri.secret, &event.pubkey, &event.content).unwrap();
         let response: Response = Response::from_json(content).unwrap();
         let pending = nostr_manager.get_pending_nwc_invoices().unwrap();
@@ -1665,4 +2604,415 @@ mod wasm_test {
             _ => panic!("wrong spending conditions"),
         }
     }
+
+    fn create_budget_nwc(
+        storage: &MemoryStorage,
+        budget: u64,
+    ) -> (
+        NostrManager<MemoryStorage>,
+        NostrWalletConnect,
+        NostrWalletConnectURI,
+    ) {
+        let logger = Arc::new(MutinyLogger::default());
+        let xprivkey = ExtendedPrivKey::new_master(Network::Regtest, &[0; 64]).unwrap();
+        let stop = Arc::new(AtomicBool::new(false));
+        let nostr_manager = NostrManager::from_mnemonic(
+            xprivkey,
+            NostrKeySource::Derived,
+            storage.clone(),
+            logger,
+            stop,
+        )
+        .unwrap();
+
+        let profile = nostr_manager
+            .create_new_profile(
+                ProfileType::Normal {
+                    name: "test".to_string(),
+                },
+                SpendingConditions::Budget(BudgetedSpendingConditions {
+                    budget,
+                    single_max: None,
+                    payments: vec![],
+                    period: BudgetPeriod::Day,
+                }),
+                NwcProfileTag::General,
+            )
+            .unwrap();
+
+        let secp = Secp256k1::new();
+        let mut nwc = NostrWalletConnect::new(&secp, xprivkey, profile.profile()).unwrap();
+        let uri = nwc.get_nwc_uri().unwrap().unwrap();
+
+        (nostr_manager, nwc, uri)
+    }
+
+    fn d_tag(event: &Event) -> Option<String> {
+        event
+            .tags
+            .iter()
+            .map(|t| t.as_vec())
+            .find(|t| t.first().is_some_and(|n| n == "d"))
+            .and_then(|t| t.get(1).cloned())
+    }
+
+    #[test]
+    async fn test_process_nwc_event_multi_pay_invoice() {
+        let storage = MemoryStorage::default();
+        let mut node = MockInvoiceHandler::new();
+
+        let (invoice_1, preimage_1) = create_dummy_invoice(Some(4_000), Network::Regtest, None);
+        let (invoice_2, preimage_2) = create_dummy_invoice(Some(5_000), Network::Regtest, None);
+        let (invoice_3, _) = create_dummy_invoice(Some(6_000), Network::Regtest, None);
+        let preimages = HashMap::from([
+            (*invoice_1.payment_hash(), preimage_1),
+            (*invoice_2.payment_hash(), preimage_2),
+        ]);
+
+        node.expect_skip_hodl_invoices().returning(|| true);
+        node.expect_logger().return_const(MutinyLogger::default());
+        node.expect_get_outbound_payment_status().return_const(None);
+        node.expect_pay_invoice()
+            .times(2)
+            .returning(move |inv, _, labels| {
+                let mut mutiny_invoice: MutinyInvoice = inv.clone().into();
+                mutiny_invoice.preimage = Some(preimages[inv.payment_hash()].to_hex());
+                mutiny_invoice.status = HTLCStatus::Succeeded;
+                mutiny_invoice.labels = labels;
+                mutiny_invoice.fees_paid = Some(0);
+                Ok(mutiny_invoice)
+            });
+
+        let (nostr_manager, mut nwc, uri) = create_budget_nwc(&storage, 10);
+
+        // the third invoice goes over the budget
+        let event = create_nwc_request_event(
+            &uri,
+            Request {
+                method: Method::MultiPayInvoice,
+                params: RequestParams::MultiPayInvoice(MultiPayInvoiceRequestParams {
+                    invoices: vec![
+                        PayInvoiceRequestParams {
+                            invoice: invoice_1.to_string(),
+                        },
+                        PayInvoiceRequestParams {
+                            invoice: invoice_2.to_string(),
+                        },
+                        PayInvoiceRequestParams {
+                            invoice: invoice_3.to_string(),
+                        },
+                    ],
+                }),
+            },
+        );
+        let events = nwc
+            .handle_nwc_request(event, &node, &nostr_manager)
+            .await
+            .unwrap();
+        assert_eq!(events.len(), 3);
+
+        for (event, (invoice, preimage)) in events
+            .iter()
+            .zip([(&invoice_1, preimage_1), (&invoice_2, preimage_2)])
+        {
+            assert_eq!(d_tag(event), Some(invoice.payment_hash().to_hex()));
+            let response = decrypt_response(event.clone(), &uri.secret);
+            assert_eq!(response.result_type, Method::MultiPayInvoice);
+            assert!(response.error.is_none());
+            match response.result {
+                Some(ResponseResult::MultiPayInvoice(PayInvoiceResponseResult {
+                    preimage: pre,
+                })) => {
+                    assert_eq!(pre, preimage.to_hex());
+                }
+                _ => panic!("wrong response"),
+            }
+        }
+
+        assert_eq!(d_tag(&events[2]), Some(invoice_3.payment_hash().to_hex()));
+        let response = decrypt_response(events[2].clone(), &uri.secret);
+        assert_eq!(response.result_type, Method::MultiPayInvoice);
+        check_nwc_error_response(
+            events[2].clone(),
+            &uri.secret,
+            NIP47Error {
+                code: ErrorCode::QuotaExceeded,
+                message: "Budget exceeded.".to_string(),
+            },
+        );
+
+        // the invoice over budget waits for approval
+        let pending = nostr_manager.get_pending_nwc_invoices().unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].invoice, invoice_3);
+
+        match nwc.profile.spending_conditions {
+            SpendingConditions::Budget(budget) => {
+                assert_eq!(budget.payments.len(), 2);
+                assert_eq!(budget.sum_payments(), 9);
+            }
+            _ => panic!("wrong spending conditions"),
+        }
+    }
+
+    #[test]
+    async fn test_process_nwc_event_pay_keysend() {
+        let storage = MemoryStorage::default();
+        let mut node = MockInvoiceHandler::new();
+
+        let to_node = PublicKey::from_str(
+            "02465ed5be53d04fde66c9418ff14a5f2267723810176c9212b722e542dc1afb1b",
+        )
+        .unwrap();
+        let (invoice, preimage) = create_dummy_invoice(Some(1_000), Network::Regtest, None);
+
+        node.expect_logger().return_const(MutinyLogger::default());
+        node.expect_get_outbound_payment_status().return_const(None);
+        node.expect_keysend()
+            .times(2)
+            .withf(move |pk, _, message, custom_tlvs, labels| {
+                *pk == to_node
+                    && message.is_none()
+                    && custom_tlvs
+                        == &vec![CustomTlv {
+                            tlv_type: 696969,
+                            value: b"boost".to_vec(),
+                        }]
+                    && labels == &vec!["test".to_string()]
+            })
+            .returning(move |_, amt_sats, _, _, labels| {
+                let mut mutiny_invoice: MutinyInvoice = invoice.clone().into();
+                mutiny_invoice.amount_sats = Some(amt_sats);
+                mutiny_invoice.preimage = Some(preimage.to_hex());
+                mutiny_invoice.status = HTLCStatus::Succeeded;
+                mutiny_invoice.labels = labels;
+                mutiny_invoice.fees_paid = Some(0);
+                Ok(mutiny_invoice)
+            });
+
+        let (nostr_manager, mut nwc, uri) = create_budget_nwc(&storage, 10);
+
+        let keysend = |amount: u64| PayKeysendRequestParams {
+            amount,
+            pubkey: to_node.to_string(),
+            preimage: None,
+            tlv_records: vec![KeysendTLVRecord {
+                type_: 696969,
+                value: b"boost".to_hex(),
+            }],
+        };
+
+        // test a keysend within the budget
+        let event = create_nwc_request_event(
+            &uri,
+            Request {
+                method: Method::PayKeysend,
+                params: RequestParams::PayKeysend(keysend(1_000)),
+            },
+        );
+        let result = nwc.handle_nwc_request(event, &node, &nostr_manager).await;
+        let response = decrypt_response(single_response(result), &uri.secret);
+        assert!(response.error.is_none());
+        match response.result {
+            Some(ResponseResult::PayKeysend(PayKeysendResponseResult { preimage: pre })) => {
+                assert_eq!(pre, preimage.to_hex());
+            }
+            _ => panic!("wrong response"),
+        }
+
+        match nwc.profile.spending_conditions.clone() {
+            SpendingConditions::Budget(budget) => {
+                assert_eq!(budget.payments.len(), 1);
+                assert_eq!(budget.payments[0].amt, 1);
+                assert_eq!(budget.payments[0].hash, invoice.payment_hash().to_hex());
+            }
+            _ => panic!("wrong spending conditions"),
+        }
+
+        // test a multi keysend, the second one goes over the budget
+        let event = create_nwc_request_event(
+            &uri,
+            Request {
+                method: Method::MultiPayKeysend,
+                params: RequestParams::MultiPayKeysend(MultiPayKeysendRequestParams {
+                    keysends: vec![keysend(4_000), keysend(8_000)],
+                }),
+            },
+        );
+        let events = nwc
+            .handle_nwc_request(event, &node, &nostr_manager)
+            .await
+            .unwrap();
+        assert_eq!(events.len(), 2);
+
+        assert_eq!(d_tag(&events[0]), Some(to_node.to_string()));
+        let response = decrypt_response(events[0].clone(), &uri.secret);
+        assert_eq!(response.result_type, Method::MultiPayKeysend);
+        assert!(response.error.is_none());
+        match response.result {
+            Some(ResponseResult::MultiPayKeysend(PayKeysendResponseResult { preimage: pre })) => {
+                assert_eq!(pre, preimage.to_hex());
+            }
+            _ => panic!("wrong response"),
+        }
+
+        assert_eq!(d_tag(&events[1]), Some(to_node.to_string()));
+        check_nwc_error_response(
+            events[1].clone(),
+            &uri.secret,
+            NIP47Error {
+                code: ErrorCode::QuotaExceeded,
+                message: "Budget exceeded.".to_string(),
+            },
+        );
+
+        // keysends are never put in the manual approval list
+        check_no_pending_invoices(&storage);
+
+        match nwc.profile.spending_conditions {
+            SpendingConditions::Budget(budget) => {
+                assert_eq!(budget.payments.len(), 2);
+                assert_eq!(budget.sum_payments(), 5);
+            }
+            _ => panic!("wrong spending conditions"),
+        }
+    }
+
+    async fn list_transactions(
+        nwc: &mut NostrWalletConnect,
+        node: &MockInvoiceHandler,
+        nostr_manager: &NostrManager<MemoryStorage>,
+        uri: &NostrWalletConnectURI,
+        params: ListTransactionsRequestParams,
+    ) -> Vec<String> {
+        let event = create_nwc_request_event(
+            uri,
+            Request {
+                method: Method::ListTransactions,
+                params: RequestParams::ListTransactions(params),
+            },
+        );
+        let result = nwc.handle_nwc_request(event, node, nostr_manager).await;
+        let response = decrypt_response(single_response(result), &uri.secret);
+        assert!(response.error.is_none());
+        match response.result {
+            Some(ResponseResult::ListTransactions(txs)) => {
+                txs.into_iter().map(|tx| tx.payment_hash).collect()
+            }
+            _ => panic!("wrong response"),
+        }
+    }
+
+    #[test]
+    async fn test_process_nwc_event_list_transactions() {
+        let storage = MemoryStorage::default();
+        let mut node = MockInvoiceHandler::new();
+
+        let label = "test".to_string();
+        let payment = |msats: u64, inbound: bool, label: &str, last_updated: u64| {
+            let (invoice, _) = create_dummy_invoice(Some(msats), Network::Regtest, None);
+            let mut mutiny_invoice: MutinyInvoice = invoice.into();
+            mutiny_invoice.inbound = inbound;
+            mutiny_invoice.status = HTLCStatus::Succeeded;
+            mutiny_invoice.labels = vec![label.to_string()];
+            mutiny_invoice.last_updated = last_updated;
+            mutiny_invoice
+        };
+        let received = payment(1_000, true, &label, 100);
+        let sent = payment(2_000, false, &label, 200);
+        let other_profile = payment(3_000, true, "other", 300);
+        let activity = vec![
+            ActivityItem::Lightning(Box::new(other_profile)),
+            ActivityItem::Lightning(Box::new(sent.clone())),
+            ActivityItem::Lightning(Box::new(received.clone())),
+        ];
+
+        node.expect_logger().return_const(MutinyLogger::default());
+        node.expect_get_activity()
+            .returning(move || Ok(activity.clone()));
+
+        let (nostr_manager, mut nwc, uri) = create_budget_nwc(&storage, 10);
+
+        let params = || ListTransactionsRequestParams {
+            from: None,
+            until: None,
+            limit: None,
+            offset: None,
+            unpaid: None,
+            transaction_type: None,
+        };
+
+        // only payments of this profile are listed
+        let txs = list_transactions(&mut nwc, &node, &nostr_manager, &uri, params()).await;
+        assert_eq!(
+            txs,
+            vec![sent.payment_hash.to_hex(), received.payment_hash.to_hex()]
+        );
+
+        let txs = list_transactions(
+            &mut nwc,
+            &node,
+            &nostr_manager,
+            &uri,
+            ListTransactionsRequestParams {
+                transaction_type: Some(TransactionType::Incoming),
+                ..params()
+            },
+        )
+        .await;
+        assert_eq!(txs, vec![received.payment_hash.to_hex()]);
+
+        let txs = list_transactions(
+            &mut nwc,
+            &node,
+            &nostr_manager,
+            &uri,
+            ListTransactionsRequestParams {
+                transaction_type: Some(TransactionType::Outgoing),
+                ..params()
+            },
+        )
+        .await;
+        assert_eq!(txs, vec![sent.payment_hash.to_hex()]);
+
+        let txs = list_transactions(
+            &mut nwc,
+            &node,
+            &nostr_manager,
+            &uri,
+            ListTransactionsRequestParams {
+                from: Some(Timestamp::from(150)),
+                ..params()
+            },
+        )
+        .await;
+        assert_eq!(txs, vec![sent.payment_hash.to_hex()]);
+
+        let txs = list_transactions(
+            &mut nwc,
+            &node,
+            &nostr_manager,
+            &uri,
+            ListTransactionsRequestParams {
+                until: Some(Timestamp::from(150)),
+                ..params()
+            },
+        )
+        .await;
+        assert_eq!(txs, vec![received.payment_hash.to_hex()]);
+
+        let txs = list_transactions(
+            &mut nwc,
+            &node,
+            &nostr_manager,
+            &uri,
+            ListTransactionsRequestParams {
+                limit: Some(1),
+                offset: Some(1),
+                ..params()
+            },
+        )
+        .await;
+        assert_eq!(txs, vec![received.payment_hash.to_hex()]);
+    }
 }
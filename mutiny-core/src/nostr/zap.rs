@@ -0,0 +1,251 @@
+use crate::error::MutinyError;
+use crate::MutinyInvoice;
+use bitcoin::hashes::{sha256, Hash};
+use lightning_invoice::{Bolt11Invoice, Bolt11InvoiceDescription};
+use nostr::key::XOnlyPublicKey;
+use nostr::{Event, EventBuilder, EventId, JsonUtil, Kind, Tag};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Relays we ask recipients to publish our zap receipts to
+pub(crate) const ZAP_RELAYS: [&str; 2] = ["wss://relay.mutinywallet.com", "wss://relay.primal.net"];
+
+/// How long we wait for the recipient to publish a zap receipt
+pub(crate) const ZAP_RECEIPT_TIMEOUT_SECS: u64 = 30;
+
+/// Who can see that a zap came from us, see NIP-57
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ZapPrivacy {
+    /// Signed with our nostr key, anyone can see who sent it
+    #[default]
+    Public,
+    /// Only the recipient can see who sent it
+    Private,
+    /// Signed with a random key, nobody knows who sent it
+    Anonymous,
+}
+
+impl FromStr for ZapPrivacy {
+    type Err = MutinyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "public" => Ok(Self::Public),
+            "private" => Ok(Self::Private),
+            "anonymous" | "anon" => Ok(Self::Anonymous),
+            _ => Err(MutinyError::InvalidArgumentsError),
+        }
+    }
+}
+
+/// A zap we paid, with the recipient's receipt if they published one.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SentZap {
+    pub invoice: MutinyInvoice,
+    pub zap_request: EventId,
+    /// The verified zap receipt, `None` if it wasn't published in time
+    pub receipt: Option<EventId>,
+}
+
+fn parse_tag(data: Vec<String>) -> Result<Tag, MutinyError> {
+    Tag::parse(data).map_err(|e| MutinyError::Other(anyhow::anyhow!("Invalid tag: {e}")))
+}
+
+/// The unsigned kind 9734 zap request for a recipient and optionally one of their events.
+pub(crate) fn zap_request_builder(
+    recipient: XOnlyPublicKey,
+    event_id: Option<EventId>,
+    amount_msat: u64,
+    lnurl: String,
+    comment: String,
+) -> Result<EventBuilder, MutinyError> {
+    let mut tags = vec![Tag::PublicKey {
+        public_key: recipient,
+        relay_url: None,
+        alias: None,
+        uppercase: false,
+    }];
+    if let Some(event_id) = event_id {
+        tags.push(Tag::Event {
+            event_id,
+            relay_url: None,
+            marker: None,
+        });
+    }
+
+    let mut relays = vec!["relays".to_string()];
+    relays.extend(ZAP_RELAYS.iter().map(|r| r.to_string()));
+    tags.push(parse_tag(relays)?);
+    tags.push(parse_tag(vec![
+        "amount".to_string(),
+        amount_msat.to_string(),
+    ])?);
+    tags.push(parse_tag(vec!["lnurl".to_string(), lnurl])?);
+
+    Ok(EventBuilder::new(Kind::ZapRequest, comment, tags))
+}
+
+fn tag_value(event: &Event, name: &str) -> Option<String> {
+    event
+        .tags
+        .iter()
+        .map(|t| t.as_vec())
+        .find(|t| t.first().is_some_and(|n| n == name))
+        .and_then(|t| t.get(1).cloned())
+}
+
+/// Checks the invoice's description hash commits to the zap request,
+/// as NIP-57 requires of the invoices a service gives for zaps.
+pub(crate) fn invoice_commits_to(invoice: &Bolt11Invoice, zap_request: &str) -> bool {
+    let description_hash = sha256::Hash::hash(zap_request.as_bytes());
+    match invoice.description() {
+        Bolt11InvoiceDescription::Hash(hash) => hash.0 == description_hash,
+        Bolt11InvoiceDescription::Direct(_) => false,
+    }
+}
+
+/// Checks a zap receipt was published by the recipient's LNURL service
+/// for our zap request and the invoice we paid.
+pub(crate) fn verify_zap_receipt(
+    receipt: &Event,
+    zap_request: &Event,
+    invoice: &Bolt11Invoice,
+    nostr_pubkey: XOnlyPublicKey,
+) -> bool {
+    if receipt.kind != Kind::ZapReceipt
+        || receipt.pubkey != nostr_pubkey
+        || receipt.verify().is_err()
+    {
+        return false;
+    }
+
+    let bolt11 = tag_value(receipt, "bolt11").and_then(|b| Bolt11Invoice::from_str(&b).ok());
+    if bolt11.as_ref() != Some(invoice) {
+        return false;
+    }
+
+    // the description is our zap request, which the invoice commits to
+    let description = match tag_value(receipt, "description") {
+        Some(description) => description,
+        None => return false,
+    };
+    invoice_commits_to(invoice, &description)
+        && Event::from_json(&description).is_ok_and(|e| e.id == zap_request.id)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::*;
+    use crate::utils::now;
+    use bitcoin::secp256k1::{Secp256k1, SecretKey};
+    use lightning::ln::PaymentSecret;
+    use lightning_invoice::{Currency, InvoiceBuilder};
+    use nostr::Keys;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    fn zap_invoice(description: &str, amount_msat: u64) -> Bolt11Invoice {
+        let secp = Secp256k1::new();
+        let node_key = SecretKey::from_slice(&[9u8; 32]).unwrap();
+        InvoiceBuilder::new(Currency::Regtest)
+            .description_hash(sha256::Hash::hash(description.as_bytes()))
+            .payment_hash(sha256::Hash::hash(&[1u8; 32]))
+            .payment_secret(PaymentSecret([2u8; 32]))
+            .duration_since_epoch(now())
+            .min_final_cltv_expiry_delta(144)
+            .amount_milli_satoshis(amount_msat)
+            .build_signed(|hash| secp.sign_ecdsa_recoverable(hash, &node_key))
+            .unwrap()
+    }
+
+    fn zap_receipt(keys: &Keys, invoice: &Bolt11Invoice, description: String) -> Event {
+        let tags = vec![
+            parse_tag(vec!["bolt11".to_string(), invoice.to_string()]).unwrap(),
+            parse_tag(vec!["description".to_string(), description]).unwrap(),
+        ];
+        EventBuilder::new(Kind::ZapReceipt, "", tags)
+            .to_event(keys)
+            .unwrap()
+    }
+
+    #[test]
+    async fn test_zap_privacy_from_str() {
+        let test_name = "test_zap_privacy_from_str";
+        log!("{}", test_name);
+
+        assert_eq!(ZapPrivacy::from_str("public").unwrap(), ZapPrivacy::Public);
+        assert_eq!(
+            ZapPrivacy::from_str("Private").unwrap(),
+            ZapPrivacy::Private
+        );
+        assert_eq!(ZapPrivacy::from_str("anon").unwrap(), ZapPrivacy::Anonymous);
+        assert!(ZapPrivacy::from_str("secret").is_err());
+    }
+
+    #[test]
+    async fn test_verify_zap_receipt() {
+        let test_name = "test_verify_zap_receipt";
+        log!("{}", test_name);
+
+        let sender = Keys::generate();
+        let service = Keys::generate();
+        let recipient = Keys::generate().public_key();
+
+        let zap_request = zap_request_builder(
+            recipient,
+            None,
+            21_000,
+            "lnurl1test".to_string(),
+            "gm".to_string(),
+        )
+        .unwrap()
+        .to_event(&sender)
+        .unwrap();
+        assert_eq!(zap_request.kind, Kind::ZapRequest);
+        assert_eq!(tag_value(&zap_request, "amount"), Some("21000".to_string()));
+
+        let description = zap_request.as_json();
+        let invoice = zap_invoice(&description, 21_000);
+        assert!(invoice_commits_to(&invoice, &description));
+        let receipt = zap_receipt(&service, &invoice, description.clone());
+        assert!(verify_zap_receipt(
+            &receipt,
+            &zap_request,
+            &invoice,
+            service.public_key()
+        ));
+
+        // published by someone other than the recipient's service
+        let forged = zap_receipt(&Keys::generate(), &invoice, description.clone());
+        assert!(!verify_zap_receipt(
+            &forged,
+            &zap_request,
+            &invoice,
+            service.public_key()
+        ));
+
+        // for an invoice that doesn't commit to our zap request
+        let other = zap_invoice("other", 21_000);
+        assert!(!invoice_commits_to(&other, &description));
+        let receipt = zap_receipt(&service, &other, zap_request.as_json());
+        assert!(!verify_zap_receipt(
+            &receipt,
+            &zap_request,
+            &other,
+            service.public_key()
+        ));
+
+        // for another invoice than the one we paid
+        let other = zap_invoice(&description, 42_000);
+        let receipt = zap_receipt(&service, &other, description);
+        assert!(!verify_zap_receipt(
+            &receipt,
+            &zap_request,
+            &invoice,
+            service.public_key()
+        ));
+    }
+}
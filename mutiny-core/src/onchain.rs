@@ -8,18 +8,20 @@ use bdk::chain::{BlockId, ConfirmationTime};
 use bdk::psbt::PsbtUtils;
 use bdk::template::DescriptorTemplateOut;
 use bdk::wallet::{AddressIndex, Update};
-use bdk::{FeeRate, LocalUtxo, SignOptions, TransactionDetails, Wallet};
-use bdk_esplora::EsploraAsyncExt;
+use bdk::{FeeRate, KeychainKind, LocalUtxo, SignOptions, TransactionDetails, Wallet};
 use bitcoin::consensus::serialize;
 use bitcoin::hashes::hex::ToHex;
 use bitcoin::psbt::{Input, PartiallySignedTransaction};
-use bitcoin::util::bip32::{ChildNumber, DerivationPath, ExtendedPrivKey};
+use bitcoin::util::bip32::{
+    ChildNumber, DerivationPath, ExtendedPrivKey, ExtendedPubKey, Fingerprint,
+};
 use bitcoin::{Address, Network, OutPoint, Script, Transaction, Txid};
-use esplora_client::AsyncClient;
 use lightning::events::bump_transaction::{Utxo, WalletSource};
 use lightning::util::logger::Logger;
 use lightning::{log_debug, log_error, log_info, log_trace, log_warn};
+use serde::{Deserialize, Serialize};
 
+use crate::chainsource::{ChainSource, KeychainSpks, SpkIter};
 use crate::error::MutinyError;
 use crate::fees::MutinyFeeEstimator;
 use crate::labels::*;
@@ -29,15 +31,25 @@ use crate::utils::{now, sleep};
 
 const DEFAULT_STOP_GAP: usize = 20;
 const FULL_SYNC_STOP_GAP: usize = 150;
+pub(crate) const FROZEN_UTXOS_KEY: &str = "frozen_utxos";
+
+/// The set of frozen UTXOs as it is saved to storage, versioned so it is backed up to VSS
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub(crate) struct FrozenUtxos {
+    pub outpoints: HashSet<OutPoint>,
+    pub version: u32,
+}
 
 #[derive(Clone)]
 pub struct OnChainWallet<S: MutinyStorage> {
     pub wallet: Arc<RwLock<Wallet<OnChainStorage<S>>>>,
     pub(crate) storage: S,
     pub network: Network,
-    pub blockchain: Arc<AsyncClient>,
+    pub blockchain: Arc<dyn ChainSource>,
     pub fees: Arc<MutinyFeeEstimator<S>>,
     pub(crate) stop: Arc<AtomicBool>,
+    /// If the wallet was created without private keys, PSBTs must be signed elsewhere
+    watch_only: bool,
     logger: Arc<MutinyLogger>,
 }
 
@@ -46,7 +58,7 @@ impl<S: MutinyStorage> OnChainWallet<S> {
         xprivkey: ExtendedPrivKey,
         db: S,
         network: Network,
-        esplora: Arc<AsyncClient>,
+        blockchain: Arc<dyn ChainSource>,
         fees: Arc<MutinyFeeEstimator<S>>,
         stop: Arc<AtomicBool>,
         logger: Arc<MutinyLogger>,
@@ -66,13 +78,62 @@ impl<S: MutinyStorage> OnChainWallet<S> {
             wallet: Arc::new(RwLock::new(wallet)),
             storage: db,
             network,
-            blockchain: esplora,
+            blockchain,
+            fees,
+            stop,
+            watch_only: false,
+            logger,
+        })
+    }
+
+    /// Creates a wallet from descriptors that do not need to contain private keys,
+    /// such as ones created by [`get_tr_descriptors_for_xpub`].
+    ///
+    /// If the descriptors are public, the wallet can only create unsigned PSBTs
+    /// that need to be signed by an external signer and given back with
+    /// [`OnChainWallet::broadcast_signed_psbt`].
+    ///
+    /// The wallet is persisted under the same keys as other on-chain wallets,
+    /// so it should be given its own storage.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new_watch_only(
+        receive_descriptor: &str,
+        change_descriptor: Option<&str>,
+        db: S,
+        network: Network,
+        blockchain: Arc<dyn ChainSource>,
+        fees: Arc<MutinyFeeEstimator<S>>,
+        stop: Arc<AtomicBool>,
+        logger: Arc<MutinyLogger>,
+    ) -> Result<OnChainWallet<S>, MutinyError> {
+        let wallet = Wallet::new(
+            receive_descriptor,
+            change_descriptor,
+            OnChainStorage(db.clone()),
+            network,
+        )?;
+        let watch_only = wallet
+            .get_signers(KeychainKind::External)
+            .signers()
+            .is_empty();
+
+        Ok(OnChainWallet {
+            wallet: Arc::new(RwLock::new(wallet)),
+            storage: db,
+            network,
+            blockchain,
             fees,
             stop,
+            watch_only,
             logger,
         })
     }
 
+    /// Returns true if the wallet can not sign transactions itself.
+    pub fn is_watch_only(&self) -> bool {
+        self.watch_only
+    }
+
     pub async fn broadcast_transaction(&self, tx: Transaction) -> Result<(), MutinyError> {
         let txid = tx.txid();
         log_info!(self.logger, "Broadcasting transaction: {txid}");
@@ -150,6 +211,10 @@ impl<S: MutinyStorage> OnChainWallet<S> {
                 for ((a, b), c) in spk_vec {
                     spk_map.entry(a).or_insert_with(Vec::new).push((b, c));
                 }
+                let spks: KeychainSpks = spk_map
+                    .into_iter()
+                    .map(|(k, v)| (k, Box::new(v.into_iter()) as SpkIter))
+                    .collect();
 
                 let chain = wallet.local_chain();
                 let chain_tip = chain.tip().unwrap_or_default();
@@ -161,7 +226,7 @@ impl<S: MutinyStorage> OnChainWallet<S> {
                     .map(|canonical_tx| canonical_tx.node.txid)
                     .collect::<Vec<Txid>>();
 
-                (checkpoints.clone(), spk_map, unconfirmed_txids)
+                (checkpoints.clone(), spks, unconfirmed_txids)
             } else {
                 log_error!(self.logger, "Could not get wallet lock to sync");
                 return Err(MutinyError::WalletOperationFailed);
@@ -170,14 +235,7 @@ impl<S: MutinyStorage> OnChainWallet<S> {
 
         let update = self
             .blockchain
-            .scan(
-                &checkpoints,
-                spks,
-                txids,
-                core::iter::empty(),
-                DEFAULT_STOP_GAP,
-                5,
-            )
+            .scan_wallet(&checkpoints, spks, txids, DEFAULT_STOP_GAP)
             .await?;
 
         for _ in 0..10 {
@@ -200,7 +258,11 @@ impl<S: MutinyStorage> OnChainWallet<S> {
         let (checkpoints, spks) = {
             if let Ok(wallet) = self.wallet.try_read() {
                 let checkpoints = wallet.checkpoints();
-                let spks = wallet.spks_of_all_keychains();
+                let spks: KeychainSpks = wallet
+                    .spks_of_all_keychains()
+                    .into_iter()
+                    .map(|(k, v)| (k, Box::new(v) as SpkIter))
+                    .collect();
 
                 (checkpoints.clone(), spks)
             } else {
@@ -211,14 +273,7 @@ impl<S: MutinyStorage> OnChainWallet<S> {
 
         let update = self
             .blockchain
-            .scan(
-                &checkpoints,
-                spks,
-                core::iter::empty(),
-                core::iter::empty(),
-                FULL_SYNC_STOP_GAP,
-                5,
-            )
+            .scan_wallet(&checkpoints, spks, vec![], FULL_SYNC_STOP_GAP)
             .await?;
 
         // get new wallet lock for writing and apply the update
@@ -283,6 +338,55 @@ impl<S: MutinyStorage> OnChainWallet<S> {
         Ok(self.wallet.try_read()?.list_unspent().collect())
     }
 
+    /// Returns true if the script belongs to this wallet.
+    pub(crate) fn is_mine(&self, script: &Script) -> Result<bool, MutinyError> {
+        Ok(self.wallet.try_read()?.is_mine(script))
+    }
+
+    /// Returns the UTXOs that have been frozen, these are never used by coin selection.
+    pub fn get_frozen_utxos(&self) -> Result<HashSet<OutPoint>, MutinyError> {
+        Ok(self.read_frozen_utxos()?.outpoints)
+    }
+
+    fn read_frozen_utxos(&self) -> Result<FrozenUtxos, MutinyError> {
+        Ok(self
+            .storage
+            .get_data::<FrozenUtxos>(FROZEN_UTXOS_KEY)?
+            .unwrap_or_default())
+    }
+
+    fn write_frozen_utxos(&self, mut frozen: FrozenUtxos) -> Result<(), MutinyError> {
+        frozen.version += 1;
+        let version = Some(frozen.version);
+        self.storage
+            .set_data(FROZEN_UTXOS_KEY.to_string(), frozen, version)
+    }
+
+    /// Freezes the given UTXOs so they are not spent when creating transactions.
+    pub fn freeze_utxos(&self, outpoints: &[OutPoint]) -> Result<(), MutinyError> {
+        let mut frozen = self.read_frozen_utxos()?;
+        frozen.outpoints.extend(outpoints);
+        self.write_frozen_utxos(frozen)
+    }
+
+    /// Unfreezes the given UTXOs so they can be spent again.
+    pub fn unfreeze_utxos(&self, outpoints: &[OutPoint]) -> Result<(), MutinyError> {
+        let mut frozen = self.read_frozen_utxos()?;
+        for outpoint in outpoints {
+            frozen.outpoints.remove(outpoint);
+        }
+        self.write_frozen_utxos(frozen)
+    }
+
+    /// Returns an error if any of the given UTXOs are frozen.
+    fn check_not_frozen(&self, utxos: &[OutPoint]) -> Result<(), MutinyError> {
+        let frozen = self.get_frozen_utxos()?;
+        if utxos.iter().any(|u| frozen.contains(u)) {
+            return Err(MutinyError::FrozenUtxo);
+        }
+        Ok(())
+    }
+
     pub fn list_transactions(
         &self,
         include_raw: bool,
@@ -436,6 +540,56 @@ impl<S: MutinyStorage> OnChainWallet<S> {
         amount: u64,
         fee_rate: Option<f32>,
     ) -> Result<PartiallySignedTransaction, MutinyError> {
+        let psbt = self.create_unsigned_psbt_to_spk(spk, amount, None, fee_rate)?;
+        self.sign_psbt(psbt)
+    }
+
+    /// Creates a signed PSBT that only spends the given UTXOs,
+    /// any change is sent back to the wallet.
+    pub fn create_signed_psbt_with_utxos(
+        &self,
+        send_to: Address,
+        amount: u64,
+        utxos: &[OutPoint],
+        fee_rate: Option<f32>,
+    ) -> Result<PartiallySignedTransaction, MutinyError> {
+        let psbt = self.create_unsigned_psbt(send_to, amount, Some(utxos), fee_rate)?;
+        self.sign_psbt(psbt)
+    }
+
+    /// Creates an unsigned PSBT sending to the given address, so it can be signed
+    /// by an external signer. If utxos are given, only those will be spent.
+    pub fn create_unsigned_psbt(
+        &self,
+        send_to: Address,
+        amount: u64,
+        utxos: Option<&[OutPoint]>,
+        fee_rate: Option<f32>,
+    ) -> Result<PartiallySignedTransaction, MutinyError> {
+        if !send_to.is_valid_for_network(self.network) {
+            return Err(MutinyError::IncorrectNetwork(send_to.network));
+        }
+
+        self.create_unsigned_psbt_to_spk(send_to.script_pubkey(), amount, utxos, fee_rate)
+    }
+
+    /// Creates an unsigned PSBT paying to the given script, such as a channel funding output.
+    /// If utxos are given, only those will be spent.
+    pub fn create_unsigned_psbt_to_spk(
+        &self,
+        spk: Script,
+        amount: u64,
+        utxos: Option<&[OutPoint]>,
+        fee_rate: Option<f32>,
+    ) -> Result<PartiallySignedTransaction, MutinyError> {
+        if utxos.is_some_and(|u| u.is_empty()) {
+            return Err(MutinyError::InvalidArgumentsError);
+        }
+        let frozen = self.get_frozen_utxos()?;
+        if utxos.is_some_and(|utxos| utxos.iter().any(|u| frozen.contains(u))) {
+            return Err(MutinyError::FrozenUtxo);
+        }
+
         let mut wallet = self.wallet.try_write()?;
 
         let fee_rate = if let Some(rate) = fee_rate {
@@ -444,16 +598,33 @@ impl<S: MutinyStorage> OnChainWallet<S> {
             let sat_per_kwu = self.fees.get_normal_fee_rate();
             FeeRate::from_sat_per_kwu(sat_per_kwu as f32)
         };
-        let (mut psbt, details) = {
+        let (psbt, details) = {
             let mut builder = wallet.build_tx();
+            if let Some(utxos) = utxos {
+                builder.manually_selected_only().add_utxos(utxos)?;
+            }
             builder
                 .add_recipient(spk, amount)
+                .unspendable(frozen.into_iter().collect())
                 .enable_rbf()
                 .fee_rate(fee_rate);
             builder.finish()?
         };
         log_debug!(self.logger, "Transaction details: {details:#?}");
         log_debug!(self.logger, "Unsigned PSBT: {psbt}");
+        Ok(psbt)
+    }
+
+    /// Signs the given PSBT with the wallet's keys.
+    fn sign_psbt(
+        &self,
+        mut psbt: PartiallySignedTransaction,
+    ) -> Result<PartiallySignedTransaction, MutinyError> {
+        if self.watch_only {
+            return Err(MutinyError::WalletSigningFailed);
+        }
+
+        let wallet = self.wallet.try_read()?;
         let finalized = wallet.sign(&mut psbt, SignOptions::default())?;
         log_debug!(self.logger, "finalized: {finalized}");
         Ok(psbt)
@@ -467,6 +638,56 @@ impl<S: MutinyStorage> OnChainWallet<S> {
         fee_rate: Option<f32>,
     ) -> Result<Txid, MutinyError> {
         let psbt = self.create_signed_psbt(destination_address, amount, fee_rate)?;
+        self.broadcast_psbt(psbt, labels).await
+    }
+
+    /// Sends to the given address only spending the given UTXOs.
+    pub async fn send_with_utxos(
+        &self,
+        destination_address: Address,
+        amount: u64,
+        utxos: &[OutPoint],
+        labels: Vec<String>,
+        fee_rate: Option<f32>,
+    ) -> Result<Txid, MutinyError> {
+        let psbt =
+            self.create_signed_psbt_with_utxos(destination_address, amount, utxos, fee_rate)?;
+        self.broadcast_psbt(psbt, labels).await
+    }
+
+    /// Finalizes a PSBT that was created by this wallet and signed by an external signer,
+    /// then broadcasts it.
+    pub async fn broadcast_signed_psbt(
+        &self,
+        psbt: PartiallySignedTransaction,
+        labels: Vec<String>,
+    ) -> Result<Txid, MutinyError> {
+        let psbt = self.finalize_signed_psbt(psbt)?;
+        self.broadcast_psbt(psbt, labels).await
+    }
+
+    /// Finalizes a PSBT that was created by this wallet and signed by an external signer.
+    pub(crate) fn finalize_signed_psbt(
+        &self,
+        mut psbt: PartiallySignedTransaction,
+    ) -> Result<PartiallySignedTransaction, MutinyError> {
+        let finalized = {
+            let wallet = self.wallet.try_read()?;
+            wallet.finalize_psbt(&mut psbt, SignOptions::default())?
+        };
+        if !finalized {
+            log_error!(self.logger, "Could not finalize signed PSBT: {psbt}");
+            return Err(MutinyError::WalletSigningFailed);
+        }
+
+        Ok(psbt)
+    }
+
+    async fn broadcast_psbt(
+        &self,
+        psbt: PartiallySignedTransaction,
+        labels: Vec<String>,
+    ) -> Result<Txid, MutinyError> {
         self.label_psbt(&psbt, labels)?;
 
         let raw_transaction = psbt.extract_tx();
@@ -530,11 +751,43 @@ impl<S: MutinyStorage> OnChainWallet<S> {
         Ok(payjoin)
     }
 
+    /// Signs our inputs of a payjoin proposal we are creating as the receiver.
+    pub(crate) fn sign_payjoin_proposal(
+        &self,
+        mut psbt: PartiallySignedTransaction,
+    ) -> Result<PartiallySignedTransaction, MutinyError> {
+        if self.watch_only {
+            return Err(MutinyError::WalletSigningFailed);
+        }
+
+        let wallet = self.wallet.try_read()?;
+        // the payjoin library only gives us the witness utxo of our contributed input
+        let options = SignOptions {
+            trust_witness_utxo: true,
+            ..Default::default()
+        };
+        let finalized = wallet.sign(&mut psbt, options)?;
+        log_debug!(self.logger, "payjoin proposal finalized: {finalized}");
+        Ok(psbt)
+    }
+
     pub fn create_sweep_psbt(
         &self,
         spk: Script,
         fee_rate: Option<f32>,
     ) -> Result<PartiallySignedTransaction, MutinyError> {
+        let psbt = self.create_unsigned_sweep_psbt(spk, fee_rate)?;
+        self.sign_psbt(psbt)
+    }
+
+    /// Creates an unsigned PSBT that spends all of the wallet's funds to the given script,
+    /// so it can be signed by an external signer.
+    pub fn create_unsigned_sweep_psbt(
+        &self,
+        spk: Script,
+        fee_rate: Option<f32>,
+    ) -> Result<PartiallySignedTransaction, MutinyError> {
+        let frozen = self.get_frozen_utxos()?;
         let mut wallet = self.wallet.try_write()?;
 
         let fee_rate = if let Some(rate) = fee_rate {
@@ -543,10 +796,11 @@ impl<S: MutinyStorage> OnChainWallet<S> {
             let sat_per_kwu = self.fees.get_normal_fee_rate();
             FeeRate::from_sat_per_kwu(sat_per_kwu as f32)
         };
-        let (mut psbt, details) = {
+        let (psbt, details) = {
             let mut builder = wallet.build_tx();
             builder
                 .drain_wallet() // Spend all outputs in this wallet.
+                .unspendable(frozen.into_iter().collect())
                 .drain_to(spk)
                 .enable_rbf()
                 .fee_rate(fee_rate);
@@ -554,8 +808,6 @@ impl<S: MutinyStorage> OnChainWallet<S> {
         };
         log_debug!(self.logger, "Transaction details: {details:#?}");
         log_debug!(self.logger, "Unsigned PSBT: {psbt}");
-        let finalized = wallet.sign(&mut psbt, SignOptions::default())?;
-        log_debug!(self.logger, "finalized: {finalized}");
         Ok(psbt)
     }
 
@@ -590,8 +842,24 @@ impl<S: MutinyStorage> OnChainWallet<S> {
         amount_sats: u64,
         absolute_fee: u64,
     ) -> Result<PartiallySignedTransaction, MutinyError> {
+        let psbt =
+            self.create_unsigned_sweep_psbt_to_output(utxos, spk, amount_sats, absolute_fee)?;
+        self.sign_psbt(psbt)
+    }
+
+    /// Same as [`OnChainWallet::create_sweep_psbt_to_output`] but the PSBT is not signed,
+    /// so it can be signed by an external signer.
+    pub(crate) fn create_unsigned_sweep_psbt_to_output(
+        &self,
+        utxos: &[OutPoint],
+        spk: Script,
+        amount_sats: u64,
+        absolute_fee: u64,
+    ) -> Result<PartiallySignedTransaction, MutinyError> {
+        self.check_not_frozen(utxos)?;
+
         let mut wallet = self.wallet.try_write()?;
-        let (mut psbt, details) = {
+        let (psbt, details) = {
             let mut builder = wallet.build_tx();
             builder
                 .manually_selected_only()
@@ -603,8 +871,6 @@ impl<S: MutinyStorage> OnChainWallet<S> {
         };
         log_debug!(self.logger, "Transaction details: {details:#?}");
         log_debug!(self.logger, "Unsigned PSBT: {psbt}");
-        let finalized = wallet.sign(&mut psbt, SignOptions::default())?;
-        log_debug!(self.logger, "finalized: {finalized}");
         Ok(psbt)
     }
 
@@ -614,7 +880,7 @@ impl<S: MutinyStorage> OnChainWallet<S> {
         amount: u64,
         fee_rate: Option<f32>,
     ) -> Result<u64, MutinyError> {
-        let psbt = self.create_signed_psbt_to_spk(spk, amount, fee_rate)?;
+        let psbt = self.create_unsigned_psbt_to_spk(spk, amount, None, fee_rate)?;
 
         psbt.fee_amount().ok_or(MutinyError::WalletOperationFailed)
     }
@@ -624,7 +890,7 @@ impl<S: MutinyStorage> OnChainWallet<S> {
         spk: Script,
         fee_rate: Option<f32>,
     ) -> Result<u64, MutinyError> {
-        let psbt = self.create_sweep_psbt(spk, fee_rate)?;
+        let psbt = self.create_unsigned_sweep_psbt(spk, fee_rate)?;
 
         psbt.fee_amount().ok_or(MutinyError::WalletOperationFailed)
     }
@@ -676,6 +942,24 @@ fn get_tr_descriptors_for_extended_key(
     Ok((receive_descriptor_template, change_descriptor_template))
 }
 
+/// Creates the public taproot descriptors for the given account xpub, matching the
+/// descriptors our own wallet uses. The fingerprint is of the master key the xpub
+/// was derived from, external signers need it to find their keys.
+pub fn get_tr_descriptors_for_xpub(
+    account_xpub: ExtendedPubKey,
+    master_fingerprint: Fingerprint,
+    network: Network,
+    account_number: u32,
+) -> (String, String) {
+    let coin_type = coin_type_from_network(network);
+    let origin = format!("[{master_fingerprint}/86'/{coin_type}'/{account_number}']");
+
+    let receive_descriptor = format!("tr({origin}{account_xpub}/0/*)");
+    let change_descriptor = format!("tr({origin}{account_xpub}/1/*)");
+
+    (receive_descriptor, change_descriptor)
+}
+
 pub(crate) fn coin_type_from_network(network: Network) -> u32 {
     match network {
         Network::Bitcoin => 0,
@@ -701,9 +985,11 @@ pub(crate) fn get_esplora_url(network: Network, user_provided_url: Option<String
 
 impl<S: MutinyStorage> WalletSource for OnChainWallet<S> {
     fn list_confirmed_utxos(&self) -> Result<Vec<Utxo>, ()> {
+        let frozen = self.get_frozen_utxos().map_err(|_| ())?;
         let wallet = self.wallet.try_read().map_err(|_| ())?;
         let utxos = wallet
             .list_unspent()
+            .filter(|u| !frozen.contains(&u.outpoint))
             .map(|u| Utxo {
                 outpoint: u.outpoint,
                 output: u.txout,
@@ -763,37 +1049,37 @@ impl<S: MutinyStorage> WalletSource for OnChainWallet<S> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::chainsource::EsploraChainSource;
     use crate::test_utils::*;
     use crate::{encrypt::encryption_key_from_pass, storage::MemoryStorage};
     use bip39::Mnemonic;
+    use bitcoin::secp256k1::Secp256k1;
     use bitcoin::Address;
-    use esplora_client::Builder;
     use std::str::FromStr;
     use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
     wasm_bindgen_test_configure!(run_in_browser);
 
     async fn create_wallet() -> OnChainWallet<MemoryStorage> {
         let mnemonic = Mnemonic::from_str("abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about").expect("could not generate");
-        let esplora = Arc::new(
-            Builder::new("https://blockstream.info/testnet/api/")
-                .build_async()
-                .unwrap(),
-        );
         let pass = uuid::Uuid::new_v4().to_string();
         let cipher = encryption_key_from_pass(&pass).unwrap();
         let db = MemoryStorage::new(Some(pass), Some(cipher), None);
         let logger = Arc::new(MutinyLogger::default());
+        let chain_source: Arc<dyn ChainSource> = Arc::new(
+            EsploraChainSource::new("https://blockstream.info/testnet/api/", logger.clone())
+                .unwrap(),
+        );
         let network = Network::Testnet;
         let fees = Arc::new(MutinyFeeEstimator::new(
             db.clone(),
             network,
-            esplora.clone(),
+            chain_source.clone(),
             logger.clone(),
         ));
         let stop = Arc::new(AtomicBool::new(false));
         let xpriv = ExtendedPrivKey::new_master(network, &mnemonic.to_seed("")).unwrap();
 
-        OnChainWallet::new(xpriv, db, network, esplora, fees, stop, logger).unwrap()
+        OnChainWallet::new(xpriv, db, network, chain_source, fees, stop, logger).unwrap()
     }
 
     #[test]
@@ -845,4 +1131,110 @@ mod tests {
         assert!(label.clone().unwrap().addresses.contains(&send_to_addr));
         assert!(label.unwrap().addresses.contains(&change_addr));
     }
+
+    #[test]
+    async fn test_freeze_utxos() {
+        let test_name = "freeze_utxos";
+        log!("{}", test_name);
+        let wallet = create_wallet().await;
+
+        let outpoint = OutPoint::from_str(
+            "e47b5b7a879f13a8213815cf3dc3f5b35af1e217f4126e9bc4f75a8ca04909ab:0",
+        )
+        .unwrap();
+        let other = OutPoint::from_str(
+            "e47b5b7a879f13a8213815cf3dc3f5b35af1e217f4126e9bc4f75a8ca04909ab:1",
+        )
+        .unwrap();
+
+        assert!(wallet.get_frozen_utxos().unwrap().is_empty());
+
+        wallet.freeze_utxos(&[outpoint, other]).unwrap();
+        let frozen = wallet.get_frozen_utxos().unwrap();
+        assert_eq!(frozen.len(), 2);
+        assert!(frozen.contains(&outpoint));
+
+        // selecting a frozen utxo should fail before coin selection
+        let send_to_addr = Address::from_str("mrKjeffvbnmKJURrLNdqLkfrptLrFtnkFx").unwrap();
+        let result = wallet.create_signed_psbt_with_utxos(send_to_addr, 1_000, &[outpoint], None);
+        assert_eq!(result.unwrap_err(), MutinyError::FrozenUtxo);
+
+        wallet.unfreeze_utxos(&[outpoint]).unwrap();
+        let frozen = wallet.get_frozen_utxos().unwrap();
+        assert_eq!(frozen.len(), 1);
+        assert!(frozen.contains(&other));
+
+        // every change bumps the version so it is backed up to vss
+        let stored: FrozenUtxos = wallet.storage.get_data(FROZEN_UTXOS_KEY).unwrap().unwrap();
+        assert_eq!(stored.version, 2);
+    }
+
+    #[test]
+    async fn test_watch_only_wallet() {
+        let test_name = "watch_only_wallet";
+        log!("{}", test_name);
+        let wallet = create_wallet().await;
+        assert!(!wallet.is_watch_only());
+
+        let mnemonic = Mnemonic::from_str("abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about").expect("could not generate");
+        let network = Network::Testnet;
+        let xpriv = ExtendedPrivKey::new_master(network, &mnemonic.to_seed("")).unwrap();
+        let secp = Secp256k1::new();
+        let path = DerivationPath::from_str("m/86'/1'/0'").unwrap();
+        let account_xpub =
+            ExtendedPubKey::from_priv(&secp, &xpriv.derive_priv(&secp, &path).unwrap());
+        let (receive, change) =
+            get_tr_descriptors_for_xpub(account_xpub, xpriv.fingerprint(&secp), network, 0);
+
+        let db = MemoryStorage::default();
+        let logger = Arc::new(MutinyLogger::default());
+        let chain_source = wallet.blockchain.clone();
+        let fees = Arc::new(MutinyFeeEstimator::new(
+            db.clone(),
+            network,
+            chain_source.clone(),
+            logger.clone(),
+        ));
+        let stop = Arc::new(AtomicBool::new(false));
+        let watch_only = OnChainWallet::new_watch_only(
+            &receive,
+            Some(&change),
+            db,
+            network,
+            chain_source,
+            fees,
+            stop,
+            logger,
+        )
+        .unwrap();
+        assert!(watch_only.is_watch_only());
+
+        // both wallets should derive the same addresses
+        let expected = wallet
+            .wallet
+            .try_write()
+            .unwrap()
+            .get_address(AddressIndex::Peek(0))
+            .address;
+        let actual = watch_only
+            .wallet
+            .try_write()
+            .unwrap()
+            .get_address(AddressIndex::Peek(0))
+            .address;
+        assert_eq!(expected, actual);
+
+        // a watch only wallet can not sign
+        let psbt = PartiallySignedTransaction::from_unsigned_tx(Transaction {
+            version: 2,
+            lock_time: bitcoin::PackedLockTime::ZERO,
+            input: vec![],
+            output: vec![],
+        })
+        .unwrap();
+        assert_eq!(
+            watch_only.sign_psbt(psbt).unwrap_err(),
+            MutinyError::WalletSigningFailed
+        );
+    }
 }
@@ -0,0 +1,441 @@
+use crate::error::MutinyError;
+use crate::logging::MutinyLogger;
+use crate::onchain::OnChainWallet;
+use crate::storage::MutinyStorage;
+use crate::utils;
+use async_lock::Mutex;
+use async_trait::async_trait;
+use bitcoin::consensus::{deserialize, serialize, Decodable, Encodable};
+use bitcoin::psbt::PartiallySignedTransaction;
+use bitcoin::{OutPoint, Script};
+use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use futures::channel::oneshot;
+use futures::{pin_mut, select, FutureExt, StreamExt};
+use lightning::util::logger::Logger;
+use lightning::{log_debug, log_info, log_warn};
+use payjoin::receive::{Error, Headers, UncheckedProposal};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+pub(crate) const PAYJOIN_SEEN_INPUTS_KEY: &str = "payjoin_seen_inputs";
+
+/// A BIP78 request from a payjoin sender, containing their original PSBT.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct PayjoinRequest {
+    /// Identifier set by the transport so it can match a response to its request
+    pub id: String,
+    /// The base64 encoded original PSBT
+    pub body: Vec<u8>,
+    /// The query string of the request, without the leading `?`
+    pub query: String,
+    pub headers: HashMap<String, String>,
+}
+
+impl Headers for PayjoinRequest {
+    fn get_header(&self, key: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// Our response to a [`PayjoinRequest`].
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum PayjoinResponse {
+    /// The base64 encoded payjoin proposal PSBT
+    Proposal(String),
+    /// A BIP78 JSON error, the sender should broadcast their original transaction
+    Error(String),
+}
+
+/// Carries payjoin requests between senders and our receiver.
+///
+/// This could be a local HTTP server, or a relay that senders post to and we poll.
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+pub trait PayjoinTransport: Send + Sync {
+    /// The endpoint senders should post to, this is used as the `pj=` parameter in BIP21 URIs.
+    fn endpoint(&self) -> String;
+
+    /// Waits for the next request from a sender.
+    /// Returns `None` once the transport is closed.
+    async fn next_request(&self) -> Option<PayjoinRequest>;
+
+    /// Sends the response for a request given by [`PayjoinTransport::next_request`].
+    async fn respond(
+        &self,
+        request: &PayjoinRequest,
+        response: PayjoinResponse,
+    ) -> Result<(), MutinyError>;
+}
+
+type PendingRequest = (PayjoinRequest, oneshot::Sender<PayjoinResponse>);
+
+/// An in-memory [`PayjoinTransport`], requests are given to it with a [`ChannelSender`].
+///
+/// Useful when the application runs its own server, or to stand in for a sender in tests.
+pub struct ChannelTransport {
+    endpoint: String,
+    requests: Mutex<UnboundedReceiver<PendingRequest>>,
+    pending: Mutex<HashMap<String, oneshot::Sender<PayjoinResponse>>>,
+}
+
+/// The sending half of a [`ChannelTransport`].
+#[derive(Clone)]
+pub struct ChannelSender {
+    requests: UnboundedSender<PendingRequest>,
+}
+
+impl ChannelTransport {
+    pub fn new(endpoint: String) -> (Self, ChannelSender) {
+        let (sender, receiver) = unbounded();
+        let transport = Self {
+            endpoint,
+            requests: Mutex::new(receiver),
+            pending: Mutex::new(HashMap::new()),
+        };
+
+        (transport, ChannelSender { requests: sender })
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl PayjoinTransport for ChannelTransport {
+    fn endpoint(&self) -> String {
+        self.endpoint.clone()
+    }
+
+    async fn next_request(&self) -> Option<PayjoinRequest> {
+        let (request, responder) = self.requests.lock().await.next().await?;
+        self.pending
+            .lock()
+            .await
+            .insert(request.id.clone(), responder);
+        Some(request)
+    }
+
+    async fn respond(
+        &self,
+        request: &PayjoinRequest,
+        response: PayjoinResponse,
+    ) -> Result<(), MutinyError> {
+        let responder = self
+            .pending
+            .lock()
+            .await
+            .remove(&request.id)
+            .ok_or(MutinyError::NotFound)?;
+
+        // the sender may have given up waiting, nothing to do then
+        let _ = responder.send(response);
+        Ok(())
+    }
+}
+
+impl ChannelSender {
+    /// Sends a request to the receiver and waits for its response.
+    pub async fn send(&self, request: PayjoinRequest) -> Result<PayjoinResponse, MutinyError> {
+        let (sender, receiver) = oneshot::channel();
+        self.requests
+            .unbounded_send((request, sender))
+            .map_err(|_| MutinyError::ConnectionFailed)?;
+
+        receiver.await.map_err(|_| MutinyError::ConnectionFailed)
+    }
+}
+
+/// Handles the receiving side of BIP78, checks the sender's original PSBT
+/// and adds one of our UTXOs to it.
+pub struct PayjoinReceiver<S: MutinyStorage> {
+    wallet: Arc<OnChainWallet<S>>,
+    storage: S,
+    logger: Arc<MutinyLogger>,
+}
+
+impl<S: MutinyStorage> PayjoinReceiver<S> {
+    pub fn new(wallet: Arc<OnChainWallet<S>>, storage: S, logger: Arc<MutinyLogger>) -> Self {
+        Self {
+            wallet,
+            storage,
+            logger,
+        }
+    }
+
+    /// Handles requests from the transport until it is closed or we are stopped.
+    pub(crate) async fn run(&self, transport: Arc<dyn PayjoinTransport>, stop: Arc<AtomicBool>) {
+        loop {
+            if stop.load(Ordering::Relaxed) {
+                break;
+            }
+
+            let read_fut = transport.next_request().fuse();
+            let delay_fut = Box::pin(utils::sleep(1_000)).fuse();
+            pin_mut!(read_fut, delay_fut);
+            select! {
+                request = read_fut => {
+                    let Some(request) = request else {
+                        log_info!(self.logger, "Payjoin transport closed");
+                        break;
+                    };
+
+                    let response = self.handle_request(&request);
+                    if let Err(e) = transport.respond(&request, response).await {
+                        log_warn!(self.logger, "Failed to send payjoin response: {e}");
+                    }
+                }
+                _ = delay_fut => {}
+            }
+        }
+    }
+
+    /// Processes a sender's request, returning either our proposal or an error
+    /// that tells the sender to fall back to their original transaction.
+    pub fn handle_request(&self, request: &PayjoinRequest) -> PayjoinResponse {
+        match self.process_request(request) {
+            Ok(psbt) => PayjoinResponse::Proposal(psbt.to_string()),
+            Err(e) => {
+                log_warn!(self.logger, "Rejecting payjoin request: {e}");
+                let (code, message) = match e {
+                    Error::BadRequest(e) => ("original-psbt-rejected", e.to_string()),
+                    Error::Server(_) => ("unavailable", "Receiver is unavailable".to_string()),
+                };
+                let error = serde_json::json!({ "errorCode": code, "message": message });
+                PayjoinResponse::Error(error.to_string())
+            }
+        }
+    }
+
+    fn process_request(
+        &self,
+        request: &PayjoinRequest,
+    ) -> Result<payjoin::bitcoin::psbt::Psbt, Error> {
+        let proposal = UncheckedProposal::from_request(
+            request.body.as_slice(),
+            request.query.as_str(),
+            request.clone(),
+        )?;
+        let original_tx = proposal.extract_tx_to_schedule_broadcast();
+
+        // esplora can't test mempool acceptance, so we only make sure
+        // the sender gave us a fully signed transaction
+        let proposal = proposal.check_can_broadcast(|tx| {
+            Ok(tx
+                .input
+                .iter()
+                .all(|i| !i.witness.is_empty() || !i.script_sig.is_empty()))
+        })?;
+        let proposal = proposal.check_inputs_not_owned(|script| self.is_mine(script))?;
+        let proposal = proposal.check_no_mixed_input_scripts()?;
+
+        let seen = self.get_seen_inputs().map_err(server_error)?;
+        let proposal = proposal.check_no_inputs_seen_before(|outpoint| {
+            let outpoint: OutPoint = from_payjoin(outpoint).map_err(server_error)?;
+            Ok(seen.contains(&outpoint))
+        })?;
+        let mut proposal = proposal.identify_receiver_outputs(|script| self.is_mine(script))?;
+
+        // contribute one of our confirmed utxos, preferring one that
+        // doesn't reveal which output is the payment
+        let frozen = self.wallet.get_frozen_utxos().map_err(server_error)?;
+        let utxos = self
+            .wallet
+            .list_utxos()
+            .map_err(server_error)?
+            .into_iter()
+            .filter(|u| u.confirmation_time.is_confirmed() && !frozen.contains(&u.outpoint))
+            .collect::<Vec<_>>();
+        let candidates = utxos
+            .iter()
+            .map(|u| {
+                let amount = payjoin::bitcoin::Amount::from_sat(u.txout.value);
+                Ok((amount, to_payjoin(&u.outpoint)?))
+            })
+            .collect::<Result<HashMap<_, _>, MutinyError>>()
+            .map_err(server_error)?;
+        let selected = match proposal.try_preserving_privacy(candidates.clone()) {
+            Ok(outpoint) => outpoint,
+            Err(_) => *candidates
+                .values()
+                .next()
+                .ok_or_else(|| server_error(MutinyError::InsufficientBalance))?,
+        };
+        let selected_outpoint: OutPoint = from_payjoin(&selected).map_err(server_error)?;
+        let utxo = utxos
+            .iter()
+            .find(|u| u.outpoint == selected_outpoint)
+            .ok_or_else(|| server_error(MutinyError::WalletOperationFailed))?;
+        log_debug!(self.logger, "Contributing {selected_outpoint} to payjoin");
+        proposal.contribute_witness_input(to_payjoin(&utxo.txout).map_err(server_error)?, selected);
+
+        let proposal = proposal.finalize_proposal(|psbt| self.sign_proposal(psbt), None)?;
+
+        // remember the sender's inputs so the same transaction can't be used to probe our utxos
+        let inputs = original_tx
+            .input
+            .iter()
+            .map(|i| from_payjoin(&i.previous_output))
+            .collect::<Result<Vec<OutPoint>, MutinyError>>()
+            .map_err(server_error)?;
+        self.add_seen_inputs(inputs).map_err(server_error)?;
+
+        Ok(proposal.psbt().clone())
+    }
+
+    fn is_mine(&self, script: &payjoin::bitcoin::Script) -> Result<bool, Error> {
+        let script = Script::from(script.to_bytes());
+        self.wallet.is_mine(&script).map_err(server_error)
+    }
+
+    fn sign_proposal(
+        &self,
+        psbt: &payjoin::bitcoin::psbt::Psbt,
+    ) -> Result<payjoin::bitcoin::psbt::Psbt, Error> {
+        let psbt = PartiallySignedTransaction::from_str(&psbt.to_string())
+            .map_err(|_| server_error(MutinyError::PayjoinConfigError))?;
+        let signed = self
+            .wallet
+            .sign_payjoin_proposal(psbt)
+            .map_err(server_error)?;
+        payjoin::bitcoin::psbt::Psbt::from_str(&signed.to_string())
+            .map_err(|_| server_error(MutinyError::PayjoinConfigError))
+    }
+
+    fn get_seen_inputs(&self) -> Result<HashSet<OutPoint>, MutinyError> {
+        Ok(self
+            .storage
+            .get_data::<HashSet<OutPoint>>(PAYJOIN_SEEN_INPUTS_KEY)?
+            .unwrap_or_default())
+    }
+
+    fn add_seen_inputs(&self, inputs: Vec<OutPoint>) -> Result<(), MutinyError> {
+        let mut seen = self.get_seen_inputs()?;
+        seen.extend(inputs);
+        self.storage
+            .set_data(PAYJOIN_SEEN_INPUTS_KEY.to_string(), seen, None)
+    }
+}
+
+fn server_error(e: MutinyError) -> Error {
+    Error::Server(e.into())
+}
+
+/// payjoin uses a newer version of rust-bitcoin, convert through consensus encoding
+fn to_payjoin<T: Encodable, U: payjoin::bitcoin::consensus::Decodable>(
+    t: &T,
+) -> Result<U, MutinyError> {
+    payjoin::bitcoin::consensus::deserialize(&serialize(t))
+        .map_err(|_| MutinyError::PayjoinConfigError)
+}
+
+fn from_payjoin<T: payjoin::bitcoin::consensus::Encodable, U: Decodable>(
+    t: &T,
+) -> Result<U, MutinyError> {
+    deserialize(&payjoin::bitcoin::consensus::serialize(t))
+        .map_err(|_| MutinyError::PayjoinConfigError)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use crate::test_utils::*;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    fn dummy_request(id: &str) -> PayjoinRequest {
+        PayjoinRequest {
+            id: id.to_string(),
+            body: b"not a psbt".to_vec(),
+            query: "v=1".to_string(),
+            headers: HashMap::from([
+                ("Content-Type".to_string(), "text/plain".to_string()),
+                ("content-length".to_string(), "10".to_string()),
+            ]),
+        }
+    }
+
+    #[test]
+    async fn test_request_headers() {
+        let test_name = "test_request_headers";
+        log!("{}", test_name);
+
+        let request = dummy_request("1");
+        assert_eq!(request.get_header("content-type"), Some("text/plain"));
+        assert_eq!(request.get_header("Content-Length"), Some("10"));
+        assert_eq!(request.get_header("Authorization"), None);
+    }
+
+    #[test]
+    async fn test_channel_transport() {
+        let test_name = "test_channel_transport";
+        log!("{}", test_name);
+
+        let (transport, sender) = ChannelTransport::new("https://example.com/pj".to_string());
+        assert_eq!(transport.endpoint(), "https://example.com/pj");
+
+        let request = dummy_request("1");
+        let expected = request.clone();
+        let send_fut = sender.send(request);
+        let receive_fut = async {
+            let request = transport.next_request().await.unwrap();
+            assert_eq!(request, expected);
+            transport
+                .respond(&request, PayjoinResponse::Proposal("psbt".to_string()))
+                .await
+                .unwrap();
+        };
+        let (response, _) = futures::join!(send_fut, receive_fut);
+        assert_eq!(
+            response.unwrap(),
+            PayjoinResponse::Proposal("psbt".to_string())
+        );
+
+        // can't respond to a request that was never received
+        let result = transport
+            .respond(&dummy_request("2"), PayjoinResponse::Error(String::new()))
+            .await;
+        assert_eq!(result, Err(MutinyError::NotFound));
+    }
+
+    #[test]
+    async fn test_receiver_rejects_invalid_request() {
+        let test_name = "test_receiver_rejects_invalid_request";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::default();
+        let mw = create_mutiny_wallet(storage.clone()).await;
+        let receiver = PayjoinReceiver::new(
+            mw.node_manager.wallet.clone(),
+            storage.clone(),
+            mw.logger.clone(),
+        );
+
+        let (transport, sender) = ChannelTransport::new("https://example.com/pj".to_string());
+        let send_fut = sender.send(dummy_request("1"));
+        let receive_fut = async {
+            let request = transport.next_request().await.unwrap();
+            let response = receiver.handle_request(&request);
+            transport.respond(&request, response).await.unwrap();
+        };
+        let (response, _) = futures::join!(send_fut, receive_fut);
+
+        let PayjoinResponse::Error(error) = response.unwrap() else {
+            panic!("expected an error response");
+        };
+        let error: serde_json::Value = serde_json::from_str(&error).unwrap();
+        assert_eq!(error["errorCode"], "original-psbt-rejected");
+
+        // nothing should be marked as seen for a rejected request
+        assert!(storage
+            .get_data::<HashSet<OutPoint>>(PAYJOIN_SEEN_INPUTS_KEY)
+            .unwrap()
+            .is_none());
+    }
+}
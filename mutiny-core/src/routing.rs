@@ -0,0 +1,274 @@
+use crate::error::MutinyError;
+use crate::storage::MutinyStorage;
+use fedimint_core::config::FederationId;
+use serde::{Deserialize, Serialize};
+
+pub const ROUTING_POLICY_KEY: &str = "routing_policy";
+
+/// Where a payment is sent from, or where an invoice is created.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PaymentSource {
+    Federation(FederationId),
+    /// Our lightning node(s)
+    Node,
+}
+
+/// Limits on when a source can be used.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SourceLimits {
+    pub source: PaymentSource,
+    /// Don't pay from this source if the fee would be higher than this
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_fee_sats: Option<u64>,
+    /// Don't pay from this source if it would leave less than this balance
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_balance_sats: Option<u64>,
+    /// Don't create invoices with this source once its balance is above this
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_balance_sats: Option<u64>,
+}
+
+/// Decides which sources are used to pay invoices and create them.
+///
+/// The default policy uses federations first, in the order they were added,
+/// then our lightning node, without any limits.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct RoutingPolicy {
+    /// Sources to try first, in order.
+    /// Sources that are not listed are tried after these in the default order.
+    #[serde(default)]
+    pub preferred: Vec<PaymentSource>,
+    /// If set, sources that are not in `preferred` are never used
+    #[serde(default)]
+    pub preferred_only: bool,
+    #[serde(default)]
+    pub limits: Vec<SourceLimits>,
+}
+
+impl RoutingPolicy {
+    /// Orders the available sources according to the policy,
+    /// `available` should be in the default order.
+    pub fn order_sources(&self, available: &[PaymentSource]) -> Vec<PaymentSource> {
+        let mut ordered: Vec<PaymentSource> = Vec::with_capacity(available.len());
+        for source in self.preferred.iter() {
+            if available.contains(source) && !ordered.contains(source) {
+                ordered.push(*source);
+            }
+        }
+
+        if !self.preferred_only {
+            for source in available {
+                if !ordered.contains(source) {
+                    ordered.push(*source);
+                }
+            }
+        }
+
+        ordered
+    }
+
+    pub fn limits(&self, source: &PaymentSource) -> Option<&SourceLimits> {
+        self.limits.iter().find(|l| &l.source == source)
+    }
+
+    /// Checks if a payment of `amount_sats` costing `fee_sats` can be sent from the source.
+    pub fn check_payment(
+        &self,
+        source: &PaymentSource,
+        amount_sats: u64,
+        fee_sats: Option<u64>,
+        balance_sats: u64,
+    ) -> Result<(), QuoteError> {
+        let fee = fee_sats.unwrap_or_default();
+        if balance_sats < amount_sats + fee {
+            return Err(QuoteError::InsufficientBalance);
+        }
+
+        if let Some(limits) = self.limits(source) {
+            if limits
+                .max_fee_sats
+                .is_some_and(|max| fee_sats.is_some_and(|fee| fee > max))
+            {
+                return Err(QuoteError::FeeTooHigh);
+            }
+            if limits
+                .min_balance_sats
+                .is_some_and(|min| balance_sats - amount_sats - fee < min)
+            {
+                return Err(QuoteError::BelowMinBalance);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks if an invoice can be created with the source.
+    pub fn can_receive(&self, source: &PaymentSource, balance_sats: u64) -> bool {
+        self.limits(source)
+            .and_then(|l| l.max_balance_sats)
+            .map_or(true, |max| balance_sats < max)
+    }
+}
+
+/// Why a source can't be used for a payment.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QuoteError {
+    InsufficientBalance,
+    /// The expected fee is above the source's `max_fee_sats`
+    FeeTooHigh,
+    /// Paying would leave less than the source's `min_balance_sats`
+    BelowMinBalance,
+    /// No route was found to the recipient
+    NoRoute,
+}
+
+impl From<QuoteError> for MutinyError {
+    fn from(e: QuoteError) -> Self {
+        match e {
+            QuoteError::InsufficientBalance | QuoteError::BelowMinBalance => {
+                MutinyError::InsufficientBalance
+            }
+            QuoteError::FeeTooHigh => MutinyError::FeeLimitExceeded,
+            QuoteError::NoRoute => MutinyError::RoutingFailed,
+        }
+    }
+}
+
+/// The expected cost of paying an invoice from a source.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct PaymentQuote {
+    pub source: PaymentSource,
+    pub balance_sats: u64,
+    /// The expected fee, if it could be estimated
+    pub fee_sats: Option<u64>,
+    /// Set if the policy would skip this source
+    pub error: Option<QuoteError>,
+}
+
+pub(crate) fn get_routing_policy<S: MutinyStorage>(
+    storage: &S,
+) -> Result<RoutingPolicy, MutinyError> {
+    Ok(storage
+        .get_data::<RoutingPolicy>(ROUTING_POLICY_KEY)?
+        .unwrap_or_default())
+}
+
+pub(crate) fn set_routing_policy<S: MutinyStorage>(
+    storage: &S,
+    policy: RoutingPolicy,
+) -> Result<(), MutinyError> {
+    storage.set_data(ROUTING_POLICY_KEY.to_string(), policy, None)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use crate::test_utils::*;
+    use std::str::FromStr;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    fn federation(n: u8) -> PaymentSource {
+        let id = FederationId::from_str(&format!("{:02x}", n).repeat(32)).unwrap();
+        PaymentSource::Federation(id)
+    }
+
+    #[test]
+    async fn test_order_sources() {
+        let test_name = "test_order_sources";
+        log!("{}", test_name);
+
+        let available = vec![federation(1), federation(2), PaymentSource::Node];
+
+        let policy = RoutingPolicy::default();
+        assert_eq!(policy.order_sources(&available), available);
+
+        let mut policy = RoutingPolicy {
+            preferred: vec![PaymentSource::Node, federation(2), federation(3)],
+            ..Default::default()
+        };
+        assert_eq!(
+            policy.order_sources(&available),
+            vec![PaymentSource::Node, federation(2), federation(1)]
+        );
+
+        policy.preferred_only = true;
+        assert_eq!(
+            policy.order_sources(&available),
+            vec![PaymentSource::Node, federation(2)]
+        );
+    }
+
+    #[test]
+    async fn test_check_payment() {
+        let test_name = "test_check_payment";
+        log!("{}", test_name);
+
+        let source = federation(1);
+        let policy = RoutingPolicy {
+            limits: vec![SourceLimits {
+                source,
+                max_fee_sats: Some(10),
+                min_balance_sats: Some(1_000),
+                max_balance_sats: Some(50_000),
+            }],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            policy.check_payment(&source, 1_000, Some(5), 10_000),
+            Ok(())
+        );
+        assert_eq!(
+            policy.check_payment(&source, 1_000, Some(20), 10_000),
+            Err(QuoteError::FeeTooHigh)
+        );
+        assert_eq!(
+            policy.check_payment(&source, 9_000, Some(5), 10_000),
+            Err(QuoteError::BelowMinBalance)
+        );
+        assert_eq!(
+            policy.check_payment(&source, 10_000, Some(5), 10_000),
+            Err(QuoteError::InsufficientBalance)
+        );
+
+        // other sources have no limits
+        let node = PaymentSource::Node;
+        assert_eq!(
+            policy.check_payment(&node, 9_000, Some(500), 10_000),
+            Ok(())
+        );
+
+        assert!(policy.can_receive(&source, 49_999));
+        assert!(!policy.can_receive(&source, 50_000));
+        assert!(policy.can_receive(&node, 1_000_000));
+    }
+
+    #[test]
+    async fn test_routing_policy_storage() {
+        let test_name = "test_routing_policy_storage";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::default();
+        assert_eq!(
+            get_routing_policy(&storage).unwrap(),
+            RoutingPolicy::default()
+        );
+
+        let policy = RoutingPolicy {
+            preferred: vec![PaymentSource::Node],
+            preferred_only: false,
+            limits: vec![SourceLimits {
+                source: federation(1),
+                max_fee_sats: Some(10),
+                min_balance_sats: None,
+                max_balance_sats: None,
+            }],
+        };
+        set_routing_policy(&storage, policy.clone()).unwrap();
+        assert_eq!(get_routing_policy(&storage).unwrap(), policy);
+    }
+}
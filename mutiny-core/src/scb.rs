@@ -0,0 +1,457 @@
+use crate::chainsource::{ChainSource, OutputSpend};
+use crate::error::{MutinyError, MutinyStorageError};
+use crate::event::EventHandler;
+use crate::keymanager::PhantomKeysManager;
+use crate::ldkstorage::MutinyNodePersister;
+use crate::logging::MutinyLogger;
+use crate::messagehandler::MutinyMessageHandler;
+use crate::peermanager::PeerManagerImpl;
+use crate::storage::MutinyStorage;
+use crate::utils::sleep;
+use bitcoin::hashes::hex::{FromHex, ToHex};
+use bitcoin::hashes::Hash;
+use bitcoin::secp256k1::PublicKey;
+use bitcoin::{Script, WPubkeyHash};
+use lightning::chain::transaction::OutPoint;
+use lightning::io::{Cursor, Read};
+use lightning::ln::msgs::DecodeError;
+use lightning::ln::ChannelId;
+use lightning::sign::{
+    ChannelSigner, SignerProvider, SpendableOutputDescriptor, StaticPaymentOutputDescriptor,
+};
+use lightning::util::logger::Logger;
+use lightning::util::ser::{Readable, Writeable, Writer};
+use lightning::{log_debug, log_error, log_info, log_warn};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// The current version of the static channel backup encoding.
+/// Backups with a newer version than this can not be read.
+pub const STATIC_CHANNEL_BACKUP_VERSION: u8 = 1;
+
+/// How often we check on the channels we are recovering
+const RECOVERY_INTERVAL_SECS: u64 = 60;
+
+/// A backup of the channels of all of our nodes.
+///
+/// This does not contain any channel state, it only lets us find our peers
+/// so they can force close the channels and tells us which keys to use to
+/// sweep our balance afterwards. It only needs to be updated when channels
+/// are opened.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StaticChannelBackup {
+    pub created_at: u64,
+    pub nodes: Vec<NodeChannelBackup>,
+}
+
+/// The channels of a single node.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NodeChannelBackup {
+    pub child_index: u32,
+    pub pubkey: PublicKey,
+    pub channels: Vec<ChannelBackup>,
+}
+
+/// Everything needed to recover the funds of a single channel.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChannelBackup {
+    pub channel_id: ChannelId,
+    pub funding_txo: OutPoint,
+    pub counterparty: PublicKey,
+    /// Where to reach the peer, if we have it saved
+    pub peer_connection_string: Option<String>,
+    /// Used to derive the key that our side of the channel is paid to
+    pub channel_keys_id: [u8; 32],
+    /// If the channel was already closing when the backup was made
+    pub closed: bool,
+}
+
+impl Writeable for ChannelBackup {
+    fn write<W: Writer>(&self, w: &mut W) -> Result<(), lightning::io::Error> {
+        self.channel_id.write(w)?;
+        self.funding_txo.write(w)?;
+        self.counterparty.write(w)?;
+        self.peer_connection_string.write(w)?;
+        self.channel_keys_id.write(w)?;
+        self.closed.write(w)
+    }
+}
+
+impl Readable for ChannelBackup {
+    fn read<R: Read>(r: &mut R) -> Result<Self, DecodeError> {
+        Ok(Self {
+            channel_id: Readable::read(r)?,
+            funding_txo: Readable::read(r)?,
+            counterparty: Readable::read(r)?,
+            peer_connection_string: Readable::read(r)?,
+            channel_keys_id: Readable::read(r)?,
+            closed: Readable::read(r)?,
+        })
+    }
+}
+
+impl Writeable for NodeChannelBackup {
+    fn write<W: Writer>(&self, w: &mut W) -> Result<(), lightning::io::Error> {
+        self.child_index.write(w)?;
+        self.pubkey.write(w)?;
+        (self.channels.len() as u16).write(w)?;
+        for channel in self.channels.iter() {
+            channel.write(w)?;
+        }
+        Ok(())
+    }
+}
+
+impl Readable for NodeChannelBackup {
+    fn read<R: Read>(r: &mut R) -> Result<Self, DecodeError> {
+        let child_index = Readable::read(r)?;
+        let pubkey = Readable::read(r)?;
+        let len: u16 = Readable::read(r)?;
+        let mut channels = Vec::with_capacity(len as usize);
+        for _ in 0..len {
+            channels.push(Readable::read(r)?);
+        }
+
+        Ok(Self {
+            child_index,
+            pubkey,
+            channels,
+        })
+    }
+}
+
+impl Writeable for StaticChannelBackup {
+    fn write<W: Writer>(&self, w: &mut W) -> Result<(), lightning::io::Error> {
+        STATIC_CHANNEL_BACKUP_VERSION.write(w)?;
+        self.created_at.write(w)?;
+        (self.nodes.len() as u16).write(w)?;
+        for node in self.nodes.iter() {
+            node.write(w)?;
+        }
+        Ok(())
+    }
+}
+
+impl Readable for StaticChannelBackup {
+    fn read<R: Read>(r: &mut R) -> Result<Self, DecodeError> {
+        let version: u8 = Readable::read(r)?;
+        if version == 0 || version > STATIC_CHANNEL_BACKUP_VERSION {
+            return Err(DecodeError::UnknownVersion);
+        }
+
+        let created_at = Readable::read(r)?;
+        let len: u16 = Readable::read(r)?;
+        let mut nodes = Vec::with_capacity(len as usize);
+        for _ in 0..len {
+            nodes.push(Readable::read(r)?);
+        }
+
+        Ok(Self { created_at, nodes })
+    }
+}
+
+/// Backups are shared as hex strings
+impl fmt::Display for StaticChannelBackup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.encode().to_hex())
+    }
+}
+
+impl FromStr for StaticChannelBackup {
+    type Err = MutinyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = Vec::<u8>::from_hex(s.trim())?;
+        Ok(Self::read(&mut Cursor::new(bytes))?)
+    }
+}
+
+/// Recovers the funds of channels we lost the state of.
+///
+/// Once connected to the peer we ask them to force close the channel. After
+/// their commitment transaction confirms we sweep our `to_remote` output
+/// like any other spendable output.
+pub(crate) struct ChannelRecovery<S: MutinyStorage> {
+    pub persister: Arc<MutinyNodePersister<S>>,
+    pub keys_manager: Arc<PhantomKeysManager<S>>,
+    pub event_handler: EventHandler<S>,
+    pub message_handler: Arc<MutinyMessageHandler<S>>,
+    pub peer_manager: Arc<PeerManagerImpl<S>>,
+    pub chain_source: Arc<dyn ChainSource>,
+    pub logger: Arc<MutinyLogger>,
+    pub running: Arc<AtomicBool>,
+    pub stop: Arc<AtomicBool>,
+}
+
+impl<S: MutinyStorage> ChannelRecovery<S> {
+    /// Runs until all pending channels are recovered or the node is stopped
+    pub(crate) async fn run(self) {
+        loop {
+            if self.stop.load(Ordering::Relaxed) {
+                break;
+            }
+
+            let channels = match self.persister.get_channel_recovery() {
+                Ok(channels) => channels,
+                Err(e) => {
+                    log_error!(self.logger, "could not read channel recovery: {e}");
+                    break;
+                }
+            };
+            if channels.is_empty() {
+                log_info!(self.logger, "channel recovery complete");
+                break;
+            }
+
+            let mut resolved = vec![];
+            for channel in channels {
+                match self.recover_channel(&channel).await {
+                    Ok(true) => {
+                        log_info!(
+                            self.logger,
+                            "recovered channel {}",
+                            channel.channel_id.0.to_hex()
+                        );
+                        resolved.push(channel.channel_id);
+                    }
+                    Ok(false) => {}
+                    Err(e) => log_warn!(
+                        self.logger,
+                        "could not recover channel {}: {e}",
+                        channel.channel_id.0.to_hex()
+                    ),
+                }
+            }
+
+            // re-read so we keep any channels that were added while we were checking
+            if !resolved.is_empty() {
+                let res = self
+                    .persister
+                    .get_channel_recovery()
+                    .and_then(|mut pending| {
+                        pending.retain(|c| !resolved.contains(&c.channel_id));
+                        self.persister.set_channel_recovery(pending)
+                    });
+                if let Err(e) = res {
+                    log_error!(self.logger, "could not save channel recovery: {e}");
+                }
+            }
+
+            // sends any force close requests we queued
+            self.peer_manager.process_events();
+
+            for _ in 0..RECOVERY_INTERVAL_SECS {
+                if self.stop.load(Ordering::Relaxed) {
+                    break;
+                }
+                sleep(1_000).await;
+            }
+        }
+
+        self.running.store(false, Ordering::Relaxed);
+    }
+
+    /// Returns true once there is nothing left to do for the channel
+    async fn recover_channel(&self, channel: &ChannelBackup) -> Result<bool, MutinyError> {
+        let funding_txid = channel.funding_txo.txid;
+        let funding_index = channel.funding_txo.index as usize;
+        let spend = self
+            .chain_source
+            .get_output_spend(&channel.funding_txo.into_bitcoin_outpoint())
+            .await?;
+
+        let closing_txid = match spend {
+            Some(OutputSpend {
+                txid,
+                confirmation: Some(_),
+            }) => txid,
+            // wait for the closing transaction to confirm
+            Some(_) => return Ok(false),
+            None => {
+                if !channel.closed {
+                    self.request_force_close(channel);
+                }
+                return Ok(false);
+            }
+        };
+
+        let channel_value_satoshis = self
+            .chain_source
+            .get_tx(&funding_txid)
+            .await?
+            .and_then(|tx| tx.output.get(funding_index).map(|o| o.value))
+            .ok_or(MutinyError::ChainAccessFailed)?;
+        let closing_tx = self
+            .chain_source
+            .get_tx(&closing_txid)
+            .await?
+            .ok_or(MutinyError::ChainAccessFailed)?;
+
+        // our balance is paid to our static payment key, find it in the closing tx
+        let signer = self
+            .keys_manager
+            .derive_channel_signer(channel_value_satoshis, channel.channel_keys_id);
+        let payment_point = signer.pubkeys().payment_point;
+        let to_remote = Script::new_v0_p2wpkh(&WPubkeyHash::hash(&payment_point.serialize()));
+
+        let outputs: Vec<SpendableOutputDescriptor> = closing_tx
+            .output
+            .iter()
+            .enumerate()
+            .filter(|(_, o)| o.script_pubkey == to_remote)
+            .map(|(index, output)| {
+                SpendableOutputDescriptor::StaticPaymentOutput(StaticPaymentOutputDescriptor {
+                    outpoint: OutPoint {
+                        txid: closing_txid,
+                        index: index as u16,
+                    },
+                    output: output.clone(),
+                    channel_keys_id: channel.channel_keys_id,
+                    channel_value_satoshis,
+                    channel_transaction_parameters: None,
+                })
+            })
+            .collect();
+
+        if outputs.is_empty() {
+            // either a cooperative close to our wallet or we had no balance
+            log_debug!(
+                self.logger,
+                "no outputs to sweep for channel {}",
+                channel.channel_id.0.to_hex()
+            );
+            return Ok(true);
+        }
+
+        if let Err(e) = self.event_handler.handle_spendable_outputs(&outputs).await {
+            // these are retried when the node starts
+            log_warn!(self.logger, "could not sweep recovered channel: {e}");
+            if let Err(e) = self.persister.persist_failed_spendable_outputs(outputs) {
+                log_error!(
+                    self.logger,
+                    "could not persist failed spendable outputs: {e}"
+                );
+                return Err(MutinyError::PersistenceFailed {
+                    source: MutinyStorageError::Other(e),
+                });
+            }
+        }
+
+        Ok(true)
+    }
+
+    fn request_force_close(&self, channel: &ChannelBackup) {
+        let connected = self
+            .peer_manager
+            .get_peer_node_ids()
+            .iter()
+            .any(|(pk, _)| pk == &channel.counterparty);
+
+        // the node's reconnection loop connects to the peer for us
+        if connected {
+            log_debug!(
+                self.logger,
+                "asking {} to force close channel {}",
+                channel.counterparty,
+                channel.channel_id.0.to_hex()
+            );
+            self.message_handler
+                .request_force_close(channel.counterparty, channel.channel_id);
+        } else {
+            log_debug!(
+                self.logger,
+                "waiting to connect to {} to recover channel",
+                channel.counterparty
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::*;
+    use bitcoin::hashes::Hash;
+    use bitcoin::secp256k1::{Secp256k1, SecretKey};
+    use bitcoin::Txid;
+
+    use wasm_bindgen_test::{wasm_bindgen_test as test, wasm_bindgen_test_configure};
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    fn dummy_pubkey(byte: u8) -> PublicKey {
+        let secret = SecretKey::from_slice(&[byte; 32]).unwrap();
+        PublicKey::from_secret_key(&Secp256k1::new(), &secret)
+    }
+
+    fn dummy_backup() -> StaticChannelBackup {
+        let funding_txo = OutPoint {
+            txid: Txid::all_zeros(),
+            index: 1,
+        };
+        StaticChannelBackup {
+            created_at: 1_700_000_000,
+            nodes: vec![NodeChannelBackup {
+                child_index: 0,
+                pubkey: dummy_pubkey(1),
+                channels: vec![
+                    ChannelBackup {
+                        channel_id: ChannelId::v1_from_funding_outpoint(funding_txo),
+                        funding_txo,
+                        counterparty: dummy_pubkey(2),
+                        peer_connection_string: Some(format!("{}@127.0.0.1:9735", dummy_pubkey(2))),
+                        channel_keys_id: [3; 32],
+                        closed: false,
+                    },
+                    ChannelBackup {
+                        channel_id: ChannelId([4; 32]),
+                        funding_txo: OutPoint {
+                            txid: Txid::all_zeros(),
+                            index: 2,
+                        },
+                        counterparty: dummy_pubkey(5),
+                        peer_connection_string: None,
+                        channel_keys_id: [6; 32],
+                        closed: true,
+                    },
+                ],
+            }],
+        }
+    }
+
+    #[test]
+    fn test_static_channel_backup_roundtrip() {
+        let test_name = "test_static_channel_backup_roundtrip";
+        log!("{}", test_name);
+
+        let backup = dummy_backup();
+        let string = backup.to_string();
+        let parsed = StaticChannelBackup::from_str(&string).unwrap();
+        assert_eq!(parsed, backup);
+
+        // empty backups are valid too
+        let empty = StaticChannelBackup {
+            created_at: 0,
+            nodes: vec![],
+        };
+        assert_eq!(
+            StaticChannelBackup::from_str(&empty.to_string()).unwrap(),
+            empty
+        );
+    }
+
+    #[test]
+    fn test_static_channel_backup_unknown_version() {
+        let test_name = "test_static_channel_backup_unknown_version";
+        log!("{}", test_name);
+
+        let mut bytes = dummy_backup().encode();
+        bytes[0] = STATIC_CHANNEL_BACKUP_VERSION + 1;
+        assert!(StaticChannelBackup::from_str(&bytes.to_hex()).is_err());
+
+        // garbage is rejected
+        assert!(StaticChannelBackup::from_str("deadbeef").is_err());
+    }
+}
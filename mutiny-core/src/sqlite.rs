@@ -0,0 +1,548 @@
+use crate::encrypt::Cipher;
+use crate::error::{MutinyError, MutinyStorageError};
+use crate::gossip::NETWORK_GRAPH_KEY;
+use crate::logging::MutinyLogger;
+use crate::storage::{
+    fetch_newer_vss_value, DeviceLock, MemoryStorage, MutinyStorage, DEVICE_LOCK_KEY,
+};
+use crate::vss::MutinyVssClient;
+use anyhow::anyhow;
+use async_trait::async_trait;
+use lightning::log_debug;
+use lightning::util::logger::Logger;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+
+pub const WALLET_DATABASE_FILE_NAME: &str = "wallet.sqlite";
+const WALLET_TABLE_NAME: &str = "wallet_store";
+
+/// The database used by the last [`SqliteStorage`] that was created.
+///
+/// `import` and `clear` are not called on a storage instance, so they
+/// operate on this database, the same way `IndexedDbStorage` always uses
+/// the same database in the browser.
+static DATABASE_PATH: RwLock<Option<PathBuf>> = RwLock::new(None);
+
+/// A [`MutinyStorage`] backed by a SQLite database on disk.
+///
+/// All values are kept in a single key-value table as JSON, values that
+/// need encryption are encrypted before they are written.
+#[derive(Clone)]
+pub struct SqliteStorage {
+    pub(crate) password: Option<String>,
+    pub cipher: Option<Cipher>,
+    path: PathBuf,
+    /// The connection to the database, this is `None` after the storage has been stopped
+    connection: Arc<Mutex<Option<Connection>>>,
+    vss: Option<Arc<MutinyVssClient>>,
+    logger: Arc<MutinyLogger>,
+}
+
+impl SqliteStorage {
+    /// Opens the wallet database in the given directory, creating it if it does not exist yet.
+    /// If a VSS client is given, any newer values from VSS are pulled into the database.
+    pub async fn new(
+        data_dir: impl AsRef<Path>,
+        password: Option<String>,
+        cipher: Option<Cipher>,
+        vss: Option<Arc<MutinyVssClient>>,
+        logger: Arc<MutinyLogger>,
+    ) -> Result<SqliteStorage, MutinyError> {
+        std::fs::create_dir_all(data_dir.as_ref()).map_err(|e| {
+            MutinyError::write_err(anyhow!("Failed to create data directory: {e}").into())
+        })?;
+        let path = data_dir.as_ref().join(WALLET_DATABASE_FILE_NAME);
+        let connection = Self::open_database(&path)?;
+        *DATABASE_PATH
+            .write()
+            .map_err(|_| MutinyError::write_err(MutinyStorageError::LockError))? =
+            Some(path.clone());
+
+        let storage = SqliteStorage {
+            password: password.filter(|p| !p.is_empty()),
+            cipher,
+            path,
+            connection: Arc::new(Mutex::new(Some(connection))),
+            vss,
+            logger,
+        };
+        storage.sync_from_vss().await?;
+
+        Ok(storage)
+    }
+
+    fn open_database(path: &Path) -> Result<Connection, MutinyError> {
+        let connection = Connection::open(path).map_err(|e| {
+            MutinyError::read_err(anyhow!("Failed to open sqlite database: {e}").into())
+        })?;
+
+        connection
+            .execute_batch(&format!(
+                "PRAGMA journal_mode = WAL;
+                CREATE TABLE IF NOT EXISTS {WALLET_TABLE_NAME} (
+                    key TEXT PRIMARY KEY NOT NULL,
+                    value TEXT NOT NULL
+                );"
+            ))
+            .map_err(|e| {
+                MutinyError::read_err(anyhow!("Failed to create sqlite tables: {e}").into())
+            })?;
+
+        Ok(connection)
+    }
+
+    fn database_path() -> Result<PathBuf, MutinyError> {
+        DATABASE_PATH
+            .read()
+            .map_err(|_| MutinyError::read_err(MutinyStorageError::LockError))?
+            .clone()
+            .ok_or(MutinyError::NotRunning)
+    }
+
+    /// Runs the given function with the open database connection
+    fn with_connection<T>(
+        &self,
+        f: impl FnOnce(&mut Connection) -> rusqlite::Result<T>,
+    ) -> Result<T, MutinyError> {
+        let mut lock = self
+            .connection
+            .lock()
+            .map_err(|_| MutinyError::read_err(MutinyStorageError::LockError))?;
+        let connection = lock.as_mut().ok_or(MutinyError::NotRunning)?;
+
+        f(connection).map_err(|e| MutinyError::read_err(anyhow!("Sqlite error: {e}").into()))
+    }
+
+    fn save_to_database(
+        connection: &mut Connection,
+        items: &[(String, Value)],
+    ) -> rusqlite::Result<()> {
+        let tx = connection.transaction()?;
+        {
+            let mut stmt = tx.prepare_cached(&format!(
+                "INSERT OR REPLACE INTO {WALLET_TABLE_NAME} (key, value) VALUES (?1, ?2)"
+            ))?;
+            for (key, value) in items {
+                stmt.execute(params![key, value.to_string()])?;
+            }
+        }
+        tx.commit()
+    }
+
+    fn read_all(connection: &mut Connection) -> rusqlite::Result<Vec<(String, String)>> {
+        let mut stmt =
+            connection.prepare(&format!("SELECT key, value FROM {WALLET_TABLE_NAME}"))?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect()
+    }
+
+    /// Pulls any values from VSS that are newer than what we have locally and saves them.
+    async fn sync_from_vss(&self) -> Result<(), MutinyError> {
+        let Some(vss) = self.vss.as_deref() else {
+            return Ok(());
+        };
+
+        log_debug!(self.logger, "Reading from vss");
+
+        // use a memory storage to handle encryption and decryption
+        let map = MemoryStorage::new(self.password.clone(), self.cipher.clone(), None);
+        for (key, value) in self.with_connection(Self::read_all)? {
+            // we no longer need to read this key
+            if key == NETWORK_GRAPH_KEY {
+                continue;
+            }
+
+            let json: Value = serde_json::from_str(&value)?;
+            map.set(vec![(key, json)])?;
+        }
+
+        let keys = vss.list_key_versions(None).await?;
+        let mut futs = vec![];
+        for kv in keys {
+            futs.push(fetch_newer_vss_value(kv, vss, &map, &self.logger));
+        }
+        let results = futures::future::join_all(futs).await;
+        let mut items = Vec::new();
+        for result in results {
+            if let Some((key, value)) = result? {
+                map.set_data(key, value, None)?;
+            }
+        }
+
+        // write the values encrypted the same way we have them in memory
+        let memory = map
+            .memory
+            .read()
+            .map_err(|e| MutinyError::read_err(e.into()))?;
+        for (key, value) in memory.iter() {
+            items.push((key.clone(), value.clone()));
+        }
+        drop(memory);
+
+        if !items.is_empty() {
+            self.with_connection(|c| Self::save_to_database(c, &items))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl MutinyStorage for SqliteStorage {
+    fn password(&self) -> Option<&str> {
+        self.password.as_deref()
+    }
+
+    fn cipher(&self) -> Option<Cipher> {
+        self.cipher.to_owned()
+    }
+
+    fn vss_client(&self) -> Option<Arc<MutinyVssClient>> {
+        self.vss.clone()
+    }
+
+    fn set(&self, items: Vec<(String, impl Serialize)>) -> Result<(), MutinyError> {
+        let items = items
+            .into_iter()
+            .map(|(k, v)| {
+                serde_json::to_value(v)
+                    .map_err(|e| MutinyError::PersistenceFailed {
+                        source: MutinyStorageError::SerdeError { source: e },
+                    })
+                    .map(|d| (k, d))
+            })
+            .collect::<Result<Vec<(String, Value)>, MutinyError>>()?;
+
+        self.with_connection(|c| Self::save_to_database(c, &items))
+    }
+
+    fn get<T>(&self, key: impl AsRef<str>) -> Result<Option<T>, MutinyError>
+    where
+        T: for<'de> Deserialize<'de>,
+    {
+        let value: Option<String> = self.with_connection(|c| {
+            c.query_row(
+                &format!("SELECT value FROM {WALLET_TABLE_NAME} WHERE key = ?1"),
+                params![key.as_ref()],
+                |row| row.get(0),
+            )
+            .optional()
+        })?;
+
+        match value {
+            None => Ok(None),
+            Some(value) => {
+                let data: T = serde_json::from_str(&value)?;
+                Ok(Some(data))
+            }
+        }
+    }
+
+    fn delete(&self, keys: &[impl AsRef<str>]) -> Result<(), MutinyError> {
+        self.with_connection(|c| {
+            let tx = c.transaction()?;
+            {
+                let mut stmt =
+                    tx.prepare_cached(&format!("DELETE FROM {WALLET_TABLE_NAME} WHERE key = ?1"))?;
+                for key in keys {
+                    stmt.execute(params![key.as_ref()])?;
+                }
+            }
+            tx.commit()
+        })
+    }
+
+    async fn start(&mut self) -> Result<(), MutinyError> {
+        {
+            let mut lock = self
+                .connection
+                .lock()
+                .map_err(|_| MutinyError::read_err(MutinyStorageError::LockError))?;
+            if lock.is_none() {
+                *lock = Some(Self::open_database(&self.path)?);
+            }
+        }
+
+        self.sync_from_vss().await
+    }
+
+    fn stop(&self) {
+        if let Ok(mut lock) = self.connection.lock() {
+            // dropping the connection closes it
+            lock.take();
+        }
+    }
+
+    fn connected(&self) -> Result<bool, MutinyError> {
+        let lock = self
+            .connection
+            .lock()
+            .map_err(|_| MutinyError::read_err(MutinyStorageError::LockError))?;
+        Ok(lock.is_some())
+    }
+
+    fn scan_keys(&self, prefix: &str, suffix: Option<&str>) -> Result<Vec<String>, MutinyError> {
+        let keys: Vec<String> = self.with_connection(|c| {
+            let mut stmt = c.prepare(&format!("SELECT key FROM {WALLET_TABLE_NAME}"))?;
+            let rows = stmt.query_map([], |row| row.get(0))?;
+            rows.collect()
+        })?;
+
+        // filter in rust rather than with LIKE, keys can contain `_` and `%`
+        Ok(keys
+            .into_iter()
+            .filter(|key| {
+                key.starts_with(prefix) && (suffix.is_none() || key.ends_with(suffix.unwrap()))
+            })
+            .collect())
+    }
+
+    fn change_password(
+        &mut self,
+        new: Option<String>,
+        new_cipher: Option<Cipher>,
+    ) -> Result<(), MutinyError> {
+        self.password = new;
+        self.cipher = new_cipher;
+        Ok(())
+    }
+
+    async fn import(json: Value) -> Result<(), MutinyError> {
+        Self::clear().await?;
+
+        let map = json
+            .as_object()
+            .ok_or(MutinyError::write_err(MutinyStorageError::Other(anyhow!(
+                "json is not an object"
+            ))))?;
+        let items: Vec<(String, Value)> = map
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+
+        let mut connection = Self::open_database(&Self::database_path()?)?;
+        Self::save_to_database(&mut connection, &items).map_err(|e| {
+            MutinyError::write_err(anyhow!("Failed to write to sqlite: {e}").into())
+        })?;
+
+        Ok(())
+    }
+
+    async fn clear() -> Result<(), MutinyError> {
+        let connection = Self::open_database(&Self::database_path()?)?;
+        connection
+            .execute(&format!("DELETE FROM {WALLET_TABLE_NAME}"), [])
+            .map_err(|e| MutinyError::write_err(anyhow!("Failed clear sqlite: {e}").into()))?;
+
+        Ok(())
+    }
+
+    async fn fetch_device_lock(&self) -> Result<Option<DeviceLock>, MutinyError> {
+        match self.vss.as_ref() {
+            None => self.get_device_lock(),
+            Some(vss) => {
+                let json = vss.get_object(DEVICE_LOCK_KEY).await?;
+                let device_lock = serde_json::from_value(json.value)?;
+                Ok(Some(device_lock))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encrypt::encryption_key_from_pass;
+    use crate::generate_seed;
+    use crate::storage::MNEMONIC_KEY;
+    use crate::test_utils::*;
+    use serde_json::json;
+
+    /// `import` and `clear` use the last opened database, so tests
+    /// that open a database can't run at the same time.
+    static DATABASE_LOCK: async_lock::Mutex<()> = async_lock::Mutex::new(());
+
+    fn temp_dir(test_name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("{test_name}-{}", uuid::Uuid::new_v4()))
+    }
+
+    #[tokio::test]
+    async fn test_empty_string_as_none() {
+        let test_name = "test_empty_string_as_none";
+        log!("{test_name}");
+        let _lock = DATABASE_LOCK.lock().await;
+
+        let logger = Arc::new(MutinyLogger::default());
+        let storage = SqliteStorage::new(
+            temp_dir(test_name),
+            Some("".to_string()),
+            None,
+            None,
+            logger,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(storage.password, None);
+    }
+
+    #[tokio::test]
+    async fn test_get_set_delete() {
+        let test_name = "test_get_set_delete";
+        log!("{test_name}");
+        let _lock = DATABASE_LOCK.lock().await;
+
+        let key = "test_key".to_string();
+        let value = "test_value";
+
+        let dir = temp_dir(test_name);
+        let logger = Arc::new(MutinyLogger::default());
+        let password = "password".to_string();
+        let cipher = encryption_key_from_pass(&password).unwrap();
+        let storage = SqliteStorage::new(
+            &dir,
+            Some(password.clone()),
+            Some(cipher.clone()),
+            None,
+            logger.clone(),
+        )
+        .await
+        .unwrap();
+
+        let result: Option<String> = storage.get(&key).unwrap();
+        assert_eq!(result, None);
+
+        storage.set(vec![(key.clone(), value)]).unwrap();
+
+        let result: Option<String> = storage.get(&key).unwrap();
+        assert_eq!(result, Some(value.to_string()));
+
+        // stop and reopen the database and check again
+        storage.stop();
+        assert!(!storage.connected().unwrap());
+        let storage = SqliteStorage::new(&dir, Some(password), Some(cipher), None, logger)
+            .await
+            .unwrap();
+        let result: Option<String> = storage.get(&key).unwrap();
+        assert_eq!(result, Some(value.to_string()));
+
+        storage.delete(&[key.clone()]).unwrap();
+
+        let result: Option<String> = storage.get(&key).unwrap();
+        assert_eq!(result, None);
+    }
+
+    #[tokio::test]
+    async fn test_scan_keys() {
+        let test_name = "test_scan_keys";
+        log!("{test_name}");
+        let _lock = DATABASE_LOCK.lock().await;
+
+        let logger = Arc::new(MutinyLogger::default());
+        let storage = SqliteStorage::new(temp_dir(test_name), None, None, None, logger)
+            .await
+            .unwrap();
+
+        storage
+            .set(vec![
+                ("payment_inbound/a_1".to_string(), "a"),
+                ("payment_inbound/b_2".to_string(), "b"),
+                ("payment_outbound/c_1".to_string(), "c"),
+            ])
+            .unwrap();
+
+        let mut keys = storage.scan_keys("payment_inbound/", None).unwrap();
+        keys.sort();
+        assert_eq!(keys, vec!["payment_inbound/a_1", "payment_inbound/b_2"]);
+
+        let keys = storage.scan_keys("payment_", Some("_2")).unwrap();
+        assert_eq!(keys, vec!["payment_inbound/b_2"]);
+
+        let values: HashMap<String, String> = storage.scan("payment_outbound/", None).unwrap();
+        assert_eq!(values.get("payment_outbound/c_1"), Some(&"c".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_import_and_clear() {
+        let test_name = "test_import_and_clear";
+        log!("{test_name}");
+        let _lock = DATABASE_LOCK.lock().await;
+
+        let logger = Arc::new(MutinyLogger::default());
+        let storage = SqliteStorage::new(temp_dir(test_name), None, None, None, logger)
+            .await
+            .unwrap();
+
+        let json = json!(
+            {
+                "test_key": "test_value",
+                "test_key2": "test_value2"
+            }
+        );
+
+        SqliteStorage::import(json).await.unwrap();
+
+        let result: Option<String> = storage.get("test_key").unwrap();
+        assert_eq!(result, Some("test_value".to_string()));
+
+        let result: Option<String> = storage.get("test_key2").unwrap();
+        assert_eq!(result, Some("test_value2".to_string()));
+
+        SqliteStorage::clear().await.unwrap();
+
+        let result: Option<String> = storage.get("test_key").unwrap();
+        assert_eq!(result, None);
+    }
+
+    #[tokio::test]
+    async fn test_change_password() {
+        let test_name = "test_change_password";
+        log!("{test_name}");
+        let _lock = DATABASE_LOCK.lock().await;
+
+        let dir = temp_dir(test_name);
+        let logger = Arc::new(MutinyLogger::default());
+        let password = Some("password".to_string());
+        let cipher = encryption_key_from_pass("password").unwrap();
+        let mut storage =
+            SqliteStorage::new(&dir, password.clone(), Some(cipher), None, logger.clone())
+                .await
+                .unwrap();
+
+        let seed = generate_seed(12).unwrap();
+        storage.insert_mnemonic(seed.clone()).unwrap();
+
+        let new_password = Some("new_password".to_string());
+        storage
+            .change_password_and_rewrite_storage(password.clone(), new_password.clone())
+            .unwrap();
+        assert_eq!(storage.get_mnemonic().unwrap(), Some(seed.clone()));
+        storage.stop();
+
+        // the old password should no longer work
+        let cipher = encryption_key_from_pass("password").unwrap();
+        let storage = SqliteStorage::new(&dir, password, Some(cipher), None, logger.clone())
+            .await
+            .unwrap();
+        match storage.get_mnemonic() {
+            Err(MutinyError::IncorrectPassword) => (),
+            Ok(_) => panic!("Expected IncorrectPassword error, got Ok"),
+            Err(e) => panic!("Expected IncorrectPassword error, got {:?}", e),
+        }
+        storage.stop();
+
+        let cipher = encryption_key_from_pass("new_password").unwrap();
+        let storage = SqliteStorage::new(&dir, new_password, Some(cipher), None, logger)
+            .await
+            .unwrap();
+        assert_eq!(storage.get_mnemonic().unwrap(), Some(seed));
+
+        // the mnemonic should never be stored in plaintext
+        let raw: Option<Value> = storage.get(MNEMONIC_KEY).unwrap();
+        assert!(raw.is_some_and(|v| v.is_string()));
+    }
+}
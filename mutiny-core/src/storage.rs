@@ -1,11 +1,12 @@
-use crate::ldkstorage::CHANNEL_MANAGER_KEY;
+use crate::ldkstorage::{CHANNEL_MANAGER_KEY, MONITORS_PREFIX_KEY};
 use crate::logging::MutinyLogger;
 use crate::nodemanager::{NodeStorage, DEVICE_LOCK_INTERVAL_SECS};
-use crate::utils::{now, spawn};
-use crate::vss::{MutinyVssClient, VssKeyValueItem};
+use crate::onchain::{FrozenUtxos, FROZEN_UTXOS_KEY};
+use crate::utils::{get_monitor_version, now, spawn};
+use crate::vss::{KeyVersion, MutinyVssClient, VssKeyValueItem};
 use crate::{
     encrypt::{decrypt_with_password, encrypt, encryption_key_from_pass, Cipher},
-    federation::FederationStorage,
+    federation::{FederationStorage, FEDIMINTS_PREFIX_KEY},
 };
 use crate::{
     error::{MutinyError, MutinyStorageError},
@@ -16,9 +17,10 @@ use bdk::chain::{Append, PersistBackend};
 use bip39::Mnemonic;
 use bitcoin::hashes::hex::ToHex;
 use bitcoin::hashes::Hash;
+use bitcoin::secp256k1::PublicKey;
 use hex::FromHex;
 use lightning::{ln::PaymentHash, util::logger::Logger};
-use lightning::{log_error, log_trace};
+use lightning::{log_debug, log_error, log_trace};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
@@ -39,6 +41,9 @@ pub const DEVICE_LOCK_KEY: &str = "device_lock";
 pub(crate) const EXPECTED_NETWORK_KEY: &str = "network";
 const PAYMENT_INBOUND_PREFIX_KEY: &str = "payment_inbound/";
 const PAYMENT_OUTBOUND_PREFIX_KEY: &str = "payment_outbound/";
+const OFFER_PAYMENT_PREFIX_KEY: &str = "offer_payment/";
+const BOLT12_RECEIVE_PREFIX_KEY: &str = "bolt12_receive/";
+const BOLT12_INVOICE_PREFIX_KEY: &str = "bolt12_invoice/";
 pub const LAST_DM_SYNC_TIME_KEY: &str = "last_dm_sync_time";
 
 fn needs_encryption(key: &str) -> bool {
@@ -693,6 +698,186 @@ impl MutinyStorage for () {
     }
 }
 
+/// Checks a key from VSS against the value we have in the given storage.
+/// Returns the value from VSS if it is newer than our local value,
+/// so storage implementations can use this when syncing from VSS on startup.
+pub async fn fetch_newer_vss_value<S: MutinyStorage>(
+    kv: KeyVersion,
+    vss: &MutinyVssClient,
+    current: &S,
+    logger: &MutinyLogger,
+) -> Result<Option<(String, Value)>, MutinyError> {
+    log_debug!(
+        logger,
+        "Found vss key {} with version {}",
+        kv.key,
+        kv.version
+    );
+
+    match kv.key.as_str() {
+        NODES_KEY => {
+            // we can get version from node storage, so we should compare
+            match current.get_data::<NodeStorage>(&kv.key)? {
+                Some(local) => {
+                    if local.version < kv.version {
+                        let obj = vss.get_object(&kv.key).await?;
+                        if serde_json::from_value::<NodeStorage>(obj.value.clone()).is_ok() {
+                            return Ok(Some((kv.key, obj.value)));
+                        }
+                    }
+                }
+                None => {
+                    let obj = vss.get_object(&kv.key).await?;
+                    return Ok(Some((kv.key, obj.value)));
+                }
+            }
+        }
+        FEDERATIONS_KEY => {
+            // we can get version from federation storage, so we should compare
+            match current.get_data::<FederationStorage>(&kv.key)? {
+                Some(local) => {
+                    if local.version < kv.version {
+                        let obj = vss.get_object(&kv.key).await?;
+                        if serde_json::from_value::<FederationStorage>(obj.value.clone()).is_ok() {
+                            return Ok(Some((kv.key, obj.value)));
+                        }
+                    }
+                }
+                None => {
+                    let obj = vss.get_object(&kv.key).await?;
+                    return Ok(Some((kv.key, obj.value)));
+                }
+            }
+        }
+        FROZEN_UTXOS_KEY => {
+            // we can get version from the frozen utxos, so we should compare
+            match current.get_data::<FrozenUtxos>(&kv.key)? {
+                Some(local) => {
+                    if local.version < kv.version {
+                        let obj = vss.get_object(&kv.key).await?;
+                        if serde_json::from_value::<FrozenUtxos>(obj.value.clone()).is_ok() {
+                            return Ok(Some((kv.key, obj.value)));
+                        }
+                    }
+                }
+                None => {
+                    let obj = vss.get_object(&kv.key).await?;
+                    return Ok(Some((kv.key, obj.value)));
+                }
+            }
+        }
+        DEVICE_LOCK_KEY => {
+            // we can get version from device lock, so we should compare
+            match current.get_data::<DeviceLock>(&kv.key)? {
+                Some(lock) => {
+                    // we use time as version for device lock
+                    if lock.time < kv.version {
+                        let obj = vss.get_object(&kv.key).await?;
+                        if serde_json::from_value::<DeviceLock>(obj.value.clone()).is_ok() {
+                            return Ok(Some((kv.key, obj.value)));
+                        }
+                    }
+                }
+                None => {
+                    let obj = vss.get_object(&kv.key).await?;
+                    return Ok(Some((kv.key, obj.value)));
+                }
+            }
+        }
+        key => {
+            if key.starts_with(MONITORS_PREFIX_KEY) {
+                // we can get versions from monitors, so we should compare
+                match current.get::<Vec<u8>>(&kv.key)? {
+                    Some(bytes) => {
+                        let current_version = get_monitor_version(&bytes);
+
+                        // if the current version is less than the version from vss, then we want to use the vss version
+                        if current_version < kv.version as u64 {
+                            let obj = vss.get_object(&kv.key).await?;
+                            return Ok(Some((kv.key, obj.value)));
+                        } else {
+                            log_debug!(
+                                logger,
+                                "Skipping vss key {} with version {}, current version is {current_version}",
+                                kv.key,
+                                kv.version
+                            );
+                            return Ok(None);
+                        }
+                    }
+                    None => {
+                        let obj = vss.get_object(&kv.key).await?;
+                        return Ok(Some((kv.key, obj.value)));
+                    }
+                }
+            } else if key.starts_with(CHANNEL_MANAGER_KEY) {
+                // we can get versions from channel manager, so we should compare
+                match current.get_data::<VersionedValue>(&kv.key)? {
+                    Some(local) => {
+                        if local.version < kv.version {
+                            let obj = vss.get_object(&kv.key).await?;
+                            if serde_json::from_value::<VersionedValue>(obj.value.clone()).is_ok() {
+                                return Ok(Some((kv.key, obj.value)));
+                            }
+                        } else {
+                            log_debug!(
+                                logger,
+                                "Skipping vss key {} with version {}, current version is {}",
+                                kv.key,
+                                kv.version,
+                                local.version
+                            );
+                            return Ok(None);
+                        }
+                    }
+                    None => {
+                        let obj = vss.get_object(&kv.key).await?;
+                        if serde_json::from_value::<VersionedValue>(obj.value.clone()).is_ok() {
+                            return Ok(Some((kv.key, obj.value)));
+                        }
+                    }
+                }
+            } else if key.starts_with(FEDIMINTS_PREFIX_KEY) {
+                // we can get versions from each fedimint, so we should compare
+                match current.get_data::<VersionedValue>(&kv.key)? {
+                    Some(local) => {
+                        if local.version < kv.version {
+                            let obj = vss.get_object(&kv.key).await?;
+                            if serde_json::from_value::<VersionedValue>(obj.value.clone()).is_ok() {
+                                return Ok(Some((kv.key, obj.value)));
+                            }
+                        } else {
+                            log_debug!(
+                                logger,
+                                "Skipping vss key {} with version {}, current version is {}",
+                                kv.key,
+                                kv.version,
+                                local.version
+                            );
+                            return Ok(None);
+                        }
+                    }
+                    None => {
+                        let obj = vss.get_object(&kv.key).await?;
+                        if serde_json::from_value::<VersionedValue>(obj.value.clone()).is_ok() {
+                            return Ok(Some((kv.key, obj.value)));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    log_debug!(
+        logger,
+        "Skipping vss key {} with version {}",
+        kv.key,
+        kv.version
+    );
+
+    Ok(None)
+}
+
 fn payment_key(inbound: bool, payment_hash: &[u8; 32]) -> String {
     if inbound {
         format!(
@@ -721,6 +906,74 @@ pub(crate) fn persist_payment_info<S: MutinyStorage>(
         .map_err(std::io::Error::other)
 }
 
+pub(crate) fn delete_payment_info<S: MutinyStorage>(
+    storage: &S,
+    payment_hash: &[u8; 32],
+    inbound: bool,
+) -> Result<(), MutinyError> {
+    storage.delete(&[payment_key(inbound, payment_hash)])
+}
+
+/// Offer payments are saved under their payment id until the invoice gives us
+/// their payment hash, this remembers which payment hash they were moved to.
+pub(crate) fn persist_offer_payment_hash<S: MutinyStorage>(
+    storage: &S,
+    payment_id: &[u8; 32],
+    payment_hash: &[u8; 32],
+) -> Result<(), MutinyError> {
+    let key = format!("{OFFER_PAYMENT_PREFIX_KEY}{}", payment_id.to_hex());
+    storage.set_data(key, payment_hash.to_hex(), None)
+}
+
+/// The payment hash an offer payment was moved to, if we know it yet
+pub(crate) fn read_offer_payment_hash<S: MutinyStorage>(
+    storage: &S,
+    payment_id: &[u8; 32],
+) -> Option<[u8; 32]> {
+    let key = format!("{OFFER_PAYMENT_PREFIX_KEY}{}", payment_id.to_hex());
+    let payment_hash: String = storage.get_data(key).ok().flatten()?;
+    FromHex::from_hex(&payment_hash).ok()
+}
+
+/// Saves a BOLT12 offer we created, or refund we requested payment for, under
+/// the key the invoices we send for it are tied to: the offer's signing pubkey
+/// or the refund's payer id.
+pub(crate) fn persist_bolt12_receive<S: MutinyStorage>(
+    storage: &S,
+    pubkey: &PublicKey,
+    bolt12: String,
+) -> Result<(), MutinyError> {
+    let key = format!("{BOLT12_RECEIVE_PREFIX_KEY}{}", pubkey.serialize().to_hex());
+    storage.set_data(key, bolt12, None)
+}
+
+pub(crate) fn read_bolt12_receive<S: MutinyStorage>(
+    storage: &S,
+    pubkey: &PublicKey,
+) -> Option<String> {
+    let key = format!("{BOLT12_RECEIVE_PREFIX_KEY}{}", pubkey.serialize().to_hex());
+    storage.get_data(key).ok().flatten()
+}
+
+/// Saves the offer or refund an invoice we sent was for, so the payment
+/// can be matched to it by its payment hash once it is received.
+pub(crate) fn persist_bolt12_invoice_receive<S: MutinyStorage>(
+    storage: &S,
+    payment_hash: &[u8; 32],
+    bolt12: String,
+) -> Result<(), MutinyError> {
+    let key = format!("{BOLT12_INVOICE_PREFIX_KEY}{}", payment_hash.to_hex());
+    storage.set_data(key, bolt12, None)
+}
+
+pub(crate) fn read_bolt12_invoice_receive<S: MutinyStorage>(
+    storage: &S,
+    payment_hash: &[u8; 32],
+) -> Option<String> {
+    let key = format!("{BOLT12_INVOICE_PREFIX_KEY}{}", payment_hash.to_hex());
+    storage.get_data(key).ok().flatten()
+}
+
 pub(crate) fn get_payment_info<S: MutinyStorage>(
     storage: &S,
     payment_hash: &bitcoin::hashes::sha256::Hash,
@@ -823,6 +1076,7 @@ where
 
 #[cfg(test)]
 mod tests {
+    use crate::storage::{persist_bolt12_invoice_receive, read_bolt12_invoice_receive};
     use crate::test_utils::*;
     use crate::utils::sleep;
     use crate::{encrypt::encryption_key_from_pass, storage::MemoryStorage};
@@ -862,6 +1116,32 @@ mod tests {
         assert_eq!(Some(mnemonic), stored_mnemonic);
     }
 
+    #[test]
+    fn test_bolt12_invoice_receives() {
+        let test_name = "test_bolt12_invoice_receives";
+        log!("{}", test_name);
+
+        let storage = MemoryStorage::default();
+        let offer = "lno1offer".to_string();
+        let refund = "lnr1refund".to_string();
+
+        // an offer can be paid many times, each payment is kept separately
+        persist_bolt12_invoice_receive(&storage, &[1; 32], offer.clone()).unwrap();
+        persist_bolt12_invoice_receive(&storage, &[2; 32], offer.clone()).unwrap();
+        persist_bolt12_invoice_receive(&storage, &[3; 32], refund.clone()).unwrap();
+
+        assert_eq!(
+            read_bolt12_invoice_receive(&storage, &[1; 32]),
+            Some(offer.clone())
+        );
+        assert_eq!(read_bolt12_invoice_receive(&storage, &[2; 32]), Some(offer));
+        assert_eq!(
+            read_bolt12_invoice_receive(&storage, &[3; 32]),
+            Some(refund)
+        );
+        assert_eq!(read_bolt12_invoice_receive(&storage, &[4; 32]), None);
+    }
+
     #[test]
     async fn test_device_lock() {
         let test_name = "test_device_lock";
@@ -43,6 +43,10 @@ pub fn create_nwc_request(nwc: &NostrWalletConnectURI, invoice: String) -> Event
         params: RequestParams::PayInvoice(PayInvoiceRequestParams { invoice }),
     };
 
+    create_nwc_request_event(nwc, req)
+}
+
+pub fn create_nwc_request_event(nwc: &NostrWalletConnectURI, req: Request) -> Event {
     let encrypted = encrypt(&nwc.secret, &nwc.public_key, req.as_json()).unwrap();
     let p_tag = Tag::PublicKey {
         public_key: nwc.public_key,
@@ -88,18 +92,12 @@ pub(crate) async fn create_node<S: MutinyStorage>(storage: S) -> Node<S> {
     ))));
 
     let esplora_server_url = get_esplora_url(network, None);
-    let esplora = esplora_client::Builder::new(&esplora_server_url)
-        .build_async()
-        .unwrap();
-    let tx_sync = Arc::new(EsploraSyncClient::from_client(
-        esplora.clone(),
-        logger.clone(),
-    ));
-    let esplora = Arc::new(esplora);
+    let chain_source: Arc<dyn ChainSource> =
+        Arc::new(EsploraChainSource::new(&esplora_server_url, logger.clone()).unwrap());
     let fee_estimator = Arc::new(MutinyFeeEstimator::new(
         storage.clone(),
         network,
-        esplora.clone(),
+        chain_source.clone(),
         logger.clone(),
     ));
 
@@ -108,7 +106,7 @@ pub(crate) async fn create_node<S: MutinyStorage>(storage: S) -> Node<S> {
             xprivkey,
             storage.clone(),
             network,
-            esplora.clone(),
+            chain_source.clone(),
             fee_estimator.clone(),
             stop.clone(),
             logger.clone(),
@@ -116,7 +114,11 @@ pub(crate) async fn create_node<S: MutinyStorage>(storage: S) -> Node<S> {
         .unwrap(),
     );
 
-    let chain = Arc::new(MutinyChain::new(tx_sync, wallet.clone(), logger.clone()));
+    let chain = Arc::new(MutinyChain::new(
+        chain_source,
+        wallet.clone(),
+        logger.clone(),
+    ));
 
     let mut node_builder = NodeBuilder::new(xprivkey, storage)
         .with_uuid(Uuid::new_v4().to_string())
@@ -126,7 +128,6 @@ pub(crate) async fn create_node<S: MutinyStorage>(storage: S) -> Node<S> {
         .with_chain(chain)
         .with_fee_estimator(fee_estimator)
         .with_wallet(wallet)
-        .with_esplora(esplora)
         .with_network(network);
     node_builder.with_logger(logger.clone());
 
@@ -184,22 +185,22 @@ macro_rules! log {
             println!( $( $t )* );
         }
     }
+use crate::nostr::nip47::*;
 use bitcoin::hashes::{sha256, Hash};
 use bitcoin::secp256k1::{Secp256k1, SecretKey};
 use bitcoin::{util::bip32::ExtendedPrivKey, Network};
 use lightning::ln::PaymentSecret;
 use lightning::routing::scoring::ProbabilisticScoringDecayParameters;
 use lightning_invoice::{Bolt11Invoice, InvoiceBuilder};
-use lightning_transaction_sync::EsploraSyncClient;
 #[allow(unused_imports)]
 pub(crate) use log;
-use nostr::nips::nip47::*;
 use nostr::prelude::{encrypt, NostrWalletConnectURI};
 use nostr::{Event, EventBuilder, JsonUtil, Keys, Kind, Tag};
 use std::sync::atomic::AtomicBool;
 use std::sync::Arc;
 use uuid::Uuid;
 
+use crate::chainsource::{ChainSource, EsploraChainSource};
 use crate::node::{NetworkGraph, Node, RapidGossipSync};
 use crate::nodemanager::NodeIndex;
 use crate::onchain::{get_esplora_url, OnChainWallet};
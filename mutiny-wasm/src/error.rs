@@ -51,6 +51,21 @@ pub enum MutinyJsError {
     /// We do not have enough balance to pay the given amount.
     #[error("We do not have enough balance to pay the given amount.")]
     InsufficientBalance,
+    /// None of our nodes or federations can pay on its own, only their combined
+    /// balance is enough, and a payment can't be split between them.
+    #[error(
+        "Not enough balance in a single node or federation, payments can't be split between them."
+    )]
+    SplitPaymentUnsupported,
+    /// A UTXO that was frozen was selected to be spent.
+    #[error("Cannot spend a frozen UTXO.")]
+    FrozenUtxo,
+    /// The fee for a payment is above the limit set in the routing policy.
+    #[error("The payment fee is above the configured limit.")]
+    FeeLimitExceeded,
+    /// The hold invoice has not received a payment that can be settled.
+    #[error("The hold invoice has not been paid yet.")]
+    HoldInvoiceNotAccepted,
     /// Failed to call on the given LNURL
     #[error("Failed to call on the given LNURL.")]
     LnUrlFailure,
@@ -69,6 +84,15 @@ pub enum MutinyJsError {
     /// LSP required an invoice and none was provided.
     #[error("Failed to provide an invoice to the LSP.")]
     LspInvoiceRequired,
+    /// The channel order was already paid for, or can no longer be paid.
+    #[error("The channel order cannot be paid.")]
+    ChannelOrderNotPayable,
+    /// The LSP fee is above the configured maximum.
+    #[error("The LSP fee is above the configured maximum.")]
+    LspFeeTooHigh,
+    /// The LSP fee quote does not exist or has expired.
+    #[error("The LSP fee quote was not found or has expired.")]
+    LspFeeQuoteNotFound,
     /// Subscription Client Not Configured
     #[error("Subscription Client Not Configured")]
     SubscriptionClientNotConfigured,
@@ -181,11 +205,18 @@ impl From<MutinyError> for MutinyJsError {
             MutinyError::InvoiceCreationFailed => MutinyJsError::InvoiceCreationFailed,
             MutinyError::ReserveAmountError => MutinyJsError::ReserveAmountError,
             MutinyError::InsufficientBalance => MutinyJsError::InsufficientBalance,
+            MutinyError::SplitPaymentUnsupported => MutinyJsError::SplitPaymentUnsupported,
+            MutinyError::FrozenUtxo => MutinyJsError::FrozenUtxo,
+            MutinyError::FeeLimitExceeded => MutinyJsError::FeeLimitExceeded,
+            MutinyError::HoldInvoiceNotAccepted => MutinyJsError::HoldInvoiceNotAccepted,
             MutinyError::LnUrlFailure => MutinyJsError::LnUrlFailure,
             MutinyError::LspGenericError => MutinyJsError::LspGenericError,
             MutinyError::LspFundingError => MutinyJsError::LspFundingError,
             MutinyError::LspConnectionError => MutinyJsError::LspConnectionError,
             MutinyError::LspInvoiceRequired => MutinyJsError::LspInvoiceRequired,
+            MutinyError::ChannelOrderNotPayable => MutinyJsError::ChannelOrderNotPayable,
+            MutinyError::LspFeeTooHigh => MutinyJsError::LspFeeTooHigh,
+            MutinyError::LspFeeQuoteNotFound => MutinyJsError::LspFeeQuoteNotFound,
             MutinyError::RoutingFailed => MutinyJsError::RoutingFailed,
             MutinyError::PeerInfoParseFailed => MutinyJsError::PeerInfoParseFailed,
             MutinyError::ChannelCreationFailed => MutinyJsError::ChannelCreationFailed,
@@ -5,6 +5,7 @@ use gloo_utils::format::JsValueSerdeExt;
 use lightning::util::logger::Logger;
 use lightning::{log_debug, log_error};
 use log::error;
+use mutiny_core::logging::MutinyLogger;
 use mutiny_core::storage::*;
 use mutiny_core::vss::*;
 use mutiny_core::*;
@@ -12,8 +13,6 @@ use mutiny_core::{
     encrypt::Cipher,
     error::{MutinyError, MutinyStorageError},
 };
-use mutiny_core::{federation::FederationStorage, logging::MutinyLogger};
-use mutiny_core::{federation::FEDIMINTS_PREFIX_KEY, nodemanager::NodeStorage};
 use rexie::{ObjectStore, Rexie, TransactionMode};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
@@ -291,7 +290,7 @@ impl IndexedDbStorage {
                 let keys = vss.list_key_versions(None).await?;
                 let mut futs = vec![];
                 for kv in keys {
-                    futs.push(Self::handle_vss_key(kv, vss, &map, logger));
+                    futs.push(fetch_newer_vss_value(kv, vss, &map, logger));
                 }
                 let results = futures::future::join_all(futs).await;
                 let mut items_vector = Vec::new();
@@ -312,172 +311,6 @@ impl IndexedDbStorage {
         }
     }
 
-    async fn handle_vss_key(
-        kv: KeyVersion,
-        vss: &MutinyVssClient,
-        current: &MemoryStorage,
-        logger: &MutinyLogger,
-    ) -> Result<Option<(String, Value)>, MutinyError> {
-        log_debug!(
-            logger,
-            "Found vss key {} with version {}",
-            kv.key,
-            kv.version
-        );
-
-        match kv.key.as_str() {
-            NODES_KEY => {
-                // we can get version from node storage, so we should compare
-                match current.get_data::<NodeStorage>(&kv.key)? {
-                    Some(local) => {
-                        if local.version < kv.version {
-                            let obj = vss.get_object(&kv.key).await?;
-                            if serde_json::from_value::<NodeStorage>(obj.value.clone()).is_ok() {
-                                return Ok(Some((kv.key, obj.value)));
-                            }
-                        }
-                    }
-                    None => {
-                        let obj = vss.get_object(&kv.key).await?;
-                        return Ok(Some((kv.key, obj.value)));
-                    }
-                }
-            }
-            FEDERATIONS_KEY => {
-                // we can get version from federation storage, so we should compare
-                match current.get_data::<FederationStorage>(&kv.key)? {
-                    Some(local) => {
-                        if local.version < kv.version {
-                            let obj = vss.get_object(&kv.key).await?;
-                            if serde_json::from_value::<FederationStorage>(obj.value.clone())
-                                .is_ok()
-                            {
-                                return Ok(Some((kv.key, obj.value)));
-                            }
-                        }
-                    }
-                    None => {
-                        let obj = vss.get_object(&kv.key).await?;
-                        return Ok(Some((kv.key, obj.value)));
-                    }
-                }
-            }
-            DEVICE_LOCK_KEY => {
-                // we can get version from device lock, so we should compare
-                match current.get_data::<DeviceLock>(&kv.key)? {
-                    Some(lock) => {
-                        // we use time as version for device lock
-                        if lock.time < kv.version {
-                            let obj = vss.get_object(&kv.key).await?;
-                            if serde_json::from_value::<DeviceLock>(obj.value.clone()).is_ok() {
-                                return Ok(Some((kv.key, obj.value)));
-                            }
-                        }
-                    }
-                    None => {
-                        let obj = vss.get_object(&kv.key).await?;
-                        return Ok(Some((kv.key, obj.value)));
-                    }
-                }
-            }
-            key => {
-                if key.starts_with(MONITORS_PREFIX_KEY) {
-                    // we can get versions from monitors, so we should compare
-                    match current.get::<Vec<u8>>(&kv.key)? {
-                        Some(bytes) => {
-                            let current_version = utils::get_monitor_version(&bytes);
-
-                            // if the current version is less than the version from vss, then we want to use the vss version
-                            if current_version < kv.version as u64 {
-                                let obj = vss.get_object(&kv.key).await?;
-                                return Ok(Some((kv.key, obj.value)));
-                            } else {
-                                log_debug!(
-                                    logger,
-                                    "Skipping vss key {} with version {}, current version is {current_version}",
-                                    kv.key,
-                                    kv.version
-                                );
-                                return Ok(None);
-                            }
-                        }
-                        None => {
-                            let obj = vss.get_object(&kv.key).await?;
-                            return Ok(Some((kv.key, obj.value)));
-                        }
-                    }
-                } else if key.starts_with(CHANNEL_MANAGER_KEY) {
-                    // we can get versions from channel manager, so we should compare
-                    match current.get_data::<VersionedValue>(&kv.key)? {
-                        Some(local) => {
-                            if local.version < kv.version {
-                                let obj = vss.get_object(&kv.key).await?;
-                                if serde_json::from_value::<VersionedValue>(obj.value.clone())
-                                    .is_ok()
-                                {
-                                    return Ok(Some((kv.key, obj.value)));
-                                }
-                            } else {
-                                log_debug!(
-                                    logger,
-                                    "Skipping vss key {} with version {}, current version is {}",
-                                    kv.key,
-                                    kv.version,
-                                    local.version
-                                );
-                                return Ok(None);
-                            }
-                        }
-                        None => {
-                            let obj = vss.get_object(&kv.key).await?;
-                            if serde_json::from_value::<VersionedValue>(obj.value.clone()).is_ok() {
-                                return Ok(Some((kv.key, obj.value)));
-                            }
-                        }
-                    }
-                } else if key.starts_with(FEDIMINTS_PREFIX_KEY) {
-                    // we can get versions from each fedimint, so we should compare
-                    match current.get_data::<VersionedValue>(&kv.key)? {
-                        Some(local) => {
-                            if local.version < kv.version {
-                                let obj = vss.get_object(&kv.key).await?;
-                                if serde_json::from_value::<VersionedValue>(obj.value.clone())
-                                    .is_ok()
-                                {
-                                    return Ok(Some((kv.key, obj.value)));
-                                }
-                            } else {
-                                log_debug!(
-                                    logger,
-                                    "Skipping vss key {} with version {}, current version is {}",
-                                    kv.key,
-                                    kv.version,
-                                    local.version
-                                );
-                                return Ok(None);
-                            }
-                        }
-                        None => {
-                            let obj = vss.get_object(&kv.key).await?;
-                            if serde_json::from_value::<VersionedValue>(obj.value.clone()).is_ok() {
-                                return Ok(Some((kv.key, obj.value)));
-                            }
-                        }
-                    }
-                }
-            }
-        }
-
-        log_debug!(
-            logger,
-            "Skipping vss key {} with version {}",
-            kv.key,
-            kv.version
-        );
-
-        Ok(None)
-    }
-
     async fn build_indexed_db_database() -> Result<Rexie, MutinyError> {
         let rexie = Rexie::builder(WALLET_DATABASE_NAME)
             .version(1)
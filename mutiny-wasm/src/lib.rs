@@ -19,33 +19,50 @@ use bip39::Mnemonic;
 use bitcoin::consensus::deserialize;
 use bitcoin::hashes::hex::{FromHex, ToHex};
 use bitcoin::hashes::sha256;
+use bitcoin::psbt::PartiallySignedTransaction;
 use bitcoin::secp256k1::PublicKey;
-use bitcoin::util::bip32::ExtendedPrivKey;
+use bitcoin::util::bip32::{ExtendedPrivKey, ExtendedPubKey, Fingerprint};
 use bitcoin::{Address, Network, OutPoint, Transaction, Txid};
 use fedimint_core::{api::InviteCode, config::FederationId};
 use futures::lock::Mutex;
+use futures::StreamExt;
 use gloo_utils::format::JsValueSerdeExt;
+use lightning::offers::offer::Offer;
+use lightning::offers::refund::Refund;
 use lightning::{log_error, routing::gossip::NodeId, util::logger::Logger};
 use lightning_invoice::Bolt11Invoice;
 use lnurl::lightning_address::LightningAddress;
 use lnurl::lnurl::LnUrl;
 use mutiny_core::auth::MutinyAuthClient;
+use mutiny_core::event::CustomTlv;
 use mutiny_core::lnurlauth::AuthManager;
+use mutiny_core::lnurlpay::{LnUrlPayConfig, NostrProxyTransport, PayerData};
 use mutiny_core::nostr::nip49::NIP49URI;
-use mutiny_core::nostr::nwc::{BudgetedSpendingConditions, NwcProfileTag, SpendingConditions};
+use mutiny_core::nostr::nwc::{
+    BudgetedSpendingConditions, NwcNotificationType, NwcProfileTag, SpendingConditions,
+};
+use mutiny_core::nostr::zap::ZapPrivacy;
 use mutiny_core::nostr::NostrKeySource;
+use mutiny_core::routing::{PaymentSource, RoutingPolicy};
+use mutiny_core::scb::StaticChannelBackup;
 use mutiny_core::storage::{DeviceLock, MutinyStorage, DEVICE_LOCK_KEY};
 use mutiny_core::utils::{now, parse_npub, parse_npub_or_nip05, sleep};
 use mutiny_core::vss::MutinyVssClient;
-use mutiny_core::{encrypt::encryption_key_from_pass, InvoiceHandler, MutinyWalletConfigBuilder};
+use mutiny_core::{
+    encrypt::encryption_key_from_pass, InvoiceHandler, LspFeeLimits, LspSelection,
+    MutinyWalletConfigBuilder,
+};
 use mutiny_core::{labels::Contact, MutinyWalletBuilder};
 use mutiny_core::{
     labels::LabelStorage,
     nodemanager::{create_lsp_config, NodeManager},
 };
-use mutiny_core::{logging::MutinyLogger, nostr::ProfileType};
+use mutiny_core::{
+    logging::{LogFilter, LogLevel, LogRetention, MutinyLogger},
+    nostr::ProfileType,
+};
 use nostr::key::{FromSkStr, Secp256k1, SecretKey};
-use nostr::{FromBech32, Keys, ToBech32};
+use nostr::{EventId, FromBech32, Keys, ToBech32};
 use std::str::FromStr;
 use std::sync::Arc;
 use std::{
@@ -104,6 +121,13 @@ impl MutinyWallet {
         nsec_override: Option<String>,
         nip_07_key: Option<String>,
         primal_url: Option<String>,
+        additional_lsp_urls: Option<Vec<String>>,
+        additional_lsp_connection_strings: Option<Vec<String>>,
+        lsp_selection: Option<String>,
+        max_lsp_fee_sat: Option<u64>,
+        max_lsp_fee_ppm: Option<u32>,
+        watch_only_xpub: Option<String>,
+        watch_only_fingerprint: Option<String>,
     ) -> Result<MutinyWallet, MutinyJsError> {
         // if both are set throw an error
         // todo default to nsec if both are for same key?
@@ -140,6 +164,13 @@ impl MutinyWallet {
             nsec_override,
             nip_07_key,
             primal_url,
+            additional_lsp_urls,
+            additional_lsp_connection_strings,
+            lsp_selection,
+            max_lsp_fee_sat,
+            max_lsp_fee_ppm,
+            watch_only_xpub,
+            watch_only_fingerprint,
         )
         .await
         {
@@ -174,6 +205,13 @@ impl MutinyWallet {
         nsec_override: Option<String>,
         nip_07_key: Option<String>,
         primal_url: Option<String>,
+        additional_lsp_urls: Option<Vec<String>>,
+        additional_lsp_connection_strings: Option<Vec<String>>,
+        lsp_selection: Option<String>,
+        max_lsp_fee_sat: Option<u64>,
+        max_lsp_fee_ppm: Option<u32>,
+        watch_only_xpub: Option<String>,
+        watch_only_fingerprint: Option<String>,
     ) -> Result<MutinyWallet, MutinyJsError> {
         let safe_mode = safe_mode.unwrap_or(false);
         let logger = Arc::new(MutinyLogger::default());
@@ -258,6 +296,32 @@ impl MutinyWallet {
         if let Some(url) = lsp_token {
             config_builder.with_lsp_token(url);
         }
+        for url in additional_lsp_urls.unwrap_or_default() {
+            config_builder.with_additional_lsp_url(url);
+        }
+        for connection_string in additional_lsp_connection_strings.unwrap_or_default() {
+            config_builder.with_additional_lsp_connection_string(connection_string, None);
+        }
+        if let Some(selection) = lsp_selection {
+            config_builder.with_lsp_selection(LspSelection::from_str(&selection)?);
+        }
+        if max_lsp_fee_sat.is_some() || max_lsp_fee_ppm.is_some() {
+            config_builder.with_lsp_fee_limits(LspFeeLimits {
+                max_fee_msat: max_lsp_fee_sat.map(|sat| sat * 1_000),
+                max_fee_ppm: max_lsp_fee_ppm,
+            });
+        }
+        match (watch_only_xpub, watch_only_fingerprint) {
+            (Some(xpub), Some(fingerprint)) => {
+                let xpub = ExtendedPubKey::from_str(&xpub)
+                    .map_err(|_| MutinyJsError::InvalidArgumentsError)?;
+                let fingerprint = Fingerprint::from_str(&fingerprint)
+                    .map_err(|_| MutinyJsError::InvalidArgumentsError)?;
+                config_builder.with_watch_only_xpub(xpub, fingerprint);
+            }
+            (None, None) => {}
+            _ => return Err(MutinyJsError::InvalidArgumentsError),
+        }
         if let Some(a) = auth_client {
             config_builder.with_auth_client(a);
         }
@@ -304,6 +368,11 @@ impl MutinyWallet {
         self.inner.is_safe_mode()
     }
 
+    /// Returns true if the on-chain wallet has no keys, so its PSBTs must be signed externally.
+    pub fn is_watch_only(&self) -> bool {
+        self.inner.is_watch_only()
+    }
+
     /// Returns if there is a saved wallet in storage.
     /// This is checked by seeing if a mnemonic seed exists in storage.
     #[wasm_bindgen]
@@ -395,6 +464,30 @@ impl MutinyWallet {
         Ok(self.inner.start().await?)
     }
 
+    /// Calls the given callback with every wallet event, such as a payment being
+    /// received or a channel being closed, so they don't need to be polled for.
+    ///
+    /// The event is passed as an object with a `type` field naming the event.
+    #[wasm_bindgen]
+    pub fn subscribe_events(&self, callback: js_sys::Function) {
+        let mut events = self.inner.subscribe_events();
+        let logger = self.inner.logger.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            while let Some(event) = events.next().await {
+                let value = match JsValue::from_serde(&event) {
+                    Ok(value) => value,
+                    Err(e) => {
+                        log_error!(logger, "Could not serialize event: {e}");
+                        continue;
+                    }
+                };
+                if let Err(e) = callback.call1(&JsValue::NULL, &value) {
+                    log_error!(logger, "Error calling event callback: {e:?}");
+                }
+            }
+        });
+    }
+
     /// Stops all of the nodes and background processes.
     /// Returns after node has been stopped.
     #[wasm_bindgen]
@@ -446,6 +539,7 @@ impl MutinyWallet {
             invoice: None,
             btc_amount: None,
             labels,
+            payjoin_endpoint: None,
         })
     }
 
@@ -512,6 +606,86 @@ impl MutinyWallet {
             .to_string())
     }
 
+    /// Sends an on-chain transaction to the given address, only spending the given UTXOs.
+    /// Any change is sent back to the wallet.
+    /// The amount is in satoshis and the fee rate is in sat/vbyte.
+    ///
+    /// If a fee rate is not provided, one will be used from the fee estimator.
+    #[wasm_bindgen]
+    pub async fn send_to_address_with_utxos(
+        &self,
+        destination_address: String,
+        amount: u64,
+        utxos: Vec<String>,
+        labels: Vec<String>,
+        fee_rate: Option<f32>,
+    ) -> Result<String, MutinyJsError> {
+        let send_to = Address::from_str(&destination_address)?;
+        let utxos = parse_outpoints(&utxos)?;
+        Ok(self
+            .inner
+            .node_manager
+            .send_to_address_with_utxos(send_to, amount, &utxos, labels, fee_rate)
+            .await?
+            .to_string())
+    }
+
+    /// Creates an unsigned PSBT sending to the given address, returned as a base64 string
+    /// so it can be signed by an external signer.
+    /// If utxos are given, only those will be spent.
+    /// The amount is in satoshis and the fee rate is in sat/vbyte.
+    #[wasm_bindgen]
+    pub fn create_unsigned_psbt(
+        &self,
+        destination_address: String,
+        amount: u64,
+        utxos: Option<Vec<String>>,
+        fee_rate: Option<f32>,
+    ) -> Result<String, MutinyJsError> {
+        let send_to = Address::from_str(&destination_address)?;
+        let utxos = utxos.map(|u| parse_outpoints(&u)).transpose()?;
+        Ok(self
+            .inner
+            .node_manager
+            .create_unsigned_psbt(send_to, amount, utxos.as_deref(), fee_rate)?
+            .to_string())
+    }
+
+    /// Creates an unsigned PSBT sweeping the wallet to the given address,
+    /// returned as a base64 string so it can be signed by an external signer.
+    /// The fee rate is in sat/vbyte.
+    #[wasm_bindgen]
+    pub fn create_unsigned_sweep_psbt(
+        &self,
+        destination_address: String,
+        fee_rate: Option<f32>,
+    ) -> Result<String, MutinyJsError> {
+        let send_to = Address::from_str(&destination_address)?;
+        Ok(self
+            .inner
+            .node_manager
+            .create_unsigned_sweep_psbt(send_to, fee_rate)?
+            .to_string())
+    }
+
+    /// Finalizes and broadcasts a base64 PSBT that was created by this wallet
+    /// and signed externally. Returns the txid.
+    #[wasm_bindgen]
+    pub async fn broadcast_signed_psbt(
+        &self,
+        psbt: String,
+        labels: Vec<String>,
+    ) -> Result<String, MutinyJsError> {
+        let psbt = PartiallySignedTransaction::from_str(&psbt)
+            .map_err(|_| MutinyJsError::InvalidArgumentsError)?;
+        Ok(self
+            .inner
+            .node_manager
+            .broadcast_signed_psbt(psbt, labels)
+            .await?
+            .to_string())
+    }
+
     #[wasm_bindgen]
     pub async fn send_payjoin(
         &self,
@@ -668,6 +842,33 @@ impl MutinyWallet {
         Ok(JsValue::from_serde(&self.inner.node_manager.list_utxos()?)?)
     }
 
+    /// Lists the UTXOs that are frozen and will not be spent by the wallet.
+    #[wasm_bindgen]
+    pub fn list_frozen_utxos(&self) -> Result<JsValue /* Vec<String> */, MutinyJsError> {
+        let frozen: Vec<String> = self
+            .inner
+            .node_manager
+            .list_frozen_utxos()?
+            .iter()
+            .map(|o| o.to_string())
+            .collect();
+        Ok(JsValue::from_serde(&frozen)?)
+    }
+
+    /// Freezes the given UTXOs so they are never spent, unless they are unfrozen.
+    #[wasm_bindgen]
+    pub fn freeze_utxos(&self, utxos: Vec<String>) -> Result<(), MutinyJsError> {
+        let utxos = parse_outpoints(&utxos)?;
+        Ok(self.inner.node_manager.freeze_utxos(&utxos)?)
+    }
+
+    /// Unfreezes the given UTXOs so they can be spent again.
+    #[wasm_bindgen]
+    pub fn unfreeze_utxos(&self, utxos: Vec<String>) -> Result<(), MutinyJsError> {
+        let utxos = parse_outpoints(&utxos)?;
+        Ok(self.inner.node_manager.unfreeze_utxos(&utxos)?)
+    }
+
     /// Gets a fee estimate for an low priority transaction.
     /// Value is in sat/vbyte.
     #[wasm_bindgen]
@@ -689,6 +890,14 @@ impl MutinyWallet {
         self.inner.node_manager.estimate_fee_high()
     }
 
+    /// Gets the request stats for each of the esplora servers being used.
+    #[wasm_bindgen]
+    pub fn get_chain_endpoint_stats(&self) -> Result<JsValue, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.inner.node_manager.get_chain_endpoint_stats(),
+        )?)
+    }
+
     /// Creates a new lightning node and adds it to the manager.
     #[wasm_bindgen]
     pub async fn new_node(&self) -> Result<NodeIdentity, MutinyJsError> {
@@ -719,6 +928,99 @@ impl MutinyWallet {
         Ok(())
     }
 
+    /// Adds an LSP to all the nodes that is used alongside their current LSPs.
+    ///
+    /// Requires a restart of the node manager to take effect.
+    pub async fn add_lsp(
+        &self,
+        lsp_url: Option<String>,
+        lsp_connection_string: Option<String>,
+        lsp_token: Option<String>,
+    ) -> Result<(), MutinyJsError> {
+        let lsp_config = create_lsp_config(lsp_url, lsp_connection_string, lsp_token)?
+            .ok_or(MutinyJsError::InvalidArgumentsError)?;
+
+        self.inner.node_manager.add_lsp(lsp_config).await?;
+        Ok(())
+    }
+
+    /// Gets the channel options of an LSP that sells channels with LSPS1.
+    #[wasm_bindgen]
+    pub async fn get_channel_order_info(
+        &self,
+        lsp_url: String,
+    ) -> Result<JsValue /* Lsps1Info */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self
+                .inner
+                .node_manager
+                .get_channel_order_info(&lsp_url)
+                .await?,
+        )?)
+    }
+
+    /// Buys a channel from an LSP with LSPS1, to have inbound liquidity ahead of time.
+    /// The order must be paid with `pay_channel_order` before the LSP opens the channel.
+    #[wasm_bindgen]
+    pub async fn create_channel_order(
+        &self,
+        lsp_url: String,
+        lsp_balance_sat: u64,
+        client_balance_sat: u64,
+        channel_expiry_blocks: u32,
+        token: Option<String>,
+    ) -> Result<JsValue /* ChannelOrder */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self
+                .inner
+                .node_manager
+                .create_channel_order(
+                    lsp_url,
+                    lsp_balance_sat,
+                    client_balance_sat,
+                    channel_expiry_blocks,
+                    token,
+                )
+                .await?,
+        )?)
+    }
+
+    /// Gets the latest state of a channel order from its LSP.
+    #[wasm_bindgen]
+    pub async fn get_channel_order(
+        &self,
+        order_id: String,
+    ) -> Result<JsValue /* ChannelOrder */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.inner.node_manager.get_channel_order(&order_id).await?,
+        )?)
+    }
+
+    /// Lists our channel orders, most recently updated first.
+    #[wasm_bindgen]
+    pub fn list_channel_orders(&self) -> Result<JsValue /* Vec<ChannelOrder> */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.inner.node_manager.list_channel_orders()?,
+        )?)
+    }
+
+    /// Pays for a channel order with lightning, or on-chain if `onchain` is set.
+    /// Lightning payments are not waited on, use `get_channel_order` to follow the order.
+    #[wasm_bindgen]
+    pub async fn pay_channel_order(
+        &self,
+        order_id: String,
+        onchain: bool,
+    ) -> Result<JsValue /* ChannelOrder */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self
+                .inner
+                .node_manager
+                .pay_channel_order(&order_id, onchain)
+                .await?,
+        )?)
+    }
+
     /// Attempts to connect to a peer from the selected node.
     #[wasm_bindgen]
     pub async fn connect_to_peer(
@@ -772,6 +1074,32 @@ impl MutinyWallet {
         Ok(self.inner.create_invoice(amount, labels).await?.into())
     }
 
+    /// Gets the LSP fee for receiving the amount in satoshis without creating an invoice.
+    /// Call `approve_lsp_fee_quote` with the quote's id to create the invoice.
+    #[wasm_bindgen]
+    pub async fn quote_lsp_fee(
+        &self,
+        amount: u64,
+    ) -> Result<JsValue /* LspFeeQuote */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.inner.node_manager.quote_lsp_fee(amount).await?,
+        )?)
+    }
+
+    /// Creates the invoice for an LSP fee quote, approving its fee.
+    #[wasm_bindgen]
+    pub async fn approve_lsp_fee_quote(
+        &self,
+        quote_id: String,
+        labels: Vec<String>,
+    ) -> Result<MutinyInvoice, MutinyJsError> {
+        Ok(self
+            .inner
+            .approve_lsp_fee_quote(&quote_id, labels)
+            .await?
+            .into())
+    }
+
     /// Pays a lightning invoice from the selected node.
     /// An amount should only be provided if the invoice does not have an amount.
     /// The amount should be in satoshis.
@@ -790,25 +1118,199 @@ impl MutinyWallet {
             .into())
     }
 
+    /// Pays a lightning invoice from the given federation, or from our node if no
+    /// federation id is given.
+    /// An amount should only be provided if the invoice does not have an amount.
+    /// The amount should be in satoshis.
+    #[wasm_bindgen]
+    pub async fn pay_invoice_from_source(
+        &self,
+        invoice_str: String,
+        amt_sats: Option<u64>,
+        labels: Vec<String>,
+        federation_id: Option<String>,
+    ) -> Result<MutinyInvoice, MutinyJsError> {
+        let invoice = Bolt11Invoice::from_str(&invoice_str)?;
+        let source = Self::parse_payment_source(federation_id)?;
+        Ok(self
+            .inner
+            .pay_invoice_from_source(&invoice, amt_sats, labels, source)
+            .await?
+            .into())
+    }
+
+    /// Creates a lightning invoice with the given federation, or with our node if no
+    /// federation id is given.
+    /// The amount should be in satoshis.
+    #[wasm_bindgen]
+    pub async fn create_invoice_with_source(
+        &self,
+        amount: Option<u64>,
+        labels: Vec<String>,
+        federation_id: Option<String>,
+    ) -> Result<MutinyInvoice, MutinyJsError> {
+        let source = Self::parse_payment_source(federation_id)?;
+        Ok(self
+            .inner
+            .create_invoice_with_source(amount, labels, source)
+            .await?
+            .into())
+    }
+
+    /// Creates a hold invoice for the given payment hash, the amount is in satoshis.
+    /// Payments to it are held until they are settled with the preimage or cancelled.
+    #[wasm_bindgen]
+    pub async fn create_hold_invoice(
+        &self,
+        payment_hash: String,
+        amount: Option<u64>,
+    ) -> Result<MutinyInvoice, MutinyJsError> {
+        let payment_hash = sha256::Hash::from_str(&payment_hash)?;
+        Ok(self
+            .inner
+            .node_manager
+            .create_hold_invoice(payment_hash, amount)
+            .await?
+            .into())
+    }
+
+    /// Claims the payment held for a hold invoice using its hex encoded preimage.
+    #[wasm_bindgen]
+    pub async fn settle_hold_invoice(&self, preimage: String) -> Result<(), MutinyJsError> {
+        let preimage: [u8; 32] = FromHex::from_hex(&preimage)?;
+        Ok(self
+            .inner
+            .node_manager
+            .settle_hold_invoice(preimage)
+            .await?)
+    }
+
+    /// Cancels a hold invoice, failing back the payment if one is being held.
+    #[wasm_bindgen]
+    pub async fn cancel_hold_invoice(&self, payment_hash: String) -> Result<(), MutinyJsError> {
+        let payment_hash = sha256::Hash::from_str(&payment_hash)?;
+        Ok(self
+            .inner
+            .node_manager
+            .cancel_hold_invoice(payment_hash)
+            .await?)
+    }
+
+    /// Lists our hold invoices, most recently updated first.
+    #[wasm_bindgen]
+    pub fn list_hold_invoices(&self) -> Result<JsValue /* Vec<HoldInvoice> */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.inner.node_manager.list_hold_invoices()?,
+        )?)
+    }
+
+    /// Gets the expected fee of paying the invoice from each source,
+    /// in the order they would be tried.
+    #[wasm_bindgen]
+    pub async fn quote_payment(
+        &self,
+        invoice_str: String,
+        amt_sats: Option<u64>,
+    ) -> Result<JsValue /* Vec<PaymentQuote> */, MutinyJsError> {
+        let invoice = Bolt11Invoice::from_str(&invoice_str)?;
+        Ok(JsValue::from_serde(
+            &self.inner.quote_payment(&invoice, amt_sats).await?,
+        )?)
+    }
+
+    /// Gets the policy used to choose where payments are made from and invoices are created.
+    #[wasm_bindgen]
+    pub fn get_routing_policy(&self) -> Result<JsValue /* RoutingPolicy */, MutinyJsError> {
+        Ok(JsValue::from_serde(&self.inner.get_routing_policy()?)?)
+    }
+
+    /// Sets the routing policy from its json representation.
+    #[wasm_bindgen]
+    pub fn set_routing_policy(&self, policy: String) -> Result<(), MutinyJsError> {
+        let policy: RoutingPolicy = serde_json::from_str(&policy)?;
+        Ok(self.inner.set_routing_policy(policy)?)
+    }
+
+    fn parse_payment_source(federation_id: Option<String>) -> Result<PaymentSource, MutinyJsError> {
+        match federation_id {
+            Some(id) => {
+                let id = FederationId::from_str(&id)
+                    .map_err(|_| MutinyJsError::InvalidArgumentsError)?;
+                Ok(PaymentSource::Federation(id))
+            }
+            None => Ok(PaymentSource::Node),
+        }
+    }
+
     /// Sends a spontaneous payment to a node from the selected node.
     /// The amount should be in satoshis.
+    /// Custom TLV records can be given as a list of `{ tlv_type, value }`
+    /// where the value is a byte array.
     #[wasm_bindgen]
     pub async fn keysend(
         &self,
         to_node: String,
         amt_sats: u64,
         message: Option<String>,
+        custom_tlvs: JsValue, /* Option<Vec<CustomTlv>> */
         labels: Vec<String>,
     ) -> Result<MutinyInvoice, MutinyJsError> {
         let to_node = PublicKey::from_str(&to_node)?;
+        let custom_tlvs: Vec<CustomTlv> = if custom_tlvs.is_null() || custom_tlvs.is_undefined() {
+            vec![]
+        } else {
+            custom_tlvs.into_serde()?
+        };
         Ok(self
             .inner
             .node_manager
-            .keysend(None, to_node, amt_sats, message, labels)
+            .keysend(None, to_node, amt_sats, message, custom_tlvs, labels)
+            .await?
+            .into())
+    }
+
+    /// Creates a reusable BOLT12 offer.
+    /// If no amount is provided, the payer will choose how much to pay.
+    /// The amount should be in satoshis.
+    #[wasm_bindgen]
+    pub async fn create_offer(
+        &self,
+        amount: Option<u64>,
+        description: Option<String>,
+    ) -> Result<String, MutinyJsError> {
+        Ok(self
+            .inner
+            .create_offer(amount, description)
+            .await?
+            .to_string())
+    }
+
+    /// Pays a BOLT12 offer from the selected node.
+    /// An amount should only be provided if the offer does not have an amount.
+    /// The amount should be in satoshis.
+    #[wasm_bindgen]
+    pub async fn pay_offer(
+        &self,
+        offer: String,
+        amt_sats: Option<u64>,
+        payer_note: Option<String>,
+        labels: Vec<String>,
+    ) -> Result<MutinyInvoice, MutinyJsError> {
+        let offer = Offer::from_str(&offer).map_err(|_| MutinyJsError::InvoiceInvalid)?;
+        Ok(self
+            .inner
+            .pay_offer(&offer, amt_sats, payer_note, labels)
             .await?
             .into())
     }
 
+    /// Requests payment for a BOLT12 refund to the selected node.
+    #[wasm_bindgen]
+    pub async fn request_refund(&self, refund: String) -> Result<(), MutinyJsError> {
+        let refund = Refund::from_str(&refund).map_err(|_| MutinyJsError::InvoiceInvalid)?;
+        Ok(self.inner.request_refund(&refund).await?)
+    }
+
     /// Decodes a lightning invoice into useful information.
     /// Will return an error if the invoice is for a different network.
     #[wasm_bindgen]
@@ -842,6 +1344,9 @@ impl MutinyWallet {
         zap_npub: Option<String>,
         labels: Vec<String>,
         comment: Option<String>,
+        payer_name: Option<String>,
+        payer_identifier: Option<String>,
+        payer_email: Option<String>,
     ) -> Result<MutinyInvoice, MutinyJsError> {
         let lnurl = LnUrl::from_str(&lnurl)?;
 
@@ -850,13 +1355,87 @@ impl MutinyWallet {
             None => None,
         };
 
+        let payer_data = PayerData {
+            name: payer_name.filter(|n| !n.is_empty()),
+            pubkey: None,
+            identifier: payer_identifier.filter(|i| !i.is_empty()),
+            email: payer_email.filter(|e| !e.is_empty()),
+        };
+        let payer_data = Some(payer_data).filter(|p| *p != PayerData::default());
+
         Ok(self
             .inner
-            .lnurl_pay(&lnurl, amount_sats, zap_npub, labels, comment)
+            .lnurl_pay(&lnurl, amount_sats, zap_npub, labels, comment, payer_data)
             .await?
             .into())
     }
 
+    /// Zaps a nostr profile, or one of their notes, through their lightning address.
+    /// Privacy is one of "public", "private" or "anonymous".
+    #[wasm_bindgen]
+    pub async fn zap(
+        &self,
+        npub: String,
+        amount_sats: u64,
+        event_id: Option<String>,
+        comment: Option<String>,
+        privacy: Option<String>,
+        labels: Vec<String>,
+    ) -> Result<JsValue /* SentZap */, MutinyJsError> {
+        let npub = parse_npub(&npub)?;
+        let event_id = match event_id.filter(|e| !e.is_empty()) {
+            Some(e) => Some(
+                EventId::from_bech32(&e)
+                    .or_else(|_| EventId::from_hex(&e))
+                    .map_err(|_| MutinyJsError::InvalidArgumentsError)?,
+            ),
+            None => None,
+        };
+        let privacy = match privacy {
+            Some(p) => ZapPrivacy::from_str(&p)?,
+            None => ZapPrivacy::default(),
+        };
+
+        let zap = self
+            .inner
+            .zap(npub, amount_sats, event_id, comment, privacy, labels)
+            .await?;
+        Ok(JsValue::from_serde(&zap)?)
+    }
+
+    /// Starts receiving payments to the lightning address `username@domain`.
+    /// Requests to the address are forwarded to us by an LNURL proxy over the given relays.
+    #[wasm_bindgen]
+    pub async fn start_lightning_address(
+        &self,
+        username: String,
+        domain: String,
+        proxy_npub: String,
+        relays: Vec<String>,
+        comment_allowed: Option<u16>,
+    ) -> Result<String, MutinyJsError> {
+        let proxy_pubkey = parse_npub(&proxy_npub)?;
+        let keys = self.inner.nostr.derive_lnurl_keys()?;
+        let transport = NostrProxyTransport::new(
+            keys,
+            proxy_pubkey,
+            relays,
+            format!("https://{domain}"),
+            self.inner.logger.clone(),
+        )
+        .await?;
+
+        let mut config = LnUrlPayConfig::new(username, domain);
+        if let Some(comment_allowed) = comment_allowed {
+            config.comment_allowed = comment_allowed;
+        }
+        let address = config.lightning_address();
+        self.inner
+            .start_lnurl_pay_server(config, Arc::new(transport))?;
+
+        Ok(address)
+    }
+
     /// Calls upon a LNURL and withdraws from it.
     /// This will fail if the LNURL is not a LNURL withdrawal.
     #[wasm_bindgen]
@@ -953,6 +1532,53 @@ impl MutinyWallet {
             .into())
     }
 
+    /// Starts opening a channel funded by the watch-only on-chain wallet.
+    /// The returned funding PSBT needs to be signed externally and given
+    /// to `fund_channel` to finish opening the channel.
+    /// The amount is in satoshis.
+    #[wasm_bindgen]
+    pub async fn open_channel_unsigned(
+        &self,
+        to_pubkey: Option<String>,
+        amount: u64,
+        fee_rate: Option<f32>,
+    ) -> Result<PendingChannelFunding, MutinyJsError> {
+        let to_pubkey = match to_pubkey {
+            Some(pubkey_str) if !pubkey_str.trim().is_empty() => {
+                Some(PublicKey::from_str(&pubkey_str)?)
+            }
+            _ => None,
+        };
+
+        Ok(self
+            .inner
+            .node_manager
+            .open_channel_unsigned(None, to_pubkey, amount, fee_rate, None)
+            .await?
+            .into())
+    }
+
+    /// Funds a channel started with `open_channel_unsigned` with its
+    /// funding PSBT, given as base64, after it was signed externally.
+    #[wasm_bindgen]
+    pub async fn fund_channel(
+        &self,
+        user_channel_id: String,
+        psbt: String,
+    ) -> Result<MutinyChannel, MutinyJsError> {
+        let user_channel_id =
+            u128::from_str(&user_channel_id).map_err(|_| MutinyJsError::InvalidArgumentsError)?;
+        let psbt = PartiallySignedTransaction::from_str(&psbt)
+            .map_err(|_| MutinyJsError::InvalidArgumentsError)?;
+
+        Ok(self
+            .inner
+            .node_manager
+            .fund_channel(None, user_channel_id, psbt)
+            .await?
+            .into())
+    }
+
     /// Opens a channel from our selected node to the given pubkey.
     /// It will spend the all the on-chain utxo in full to fund the channel.
     ///
@@ -1009,6 +1635,35 @@ impl MutinyWallet {
         )?)
     }
 
+    /// Creates a static backup of the channels of all our nodes.
+    /// This should be saved somewhere safe after opening a channel.
+    #[wasm_bindgen]
+    pub async fn create_static_channel_backup(&self) -> Result<String, MutinyJsError> {
+        Ok(self
+            .inner
+            .node_manager
+            .create_static_channel_backup()
+            .await?
+            .to_string())
+    }
+
+    /// Asks our peers to force close the channels in the backup that we have lost
+    /// the state of, and sweeps our balance once they are closed.
+    /// Returns the number of channels being recovered.
+    #[wasm_bindgen]
+    pub async fn recover_from_static_channel_backup(
+        &self,
+        backup: String,
+    ) -> Result<u32, MutinyJsError> {
+        let backup = StaticChannelBackup::from_str(&backup)?;
+        let count = self
+            .inner
+            .node_manager
+            .recover_from_static_channel_backup(backup)
+            .await?;
+        Ok(count as u32)
+    }
+
     /// Lists all the peers for all the nodes in the node manager.
     #[wasm_bindgen]
     pub async fn list_peers(&self) -> Result<JsValue /* Vec<MutinyPeer> */, MutinyJsError> {
@@ -1116,6 +1771,72 @@ impl MutinyWallet {
         Ok(self.inner.get_federation_balances().await?.into())
     }
 
+    /// Creates an address to deposit bitcoin into the given federation.
+    #[wasm_bindgen]
+    pub async fn get_federation_deposit_address(
+        &self,
+        federation_id: String,
+        labels: Vec<String>,
+    ) -> Result<JsValue /* FederationOnchainTx */, MutinyJsError> {
+        let federation_id = FederationId::from_str(&federation_id)
+            .map_err(|_| MutinyJsError::InvalidArgumentsError)?;
+        Ok(JsValue::from_serde(
+            &self
+                .inner
+                .get_federation_deposit_address(federation_id, labels)
+                .await?,
+        )?)
+    }
+
+    /// Estimates the fee the federation charges to withdraw to the given address.
+    /// The amount is in satoshis.
+    #[wasm_bindgen]
+    pub async fn estimate_federation_withdrawal_fee(
+        &self,
+        federation_id: String,
+        destination_address: String,
+        amount: u64,
+    ) -> Result<u64, MutinyJsError> {
+        let federation_id = FederationId::from_str(&federation_id)
+            .map_err(|_| MutinyJsError::InvalidArgumentsError)?;
+        let address = Address::from_str(&destination_address)?;
+        Ok(self
+            .inner
+            .estimate_federation_withdrawal_fee(federation_id, address, amount)
+            .await?)
+    }
+
+    /// Withdraws from the given federation to an on-chain address.
+    /// The amount is in satoshis, the federation's fee is paid on top of it.
+    #[wasm_bindgen]
+    pub async fn withdraw_from_federation(
+        &self,
+        federation_id: String,
+        destination_address: String,
+        amount: u64,
+        labels: Vec<String>,
+    ) -> Result<JsValue /* FederationOnchainTx */, MutinyJsError> {
+        let federation_id = FederationId::from_str(&federation_id)
+            .map_err(|_| MutinyJsError::InvalidArgumentsError)?;
+        let address = Address::from_str(&destination_address)?;
+        Ok(JsValue::from_serde(
+            &self
+                .inner
+                .withdraw_from_federation(federation_id, address, amount, labels)
+                .await?,
+        )?)
+    }
+
+    /// Lists all deposits into and withdrawals from our federations.
+    #[wasm_bindgen]
+    pub fn list_federation_onchain_txs(
+        &self,
+    ) -> Result<JsValue /* Vec<FederationOnchainTx> */, MutinyJsError> {
+        Ok(JsValue::from_serde(
+            &self.inner.list_federation_onchain_txs()?,
+        )?)
+    }
+
     pub fn get_address_labels(
         &self,
     ) -> Result<JsValue /* Map<Address, Vec<String>> */, MutinyJsError> {
@@ -1296,25 +2017,46 @@ impl MutinyWallet {
     pub async fn get_logs(
         password: Option<String>,
     ) -> Result<JsValue /* Option<Vec<String>> */, MutinyJsError> {
-        let logger = Arc::new(MutinyLogger::default());
-        // TODO Password should not be required for logs
-        let cipher = password
-            .as_ref()
-            .filter(|p| !p.is_empty())
-            .map(|p| encryption_key_from_pass(p))
-            .transpose()?;
-        let storage = IndexedDbStorage::new(password, cipher, None, logger.clone()).await?;
-        let stop = Arc::new(AtomicBool::new(false));
-        let logger = Arc::new(MutinyLogger::with_writer(
-            stop.clone(),
-            storage.clone(),
-            None,
-        ));
+        let (storage, logger, stop) = open_log_storage(password).await?;
         let res = JsValue::from_serde(&NodeManager::get_logs(storage, logger)?)?;
         stop.swap(true, Ordering::Relaxed);
         Ok(res)
     }
 
+    /// Gets the log records matching the given filters.
+    /// Times are in milliseconds since the epoch.
+    #[wasm_bindgen]
+    pub async fn query_logs(
+        password: Option<String>,
+        min_level: Option<String>,
+        start: Option<u64>,
+        end: Option<u64>,
+        module: Option<String>,
+    ) -> Result<JsValue /* Option<Vec<LogRecord>> */, MutinyJsError> {
+        let filter = log_filter(min_level, start, end, module)?;
+        let (storage, logger, stop) = open_log_storage(password).await?;
+        let res = JsValue::from_serde(&NodeManager::query_logs(storage, logger, &filter)?)?;
+        stop.swap(true, Ordering::Relaxed);
+        Ok(res)
+    }
+
+    /// Exports the logs matching the given filters as text for a support bundle.
+    /// Secrets are redacted from the logs.
+    #[wasm_bindgen]
+    pub async fn export_logs(
+        password: Option<String>,
+        min_level: Option<String>,
+        start: Option<u64>,
+        end: Option<u64>,
+        module: Option<String>,
+    ) -> Result<Option<String>, MutinyJsError> {
+        let filter = log_filter(min_level, start, end, module)?;
+        let (storage, logger, stop) = open_log_storage(password).await?;
+        let res = NodeManager::export_logs(storage, logger, &filter)?;
+        stop.swap(true, Ordering::Relaxed);
+        Ok(res)
+    }
+
     /// Get nostr wallet connect profiles
     #[wasm_bindgen]
     pub fn get_nwc_profiles(&self) -> Result<Vec<NwcProfile>, MutinyJsError> {
@@ -1449,6 +2191,25 @@ impl MutinyWallet {
             .into())
     }
 
+    /// Set which NIP-47 notifications a NWC Profile's client receives,
+    /// either `payment_received` or `payment_sent`
+    #[wasm_bindgen]
+    pub async fn set_nwc_profile_notifications(
+        &self,
+        profile_index: u32,
+        notifications: Vec<String>,
+    ) -> Result<models::NwcProfile, MutinyJsError> {
+        let notifications = notifications
+            .iter()
+            .map(|n| NwcNotificationType::from_str(n))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(self
+            .inner
+            .nostr
+            .set_nwc_profile_notifications(profile_index, notifications)?
+            .into())
+    }
+
     /// Require approval for a NWC Profile
     #[wasm_bindgen]
     pub async fn set_nwc_profile_require_approval(
@@ -1775,6 +2536,49 @@ impl MutinyWallet {
     }
 }
 
+async fn open_log_storage(
+    password: Option<String>,
+) -> Result<(IndexedDbStorage, Arc<MutinyLogger>, Arc<AtomicBool>), MutinyJsError> {
+    let logger = Arc::new(MutinyLogger::default());
+    // TODO Password should not be required for logs
+    let cipher = password
+        .as_ref()
+        .filter(|p| !p.is_empty())
+        .map(|p| encryption_key_from_pass(p))
+        .transpose()?;
+    let storage = IndexedDbStorage::new(password, cipher, None, logger.clone()).await?;
+    let stop = Arc::new(AtomicBool::new(false));
+    let logger = Arc::new(MutinyLogger::with_writer(
+        stop.clone(),
+        storage.clone(),
+        None,
+        LogRetention::default(),
+    ));
+    Ok((storage, logger, stop))
+}
+
+fn log_filter(
+    min_level: Option<String>,
+    start: Option<u64>,
+    end: Option<u64>,
+    module: Option<String>,
+) -> Result<LogFilter, MutinyJsError> {
+    let min_level = min_level.map(|l| LogLevel::from_str(&l)).transpose()?;
+    Ok(LogFilter {
+        min_level,
+        start,
+        end,
+        module,
+    })
+}
+
+fn parse_outpoints(outpoints: &[String]) -> Result<Vec<OutPoint>, MutinyJsError> {
+    outpoints
+        .iter()
+        .map(|o| OutPoint::from_str(o).map_err(|_| MutinyJsError::InvalidArgumentsError))
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use crate::utils::test::*;
@@ -1818,6 +2622,13 @@ mod tests {
             None,
             None,
             None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
         )
         .await
         .expect("mutiny wallet should initialize");
@@ -1853,6 +2664,13 @@ mod tests {
             None,
             None,
             None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
         )
         .await
         .expect("mutiny wallet should initialize");
@@ -1882,6 +2700,13 @@ mod tests {
             None,
             None,
             None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
         )
         .await;
 
@@ -1924,6 +2749,13 @@ mod tests {
             None,
             None,
             None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
         )
         .await
         .expect("mutiny wallet should initialize");
@@ -1952,6 +2784,13 @@ mod tests {
             None,
             None,
             None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
         )
         .await;
 
@@ -2001,6 +2840,13 @@ mod tests {
             None,
             None,
             None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
         )
         .await
         .unwrap();
@@ -2048,6 +2894,13 @@ mod tests {
             None,
             None,
             None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
         )
         .await
         .unwrap();
@@ -2082,6 +2935,13 @@ mod tests {
             None,
             None,
             None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
         )
         .await;
 
@@ -2120,6 +2980,13 @@ mod tests {
             None,
             None,
             None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
         )
         .await
         .expect("mutiny wallet should initialize");
@@ -2188,6 +3055,13 @@ mod tests {
             None,
             None,
             None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
         )
         .await
         .expect("mutiny wallet should initialize");
@@ -2245,6 +3119,13 @@ mod tests {
             None,
             None,
             None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
         )
         .await
         .expect("mutiny wallet should initialize");
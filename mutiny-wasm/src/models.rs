@@ -6,8 +6,10 @@ use gloo_utils::format::JsValueSerdeExt;
 use lightning_invoice::{Bolt11Invoice, Bolt11InvoiceDescription};
 use lnurl::lightning_address::LightningAddress;
 use lnurl::lnurl::LnUrl;
-use mutiny_core::event::HTLCStatus;
+use mutiny_core::event::{CustomTlv, HTLCStatus};
+use mutiny_core::federation::FederationOnchainKind;
 use mutiny_core::labels::Contact as MutinyContact;
+use mutiny_core::lnurlpay::SuccessAction;
 use mutiny_core::nostr::nwc::SpendingConditions;
 use mutiny_core::*;
 use serde::{Deserialize, Serialize, Serializer};
@@ -22,6 +24,7 @@ pub enum ActivityType {
     Lightning,
     ChannelOpen,
     ChannelClose,
+    FederationOnchain,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
@@ -71,6 +74,7 @@ impl From<mutiny_core::ActivityItem> for ActivityItem {
             }
             mutiny_core::ActivityItem::Lightning(_) => ActivityType::Lightning,
             mutiny_core::ActivityItem::ChannelClosed(_) => ActivityType::ChannelClose,
+            mutiny_core::ActivityItem::FederationOnchain(_) => ActivityType::FederationOnchain,
         };
 
         let id = match a {
@@ -79,6 +83,7 @@ impl From<mutiny_core::ActivityItem> for ActivityItem {
             mutiny_core::ActivityItem::ChannelClosed(ref c) => {
                 c.user_channel_id.map(|c| c.to_hex()).unwrap_or_default()
             }
+            mutiny_core::ActivityItem::FederationOnchain(ref t) => t.operation_id.clone(),
         };
 
         let (inbound, amount_sats) = match a {
@@ -93,6 +98,9 @@ impl From<mutiny_core::ActivityItem> for ActivityItem {
             }
             mutiny_core::ActivityItem::Lightning(ref ln) => (ln.inbound, ln.amount_sats),
             mutiny_core::ActivityItem::ChannelClosed(_) => (false, None),
+            mutiny_core::ActivityItem::FederationOnchain(ref t) => {
+                (t.kind == FederationOnchainKind::Deposit, t.amount_sats)
+            }
         };
 
         ActivityItem {
@@ -111,6 +119,7 @@ impl From<mutiny_core::ActivityItem> for ActivityItem {
 #[wasm_bindgen]
 pub struct MutinyInvoice {
     bolt11: Option<Bolt11Invoice>,
+    bolt12: Option<String>,
     description: Option<String>,
     payment_hash: String,
     preimage: Option<String>,
@@ -123,6 +132,10 @@ pub struct MutinyInvoice {
     pub last_updated: u64,
     pub potential_hodl_invoice: bool,
     labels: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    custom_tlvs: Vec<CustomTlv>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    success_action: Option<SuccessAction>,
 }
 
 #[wasm_bindgen]
@@ -137,6 +150,11 @@ impl MutinyInvoice {
         self.bolt11.clone().map(|b| b.to_string())
     }
 
+    #[wasm_bindgen(getter)]
+    pub fn bolt12(&self) -> Option<String> {
+        self.bolt12.clone()
+    }
+
     #[wasm_bindgen(getter)]
     pub fn description(&self) -> Option<String> {
         self.description.clone()
@@ -171,6 +189,18 @@ impl MutinyInvoice {
     pub fn labels(&self) -> Vec<String> {
         self.labels.clone()
     }
+
+    /// Custom TLV records sent with, or received on, a keysend payment
+    #[wasm_bindgen(getter)]
+    pub fn custom_tlvs(&self) -> JsValue /* Vec<CustomTlv> */ {
+        JsValue::from_serde(&self.custom_tlvs).unwrap()
+    }
+
+    /// What the LNURL-pay service wants shown after paying it, like a receipt or voucher
+    #[wasm_bindgen(getter)]
+    pub fn success_action(&self) -> JsValue /* Option<SuccessAction> */ {
+        JsValue::from_serde(&self.success_action).unwrap()
+    }
 }
 
 impl From<mutiny_core::MutinyInvoice> for MutinyInvoice {
@@ -183,6 +213,7 @@ impl From<mutiny_core::MutinyInvoice> for MutinyInvoice {
         };
         MutinyInvoice {
             bolt11: m.bolt11,
+            bolt12: m.bolt12,
             description: m.description,
             payment_hash: m.payment_hash.to_hex(),
             preimage: m.preimage,
@@ -195,6 +226,8 @@ impl From<mutiny_core::MutinyInvoice> for MutinyInvoice {
             last_updated: m.last_updated,
             potential_hodl_invoice,
             labels: m.labels,
+            custom_tlvs: m.custom_tlvs,
+            success_action: m.success_action,
         }
     }
 }
@@ -564,6 +597,42 @@ impl From<nodemanager::NodeIdentity> for NodeIdentity {
     }
 }
 
+/// A channel waiting for its funding PSBT to be signed by an external signer
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq)]
+#[wasm_bindgen]
+pub struct PendingChannelFunding {
+    user_channel_id: String,
+    psbt: String,
+}
+
+#[wasm_bindgen]
+impl PendingChannelFunding {
+    #[wasm_bindgen(getter)]
+    pub fn value(&self) -> JsValue {
+        JsValue::from_serde(&serde_json::to_value(self).unwrap()).unwrap()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn user_channel_id(&self) -> String {
+        self.user_channel_id.clone()
+    }
+
+    /// The unsigned funding transaction as a base64 PSBT
+    #[wasm_bindgen(getter)]
+    pub fn psbt(&self) -> String {
+        self.psbt.clone()
+    }
+}
+
+impl From<nodemanager::PendingChannelFunding> for PendingChannelFunding {
+    fn from(m: nodemanager::PendingChannelFunding) -> Self {
+        PendingChannelFunding {
+            user_channel_id: m.user_channel_id.to_string(),
+            psbt: m.psbt.to_string(),
+        }
+    }
+}
+
 // This is the FederationIdentity that refer to a specific node
 // Used for public facing identification.
 #[derive(Serialize, Deserialize, Clone, Eq, PartialEq)]
@@ -607,6 +676,7 @@ pub struct MutinyBip21RawMaterials {
     pub(crate) invoice: Option<String>,
     pub(crate) btc_amount: Option<String>,
     pub(crate) labels: Vec<String>,
+    pub(crate) payjoin_endpoint: Option<String>,
 }
 
 #[wasm_bindgen]
@@ -635,6 +705,11 @@ impl MutinyBip21RawMaterials {
     pub fn labels(&self) -> Vec<String> {
         self.labels.clone()
     }
+
+    #[wasm_bindgen(getter)]
+    pub fn payjoin_endpoint(&self) -> Option<String> {
+        self.payjoin_endpoint.clone()
+    }
 }
 
 impl From<nodemanager::MutinyBip21RawMaterials> for MutinyBip21RawMaterials {
@@ -644,6 +719,7 @@ impl From<nodemanager::MutinyBip21RawMaterials> for MutinyBip21RawMaterials {
             invoice: m.invoice.map(|i| i.to_string()),
             btc_amount: m.btc_amount,
             labels: m.labels,
+            payjoin_endpoint: m.payjoin_endpoint,
         }
     }
 }
@@ -802,6 +878,7 @@ pub struct NwcProfile {
     nwc_uri: Option<String>,
     tag: String,
     label: Option<String>,
+    notifications: Vec<String>,
 }
 
 impl Serialize for NwcProfile {
@@ -819,6 +896,7 @@ impl Serialize for NwcProfile {
             "nwc_uri": self.nwc_uri,
             "tag": self.tag,
             "label": self.label,
+            "notifications": self.notifications,
             "budget_amount": self.budget_amount(),
             "budget_period": self.budget_period(),
             "budget_remaining": self.budget_remaining(),
@@ -872,6 +950,12 @@ impl NwcProfile {
         self.label.clone()
     }
 
+    /// NIP-47 notifications the client receives, `payment_received` or `payment_sent`
+    #[wasm_bindgen(getter)]
+    pub fn notifications(&self) -> Vec<String> {
+        self.notifications.clone()
+    }
+
     #[wasm_bindgen(getter)]
     pub fn budget_amount(&self) -> Option<u64> {
         match &self.spending_conditions {
@@ -965,6 +1049,7 @@ impl From<nostr::nwc::NwcProfile> for NwcProfile {
             nwc_uri: value.nwc_uri,
             tag: value.tag.to_string(),
             label: value.label,
+            notifications: value.notifications.iter().map(|n| n.to_string()).collect(),
         }
     }
 }